@@ -3,11 +3,17 @@
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as Tokens;
 use syn::{
-    parse2,
-    Ident, ItemStruct, Type, TypePath,
+    parse::Parser, parse2, punctuated::Punctuated,
+    Attribute, Expr, ExprLit, ExprRange, Ident, ItemStruct, Lit, LitInt, Meta, MetaNameValue, Path, RangeLimits,
+    Token, Type, TypeArray, TypePath,
 };
 
-use crate::error::{fault, Error};
+use quote::ToTokens;
+
+use crate::error::{fault, fault_with_help, Error};
+
+/// Hint listing the field kinds a frame understands
+const HELP: &str = "expected one of: u8, i8, u16, i16, u32, i32, u64, i64 (optionally with _be/_le), arrays of those such as [u32; 8], #[str(n)] Str, #[bytes(n)] Bytes, rest";
 
 /// Endianness specification
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -21,7 +27,7 @@ pub enum Endian {
 pub struct Attributes {
     pub version: Option<u8>,
     pub endian: Endian,
-    pub check: bool,
+    pub tokio: bool,
 }
 
 impl Default for Attributes {
@@ -29,7 +35,7 @@ impl Default for Attributes {
         Self {
             version: None,
             endian: Endian::Big,
-            check: true,
+            tokio: false,
         }
     }
 }
@@ -48,6 +54,12 @@ pub enum Kind {
     Bytes {
         size: usize,
     },
+    Array {
+        bits: u8,
+        signed: bool,
+        endian: Option<Endian>,
+        count: usize,
+    },
     Rest,
 }
 
@@ -56,6 +68,21 @@ pub enum Kind {
 pub struct Field {
     pub name: Ident,
     pub kind: Kind,
+    /// Type whose rkyv archive the field holds, from `#[archived(T)]`
+    pub archive: Option<Tokens>,
+    /// Checksum the field stores, from `#[crc32]` or `#[crc16]`
+    pub checksum: Option<Checksum>,
+    /// Byte offset from `#[at(n)]`, with the attribute for error spans
+    pub at: Option<(usize, Tokens)>,
+}
+
+/// Checksum stored in a field
+#[derive(Debug, Clone)]
+pub struct Checksum {
+    /// 32 for CRC-32, 16 for CRC-16
+    pub bits: u8,
+    /// Bytes covered, every byte before the field when `None`
+    pub range: Option<(usize, usize)>,
 }
 
 /// Layout specification
@@ -74,8 +101,18 @@ impl Layout {
         let item_struct = parse2::<ItemStruct>(item_tokens.clone())
             .map_err(|e| fault(&item_tokens, &format!("Failed to parse struct: {}", e)))?;
         
-        let mut fields = Vec::new();
+        let mut fields: Vec<Field> = Vec::new();
         for field in item_struct.fields {
+            // `checksum()` and `verify()` serve a single checksum
+            if fields.iter().any(|field| field.checksum.is_some())
+                && field.attrs.iter().any(|attr| attr.path().is_ident("crc32") || attr.path().is_ident("crc16"))
+            {
+                return Err(fault(&field, "A frame holds at most one checksum field"));
+            }
+            // Offsets are fixed at compile time, so nothing may follow `rest`
+            if fields.last().is_some_and(|last| matches!(last.kind, Kind::Rest)) {
+                return Err(fault(&field, "`rest` must be the last field"));
+            }
             let field_def = Self::parse_field(field, &attributes.endian)?;
             fields.push(field_def);
         }
@@ -87,11 +124,42 @@ impl Layout {
         })
     }
     
-    /// Parse frame attributes
-    fn parse_attrs(_attr: TokenStream) -> Result<Attributes, Error> {
-        // Simplified attribute parsing for now
-        // TODO: Implement proper attribute parsing
-        Ok(Attributes::default())
+    /// Parse frame attributes such as `endian = "le", version = 2`
+    fn parse_attrs(attr: TokenStream) -> Result<Attributes, Error> {
+        let mut attributes = Attributes::default();
+        let pairs = Punctuated::<MetaNameValue, Token![,]>::parse_terminated.parse(attr)?;
+        
+        for pair in pairs {
+            let literal = match &pair.value {
+                Expr::Lit(ExprLit { lit, .. }) => lit,
+                _ => return Err(fault(&pair.value, "Expected a literal value")),
+            };
+            
+            if pair.path.is_ident("endian") {
+                attributes.endian = match literal {
+                    Lit::Str(text) => match text.value().as_str() {
+                        "be" | "big" => Endian::Big,
+                        "le" | "little" => Endian::Little,
+                        _ => return Err(fault(text, "Expected endian = \"be\" or \"le\"")),
+                    },
+                    _ => return Err(fault(literal, "Expected endian = \"be\" or \"le\"")),
+                };
+            } else if pair.path.is_ident("version") {
+                attributes.version = match literal {
+                    Lit::Int(number) => Some(number.base10_parse::<u8>()?),
+                    _ => return Err(fault(literal, "Expected version = <u8>")),
+                };
+            } else if pair.path.is_ident("tokio") {
+                attributes.tokio = match literal {
+                    Lit::Bool(flag) => flag.value,
+                    _ => return Err(fault(literal, "Expected tokio = true or false")),
+                };
+            } else {
+                return Err(fault_with_help(&pair.path, "Unknown frame attribute", "expected `endian`, `version` or `tokio`"));
+            }
+        }
+        
+        Ok(attributes)
     }
     
     /// Parse a field definition
@@ -100,9 +168,85 @@ impl Layout {
             .clone()
             .ok_or_else(|| fault(&field, "Field must have a name"))?;
         
-        let kind = Self::parse_type(&field.ty, default_endian)?;
+        let kind = match Self::parse_size(&field.attrs)? {
+            Some(sized) => Self::parse_sized(&field.ty, sized)?,
+            None => Self::parse_type(&field.ty, default_endian)?,
+        };
+        let archive = Self::parse_archive(&field.attrs, &kind)?;
+        let checksum = Self::parse_checksum(&field.attrs, &kind)?;
+        let at = Self::parse_at(&field.attrs)?;
         
-        Ok(Field { name, kind })
+        Ok(Field { name, kind, archive, checksum, at })
+    }
+    
+    /// Parse an `#[at(n)]` field attribute placing a field at byte `n`
+    fn parse_at(attrs: &[Attribute]) -> Result<Option<(usize, Tokens)>, Error> {
+        let mut found = None;
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("at")) {
+            if found.is_some() {
+                return Err(fault(attr, "Only one #[at(n)] is allowed per field"));
+            }
+            let offset = attr.parse_args::<LitInt>()
+                .and_then(|lit| lit.base10_parse::<usize>())
+                .map_err(|_| fault(attr, "Invalid offset in #[at(n)]"))?;
+            found = Some((offset, attr.to_token_stream()));
+        }
+        Ok(found)
+    }
+    
+    /// Parse a `#[crc32]` or `#[crc16]` field attribute, with an optional `start..end` byte range
+    fn parse_checksum(attrs: &[Attribute], kind: &Kind) -> Result<Option<Checksum>, Error> {
+        let mut found = None;
+        for attr in attrs {
+            let bits = if attr.path().is_ident("crc32") {
+                32
+            } else if attr.path().is_ident("crc16") {
+                16
+            } else {
+                continue;
+            };
+            
+            if found.is_some() {
+                return Err(fault(attr, "Only one checksum attribute is allowed per field"));
+            }
+            if !matches!(kind, Kind::Integer { bits: width, signed: false, .. } if *width == bits) {
+                return Err(fault(attr, &format!("#[crc{}] fields must have type `u{}`", bits, bits)));
+            }
+            let range = match &attr.meta {
+                Meta::Path(_) => None,
+                _ => {
+                    let invalid = || fault(attr, &format!("Expected #[crc{}] or #[crc{}(start..end)]", bits, bits));
+                    let range = attr.parse_args::<ExprRange>().map_err(|_| invalid())?;
+                    let bound = |bound: &Option<Box<Expr>>| match bound.as_deref() {
+                        Some(Expr::Lit(ExprLit { lit: Lit::Int(number), .. })) => number.base10_parse::<usize>().ok(),
+                        _ => None,
+                    };
+                    match (bound(&range.start), &range.limits, bound(&range.end)) {
+                        (Some(start), RangeLimits::HalfOpen(_), Some(end)) if start < end => Some((start, end)),
+                        _ => return Err(invalid()),
+                    }
+                }
+            };
+            found = Some(Checksum { bits, range });
+        }
+        Ok(found)
+    }
+    
+    /// Parse a `#[archived(T)]` field attribute, allowed on byte fields only
+    fn parse_archive(attrs: &[Attribute], kind: &Kind) -> Result<Option<Tokens>, Error> {
+        let mut found = None;
+        for attr in attrs.iter().filter(|attr| attr.path().is_ident("archived")) {
+            if found.is_some() {
+                return Err(fault(attr, "Only one #[archived(T)] is allowed per field"));
+            }
+            if !matches!(kind, Kind::Bytes { .. } | Kind::Rest) {
+                return Err(fault(attr, "#[archived(T)] fields must be `rest` or #[bytes(n)] `Bytes`"));
+            }
+            let path = attr.parse_args::<Path>()
+                .map_err(|_| fault(attr, "Expected a type in #[archived(T)]"))?;
+            found = Some(quote::quote! { #path });
+        }
+        Ok(found)
     }
     
     /// Parse field type to determine kind
@@ -124,14 +268,10 @@ impl Layout {
                         });
                     }
                     
-                    // Handle str(n) syntax
-                    if ident_str.starts_with("str") {
-                        return Self::parse_str(ident);
-                    }
-                    
-                    // Handle bytes(n) syntax
-                    if ident_str.starts_with("bytes") {
-                        return Self::parse_bytes(ident);
+                    // Fixed-size text and bytes carry their size in an attribute
+                    if ident_str == "Str" || ident_str == "Bytes" {
+                        let attribute = ident_str.to_lowercase();
+                        return Err(fault(ty, &format!("`{}` fields need a size, e.g. #[{}(16)]", ident_str, attribute)));
                     }
                     
                     // Handle rest keyword
@@ -140,18 +280,38 @@ impl Layout {
                     }
                 }
                 
-                Err(fault(ty, "Unsupported field type"))
+                Err(fault_with_help(ty, "Unsupported field type", HELP))
+            }
+            Type::Array(TypeArray { elem, len, .. }) => {
+                let element = match elem.as_ref() {
+                    Type::Path(TypePath { path, .. }) => path.get_ident()
+                        .and_then(|ident| Self::parse_int(&ident.to_string())),
+                    _ => None,
+                };
+                let Some((bits, signed, endian_override)) = element else {
+                    return Err(fault(elem, "Array elements must be integers such as u32 or u16_le"));
+                };
+                let count = match len {
+                    Expr::Lit(ExprLit { lit: Lit::Int(count), .. }) => count.base10_parse::<usize>()?,
+                    _ => return Err(fault(len, "Array length must be an integer literal")),
+                };
+                Ok(Kind::Array {
+                    bits,
+                    signed,
+                    endian: endian_override.or(Some(*default_endian)),
+                    count,
+                })
             }
-            _ => Err(fault(ty, "Unsupported field type")),
+            _ => Err(fault_with_help(ty, "Unsupported field type", HELP)),
         }
     }
     
     /// Parse integer type with optional endian suffix
     fn parse_int(ident: &str) -> Option<(u8, bool, Option<Endian>)> {
-        let (base, endian) = if ident.ends_with("_be") {
-            (&ident[..ident.len() - 3], Some(Endian::Big))
-        } else if ident.ends_with("_le") {
-            (&ident[..ident.len() - 3], Some(Endian::Little))
+        let (base, endian) = if let Some(base) = ident.strip_suffix("_be") {
+            (base, Some(Endian::Big))
+        } else if let Some(base) = ident.strip_suffix("_le") {
+            (base, Some(Endian::Little))
         } else {
             (ident, None)
         };
@@ -171,31 +331,44 @@ impl Layout {
         Some((bits, signed, endian))
     }
     
-    /// Parse str(n) type
-    fn parse_str(ident: &Ident) -> Result<Kind, Error> {
-        let ident_str = ident.to_string();
-        if !ident_str.starts_with("str(") || !ident_str.ends_with(")") {
-            return Err(fault(ident, "Expected str(n) format"));
+    /// Parse a `#[str(n)]` or `#[bytes(n)]` field attribute
+    fn parse_size(attrs: &[Attribute]) -> Result<Option<(&'static str, usize)>, Error> {
+        let mut found = None;
+        for attr in attrs {
+            let name = if attr.path().is_ident("str") {
+                "str"
+            } else if attr.path().is_ident("bytes") {
+                "bytes"
+            } else {
+                continue;
+            };
+            
+            if found.is_some() {
+                return Err(fault(attr, "Only one of #[str(n)] or #[bytes(n)] is allowed per field"));
+            }
+            let size = attr.parse_args::<LitInt>()
+                .and_then(|lit| lit.base10_parse::<usize>())
+                .map_err(|_| fault(attr, &format!("Invalid size in #[{}(n)]", name)))?;
+            found = Some((name, size));
         }
-        
-        let size_str = &ident_str[4..ident_str.len() - 1];
-        let size: usize = size_str.parse()
-            .map_err(|_| fault(ident, "Invalid size in str(n)"))?;
-        
-        Ok(Kind::Str { size })
+        Ok(found)
     }
     
-    /// Parse bytes(n) type
-    fn parse_bytes(ident: &Ident) -> Result<Kind, Error> {
-        let ident_str = ident.to_string();
-        if !ident_str.starts_with("bytes(") || !ident_str.ends_with(")") {
-            return Err(fault(ident, "Expected bytes(n) format"));
+    /// Parse the type of a sized field, which must match its attribute
+    fn parse_sized(ty: &Type, (name, size): (&'static str, usize)) -> Result<Kind, Error> {
+        let expected = if name == "str" { "Str" } else { "Bytes" };
+        let matches = match ty {
+            Type::Path(TypePath { path, .. }) => path.is_ident(expected),
+            _ => false,
+        };
+        if !matches {
+            return Err(fault(ty, &format!("#[{}(n)] fields must have type `{}`", name, expected)));
         }
         
-        let size_str = &ident_str[6..ident_str.len() - 1];
-        let size: usize = size_str.parse()
-            .map_err(|_| fault(ident, "Invalid size in bytes(n)"))?;
-        
-        Ok(Kind::Bytes { size })
+        Ok(if name == "str" {
+            Kind::Str { size }
+        } else {
+            Kind::Bytes { size }
+        })
     }
-} 
\ No newline at end of file
+}
\ No newline at end of file
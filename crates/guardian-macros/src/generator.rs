@@ -1,11 +1,12 @@
 //! Code generation for guardian-macros
 
 use proc_macro2::TokenStream;
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::Ident;
 
 use crate::definition::{Layout, Kind, Endian};
 use crate::error::{fault, Error};
+use crate::reader;
 
 /// Generate frame implementation from layout
 pub fn generate(layout: &Layout) -> Result<TokenStream, Error> {
@@ -13,19 +14,45 @@ pub fn generate(layout: &Layout) -> Result<TokenStream, Error> {
     let attributes = &layout.attributes;
     let fields = &layout.fields;
     
-    // Calculate minimum size for fixed fields
-    let min = calculate_min(fields);
+    // Place every field, then size the fixed prefix to the last one's end
+    let placed = place(fields)?;
+    let min = fields.iter()
+        .zip(&placed)
+        .map(|(field, offset)| offset + size(field))
+        .max()
+        .unwrap_or(0);
     
-    // Generate accessor methods
+    // Generate accessor methods and the matching layout entries
     let mut accessors = Vec::new();
-    let mut offset = 0usize;
+    let mut entries = Vec::new();
+    let mut offsets = Vec::new();
+    let mut described = Vec::new();
+    let mut checksum = quote! {};
     
-    for field in fields {
+    for (field, &offset) in fields.iter().zip(&placed) {
+        if field.checksum.is_some() {
+            checksum = generate_checksum(field, offset, min)?;
+        }
         let method = generate_accessor(field, offset)?;
         accessors.push(method);
         
-        // Update offset for next field
-        offset += size(field);
+        let name = field.name.to_string();
+        let width = match field.kind {
+            Kind::Rest => quote! { ::core::option::Option::None },
+            _ => {
+                let width = size(field);
+                quote! { ::core::option::Option::Some(#width) }
+            }
+        };
+        entries.push(quote! { (#name, #offset, #width) });
+        
+        let constant = format_ident!("OFFSET_{}", name.to_uppercase());
+        let doc = format!("Byte offset of `{}`", name);
+        offsets.push(quote! {
+            #[doc = #doc]
+            pub const #constant: usize = #offset;
+        });
+        described.push(describe(field, offset));
     }
     
     // Generate version method if specified
@@ -39,30 +66,69 @@ pub fn generate(layout: &Layout) -> Result<TokenStream, Error> {
         quote! {}
     };
     
+    // Machine-readable description, assembled at expansion time
+    let description = format!(
+        "{{\"name\":\"{}\",\"size\":{},\"fields\":[{}]}}",
+        struct_name,
+        min,
+        described.join(","),
+    );
+    
+    // Every accessor reads inside the fixed prefix or `rest`, which is
+    // last, so one length check makes them all safe on untrusted input
+    let check = quote! {
+        if source.len() < #min {
+            return ::core::result::Result::Err(::std::io::Error::new(
+                ::std::io::ErrorKind::InvalidData,
+                ::std::format!("Insufficient data: need {} bytes, got {}", #min, source.len()),
+            ));
+        }
+    };
+    
+    let reader = reader::generate(layout);
+    
     // Generate the complete implementation
     let expanded = quote! {
-        #[derive(Debug, Clone)]
+        #[derive(::core::fmt::Debug, ::core::clone::Clone)]
         pub struct #struct_name<'a> {
             source: &'a [u8],
         }
         
         impl<'a> #struct_name<'a> {
-            pub fn new(source: &'a [u8]) -> Result<Self, std::io::Error> {
-                if source.len() < #min {
-                    return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "Insufficient data"));
-                }
+            /// Bytes in the fixed-size prefix; shorter input is rejected
+            pub const SIZE: usize = #min;
+            
+            #(#offsets)*
+            
+            pub fn new(source: &'a [u8]) -> ::core::result::Result<Self, ::std::io::Error> {
+                #check
                 
-                Ok(Self { source })
+                ::core::result::Result::Ok(Self { source })
             }
             
             #(#accessors)*
             
             #version
             
+            #checksum
+            
+            /// Name, byte offset and fixed size (`None` for `rest`) of each field
+            pub fn layout() -> &'static [(&'static str, usize, ::core::option::Option<usize>)] {
+                &[#(#entries),*]
+            }
+            
+            /// JSON description of the frame: name, size and each field's
+            /// name, offset, size (`null` for `rest`), kind and endianness
+            pub fn describe() -> &'static str {
+                #description
+            }
+            
             pub fn size(&self) -> usize {
                 self.source.len()
             }
         }
+        
+        #reader
     };
     
     Ok(expanded)
@@ -73,13 +139,43 @@ fn generate_accessor(field: &crate::definition::Field, offset: usize) -> Result<
     let field_name = &field.name;
     let method_name = Ident::new(&field_name.to_string(), field_name.span());
     
+    // Archived fields borrow their validated archive for as long as the bytes
+    if let Some(archive) = &field.archive {
+        let end = match &field.kind {
+            Kind::Bytes { size } => quote! { #offset + #size },
+            _ => quote! { self.source.len() },
+        };
+        let message = format!("Invalid archive in `{}`: {{}}", field_name);
+        return Ok(quote! {
+            pub fn #method_name(&self) -> ::core::result::Result<&'a ::rkyv::Archived<#archive>, ::std::io::Error> {
+                let source: &'a [u8] = self.source;
+                ::rkyv::check_archived_root::<#archive>(&source[#offset..#end])
+                    .map_err(|e| ::std::io::Error::new(::std::io::ErrorKind::InvalidData, ::std::format!(#message, e)))
+            }
+        });
+    }
+    
     let access = match &field.kind {
         Kind::Integer { bits, signed, endian } => {
             generate_int(offset, *bits, *signed, endian)?
         }
+        Kind::Array { bits, signed, endian, .. } => {
+            let element = generate_returns(&Kind::Integer { bits: *bits, signed: *signed, endian: *endian });
+            let read = match endian {
+                Some(Endian::Little) => quote! { from_le_bytes },
+                _ => quote! { from_be_bytes },
+            };
+            let width = (*bits / 8) as usize;
+            quote! {
+                ::core::array::from_fn(|index| {
+                    let at = #offset + index * #width;
+                    #element::#read(::core::convert::TryInto::try_into(&self.source[at..at + #width]).unwrap())
+                })
+            }
+        }
         Kind::Str { size } => {
             quote! {
-                std::str::from_utf8(&self.source[#offset..#offset + #size])
+                ::core::str::from_utf8(&self.source[#offset..#offset + #size])
                     .unwrap_or("")
             }
         }
@@ -104,6 +200,69 @@ fn generate_accessor(field: &crate::definition::Field, offset: usize) -> Result<
     })
 }
 
+/// Generate `checksum()` and `verify()` for the frame's checksum field
+///
+/// CRC-32 is the IEEE polynomial, as zlib and Ethernet compute it;
+/// CRC-16 is CCITT-FALSE: polynomial 0x1021, initial 0xFFFF, no
+/// reflection. The covered bytes must lie in the fixed prefix, which
+/// `new` guarantees is present, and must not include the field itself.
+fn generate_checksum(field: &crate::definition::Field, offset: usize, min: usize) -> Result<TokenStream, Error> {
+    let Some(checksum) = &field.checksum else {
+        return Ok(quote! {});
+    };
+    let name = &field.name;
+    let (start, end) = checksum.range.unwrap_or((0, offset));
+    if end > min {
+        return Err(fault(name, &format!("Checksum range ends at byte {}, past the fixed prefix of {} bytes", end, min)));
+    }
+    if start < offset + size(field) && offset < end {
+        return Err(fault(name, "Checksum range covers the checksum field itself"));
+    }
+    
+    let compute = if checksum.bits == 32 {
+        quote! {
+            let mut crc: u32 = !0;
+            for &byte in &self.source[#start..#end] {
+                crc ^= u32::from(byte);
+                for _ in 0..8 {
+                    crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+                }
+            }
+            !crc
+        }
+    } else {
+        quote! {
+            let mut crc: u16 = 0xFFFF;
+            for &byte in &self.source[#start..#end] {
+                crc ^= u16::from(byte) << 8;
+                for _ in 0..8 {
+                    crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+                }
+            }
+            crc
+        }
+    };
+    let returns = generate_returns(&field.kind);
+    let summary = format!("CRC-{} of bytes {}..{}, which `{}` should hold", checksum.bits, start, end, name);
+    let message = format!("Checksum mismatch in `{}`: stored {{:#x}}, computed {{:#x}}", name);
+    
+    Ok(quote! {
+        #[doc = #summary]
+        pub fn checksum(&self) -> #returns {
+            #compute
+        }
+        
+        /// Checks the stored checksum against the bytes it covers
+        pub fn verify(&self) -> ::core::result::Result<(), ::std::io::Error> {
+            let (stored, computed) = (self.#name(), self.checksum());
+            if stored != computed {
+                return ::core::result::Result::Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData, ::std::format!(#message, stored, computed)));
+            }
+            ::core::result::Result::Ok(())
+        }
+    })
+}
+
 /// Generate integer access pattern
 fn generate_int(offset: usize, bits: u8, signed: bool, endian: &Option<Endian>) -> Result<TokenStream, Error> {
     let bytes = (bits / 8) as usize;
@@ -164,31 +323,94 @@ fn generate_returns(kind: &Kind) -> TokenStream {
                 }
             }
         }
+        Kind::Array { bits, signed, endian, count } => {
+            let element = generate_returns(&Kind::Integer { bits: *bits, signed: *signed, endian: *endian });
+            quote! { [#element; #count] }
+        }
         Kind::Str { .. } => quote! { &str },
         Kind::Bytes { .. } => quote! { &[u8] },
         Kind::Rest => quote! { &[u8] },
     }
 }
 
-/// Calculate minimum size for fixed fields
-fn calculate_min(fields: &[crate::definition::Field]) -> usize {
-    fields.iter()
-        .filter_map(|field| {
-            if matches!(field.kind, Kind::Rest) {
-                None
-            } else {
-                Some(size(field))
+/// Byte offset of each field
+///
+/// Fields follow one another unless `#[at(n)]` places them, leaving
+/// the bytes skipped as padding. A placement before the end of the
+/// field declared ahead of it is refused, pointing at the attribute:
+/// fields are declared in byte order and never share bytes.
+fn place(fields: &[crate::definition::Field]) -> Result<Vec<usize>, Error> {
+    let mut offsets = Vec::with_capacity(fields.len());
+    let mut end = 0usize;
+    let mut previous: Option<(&Ident, usize)> = None;
+    for field in fields {
+        let offset = match (&field.at, previous) {
+            (Some((at, span)), Some((before, start))) if *at < start => {
+                return Err(fault(span, &format!(
+                    "`{}` is placed at byte {}, before `{}` at byte {}; declare fields in byte order",
+                    field.name, at, before, start,
+                )));
+            }
+            (Some((at, span)), Some((before, start))) if *at < end => {
+                return Err(fault(span, &format!(
+                    "`{}` at byte {} overlaps `{}`, which spans bytes {}..{}",
+                    field.name, at, before, start, end,
+                )));
             }
-        })
-        .sum()
+            (Some((at, _)), _) => *at,
+            (None, _) => end,
+        };
+        end = offset + size(field);
+        previous = Some((&field.name, offset));
+        offsets.push(offset);
+    }
+    Ok(offsets)
 }
 
 /// Get size of a field
 fn size(field: &crate::definition::Field) -> usize {
     match &field.kind {
         Kind::Integer { bits, .. } => (*bits / 8) as usize,
+        Kind::Array { bits, count, .. } => (*bits / 8) as usize * count,
         Kind::Str { size } => *size,
         Kind::Bytes { size } => *size,
         Kind::Rest => 0, // Variable size
     }
-} 
\ No newline at end of file
+} 
+
+/// Describe one field as a JSON object
+///
+/// Arrays are described as `array`, with the element kind and count as
+/// `element` and `count`. Checksum fields add `checksum`, `crc32` or
+/// `crc16`, and the byte `range` it covers.
+fn describe(field: &crate::definition::Field, offset: usize) -> String {
+    let integer = |bits: u8, signed: bool| format!("{}{}", if signed { "i" } else { "u" }, bits);
+    let order = |endian: &Option<Endian>| match endian {
+        Some(Endian::Little) => "\"little\"",
+        _ => "\"big\"",
+    };
+    let mut extra = match &field.checksum {
+        Some(checksum) => {
+            let (start, end) = checksum.range.unwrap_or((0, offset));
+            format!(",\"checksum\":\"crc{}\",\"range\":[{},{}]", checksum.bits, start, end)
+        }
+        None => String::new(),
+    };
+    let (kind, width, endian) = match &field.kind {
+        Kind::Integer { bits, signed, endian } => {
+            (integer(*bits, *signed), size(field).to_string(), order(endian))
+        }
+        Kind::Array { bits, signed, endian, count } => {
+            extra = format!(",\"element\":\"{}\",\"count\":{}", integer(*bits, *signed), count);
+            ("array".to_string(), size(field).to_string(), order(endian))
+        }
+        Kind::Str { size } => ("str".to_string(), size.to_string(), "null"),
+        Kind::Bytes { size } => ("bytes".to_string(), size.to_string(), "null"),
+        Kind::Rest => ("rest".to_string(), "null".to_string(), "null"),
+    };
+    
+    format!(
+        "{{\"name\":\"{}\",\"offset\":{},\"size\":{},\"kind\":\"{}\",\"endian\":{}{}}}",
+        field.name, offset, width, kind, endian, extra,
+    )
+}
@@ -1,12 +1,15 @@
 //! Procedural macros for Guardian-Store
 //! 
 //! Provides the #[frame] attribute macro for defining binary layouts
-//! with single-word identifier philosophy.
+//! and the `Record` derive for describing stored structs, with
+//! single-word identifier philosophy.
 
 use proc_macro::TokenStream;
 
 mod definition;
 mod generator;
+mod reader;
+mod record;
 mod error;
 
 use definition::Layout;
@@ -18,6 +21,51 @@ use generator::generate;
 /// binary data according to a specified layout, following the
 /// single-word identifier philosophy.
 /// 
+/// Alongside the accessors, `layout()` lists each field's name, byte
+/// offset and fixed size, so tests can build and fuzz matching input.
+/// `SIZE` and one `OFFSET_<FIELD>` constant per field expose the same
+/// numbers at compile time, and `describe()` returns them as JSON for
+/// external tools.
+/// 
+/// Integers are big-endian unless the frame says `#[frame(endian = "le")]`;
+/// a `_be` or `_le` suffix such as `u32_le` overrides it per field.
+/// `#[frame(version = 2)]` adds a `version()` accessor returning 2.
+/// 
+/// Fields follow one another unless `#[at(n)]` places one at byte `n`,
+/// leaving the bytes before it as padding. Fields are declared in byte
+/// order; placing one before the end of the field declared ahead of it
+/// is a compile error pointing at its `#[at(n)]`.
+/// 
+/// Fixed tables are arrays of integers, such as `[u32; 8]` or
+/// `[u16_le; 4]`, read into an array of that type with every element
+/// in the field's byte order.
+/// 
+/// A `<Name>Reader` adapter pulls frames from any `std::io::Read`, one
+/// at a time. Frames without `rest` are read back to back; frames with
+/// it are preceded by a `u32` length in the frame's byte order. With
+/// `#[frame(tokio = true)]` the reader also works over
+/// `tokio::io::AsyncRead` through `receive()`.
+/// 
+/// Fixed-size text and byte fields take their size from an attribute:
+/// `#[str(n)]` on a `Str` field reads `&str`, `#[bytes(n)]` on a
+/// `Bytes` field reads `&[u8]`.
+/// 
+/// A `u32` field marked `#[crc32]`, or a `u16` field marked `#[crc16]`,
+/// holds a checksum of the bytes before it, or of the bytes given as
+/// in `#[crc32(4..20)]`. `checksum()` computes it over those bytes and
+/// `verify()` fails with `InvalidData` when it differs from the stored
+/// value. CRC-32 is the IEEE polynomial used by zlib; CRC-16 is
+/// CCITT-FALSE. A frame has at most one checksum field.
+/// 
+/// A `rest` or `Bytes` field holding an rkyv archive can say so with
+/// `#[archived(T)]`. Its accessor then validates the bytes and returns
+/// `&rkyv::Archived<T>` borrowed from the frame's input, so archived
+/// sub-objects such as a user's location are read in place without
+/// deserializing. The crate using the frame needs `rkyv` with its
+/// `validation` feature, and the field's bytes must be aligned as the
+/// archive requires; misaligned or malformed bytes are an
+/// `InvalidData` error.
+/// 
 /// # Example
 /// ```rust
 /// use guardian_macros::frame;
@@ -26,6 +74,11 @@ use generator::generate;
 /// pub struct Packet {
 ///     id: u32,
 ///     kind: u16,
+///     table: [u32; 4],
+///     #[str(8)]
+///     tag: Str,
+///     #[bytes(4)]
+///     key: Bytes,
 ///     data: rest,
 /// }
 /// ```
@@ -42,4 +95,35 @@ pub fn frame(attr: TokenStream, item: TokenStream) -> TokenStream {
         Ok(tokens) => tokens.into(),
         Err(error) => error.into_compile_error().into(),
     }
-} 
\ No newline at end of file
+}
+
+/// Derive macro listing a struct's fields for schema registration
+/// 
+/// Adds an associated `FIELDS` constant holding each field's name and
+/// declared type as written, in declaration order. Only structs with
+/// named fields are supported.
+/// 
+/// Fields marked `#[index(unique)]` are also named in `UNIQUE`, which
+/// the store reads to refuse two records sharing a value. Fields marked
+/// `#[secret]` are named in `SECRET`, for the store to encrypt at rest.
+/// 
+/// # Example
+/// ```rust
+/// use guardian_macros::Record;
+/// 
+/// #[derive(Record)]
+/// pub struct Point {
+///     latitude: f64,
+///     longitude: f64,
+/// }
+/// 
+/// assert_eq!(Point::FIELDS, &[("latitude", "f64"), ("longitude", "f64")]);
+/// ```
+#[proc_macro_derive(Record, attributes(index, secret))]
+pub fn record(item: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(item as syn::DeriveInput);
+    match record::generate(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.into_compile_error().into(),
+    }
+}
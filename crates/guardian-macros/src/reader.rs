@@ -0,0 +1,152 @@
+//! Stream reader generation for guardian-macros
+//!
+//! Frames borrow their bytes, so a reader owns one buffer and lends
+//! each frame out of it until the next read. Frames without `rest`
+//! have a fixed size and are read back to back; frames ending in
+//! `rest` are length-delimited by a `u32` prefix in the frame's
+//! default byte order.
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+use crate::definition::{Endian, Kind, Layout};
+
+/// Default cap on a length-delimited frame, in bytes
+const LIMIT: usize = 16 * 1024 * 1024;
+
+/// Generate the `<Frame>Reader` adapter for a layout
+pub fn generate(layout: &Layout) -> TokenStream {
+    let name = &layout.name;
+    let reader = format_ident!("{}Reader", name);
+    let delimited = layout.fields.iter().any(|field| matches!(field.kind, Kind::Rest));
+    let doc = format!("Reads `{}` frames one at a time from a byte stream", name);
+    
+    let sync = frame(layout, delimited, quote! { Self::fill(&mut self.source, &mut prefix)? }, quote! { Self::fill(&mut self.source, &mut self.buffer)? });
+    let tokio = frame(layout, delimited, quote! { Self::gather(&mut self.source, &mut prefix).await? }, quote! { Self::gather(&mut self.source, &mut self.buffer).await? });
+    
+    let asynchronous = if layout.attributes.tokio {
+        quote! {
+            impl<R: ::tokio::io::AsyncRead + ::core::marker::Unpin> #reader<R> {
+                /// Receives the next frame, or `None` at a clean end of stream
+                pub async fn receive(&mut self) -> ::core::result::Result<::core::option::Option<#name<'_>>, ::std::io::Error> {
+                    #tokio
+                }
+                
+                /// Fills a buffer; false if the stream ended before its first byte
+                async fn gather(source: &mut R, buffer: &mut [u8]) -> ::core::result::Result<bool, ::std::io::Error> {
+                    use ::tokio::io::AsyncReadExt;
+                    let mut filled = 0;
+                    while filled < buffer.len() {
+                        match source.read(&mut buffer[filled..]).await {
+                            ::core::result::Result::Ok(0) if filled == 0 => return ::core::result::Result::Ok(false),
+                            ::core::result::Result::Ok(0) => return ::core::result::Result::Err(::std::io::Error::new(::std::io::ErrorKind::UnexpectedEof, "Stream ended mid-frame")),
+                            ::core::result::Result::Ok(read) => filled += read,
+                            ::core::result::Result::Err(e) if e.kind() == ::std::io::ErrorKind::Interrupted => continue,
+                            ::core::result::Result::Err(e) => return ::core::result::Result::Err(e),
+                        }
+                    }
+                    ::core::result::Result::Ok(true)
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+    
+    quote! {
+        #[doc = #doc]
+        #[derive(::core::fmt::Debug)]
+        pub struct #reader<R> {
+            source: R,
+            buffer: ::std::vec::Vec<u8>,
+            limit: usize,
+        }
+        
+        impl<R> #reader<R> {
+            /// Wraps a byte stream
+            pub fn new(source: R) -> Self {
+                Self {
+                    source,
+                    buffer: ::std::vec::Vec::new(),
+                    limit: #LIMIT,
+                }
+            }
+            
+            /// Sets the largest length-delimited frame accepted, in bytes
+            pub fn limit(mut self, bytes: usize) -> Self {
+                self.limit = bytes;
+                self
+            }
+            
+            /// Returns the wrapped stream
+            pub fn inner(self) -> R {
+                self.source
+            }
+        }
+        
+        impl<R: ::std::io::Read> #reader<R> {
+            /// Reads the next frame, or `None` at a clean end of stream
+            pub fn read(&mut self) -> ::core::result::Result<::core::option::Option<#name<'_>>, ::std::io::Error> {
+                #sync
+            }
+            
+            /// Fills a buffer; false if the stream ended before its first byte
+            fn fill(source: &mut R, buffer: &mut [u8]) -> ::core::result::Result<bool, ::std::io::Error> {
+                let mut filled = 0;
+                while filled < buffer.len() {
+                    match source.read(&mut buffer[filled..]) {
+                        ::core::result::Result::Ok(0) if filled == 0 => return ::core::result::Result::Ok(false),
+                        ::core::result::Result::Ok(0) => return ::core::result::Result::Err(::std::io::Error::new(::std::io::ErrorKind::UnexpectedEof, "Stream ended mid-frame")),
+                        ::core::result::Result::Ok(read) => filled += read,
+                        ::core::result::Result::Err(e) if e.kind() == ::std::io::ErrorKind::Interrupted => continue,
+                        ::core::result::Result::Err(e) => return ::core::result::Result::Err(e),
+                    }
+                }
+                ::core::result::Result::Ok(true)
+            }
+        }
+        
+        #asynchronous
+    }
+}
+
+/// Generate the body reading one frame, given how to fill the prefix and the buffer
+fn frame(layout: &Layout, delimited: bool, prefix: TokenStream, body: TokenStream) -> TokenStream {
+    let name = &layout.name;
+    
+    let length = if delimited {
+        let decode = match layout.attributes.endian {
+            Endian::Big => quote! { from_be_bytes },
+            Endian::Little => quote! { from_le_bytes },
+        };
+        quote! {
+            let mut prefix = [0u8; 4];
+            if !#prefix {
+                return ::core::result::Result::Ok(::core::option::Option::None);
+            }
+            let length = u32::#decode(prefix) as usize;
+            if length > self.limit {
+                return ::core::result::Result::Err(::std::io::Error::new(
+                    ::std::io::ErrorKind::InvalidData,
+                    ::std::format!("Frame of {} bytes exceeds the limit of {}", length, self.limit),
+                ));
+            }
+            self.buffer.resize(length, 0);
+            if !#body {
+                return ::core::result::Result::Err(::std::io::Error::new(::std::io::ErrorKind::UnexpectedEof, "Stream ended mid-frame"));
+            }
+        }
+    } else {
+        quote! {
+            self.buffer.resize(#name::SIZE, 0);
+            if !#body {
+                return ::core::result::Result::Ok(::core::option::Option::None);
+            }
+        }
+    };
+    
+    quote! {
+        #length
+        #name::new(&self.buffer).map(::core::option::Option::Some)
+    }
+}
@@ -0,0 +1,98 @@
+//! Record derive for guardian-macros
+//!
+//! Lists a struct's fields with their declared types, so the storage
+//! engine can register each schema version's layout without keeping a
+//! second, hand-written copy of it. Fields marked `#[index(unique)]`
+//! are listed apart, for the store to hold their values unique, and so
+//! are fields marked `#[secret]`, for the store to encrypt.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Attribute, Data, DeriveInput, Fields};
+
+use crate::error::{fault, Error};
+
+/// Generate the `FIELDS` table of a struct with named fields
+pub fn generate(input: &DeriveInput) -> Result<TokenStream, Error> {
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(fault(name, "Record needs a struct with named fields")),
+        },
+        _ => return Err(fault(name, "Record can only be derived for structs")),
+    };
+    
+    let entries = fields.iter().map(|field| {
+        let field_name = field.ident.as_ref().map(ToString::to_string).unwrap_or_default();
+        let ty = &field.ty;
+        let kind = bare(&quote!(#ty).to_string());
+        quote! { (#field_name, #kind) }
+    });
+    
+    let mut unique = Vec::new();
+    let mut secret = Vec::new();
+    for field in fields {
+        let field_name = field.ident.as_ref().map(ToString::to_string).unwrap_or_default();
+        if marked(&field.attrs)? {
+            unique.push(field_name.clone());
+        }
+        if hidden(&field.attrs)? {
+            secret.push(field_name);
+        }
+    }
+    
+    Ok(quote! {
+        impl #impl_generics #name #type_generics #where_clause {
+            /// Field names and declared types, in declaration order
+            pub const FIELDS: &'static [(&'static str, &'static str)] = &[#(#entries),*];
+            
+            /// Names of the fields marked `#[index(unique)]`, in declaration order
+            pub const UNIQUE: &'static [&'static str] = &[#(#unique),*];
+            
+            /// Names of the fields marked `#[secret]`, in declaration order
+            pub const SECRET: &'static [&'static str] = &[#(#secret),*];
+        }
+    })
+}
+
+/// Whether a field carries `#[index(unique)]`, the only index option
+fn marked(attrs: &[Attribute]) -> Result<bool, Error> {
+    let mut unique = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("index")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("unique") {
+                unique = true;
+                Ok(())
+            } else {
+                Err(meta.error("Expected #[index(unique)]"))
+            }
+        })?;
+    }
+    Ok(unique)
+}
+
+/// Whether a field carries `#[secret]`, which takes no options
+fn hidden(attrs: &[Attribute]) -> Result<bool, Error> {
+    let mut secret = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("secret")) {
+        attr.meta.require_path_only()?;
+        secret = true;
+    }
+    Ok(secret)
+}
+
+/// Drops spacing and module paths, so `model :: Profile` reads `Profile`
+fn bare(kind: &str) -> String {
+    let kind = kind.replace(' ', "");
+    let mut parts = kind.split("::").collect::<Vec<_>>();
+    let last = parts.pop().unwrap_or_default();
+    let mut out = String::new();
+    for part in parts {
+        out.push_str(part.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_'));
+    }
+    out.push_str(last);
+    out
+}
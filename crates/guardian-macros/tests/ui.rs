@@ -0,0 +1,8 @@
+//! Compile-time tests for the frame macro
+
+#[test]
+fn test_ui() {
+    let cases = trybuild::TestCases::new();
+    cases.pass("tests/ui/pass_*.rs");
+    cases.compile_fail("tests/ui/fail_*.rs");
+}
@@ -0,0 +1,9 @@
+use guardian_macros::frame;
+
+#[frame]
+pub struct Misplaced {
+    #[archived(u64)]
+    id: u32, // archives live in byte fields
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use guardian_macros::frame;
+
+#[frame]
+pub struct Table {
+    id: u32,
+    names: [Str; 4], // only integers form tables
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use guardian_macros::frame;
+
+#[frame]
+pub struct Sealed {
+    id: u32,
+    #[crc32]
+    crc: u16, // a CRC-32 needs four bytes
+}
+
+fn main() {}
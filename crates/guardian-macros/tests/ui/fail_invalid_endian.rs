@@ -0,0 +1,8 @@
+use guardian_macros::frame;
+
+#[frame(endian = "middle")]
+pub struct Middle {
+    id: u32,
+}
+
+fn main() {}
@@ -5,4 +5,5 @@ pub struct Invalid {
     id: u32,
     invalid: MyCustomType, // This should cause a compilation error
     data: rest,
-} 
\ No newline at end of file
+} 
+fn main() {}
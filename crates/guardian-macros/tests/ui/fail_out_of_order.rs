@@ -0,0 +1,11 @@
+use guardian_macros::frame;
+
+#[frame]
+pub struct Unordered {
+    #[at(8)]
+    stamp: u64,
+    #[at(0)]
+    id: u32, // declared after a field it precedes
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use guardian_macros::frame;
+
+#[frame]
+pub struct Overlap {
+    id: u32,
+    #[at(2)]
+    kind: u16, // bytes 2..4 belong to `id`
+}
+
+fn main() {}
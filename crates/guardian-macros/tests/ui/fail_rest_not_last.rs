@@ -0,0 +1,10 @@
+use guardian_macros::frame;
+
+#[frame]
+pub struct Trailing {
+    id: u32,
+    data: rest,
+    crc: u16, // Offsets after `rest` are unknowable
+}
+
+fn main() {}
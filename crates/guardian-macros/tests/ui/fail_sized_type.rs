@@ -0,0 +1,9 @@
+use guardian_macros::frame;
+
+#[frame]
+pub struct Mismatch {
+    #[bytes(4)]
+    key: Str, // #[bytes(n)] goes with Bytes
+}
+
+fn main() {}
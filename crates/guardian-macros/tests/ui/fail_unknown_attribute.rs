@@ -0,0 +1,8 @@
+use guardian_macros::frame;
+
+#[frame(order = "le")] // Not a frame attribute
+pub struct Unknown {
+    id: u32,
+}
+
+fn main() {}
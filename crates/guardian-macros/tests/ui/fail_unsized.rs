@@ -0,0 +1,9 @@
+use guardian_macros::frame;
+
+#[frame]
+pub struct Unsized {
+    id: u32,
+    name: Str, // Needs #[str(n)]
+}
+
+fn main() {}
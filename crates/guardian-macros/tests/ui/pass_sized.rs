@@ -0,0 +1,21 @@
+use guardian_macros::frame;
+
+#[frame]
+pub struct Sized {
+    id: u16,
+    #[str(5)]
+    name: Str,
+    #[bytes(3)]
+    key: Bytes,
+    data: rest,
+}
+
+fn main() {
+    let data = *b"\x00\x07helloabc!";
+    let frame = Sized::new(&data).unwrap();
+    assert_eq!(frame.id(), 7);
+    assert_eq!(frame.name(), "hello");
+    assert_eq!(frame.key(), b"abc");
+    assert_eq!(frame.data(), b"!");
+    assert_eq!(Sized::layout()[2], ("key", 7, Some(3)));
+}
@@ -1,15 +1,40 @@
 //! Performance benchmarks for Guardian-Store
 
-use criterion::{criterion_group, criterion_main, Criterion, BenchmarkId};
-use guardian_store::{Store, User, Location};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion, BenchmarkId};
+use guardian_store::{Compression, Durability, Store, User, Location, Position, Profile};
+use guardian_store::compaction::Config;
+use guardian_store::index::{Index, Operation};
+use std::sync::Arc;
+use std::time::Duration;
 use tempfile::TempDir;
 
+/// Records in the stores scanned and compacted
+const RECORDS: u64 = 100_000;
+
+/// Index entries written per batch when building large indexes
+const CHUNK: u64 = 100_000;
+
+/// Producers writing at once through the write queue
+const PRODUCERS: u64 = 32;
+
+/// Opens a fresh store holding `count` benchmark users in small segments
+fn populate(count: u64) -> (TempDir, Store) {
+    let temp_dir = TempDir::new().unwrap();
+    let store = Store::builder().path(temp_dir.path()).segment(1024 * 1024).open().unwrap();
+    let users: Vec<User> = (0..count).map(create_benchmark_user).collect();
+    for chunk in users.chunks(CHUNK as usize) {
+        store.batch(chunk).unwrap();
+    }
+    (temp_dir, store)
+}
+
 fn create_benchmark_user(id: u64) -> User {
     let location = Location {
         street: format!("{} Benchmark Street", id),
         city: "Benchmark City".to_string(),
         country: "Benchmark Country".to_string(),
         postal: "54321".to_string(),
+        point: None,
     };
     
     User {
@@ -25,7 +50,7 @@ fn create_benchmark_user(id: u64) -> User {
         updated: std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
-            .as_secs(),
+            .as_secs(),        revision: 0,
     }
 }
 
@@ -36,7 +61,7 @@ fn benchmark_write(c: &mut Criterion) {
         group.bench_with_input(BenchmarkId::new("single_write", size), size, |b, &size| {
             b.iter(|| {
                 let temp_dir = TempDir::new().unwrap();
-                let mut store = Store::new(temp_dir.path()).unwrap();
+                let store = Store::new(temp_dir.path()).unwrap();
                 let user = create_benchmark_user(size);
                 store.save(&user).unwrap();
             });
@@ -52,7 +77,7 @@ fn benchmark_read(c: &mut Criterion) {
     for size in [10, 100, 1000].iter() {
         group.bench_with_input(BenchmarkId::new("single_read", size), size, |b, &size| {
             let temp_dir = TempDir::new().unwrap();
-            let mut store = Store::new(temp_dir.path()).unwrap();
+            let store = Store::new(temp_dir.path()).unwrap();
             let user = create_benchmark_user(size);
             store.save(&user).unwrap();
             
@@ -72,7 +97,7 @@ fn benchmark_batch_write(c: &mut Criterion) {
         group.bench_with_input(BenchmarkId::new("batch_write", size), size, |b, &size| {
             b.iter(|| {
                 let temp_dir = TempDir::new().unwrap();
-                let mut store = Store::new(temp_dir.path()).unwrap();
+                let store = Store::new(temp_dir.path()).unwrap();
                 let users: Vec<User> = (0..size).map(create_benchmark_user).collect();
                 store.batch(&users).unwrap();
             });
@@ -82,5 +107,210 @@ fn benchmark_batch_write(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, benchmark_write, benchmark_read, benchmark_batch_write);
+fn benchmark_scan(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scan_operations");
+    group.sample_size(10);
+    let (_temp_dir, store) = populate(RECORDS);
+    
+    group.bench_function(BenchmarkId::new("scan", RECORDS), |b| {
+        b.iter(|| assert_eq!(store.scan().count() as u64, RECORDS));
+    });
+    group.bench_function(BenchmarkId::new("archived", RECORDS), |b| {
+        b.iter(|| {
+            let mut archives = store.archived();
+            let mut count = 0;
+            while let Some(user) = archives.advance() {
+                user.unwrap();
+                count += 1;
+            }
+            assert_eq!(count, RECORDS);
+        });
+    });
+    group.bench_function(BenchmarkId::new("keys", RECORDS), |b| {
+        b.iter(|| assert_eq!(store.keys().count() as u64, RECORDS));
+    });
+    
+    group.finish();
+}
+
+/// Records written and read back per compression benchmark
+const SAMPLE: u64 = 1000;
+
+/// A user whose strings all fit inline, archiving to about 120 bytes
+fn create_small_user(id: u64) -> User {
+    User {
+        id,
+        name: "U".to_string(),
+        email: String::new(),
+        location: Location {
+            street: String::new(),
+            city: String::new(),
+            country: String::new(),
+            postal: String::new(),
+            point: None,
+        },
+        profile: None,
+        created: 0,
+        updated: 0,
+        revision: 0,
+    }
+}
+
+/// A user with a long profile, archiving to a record of about 2KB
+fn create_large_user(id: u64) -> User {
+    let mut user = create_benchmark_user(id);
+    user.profile = Some(Profile {
+        age: 30,
+        job: "Benchmark Engineer".to_string(),
+        interests: (0..64).map(|i| format!("interest number {}", i)).collect(),
+    });
+    user
+}
+
+/// Compares compressing every record, none, and only those over 1KB
+///
+/// Time is measured by criterion; the bytes each setting leaves on disk
+/// are printed once per case, since the tradeoff is time against space.
+fn benchmark_cutoff(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compression_cutoff");
+    group.sample_size(10);
+    for shape in ["small", "large"] {
+        let create = if shape == "small" { create_small_user } else { create_large_user };
+        let users: Vec<User> = (0..SAMPLE).map(create).collect();
+        for (name, cutoff) in [("always", 0), ("over_1k", 1024), ("never", usize::MAX)] {
+            let open = |path: &std::path::Path| {
+                Store::builder().path(path).compression(Compression::Lz4).cutoff(cutoff).open().unwrap()
+            };
+            
+            let temp_dir = TempDir::new().unwrap();
+            let store = open(temp_dir.path());
+            store.batch(&users).unwrap();
+            let bytes: u64 = std::fs::read_dir(temp_dir.path().join("segments")).unwrap()
+                .map(|entry| entry.unwrap().metadata().unwrap().len())
+                .sum();
+            eprintln!("compression_cutoff/{}/{}: {} bytes on disk", shape, name, bytes);
+            
+            group.bench_function(BenchmarkId::new(format!("write_{}", shape), name), |b| {
+                b.iter_batched(
+                    || TempDir::new().unwrap(),
+                    |temp_dir| open(temp_dir.path()).batch(&users).unwrap(),
+                    BatchSize::PerIteration,
+                );
+            });
+            group.bench_function(BenchmarkId::new(format!("read_{}", shape), name), |b| {
+                b.iter(|| {
+                    for id in 0..SAMPLE {
+                        store.find(id).unwrap().unwrap();
+                    }
+                });
+            });
+        }
+    }
+    
+    group.finish();
+}
+
+fn benchmark_compaction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compaction");
+    group.sample_size(10);
+    let config = Config {
+        threshold: 0.0,
+        limit: usize::MAX,
+        throttle: false,
+        ..Config::default()
+    };
+    
+    // Percentage of records deleted before compacting
+    for dead in [10, 50, 90] {
+        group.bench_with_input(BenchmarkId::new("major", dead), &dead, |b, &dead| {
+            b.iter_batched(
+                || {
+                    let (temp_dir, store) = populate(RECORDS);
+                    for id in (0..RECORDS).filter(|id| id % 100 < dead) {
+                        store.delete(id).unwrap();
+                    }
+                    (temp_dir, store)
+                },
+                |(_temp_dir, store)| store.compact(config.clone()).unwrap(),
+                BatchSize::PerIteration,
+            );
+        });
+    }
+    
+    group.finish();
+}
+
+fn benchmark_index_load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("index_load");
+    group.sample_size(10);
+    
+    for keys in [1_000_000u64, 10_000_000] {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("index");
+        {
+            let mut index = Index::new(&path).unwrap();
+            for start in (0..keys).step_by(CHUNK as usize) {
+                let operations = (start..(start + CHUNK).min(keys))
+                    .map(|id| Operation::Put { key: id.to_le_bytes().to_vec(), position: Position::default() })
+                    .collect();
+                index.batch(operations).unwrap();
+            }
+            index.merge().unwrap();
+        }
+        
+        group.bench_with_input(BenchmarkId::new("open", keys), &path, |b, path| {
+            b.iter(|| Index::new(path).unwrap());
+        });
+    }
+    
+    group.finish();
+}
+
+fn benchmark_window(c: &mut Criterion) {
+    let mut group = c.benchmark_group("group_commit");
+    group.sample_size(10);
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    
+    for micros in [0u64, 100, 1_000, 5_000] {
+        group.bench_with_input(BenchmarkId::new("window_us", micros), &micros, |b, &micros| {
+            b.iter_batched(
+                || TempDir::new().unwrap(),
+                |temp_dir| runtime.block_on(async {
+                    let store = Store::builder()
+                        .path(temp_dir.path())
+                        .durability(Durability::Sync)
+                        .window(Duration::from_micros(micros))
+                        .open()
+                        .unwrap();
+                    let writer = Arc::new(store.writer(PRODUCERS as usize).unwrap());
+                    let producers: Vec<_> = (0..PRODUCERS)
+                        .map(|id| {
+                            let writer = Arc::clone(&writer);
+                            tokio::spawn(async move { writer.save(create_benchmark_user(id)).await.unwrap() })
+                        })
+                        .collect();
+                    for producer in producers {
+                        producer.await.unwrap();
+                    }
+                    Arc::into_inner(writer).unwrap().close().await.unwrap();
+                }),
+                BatchSize::PerIteration,
+            );
+        });
+    }
+    
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    benchmark_write,
+    benchmark_read,
+    benchmark_batch_write,
+    benchmark_scan,
+    benchmark_cutoff,
+    benchmark_compaction,
+    benchmark_index_load,
+    benchmark_window,
+);
 criterion_main!(benches); 
\ No newline at end of file
@@ -0,0 +1,208 @@
+//! Geohash index over user locations
+//!
+//! Each point is quantized to 32 bits per axis and the bits are
+//! interleaved into a 64-bit geohash, so nearby points share long key
+//! prefixes. A radius query picks the cell size that covers the radius,
+//! scans the cell holding the center plus its eight neighbours, and
+//! leaves exact distance filtering to the caller.
+
+use std::path::{Path, PathBuf};
+use crate::Result;
+use crate::index::{Index, Shape, Snapshot};
+use crate::model::{Point, Position, User};
+
+/// Directory holding the geohash index inside a store
+const NAME: &str = "atlas";
+
+/// Quantization bits per axis
+const BITS: u32 = 32;
+
+/// Mean Earth radius in meters
+pub const RADIUS: f64 = 6_371_000.0;
+
+/// Secondary index on user coordinates
+pub struct Atlas {
+    /// Entries keyed by geohash then user ID
+    index: Index,
+}
+
+impl Atlas {
+    /// Opens the atlas of a store base directory
+    pub fn new<P: AsRef<Path>>(base: P) -> Result<Self> {
+        Ok(Self {
+            index: Index::new(Self::locate(base).join("points"))?,
+        })
+    }
+
+    /// Directory of the atlas for a base directory
+    pub fn locate<P: AsRef<Path>>(base: P) -> PathBuf {
+        base.as_ref().join(NAME)
+    }
+
+    /// Adds a user's location, if it has coordinates
+    pub fn insert(&mut self, user: &User) -> Result<()> {
+        match user.location.point {
+            Some(point) => self.index.put(&key(hash(point), user.id), Position::default()),
+            None => Ok(()),
+        }
+    }
+
+    /// Drops a user's location, if it had coordinates
+    pub fn remove(&mut self, user: &User) -> Result<()> {
+        match user.location.point {
+            Some(point) => self.index.delete(&key(hash(point), user.id)),
+            None => Ok(()),
+        }
+    }
+
+    /// IDs of users possibly within `radius` meters of `center`
+    ///
+    /// A superset: callers must check the exact distance.
+    pub fn near(&self, center: Point, radius: f64) -> Result<Vec<u64>> {
+        let mut ids = Vec::new();
+        for (low, high) in cover(center, radius) {
+            for result in self.index.after(&low.to_be_bytes()) {
+                let (key, _) = result?;
+                let (hash, id) = split(&key);
+                if hash > high {
+                    break;
+                }
+                ids.push(id);
+            }
+        }
+
+        ids.sort_unstable();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Flushes the index to disk
+    pub fn sync(&self) -> Result<()> {
+        self.index.sync()
+    }
+
+    /// Approximate bytes of memory held by the index
+    pub fn memory(&self) -> usize {
+        self.index.memory()
+    }
+
+    /// Drops every entry
+    pub fn clear(&mut self) -> Result<()> {
+        self.index.clear()
+    }
+
+    /// The index as it is now, by name
+    pub fn snapshots(&self) -> Vec<(&'static str, Snapshot)> {
+        vec![("atlas", self.index.snapshot())]
+    }
+
+    /// Entries and size of the index, counted in a full walk
+    pub fn shape(&self) -> Result<Shape> {
+        self.index.shape("atlas")
+    }
+
+    /// Merges the index delta into its table when above `floor` bytes
+    pub fn shrink(&mut self, floor: usize) -> Result<()> {
+        self.index.shrink(floor)
+    }
+}
+
+/// Inclusive geohash ranges of the cells covering a circle
+fn cover(center: Point, radius: f64) -> Vec<(u64, u64)> {
+    // Angular extent of the radius along each axis; a circle reaching
+    // a pole spans every longitude
+    let height = (radius / RADIUS).to_degrees();
+    let width = if center.latitude.abs() + height >= 90.0 {
+        360.0
+    } else {
+        height / center.latitude.to_radians().cos()
+    };
+
+    // Deepest level whose cells are still at least as large as the radius
+    let mut depth = 0;
+    while depth < BITS
+        && 180.0 / 2f64.powi(depth as i32 + 1) >= height
+        && 360.0 / 2f64.powi(depth as i32 + 1) >= width
+    {
+        depth += 1;
+    }
+    if depth == 0 {
+        return vec![(0, u64::MAX)];
+    }
+
+    let shift = BITS - depth;
+    let cells = 1i64 << depth;
+    let row = (latitude(center.latitude) >> shift) as i64;
+    let column = (longitude(center.longitude) >> shift) as i64;
+    let span = u64::MAX >> (2 * depth);
+
+    let mut ranges = Vec::with_capacity(9);
+    for dy in -1..=1 {
+        let y = row + dy;
+        if !(0..cells).contains(&y) {
+            continue;
+        }
+        for dx in -1..=1 {
+            // Longitude wraps around the antimeridian
+            let x = (column + dx).rem_euclid(cells);
+            let low = interleave((x as u32) << shift, (y as u32) << shift);
+            ranges.push((low, low | span));
+        }
+    }
+
+    ranges.sort_unstable();
+    ranges.dedup();
+    ranges
+}
+
+/// Geohash of a point
+fn hash(point: Point) -> u64 {
+    interleave(longitude(point.longitude), latitude(point.latitude))
+}
+
+/// Quantizes a latitude onto the full `u32` range
+fn latitude(degrees: f64) -> u32 {
+    quantize(degrees, -90.0, 90.0)
+}
+
+/// Quantizes a longitude onto the full `u32` range
+fn longitude(degrees: f64) -> u32 {
+    quantize(degrees, -180.0, 180.0)
+}
+
+/// Maps `value` within `min..=max` onto `0..=u32::MAX`
+fn quantize(value: f64, min: f64, max: f64) -> u32 {
+    let scaled = (value - min) / (max - min) * 2f64.powi(BITS as i32);
+    scaled.clamp(0.0, u32::MAX as f64) as u32
+}
+
+/// Interleaves two coordinates, `x` taking the higher bit of each pair
+fn interleave(x: u32, y: u32) -> u64 {
+    spread(x) << 1 | spread(y)
+}
+
+/// Spaces the bits of `value` out to every other bit position
+fn spread(value: u32) -> u64 {
+    let mut value = value as u64;
+    value = (value | value << 16) & 0x0000_FFFF_0000_FFFF;
+    value = (value | value << 8) & 0x00FF_00FF_00FF_00FF;
+    value = (value | value << 4) & 0x0F0F_0F0F_0F0F_0F0F;
+    value = (value | value << 2) & 0x3333_3333_3333_3333;
+    value = (value | value << 1) & 0x5555_5555_5555_5555;
+    value
+}
+
+/// Encodes an atlas key so byte order matches geohash order
+fn key(hash: u64, id: u64) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    key[..8].copy_from_slice(&hash.to_be_bytes());
+    key[8..].copy_from_slice(&id.to_be_bytes());
+    key
+}
+
+/// Decodes an atlas key into its geohash and ID
+fn split(key: &[u8]) -> (u64, u64) {
+    let hash = u64::from_be_bytes(key[..8].try_into().unwrap());
+    let id = u64::from_be_bytes(key[8..16].try_into().unwrap());
+    (hash, id)
+}
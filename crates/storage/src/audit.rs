@@ -0,0 +1,166 @@
+//! Record-level audit log
+//!
+//! When enabled, every save and delete appends an entry naming who did
+//! it, when, and what, to segments of its own. They are never compacted,
+//! so the trail outlives the records it describes. An index keyed by
+//! user ID and entry position reads one record's trail back in write
+//! order, and one keyed by position alone reads the whole log as a
+//! change stream.
+
+use std::path::{Path, PathBuf};
+use rkyv::{to_bytes, Archive, Deserialize, Serialize};
+use crate::{Error, Result};
+use crate::index::Index;
+use crate::model::Position;
+use crate::segment::Segment;
+
+/// Directory holding the audit log inside a store
+const NAME: &str = "audit";
+
+/// Length of an audit key: ID, segment and offset
+const LENGTH: usize = 24;
+
+/// What an audited operation did
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[archive(check_bytes)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Stored a new revision of the record
+    Save,
+    /// Removed the record
+    Delete,
+}
+
+/// One audited operation on a record
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, Debug, Clone, PartialEq, Eq)]
+#[archive(check_bytes)]
+pub struct Entry {
+    /// User the operation touched
+    pub id: u64,
+    /// Who performed it, as named when the store was opened
+    pub actor: String,
+    /// When it happened, in seconds since the epoch
+    pub time: u64,
+    /// What it did
+    pub action: Action,
+    /// Revision written, or the last one for a delete
+    pub revision: u64,
+}
+
+/// Append-only log of audited operations
+pub struct Audit {
+    /// Segments holding the entries
+    segment: Segment,
+    /// Entry positions keyed by user ID and position
+    index: Index,
+    /// Entry positions keyed by position alone, in write order
+    log: Index,
+}
+
+impl Audit {
+    /// Opens the audit log of a store base directory
+    pub fn new<P: AsRef<Path>>(base: P) -> Result<Self> {
+        let path = Self::locate(base);
+        let fresh = !path.join("log").exists();
+        let index = Index::new(path.join("entries"))?;
+        let mut log = Index::new(path.join("log"))?;
+        // Logs written before the change stream existed fill it from the entries
+        if fresh {
+            for result in index.scan() {
+                let (_, position) = result?;
+                log.put(&place(position), position)?;
+            }
+        }
+        Ok(Self {
+            segment: Segment::new(path.join("segments"))?,
+            index,
+            log,
+        })
+    }
+
+    /// Directory of the audit log for a base directory
+    pub fn locate<P: AsRef<Path>>(base: P) -> PathBuf {
+        base.as_ref().join(NAME)
+    }
+
+    /// Appends entries, all landing or none
+    pub fn append(&mut self, entries: &[Entry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+        let encoded = entries.iter()
+            .map(|entry| to_bytes::<_, 256>(entry)
+                .map(|bytes| bytes.into_vec())
+                .map_err(|e| Error::Serialize(format!("Serialization failed: {:?}", e))))
+            .collect::<Result<Vec<_>>>()?;
+        let positions = self.segment.push(encoded, u64::MAX)?;
+        for (entry, position) in entries.iter().zip(positions) {
+            self.index.put(&key(entry.id, position), position)?;
+            self.log.put(&place(position), position)?;
+        }
+        Ok(())
+    }
+
+    /// Entries of one record, oldest first
+    pub fn entries(&self, id: u64) -> Result<Vec<Entry>> {
+        let mut found = Vec::new();
+        for result in self.index.after(&id.to_be_bytes()) {
+            let (key, position) = result?;
+            if key[..8] != id.to_be_bytes() {
+                break;
+            }
+            found.push(self.segment.former::<Entry>(position)?);
+        }
+        Ok(found)
+    }
+
+    /// Up to `limit` entries written after `cursor`, or from the start, with their positions
+    pub fn since(&self, cursor: Option<Position>, limit: usize) -> Result<Vec<(Position, Entry)>> {
+        let from = cursor.map(place);
+        self.log.page(from.as_ref().map(|from| &from[..]), limit)?
+            .into_iter()
+            .map(|(_, position)| Ok((position, self.segment.former::<Entry>(position)?)))
+            .collect()
+    }
+
+    /// Flushes entries and indexes to disk
+    pub fn sync(&self) -> Result<()> {
+        self.segment.sync()?;
+        self.index.sync()?;
+        self.log.sync()
+    }
+
+    /// Seals the active segment and flushes the indexes
+    pub fn seal(&self) -> Result<()> {
+        self.segment.seal()?;
+        self.index.sync()?;
+        self.log.sync()
+    }
+
+    /// Approximate bytes of memory held by the indexes and segment caches
+    pub fn memory(&self) -> usize {
+        self.index.memory() + self.log.memory() + self.segment.memory()
+    }
+
+    /// Merges index deltas above `floor` bytes into their tables
+    pub fn shrink(&mut self, floor: usize) -> Result<()> {
+        self.index.shrink(floor)?;
+        self.log.shrink(floor)
+    }
+}
+
+/// Encodes an audit key so byte order matches ID then write order
+fn key(id: u64, position: Position) -> [u8; LENGTH] {
+    let mut key = [0u8; LENGTH];
+    key[..8].copy_from_slice(&id.to_be_bytes());
+    key[8..].copy_from_slice(&place(position));
+    key
+}
+
+/// Encodes a log key so byte order matches write order
+fn place(position: Position) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    key[..8].copy_from_slice(&position.segment.to_be_bytes());
+    key[8..].copy_from_slice(&position.offset.to_be_bytes());
+    key
+}
@@ -0,0 +1,124 @@
+//! Pluggable file I/O
+//!
+//! Segment files reach the disk only through a `Backend`, so tests
+//! and alternative I/O engines can stand in for the standard library.
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use crate::{directory, Result};
+
+/// An open file as seen through a backend
+pub trait Handle: Read + Write + Seek + Send {
+    /// Flushes contents and metadata to stable storage
+    fn sync(&self) -> Result<()>;
+    
+    /// Flushes contents to stable storage, skipping metadata where possible
+    fn data(&self) -> Result<()>;
+    
+    /// Current length in bytes
+    fn size(&self) -> Result<u64>;
+    
+    /// Writes all of `bytes` at `offset`, wherever the cursor is
+    ///
+    /// By default this seeks and writes; handles that can write at an
+    /// offset directly do so.
+    fn put(&mut self, offset: u64, bytes: &[u8]) -> Result<()> {
+        self.seek(SeekFrom::Start(offset))?;
+        self.write_all(bytes)?;
+        Ok(())
+    }
+    
+    /// Reserves disk blocks for the first `size` bytes without changing the length
+    ///
+    /// A hint: does nothing where the platform or file system cannot
+    /// reserve.
+    fn reserve(&self, _size: u64) -> Result<()> {
+        Ok(())
+    }
+    
+    /// Frees blocks reserved past the end of the file
+    fn release(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Source of file handles for a store
+pub trait Backend: Send + Sync + fmt::Debug {
+    /// Opens a file for reading and writing, creating it if missing
+    fn create(&self, path: &Path) -> Result<Box<dyn Handle>>;
+    
+    /// Opens an existing file for reading
+    fn open(&self, path: &Path) -> Result<Box<dyn Handle>>;
+    
+    /// Flushes a directory's entries to disk
+    fn directory(&self, path: &Path) -> Result<()>;
+}
+
+/// Standard library files on the local disk
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Disk;
+
+impl Handle for File {
+    fn sync(&self) -> Result<()> {
+        self.sync_all()?;
+        Ok(())
+    }
+    
+    fn data(&self) -> Result<()> {
+        self.sync_data()?;
+        Ok(())
+    }
+    
+    fn size(&self) -> Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+    
+    #[cfg(unix)]
+    fn put(&mut self, offset: u64, bytes: &[u8]) -> Result<()> {
+        std::os::unix::fs::FileExt::write_all_at(self, bytes, offset)?;
+        Ok(())
+    }
+    
+    #[cfg(target_os = "linux")]
+    fn reserve(&self, size: u64) -> Result<()> {
+        use std::os::fd::AsRawFd;
+        // Safety: the descriptor stays open for the call
+        let result = unsafe { libc::fallocate(self.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, 0, size as libc::off_t) };
+        match result {
+            0 => Ok(()),
+            _ => match std::io::Error::last_os_error() {
+                unsupported if unsupported.raw_os_error() == Some(libc::EOPNOTSUPP) => Ok(()),
+                error => Err(error.into()),
+            },
+        }
+    }
+    
+    #[cfg(target_os = "linux")]
+    fn release(&self) -> Result<()> {
+        // Truncating to the current length drops blocks kept past it
+        self.set_len(self.metadata()?.len())?;
+        Ok(())
+    }
+}
+
+impl Backend for Disk {
+    fn create(&self, path: &Path) -> Result<Box<dyn Handle>> {
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .read(true)
+            .open(path)?;
+        Ok(Box::new(file))
+    }
+    
+    fn open(&self, path: &Path) -> Result<Box<dyn Handle>> {
+        Ok(Box::new(File::open(path)?))
+    }
+    
+    fn directory(&self, path: &Path) -> Result<()> {
+        directory::sync(path)
+    }
+}
@@ -0,0 +1,391 @@
+//! Value index over user attributes
+//!
+//! Maps attribute values back to the users holding them, so lookups by
+//! an attribute read only the matching records. Indexes may span
+//! several fields: keys are the index tag, each field's value bytes
+//! followed by a zero separator, then the big-endian user ID, and the
+//! primary index still resolves IDs to positions. Values of leading
+//! fields form a key prefix, so an index on country and city also
+//! serves lookups by country alone.
+//!
+//! Fields marked `#[index(unique)]` on `User` are kept in an index of
+//! their own, laid out alike, so the store can find who holds a value
+//! before letting another record take it.
+//!
+//! With a cipher, fields marked `#[secret]` are keyed by their blind
+//! index rather than their value, so the catalog holds no secret in the
+//! clear yet still serves equality lookups on them.
+//!
+//! `shapes` counts each index's entries and distinct leading values
+//! and keeps the result, from which `plan` judges whether a lookup is
+//! selective enough to beat scanning every record. Until shapes are
+//! first taken, every lookup goes through its index.
+
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use crate::{Error, Result};
+use crate::cipher::{self, Cipher};
+use crate::index::{Index, Shape, Snapshot};
+use crate::model::{Field, Position, User};
+
+/// Directory holding the catalog inside a store
+const NAME: &str = "catalog";
+
+/// Indexes of the catalog, with the fields their keys hold in order
+/// and the tag leading their keys
+const INDEXES: &[(&[Field], u8)] = &[(&[Field::Country, Field::City], 1)];
+
+/// Share of all records above which a lookup reads them all instead,
+/// as one in `SPREAD`
+const SPREAD: u64 = 10;
+
+/// How a lookup reads its users
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Plan {
+    /// Reads only the users the index names
+    Index,
+    /// Reads every user and filters, cheaper once many match
+    Scan,
+}
+
+/// Secondary index on attribute values
+pub struct Catalog {
+    /// Entries keyed by index, values, then user ID
+    index: Index,
+    /// Entries of unique fields, keyed alike
+    unique: Index,
+    /// Shapes last taken by `shapes`
+    statistics: Mutex<Vec<Shape>>,
+    /// Keys blinding secret values, if the store has any
+    cipher: Option<Arc<Cipher>>,
+}
+
+impl Catalog {
+    /// Opens the catalog of a store base directory
+    pub fn new<P: AsRef<Path>>(base: P) -> Result<Self> {
+        let path = Self::locate(base);
+        // Single-field keys from before composite indexes, rebuilt anew
+        if path.join("values").exists() {
+            for entry in std::fs::read_dir(&path)? {
+                let entry = entry?;
+                if entry.file_name().to_string_lossy().starts_with("values") {
+                    std::fs::remove_file(entry.path())?;
+                }
+            }
+        }
+        Ok(Self {
+            index: Index::new(path.join("composite"))?,
+            unique: Index::new(path.join("unique"))?,
+            statistics: Mutex::new(Vec::new()),
+            cipher: None,
+        })
+    }
+    
+    /// Keys secret fields by their blind index under `cipher`
+    pub fn cipher(mut self, cipher: Option<Arc<Cipher>>) -> Self {
+        self.cipher = cipher;
+        self
+    }
+
+    /// Directory of the catalog for a base directory
+    pub fn locate<P: AsRef<Path>>(base: P) -> PathBuf {
+        base.as_ref().join(NAME)
+    }
+
+    /// Whether a base directory holds a catalog with every index built
+    pub fn ready<P: AsRef<Path>>(base: P) -> bool {
+        let path = Self::locate(base);
+        path.join("composite").exists() && path.join("unique").exists()
+    }
+
+    /// Adds a user's indexed values
+    pub fn insert(&mut self, user: &User) -> Result<()> {
+        for &(fields, tag) in INDEXES {
+            if let Some(values) = values(user, fields) {
+                let values = self.hidden(fields, &values);
+                self.index.put(&key(tag, &values, user.id), Position::default())?;
+            }
+        }
+        for (field, value) in unique(user) {
+            let value = self.hide(field, value);
+            self.unique.put(&key(tag(field), &[value], user.id), Position::default())?;
+        }
+        Ok(())
+    }
+
+    /// Drops a user's indexed values
+    pub fn remove(&mut self, user: &User) -> Result<()> {
+        for &(fields, tag) in INDEXES {
+            if let Some(values) = values(user, fields) {
+                let values = self.hidden(fields, &values);
+                self.index.delete(&key(tag, &values, user.id))?;
+            }
+        }
+        for (field, value) in unique(user) {
+            let value = self.hide(field, value);
+            self.unique.delete(&key(tag(field), &[value], user.id))?;
+        }
+        Ok(())
+    }
+
+    /// IDs of users whose `field` equals one of `values`, ascending and distinct
+    ///
+    /// Fails with `Error::Unsupported` for a field the catalog does not index.
+    pub fn find(&self, field: Field, values: &[&str]) -> Result<Vec<u64>> {
+        let mut ids = Vec::new();
+        for value in values {
+            ids.extend(self.query(&[(field, value)])?);
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// IDs of users matching every `(field, value)` term, ascending
+    ///
+    /// The terms' fields, in any order, must be the leading fields of
+    /// one index, or a single unique field; other terms fail with
+    /// `Error::Unsupported`.
+    pub fn query(&self, terms: &[(Field, &str)]) -> Result<Vec<u64>> {
+        let (index, tag, values, width) = self.choose(terms)?;
+        let prefix = prefix(tag, &values);
+        let mut ids = Vec::new();
+        for result in index.after(&prefix) {
+            let (key, _) = result?;
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            if let Some(id) = rest(&key[prefix.len()..], width - values.len()) {
+                ids.push(id);
+            }
+        }
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// How to serve `lookups` queries, each with the same fields, over `records` users
+    ///
+    /// Scans once the shapes expect more than one in `SPREAD`
+    /// records to match; fails like `query` for unsupported terms.
+    pub fn plan(&self, terms: &[(Field, &str)], lookups: usize, records: u64) -> Result<Plan> {
+        let (_, tag, values, _) = self.choose(terms)?;
+        let statistics = self.statistics.lock().unwrap();
+        let spread = INDEXES.iter()
+            .find(|&&(_, indexed)| indexed == tag)
+            .and_then(|&(fields, _)| statistics.iter().find(|shape| shape.fields == fields))
+            .and_then(|shape| shape.spread(values.len()));
+        Ok(match spread {
+            Some(spread) if spread * lookups as u64 * SPREAD > records => Plan::Scan,
+            _ => Plan::Index,
+        })
+    }
+
+    /// Counts entries and distinct leading values of every index
+    ///
+    /// Walks each index in full; the result is kept for `plan`.
+    pub fn shapes(&self) -> Result<Vec<Shape>> {
+        let mut shapes = Vec::new();
+        for &(fields, tag) in INDEXES {
+            shapes.push(measure(&self.index, tag, fields)?);
+        }
+        for field in fields() {
+            shapes.push(measure(&self.unique, tag(field), &[field])?);
+        }
+        *self.statistics.lock().unwrap() = shapes.clone();
+        Ok(shapes)
+    }
+
+    /// Index, tag, leading values and field count serving a query
+    fn choose<'a>(&self, terms: &[(Field, &'a str)]) -> Result<(&Index, u8, Vec<Cow<'a, str>>, usize)> {
+        let chosen = INDEXES.iter().find_map(|&(fields, tag)| {
+            let leading = fields.get(..terms.len())?;
+            let values = leading.iter()
+                .map(|field| terms.iter().find(|(term, _)| term == field).map(|&(_, value)| value))
+                .collect::<Option<Vec<_>>>()?;
+            Some((&self.index, tag, self.hidden(leading, &values), fields.len()))
+        });
+        match (chosen, terms) {
+            (Some(chosen), _) => Ok(chosen),
+            (None, &[(field, value)]) if fields().any(|unique| unique == field) => {
+                Ok((&self.unique, tag(field), vec![self.hide(field, value)], 1))
+            }
+            _ => Err(Error::Unsupported(format!("Lookup by {:?}", terms.iter().map(|(field, _)| field).collect::<Vec<_>>()))),
+        }
+    }
+
+    /// Drops the entries of every index keyed by a secret field
+    ///
+    /// Blind indexes differ from key to key, so after the cipher moves
+    /// to a new key they must be filled again.
+    pub fn forget(&mut self) -> Result<()> {
+        let secret = |fields: &[Field]| fields.iter().any(|&field| cipher::fields().any(|secret| secret == field));
+        for &(fields, tag) in INDEXES {
+            if secret(fields) {
+                clear(&mut self.index, tag)?;
+            }
+        }
+        for field in fields() {
+            if secret(&[field]) {
+                clear(&mut self.unique, tag(field))?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Value as keyed: the blind index of a secret one, else itself
+    fn hide<'a>(&self, field: Field, value: &'a str) -> Cow<'a, str> {
+        match &self.cipher {
+            Some(cipher) if cipher::fields().any(|secret| secret == field) => Cow::Owned(cipher.blind(field, value)),
+            _ => Cow::Borrowed(value),
+        }
+    }
+    
+    /// Values of `fields` as keyed, see `hide`
+    fn hidden<'a>(&self, fields: &[Field], values: &[&'a str]) -> Vec<Cow<'a, str>> {
+        fields.iter().zip(values).map(|(&field, value)| self.hide(field, value)).collect()
+    }
+    
+    /// Drops every entry of every index
+    pub fn clear(&mut self) -> Result<()> {
+        self.index.clear()?;
+        self.unique.clear()
+    }
+
+    /// Both index files as they are now, by name
+    pub fn snapshots(&self) -> Vec<(&'static str, Snapshot)> {
+        vec![("composite", self.index.snapshot()), ("unique", self.unique.snapshot())]
+    }
+    
+    /// Flushes the indexes to disk
+    pub fn sync(&self) -> Result<()> {
+        self.index.sync()?;
+        self.unique.sync()
+    }
+
+    /// Approximate bytes of memory held by the indexes
+    pub fn memory(&self) -> usize {
+        self.index.memory() + self.unique.memory()
+    }
+
+    /// Merges the index deltas into their tables when above `floor` bytes
+    pub fn shrink(&mut self, floor: usize) -> Result<()> {
+        self.index.shrink(floor)?;
+        self.unique.shrink(floor)
+    }
+}
+
+/// Value of a field the catalog can index
+pub fn value(user: &User, field: Field) -> Option<&str> {
+    match field {
+        Field::Name => Some(&user.name),
+        Field::Email => Some(&user.email),
+        Field::City => Some(&user.location.city),
+        Field::Country => Some(&user.location.country),
+        _ => None,
+    }
+}
+
+/// Fields marked `#[index(unique)]` on `User`
+pub fn fields() -> impl Iterator<Item = Field> {
+    User::UNIQUE.iter().filter_map(|name| Field::named(name))
+}
+
+/// Values a user holds in its unique fields
+///
+/// Empty values stand for none and may be shared.
+pub fn unique(user: &User) -> impl Iterator<Item = (Field, &str)> {
+    fields().filter_map(|field| Some((field, value(user, field)?)))
+        .filter(|(_, value)| !value.is_empty())
+}
+
+/// Summarizes the keys under `tag` of an index on `fields`
+fn measure(index: &Index, tag: u8, fields: &[Field]) -> Result<Shape> {
+    let mut entries = 0;
+    let mut distinct = vec![0; fields.len()];
+    let mut previous: Vec<u8> = Vec::new();
+    for result in index.after(&[tag]) {
+        let (key, _) = result?;
+        if key.first() != Some(&tag) {
+            break;
+        }
+        let Some(values) = key.len().checked_sub(8).map(|end| &key[1..end]) else { continue };
+        entries += 1;
+        // Keys sort by their values, so equal leading values are adjacent
+        let mut depth = 0;
+        for (at, &byte) in values.iter().enumerate() {
+            if byte == 0 && depth < fields.len() {
+                if previous.get(..=at) != Some(&values[..=at]) {
+                    distinct[depth] += 1;
+                }
+                depth += 1;
+            }
+        }
+        previous = values.to_vec();
+    }
+    Ok(Shape {
+        name: fields.iter().map(|field| field.name()).collect::<Vec<_>>().join(","),
+        fields: fields.to_vec(),
+        entries,
+        distinct,
+        bytes: index.size()?,
+        memory: index.memory(),
+    })
+}
+
+/// Deletes every key of an index under `tag`
+fn clear(index: &mut Index, tag: u8) -> Result<()> {
+    let mut keys = Vec::new();
+    for result in index.after(&[tag]) {
+        let (key, _) = result?;
+        if key.first() != Some(&tag) {
+            break;
+        }
+        keys.push(key);
+    }
+    for key in keys {
+        index.delete(&key)?;
+    }
+    Ok(())
+}
+
+/// Values of an index's fields, if the user holds all of them
+fn values<'a>(user: &'a User, fields: &[Field]) -> Option<Vec<&'a str>> {
+    fields.iter().map(|&field| value(user, field)).collect()
+}
+
+/// Tag leading the keys of a unique field, fixed by the field itself
+fn tag(field: Field) -> u8 {
+    field as u8 + 1
+}
+
+/// Key prefix shared by every user holding leading values
+fn prefix<S: AsRef<str>>(tag: u8, values: &[S]) -> Vec<u8> {
+    let mut prefix = vec![tag];
+    for value in values {
+        prefix.extend_from_slice(value.as_ref().as_bytes());
+        prefix.push(0);
+    }
+    prefix
+}
+
+/// Encodes a catalog key
+fn key<S: AsRef<str>>(tag: u8, values: &[S], id: u64) -> Vec<u8> {
+    let mut key = prefix(tag, values);
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
+
+/// User ID ending a key past its prefix, if `skipped` values come first
+///
+/// A key with more or fewer separators belongs to a value extending the
+/// last one in the prefix, and is no match.
+fn rest(bytes: &[u8], skipped: usize) -> Option<u64> {
+    let split = bytes.len().checked_sub(8)?;
+    let (values, id) = bytes.split_at(split);
+    let whole = values.last().is_none_or(|&byte| byte == 0);
+    if !whole || values.iter().filter(|&&byte| byte == 0).count() != skipped {
+        return None;
+    }
+    Some(u64::from_be_bytes(id.try_into().ok()?))
+}
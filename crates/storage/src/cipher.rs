@@ -0,0 +1,254 @@
+//! Field-level encryption
+//!
+//! Fields marked `#[secret]` on `User` are sealed before a record is
+//! encoded and opened after it is decoded, so they sit on disk as
+//! ciphertext while every other field stays as the codec wrote it. Each
+//! sealed value is ChaCha20-Poly1305 under a fresh random nonce, bound
+//! to its field and user ID, and spelled `enc:v1:` followed by the hex
+//! of nonce and ciphertext, so it still fits a string field.
+//!
+//! Sealed values no longer compare equal, so the catalog indexes secret
+//! fields by a blind index instead: a keyed hash of the value, which
+//! keeps equality lookups and uniqueness working without storing the
+//! value itself.
+//!
+//! All keys derive from one 32-byte master key. Its fingerprint goes in
+//! the manifest, so a store is never opened with the wrong key. Master
+//! keys are numbered from 1 in the order a store was given them, and
+//! `Store::rewrap` moves to a new one. Values sealed under an earlier
+//! key keep opening while it is held, and each segment's header names
+//! the key its records were sealed under, so the store knows which
+//! segments still need an earlier key.
+
+use std::fmt::{self, Debug};
+use std::sync::{Arc, RwLock};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use crate::{Error, Result};
+use crate::codec::Codec;
+use crate::model::{Field, User};
+
+/// Prefix marking a sealed value, naming the scheme
+const PREFIX: &str = "enc:v1:";
+
+/// Master key as given to a builder, kept out of debug output
+#[derive(Clone)]
+pub struct Master(pub [u8; 32]);
+
+impl Debug for Master {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.debug_tuple("Master").field(&fingerprint(&self.0)).finish()
+    }
+}
+
+/// Keys derived from one master key
+struct Material {
+    /// Number of the master key within its store
+    version: u32,
+    /// Seals and opens field values
+    key: LessSafeKey,
+    /// Keys the blind index
+    blind: hmac::Key,
+}
+
+impl Material {
+    /// Derives every key from a master key
+    fn new(version: u32, master: &[u8; 32]) -> Self {
+        let derive = derivation(master);
+        let key = UnboundKey::new(&CHACHA20_POLY1305, derive(b"guardian seal").as_ref())
+            .expect("a SHA-256 tag is a ChaCha20 key");
+        Self {
+            version,
+            key: LessSafeKey::new(key),
+            blind: hmac::Key::new(hmac::HMAC_SHA256, derive(b"guardian blind").as_ref()),
+        }
+    }
+}
+
+/// Keys sealing secret fields, current and earlier
+pub struct Cipher {
+    /// Keys of every master key held, the current one last
+    keys: RwLock<Vec<Material>>,
+    /// Source of nonces
+    random: SystemRandom,
+}
+
+impl Cipher {
+    /// Seals under a master key with its number in the store
+    pub fn new(version: u32, master: &[u8; 32]) -> Self {
+        Self { keys: RwLock::new(vec![Material::new(version, master)]), random: SystemRandom::new() }
+    }
+
+    /// Keeps an earlier master key for opening what it sealed
+    pub fn admit(&self, version: u32, master: &[u8; 32]) {
+        let mut keys = self.keys.write().unwrap();
+        let at = keys.len() - 1;
+        keys.insert(at, Material::new(version, master));
+    }
+
+    /// Seals under a new master key from now on, keeping the others
+    pub fn rotate(&self, version: u32, master: &[u8; 32]) {
+        self.keys.write().unwrap().push(Material::new(version, master));
+    }
+
+    /// Number of the master key sealing new values
+    pub fn version(&self) -> u32 {
+        self.current(|keys| keys.version)
+    }
+
+    /// Seals a field value of a user under the current key
+    pub fn seal(&self, field: Field, id: u64, value: &str) -> Result<String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.random.fill(&mut nonce)
+            .map_err(|_| Error::Serialize("No randomness for a nonce".to_string()))?;
+        let mut data = value.as_bytes().to_vec();
+        self.current(|keys| keys.key.seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(aad(field, id)), &mut data))
+            .map_err(|_| Error::Serialize(format!("Sealing {} of user {} failed", field.name(), id)))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&data);
+        Ok(format!("{}{}", PREFIX, hex(&sealed)))
+    }
+
+    /// Opens a sealed field value, passing plaintext through
+    ///
+    /// Values written before the field was sealed read back as they
+    /// were. Every key held is tried, newest first. Fails with
+    /// `Error::Serialize` when a sealed value opens under none of them
+    /// for this field and user.
+    pub fn open(&self, field: Field, id: u64, value: &str) -> Result<String> {
+        let Some(sealed) = value.strip_prefix(PREFIX) else {
+            return Ok(value.to_string());
+        };
+        let failed = || Error::Serialize(format!("Sealed {} of user {} does not open", field.name(), id));
+        let bytes = unhex(sealed).filter(|bytes| bytes.len() >= NONCE_LEN).ok_or_else(failed)?;
+        let (nonce, data) = bytes.split_at(NONCE_LEN);
+        let keys = self.keys.read().unwrap();
+        for material in keys.iter().rev() {
+            let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| failed())?;
+            let mut data = data.to_vec();
+            if let Ok(plain) = material.key.open_in_place(nonce, Aad::from(aad(field, id)), &mut data) {
+                return String::from_utf8(plain.to_vec()).map_err(|_| failed());
+            }
+        }
+        Err(failed())
+    }
+
+    /// Blind index of a field value under the current key: equal values blind alike
+    pub fn blind(&self, field: Field, value: &str) -> String {
+        let mut context = self.current(|keys| hmac::Context::with_key(&keys.blind));
+        context.update(field.name().as_bytes());
+        context.update(&[0]);
+        context.update(value.as_bytes());
+        hex(context.sign().as_ref())
+    }
+
+    /// Runs `each` on the keys of the current master key
+    fn current<T>(&self, each: impl FnOnce(&Material) -> T) -> T {
+        each(self.keys.read().unwrap().last().expect("a cipher holds a key"))
+    }
+}
+
+impl Debug for Cipher {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let versions = self.keys.read().unwrap().iter().map(|keys| keys.version).collect::<Vec<_>>();
+        formatter.debug_struct("Cipher").field("versions", &versions).finish_non_exhaustive()
+    }
+}
+
+/// Hex fingerprint naming a master key without revealing it, kept in the manifest
+pub fn fingerprint(master: &[u8; 32]) -> String {
+    hex(&derivation(master)(b"guardian fingerprint").as_ref()[..8])
+}
+
+/// Codec sealing secret fields around another codec
+///
+/// Records keep the inner codec's name, since their layout is the
+/// inner codec's; only the secret values differ. Archived views are
+/// rebuilt from the opened user, so none are read in place.
+#[derive(Debug)]
+pub struct Sealed {
+    /// Codec laying out the record
+    inner: Arc<dyn Codec>,
+    /// Keys sealing the secret fields
+    cipher: Arc<Cipher>,
+}
+
+impl Sealed {
+    /// Wraps a codec
+    pub fn new(inner: Arc<dyn Codec>, cipher: Arc<Cipher>) -> Self {
+        Self { inner, cipher }
+    }
+}
+
+impl Codec for Sealed {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn encode(&self, user: &User) -> Result<Vec<u8>> {
+        let mut user = user.clone();
+        let id = user.id;
+        for field in fields() {
+            if let Some(value) = slot(&mut user, field) {
+                *value = self.cipher.seal(field, id, value)?;
+            }
+        }
+        self.inner.encode(&user)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<User> {
+        let mut user = self.inner.decode(bytes)?;
+        let id = user.id;
+        for field in fields() {
+            if let Some(value) = slot(&mut user, field) {
+                *value = self.cipher.open(field, id, value)?;
+            }
+        }
+        Ok(user)
+    }
+}
+
+/// Fields marked `#[secret]` on `User`
+pub fn fields() -> impl Iterator<Item = Field> {
+    User::SECRET.iter().filter_map(|name| Field::named(name))
+}
+
+/// Text field of a user that can hold a sealed value
+fn slot(user: &mut User, field: Field) -> Option<&mut String> {
+    match field {
+        Field::Name => Some(&mut user.name),
+        Field::Email => Some(&mut user.email),
+        Field::City => Some(&mut user.location.city),
+        Field::Country => Some(&mut user.location.country),
+        _ => None,
+    }
+}
+
+/// Derives a key for a label from a master key
+fn derivation(master: &[u8; 32]) -> impl Fn(&[u8]) -> hmac::Tag {
+    let master = hmac::Key::new(hmac::HMAC_SHA256, master);
+    move |label| hmac::sign(&master, label)
+}
+
+/// Binds a sealed value to its field and user
+fn aad(field: Field, id: u64) -> Vec<u8> {
+    let mut aad = field.name().as_bytes().to_vec();
+    aad.extend_from_slice(&id.to_be_bytes());
+    aad
+}
+
+/// Lowercase hex of bytes
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Bytes of lowercase or uppercase hex
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2)
+        .map(|at| u8::from_str_radix(text.get(at..at + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,99 @@
+//! Record serialization
+//!
+//! Segments store users as opaque byte strings; a codec turns users
+//! into those bytes and back. rkyv is the default and the only codec
+//! whose records can be read in place. Other codecs trade that away
+//! for a format other tools understand: their records are rebuilt in
+//! archived form whenever a zero-copy view is asked for.
+
+use std::fmt::Debug;
+use rkyv::{to_bytes, AlignedVec, Deserialize, Infallible};
+use crate::{Error, Result};
+use crate::model::{ArchivedUser, User};
+
+/// Converts users to and from their stored bytes
+pub trait Codec: Send + Sync + Debug {
+    /// Name recorded in the manifest, so a store is never read with another codec
+    fn name(&self) -> &'static str;
+
+    /// Encodes a user
+    fn encode(&self, user: &User) -> Result<Vec<u8>>;
+
+    /// Decodes a user, failing with `Error::Serialize` on malformed bytes
+    fn decode(&self, bytes: &[u8]) -> Result<User>;
+
+    /// Borrows encoded bytes as an archived user
+    ///
+    /// By default the user is decoded and archived again into
+    /// `scratch`, which callers reuse across records.
+    fn view<'b>(&self, bytes: &'b [u8], scratch: &'b mut AlignedVec) -> Result<&'b ArchivedUser> {
+        let user = self.decode(bytes)?;
+        *scratch = archive(&user)?;
+        check(scratch.as_slice())
+    }
+}
+
+/// Zero-copy rkyv archives, the default codec
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rkyv;
+
+impl Codec for Rkyv {
+    fn name(&self) -> &'static str {
+        "rkyv"
+    }
+
+    fn encode(&self, user: &User) -> Result<Vec<u8>> {
+        Ok(archive(user)?.into_vec())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<User> {
+        let mut scratch = AlignedVec::new();
+        self.view(bytes, &mut scratch)?
+            .deserialize(&mut Infallible)
+            .map_err(|e| Error::Serialize(format!("Deserialization error: {:?}", e)))
+    }
+
+    fn view<'b>(&self, bytes: &'b [u8], scratch: &'b mut AlignedVec) -> Result<&'b ArchivedUser> {
+        // Archives are only valid at their alignment
+        let bytes = if (bytes.as_ptr() as usize).is_multiple_of(16) {
+            bytes
+        } else {
+            scratch.clear();
+            scratch.extend_from_slice(bytes);
+            scratch.as_slice()
+        };
+        check(bytes)
+    }
+}
+
+/// JSON through serde, readable without this crate
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl Codec for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+
+    fn encode(&self, user: &User) -> Result<Vec<u8>> {
+        serde_json::to_vec(user)
+            .map_err(|e| Error::Serialize(format!("Serialization failed: {}", e)))
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<User> {
+        serde_json::from_slice(bytes)
+            .map_err(|e| Error::Serialize(format!("invalid JSON record: {}", e)))
+    }
+}
+
+/// Validates an aligned rkyv archive of a user
+fn check(bytes: &[u8]) -> Result<&ArchivedUser> {
+    rkyv::check_archived_root::<User>(bytes)
+        .map_err(|e| Error::Serialize(format!("invalid archive: {}", e)))
+}
+
+/// Archives a user with rkyv
+fn archive(user: &User) -> Result<AlignedVec> {
+    to_bytes::<_, 1024>(user)
+        .map_err(|e| Error::Serialize(format!("Serialization failed: {:?}", e)))
+}
@@ -3,27 +3,54 @@
 //! Handles minor and major compaction operations to optimize
 //! storage efficiency and remove deleted records.
 
-use std::path::Path;
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::Mutex;
+use tokio::sync::{watch, Mutex, Notify};
+use tokio::task::{JoinHandle, JoinSet};
 use tokio::time::sleep;
 use crate::{Error, Result};
-use crate::segment::Segment;
-use crate::index::{Index, Operation};
-use crate::model::{User, Position};
+use crate::segment::{Segment, Tally};
+use crate::index::{Index, Page};
+use crate::manifest::Counters;
+use crate::history::Purge;
+use crate::throttle::{Gate, Latch, Priority, Throttle};
+
+/// Index entries examined per locked page
+const PAGE: usize = 1024;
 
 /// Compaction service configuration
 #[derive(Debug, Clone)]
 pub struct Config {
     /// Maximum segment size before compaction
     pub max_segment_size: u64,
-    /// Compaction threshold (percentage of deleted records)
+    /// Compaction threshold (fraction of dead records in a segment)
     pub threshold: f64,
+    /// Maximum segments rewritten by one major compaction
+    pub limit: usize,
     /// Compaction interval
     pub interval: Duration,
     /// Enable throttling based on system load
     pub throttle: bool,
+    /// Maximum bytes per second moved while throttled (0 = unlimited)
+    pub bandwidth: u64,
+    /// Maximum record operations per second while throttled (0 = unlimited)
+    pub iops: u64,
+    /// Segments major compaction rewrites in parallel
+    pub workers: usize,
+    /// I/O priority of the threads rewriting segments
+    pub priority: Priority,
+}
+
+impl Config {
+    /// Builds the rate limiter described by this configuration
+    fn throttle(&self) -> Throttle {
+        if self.throttle {
+            Throttle::new(self.bandwidth, self.iops)
+        } else {
+            Throttle::unlimited()
+        }
+    }
 }
 
 impl Default for Config {
@@ -31,8 +58,13 @@ impl Default for Config {
         Self {
             max_segment_size: 256 * 1024 * 1024, // 256MB
             threshold: 0.3, // 30% deleted records
+            limit: 4,
             interval: Duration::from_secs(3600), // 1 hour
             throttle: true,
+            bandwidth: 32 * 1024 * 1024, // 32MB/s
+            iops: 5000,
+            workers: 1,
+            priority: Priority::Low,
         }
     }
 }
@@ -48,6 +80,8 @@ pub struct State {
     pub processed: u64,
     /// Total records removed
     pub removed: u64,
+    /// What retention purged ahead of the pass, when run by `Store::compact`
+    pub purged: Purge,
 }
 
 /// Compaction status
@@ -59,6 +93,8 @@ pub enum Status {
     Minor,
     /// Major compaction in progress
     Major,
+    /// Paused by the operator
+    Paused,
     /// Error state
     Error(String),
 }
@@ -72,9 +108,13 @@ pub struct Compaction {
     /// Segment manager
     segment: Arc<Segment>,
     /// Index manager
-    index: Arc<Mutex<Index>>,
-    /// Base storage path
-    base_path: String,
+    index: Arc<std::sync::Mutex<Index>>,
+    /// Pause switch for background work
+    gate: Arc<Gate>,
+    /// Lifetime counters, shared with the owning store
+    counters: Arc<std::sync::Mutex<Counters>>,
+    /// Starts the next pass without waiting out the interval
+    urgent: Arc<Notify>,
 }
 
 impl Compaction {
@@ -82,14 +122,14 @@ impl Compaction {
     pub fn new(
         config: Config,
         segment: Arc<Segment>,
-        index: Arc<Mutex<Index>>,
-        base_path: String,
+        index: Arc<std::sync::Mutex<Index>>,
     ) -> Self {
         let state = State {
             status: Status::Idle,
             last_compaction: 0,
             processed: 0,
             removed: 0,
+            purged: Purge::default(),
         };
         
         Self {
@@ -97,159 +137,393 @@ impl Compaction {
             state: Arc::new(Mutex::new(state)),
             segment,
             index,
-            base_path,
+            gate: Arc::new(Gate::default()),
+            counters: Arc::new(std::sync::Mutex::new(Counters::default())),
+            urgent: Arc::new(Notify::new()),
         }
     }
     
+    /// Counts completed passes into shared counters
+    pub fn counters(mut self, counters: Arc<std::sync::Mutex<Counters>>) -> Self {
+        self.counters = counters;
+        self
+    }
+    
+    /// Cuts the interval short whenever `urgent` is notified
+    pub fn urgent(mut self, urgent: Arc<Notify>) -> Self {
+        self.urgent = urgent;
+        self
+    }
+    
     /// Starts the compaction service
-    pub async fn start(&self) -> Result<()> {
+    ///
+    /// The returned handle stops the task; dropping it leaves the task
+    /// running detached.
+    pub async fn start(&self) -> Result<Handle> {
+        self.run()
+    }
+    
+    /// Starts the compaction service, stopping it when the guard drops
+    pub fn spawn(self) -> Result<Guard> {
+        let handle = self.run()?;
+        Ok(Guard {
+            compaction: self,
+            handle: Some(handle),
+        })
+    }
+    
+    /// Spawns the compaction loop on the current Tokio runtime
+    fn run(&self) -> Result<Handle> {
+        tokio::runtime::Handle::try_current()
+            .map_err(|_| Error::Config("Background compaction requires a Tokio runtime".to_string()))?;
+        
         let config = self.config.clone();
         let state = Arc::clone(&self.state);
         let segment = Arc::clone(&self.segment);
         let index = Arc::clone(&self.index);
-        let base_path = self.base_path.clone();
+        let gate = Arc::clone(&self.gate);
+        let counters = Arc::clone(&self.counters);
+        let urgent = Arc::clone(&self.urgent);
+        let latch = Arc::new(Latch::default());
+        let stop = Arc::clone(&latch);
+        let (busy, idle) = watch::channel(false);
         
-        tokio::spawn(async move {
+        let task = tokio::spawn(async move {
             loop {
+                // Hold off entirely while paused
+                tokio::select! {
+                    _ = gate.wait() => {}
+                    _ = latch.wait() => break,
+                }
+                
                 // Check if compaction is needed
+                busy.send_replace(true);
                 if let Err(e) = Self::check_and_compact(
                     &config,
                     &state,
                     &segment,
                     &index,
-                    &base_path,
+                    &gate,
+                    &latch,
+                    &counters,
                 ).await {
                     tracing::error!("Compaction error: {}", e);
                     
                     let mut state_guard = state.lock().await;
                     state_guard.status = Status::Error(e.to_string());
                 }
+                busy.send_replace(false);
                 
-                // Wait for next interval
-                sleep(config.interval).await;
+                // Wait for next interval, or less when disk space runs low
+                tokio::select! {
+                    _ = sleep(config.interval) => {}
+                    _ = urgent.notified() => {}
+                    _ = latch.wait() => break,
+                }
             }
         });
         
-        Ok(())
+        Ok(Handle {
+            task,
+            latch: stop,
+            idle,
+        })
     }
     
     /// Checks if compaction is needed and performs it
+    #[tracing::instrument(name = "compaction", level = "info", skip_all)]
     async fn check_and_compact(
         config: &Config,
         state: &Arc<Mutex<State>>,
         segment: &Arc<Segment>,
-        index: &Arc<Mutex<Index>>,
-        base_path: &str,
+        index: &Arc<std::sync::Mutex<Index>>,
+        gate: &Arc<Gate>,
+        latch: &Arc<Latch>,
+        counters: &std::sync::Mutex<Counters>,
     ) -> Result<()> {
+        let mut throttle = config.throttle();
+        
         let mut state_guard = state.lock().await;
         state_guard.status = Status::Minor;
+        drop(state_guard);
         
         // Perform minor compaction
-        let (processed, removed) = Self::minor_compact(segment, index).await?;
+        let (processed, removed) = Self::minor_compact(segment, index, state, gate, latch, &mut throttle).await?;
         
+        let mut state_guard = state.lock().await;
         state_guard.processed += processed;
         state_guard.removed += removed;
         state_guard.last_compaction = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)?
             .as_secs();
         
-        // Check if major compaction is needed
-        let deletion_ratio = if processed > 0 {
-            removed as f64 / processed as f64
-        } else {
-            0.0
-        };
+        // Check if major compaction is needed for any segment
+        let picked = Self::pick(&segment.tallies(), config.threshold, config.limit, segment.current());
         
-        if deletion_ratio >= config.threshold {
-            state_guard.status = Status::Major;
+        if !picked.is_empty() && !latch.tripped() {
             drop(state_guard);
-            
-            let (processed, removed) = Self::major_compact(segment, index, base_path).await?;
-            
-            let mut state_guard = state.lock().await;
-            state_guard.processed += processed;
-            state_guard.removed += removed;
-            state_guard.status = Status::Idle;
+            let crew = Crew {
+                segment: Arc::clone(segment),
+                index: Arc::clone(index),
+                state: Arc::clone(state),
+                gate: Arc::clone(gate),
+                latch: Arc::clone(latch),
+                throttle: std::sync::Mutex::new(throttle),
+                queue: std::sync::Mutex::new(VecDeque::new()),
+                priority: config.priority,
+            };
+            Self::major(Arc::new(crew), &picked, config.workers).await?;
         } else {
             state_guard.status = Status::Idle;
         }
         
+        counters.lock().unwrap().compactions += 1;
+        Ok(())
+    }
+    
+    /// Runs major compaction over the given segments, tracking it in the state
+    async fn major(crew: Arc<Crew>, picked: &[u64], workers: usize) -> Result<()> {
+        let state = Arc::clone(&crew.state);
+        state.lock().await.status = Status::Major;
+        let result = Self::major_compact(crew, picked, workers).await;
+        
+        let mut state_guard = state.lock().await;
+        state_guard.status = Status::Idle;
+        let (processed, removed) = result?;
+        state_guard.processed += processed;
+        state_guard.removed += removed;
         Ok(())
     }
     
     /// Performs minor compaction (removes deleted records from active segment)
+    ///
+    /// Stops early, keeping what it found so far, once the latch trips.
+    #[tracing::instrument(name = "minor", level = "debug", skip_all, fields(processed, removed, bytes))]
     async fn minor_compact(
         segment: &Arc<Segment>,
-        index: &Arc<Mutex<Index>>,
+        index: &Arc<std::sync::Mutex<Index>>,
+        state: &Arc<Mutex<State>>,
+        gate: &Gate,
+        latch: &Latch,
+        throttle: &mut Throttle,
     ) -> Result<(u64, u64)> {
         let mut processed = 0u64;
         let mut removed = 0u64;
+        let mut bytes = 0u64;
         let mut to_delete = Vec::new();
-        // Thu thập key cần xóa
-        {
-            let index_guard = index.lock().await;
-            for result in index_guard.scan() {
-                let (key, position) = result?;
+        let mut from = None;
+        // Thu thập key cần xóa, từng trang để không giữ khóa index khi bị điều tiết
+        'pages: while let Some(entries) = Self::page(index, &mut from)? {
+            for (key, position) in entries {
+                Self::yield_to(gate, latch, state, Status::Minor).await;
+                if latch.tripped() {
+                    break 'pages;
+                }
+                throttle.charge(position.length).await;
+                
                 processed += 1;
-                if segment.read::<User>(position).is_err() {
+                bytes += position.length;
+                if segment.read(position).is_err() {
+                    segment.retire(position);
                     to_delete.push(key);
                 }
             }
         }
         // Xóa ngoài scope của index_guard
         if !to_delete.is_empty() {
-            let mut index_guard = index.lock().await;
+            let mut index_guard = index.lock().unwrap();
             for key in to_delete {
                 index_guard.delete(&key)?;
                 removed += 1;
             }
         }
         
+        let span = tracing::Span::current();
+        span.record("processed", processed);
+        span.record("removed", removed);
+        span.record("bytes", bytes);
         Ok((processed, removed))
     }
     
-    /// Performs major compaction (rewrites segments to remove deleted records)
-    async fn major_compact(
-        segment: &Arc<Segment>,
-        index: &Arc<Mutex<Index>>,
-        base_path: &str,
-    ) -> Result<(u64, u64)> {
-        let mut processed = 0u64;
-        let mut removed = 0u64;
+    /// Performs major compaction (rewrites picked segments without dead records)
+    ///
+    /// One index scan finds the live records of every picked segment.
+    /// Up to `workers` blocking threads then take segments in pick order
+    /// and rewrite each whole: live records move to the active segment
+    /// and their index entries are repointed, unless a newer write
+    /// already replaced them. Workers share one throttle and run at the
+    /// configured I/O priority. Each emptied segment leaves the live set;
+    /// garbage collection deletes its file later. A tripped latch is
+    /// honored between segments, so no segment is left half moved. A
+    /// sealed segment whose checksum fails is left in place for repair
+    /// rather than rewritten without its unreadable records.
+    #[tracing::instrument(name = "major", level = "debug", skip_all, fields(segments = ?picked, processed, removed, bytes))]
+    async fn major_compact(crew: Arc<Crew>, picked: &[u64], workers: usize) -> Result<(u64, u64)> {
+        let mut survivors: BTreeMap<u64, Page> = picked.iter().map(|&id| (id, Vec::new())).collect();
+        let mut from = None;
+        while let Some(entries) = Self::page(&crew.index, &mut from)? {
+            for (key, position) in entries {
+                if let Some(records) = survivors.get_mut(&position.segment) {
+                    records.push((key, position));
+                }
+            }
+        }
+        crew.queue.lock().unwrap().extend(picked.iter().map(|id| (*id, survivors.remove(id).unwrap_or_default())));
+        
+        let runtime = tokio::runtime::Handle::current();
+        let mut tasks = JoinSet::new();
+        for _ in 0..workers.clamp(1, picked.len().max(1)) {
+            let crew = Arc::clone(&crew);
+            let runtime = runtime.clone();
+            tasks.spawn_blocking(move || {
+                let result = Self::work(&crew, &runtime);
+                if result.is_err() {
+                    // Other workers stop after their current segment
+                    crew.queue.lock().unwrap().clear();
+                }
+                result
+            });
+        }
         
-        // Create temporary segment and index
-        let temp_path = format!("{}_temp", base_path);
-        let temp_segment = Arc::new(Segment::new(&temp_path)?);
-        let temp_index = Arc::new(Mutex::new(Index::new(format!("{}_index", temp_path))?));
+        let (mut processed, mut removed, mut bytes) = (0u64, 0u64, 0u64);
+        let mut failure = None;
+        while let Some(joined) = tasks.join_next().await {
+            let outcome = joined
+                .map_err(|e| Error::Compact(format!("Compaction worker failed: {}", e)))
+                .and_then(|outcome| outcome);
+            match outcome {
+                Ok(done) => {
+                    processed += done.0;
+                    removed += done.1;
+                    bytes += done.2;
+                }
+                Err(error) => {
+                    failure.get_or_insert(error);
+                }
+            }
+        }
+        
+        let span = tracing::Span::current();
+        span.record("processed", processed);
+        span.record("removed", removed);
+        span.record("bytes", bytes);
+        match failure {
+            Some(error) => Err(error),
+            None => Ok((processed, removed)),
+        }
+    }
+    
+    /// Rewrites queued segments until none are left, on a blocking thread
+    ///
+    /// Returns the records processed, records removed and bytes moved.
+    fn work(crew: &Crew, runtime: &tokio::runtime::Handle) -> Result<(u64, u64, u64)> {
+        let _lowered = crew.priority.apply();
+        let (mut processed, mut removed, mut bytes) = (0u64, 0u64, 0u64);
         
-        // Copy valid records to temporary storage
-        {
-            let index_guard = index.lock().await;
-            for result in index_guard.scan() {
-                let (key, position) = result?;
+        loop {
+            runtime.block_on(Self::yield_to(&crew.gate, &crew.latch, &crew.state, Status::Major));
+            if crew.latch.tripped() {
+                break;
+            }
+            let Some((id, records)) = crew.queue.lock().unwrap().pop_front() else {
+                break;
+            };
+            if let Err(error) = crew.segment.check(id) {
+                tracing::warn!(segment = id, %error, "skipping damaged segment");
+                continue;
+            }
+            
+            removed += crew.segment.tallies().get(&id).map_or(0, |tally| tally.dead);
+            
+            for (key, position) in records {
+                // Each live record is read once and written once
+                let delay = crew.throttle.lock().unwrap().debit(position.length * 2);
+                if let Some(delay) = delay {
+                    std::thread::sleep(delay);
+                }
                 processed += 1;
+                bytes += position.length;
                 
-                if let Ok(user) = segment.read::<User>(position) {
-                    // Write to temporary segment
-                    let new_position = temp_segment.append(&user)?;
-                    
-                    // Update temporary index
-                    let mut temp_index_guard = temp_index.lock().await;
-                    temp_index_guard.put(&key, new_position)?;
+                let user = match crew.segment.read(position) {
+                    Ok(user) => user,
+                    Err(_) => {
+                        removed += 1;
+                        continue;
+                    }
+                };
+                
+                let moved = crew.segment.append(&user)?;
+                let mut index_guard = crew.index.lock().unwrap();
+                if index_guard.get(&key)? == Some(position) {
+                    index_guard.put(&key, moved)?;
                 } else {
-                    removed += 1;
+                    // Overwritten while we copied; the copy is already dead
+                    crew.segment.retire(moved);
                 }
             }
+            
+            crew.segment.release(&[id]);
         }
+        Ok((processed, removed, bytes))
+    }
+    
+    /// Segments the next major compaction would rewrite
+    pub fn plan(&self) -> Vec<u64> {
+        Self::pick(&self.segment.tallies(), self.config.threshold, self.config.limit, self.segment.current())
+    }
+    
+    /// Picks sealed segments whose dead ratio reaches the threshold, worst first
+    pub fn pick(tallies: &BTreeMap<u64, Tally>, threshold: f64, limit: usize, active: u64) -> Vec<u64> {
+        let mut candidates: Vec<(u64, f64)> = tallies.iter()
+            .filter(|(id, tally)| **id != active && tally.dead > 0 && tally.ratio() >= threshold)
+            .map(|(id, tally)| (*id, tally.ratio()))
+            .collect();
         
-        // TODO: Implement atomic replacement of old segments with new ones
-        // This would involve:
-        // 1. Creating backup of current segments
-        // 2. Moving temporary segments to final location
-        // 3. Updating the main index
-        // 4. Cleaning up old segments
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        candidates.into_iter().take(limit).map(|(id, _)| id).collect()
+    }
+    
+    /// Copies the next page of index entries so the lock isn't held while throttled
+    fn page(index: &Arc<std::sync::Mutex<Index>>, from: &mut Option<Vec<u8>>) -> Result<Option<Page>> {
+        let entries = index.lock().unwrap().page(from.as_deref(), PAGE)?;
+        match entries.last() {
+            Some((key, _)) => {
+                *from = Some(key.clone());
+                Ok(Some(entries))
+            }
+            None => Ok(None),
+        }
+    }
+    
+    /// Waits while paused, reporting the pause in the shared state
+    ///
+    /// Returns early when the latch trips so a paused task can still stop.
+    async fn yield_to(gate: &Gate, latch: &Latch, state: &Arc<Mutex<State>>, status: Status) {
+        if !gate.paused() {
+            return;
+        }
         
-        Ok((processed, removed))
+        state.lock().await.status = Status::Paused;
+        tokio::select! {
+            _ = gate.wait() => {}
+            _ = latch.wait() => {}
+        }
+        state.lock().await.status = status;
+    }
+    
+    /// Pauses compaction at the next record boundary
+    pub fn pause(&self) {
+        self.gate.pause();
+    }
+    
+    /// Resumes paused compaction
+    pub fn resume(&self) {
+        self.gate.resume();
+    }
+    
+    /// Whether compaction is currently paused
+    pub fn paused(&self) -> bool {
+        self.gate.paused()
     }
     
     /// Gets current compaction state
@@ -263,9 +537,138 @@ impl Compaction {
         let state = Arc::clone(&self.state);
         let segment = Arc::clone(&self.segment);
         let index = Arc::clone(&self.index);
-        let base_path = self.base_path.clone();
         
-        Self::check_and_compact(&config, &state, &segment, &index, &base_path).await
+        Self::check_and_compact(&config, &state, &segment, &index, &self.gate, &Arc::new(Latch::default()), &self.counters).await
+    }
+    
+    /// Rewrites exactly the given segments, whatever their dead ratio
+    ///
+    /// Skips the minor pass and the picker, for operators targeting
+    /// known segments. Every segment must be live and sealed: unknown
+    /// ones fail with `Error::Missing` and the active one with
+    /// `Error::Compact`, before anything moves.
+    pub async fn segments(&self, ids: &[u64]) -> Result<()> {
+        let live = self.segment.list();
+        let active = self.segment.current();
+        let mut picked = Vec::with_capacity(ids.len());
+        for &id in ids {
+            if !live.contains(&id) {
+                return Err(Error::Missing(format!("Segment {}", id)));
+            }
+            if id == active {
+                return Err(Error::Compact(format!("Segment {} is still being written", id)));
+            }
+            if !picked.contains(&id) {
+                picked.push(id);
+            }
+        }
+        
+        let crew = Crew {
+            segment: Arc::clone(&self.segment),
+            index: Arc::clone(&self.index),
+            state: Arc::clone(&self.state),
+            gate: Arc::clone(&self.gate),
+            latch: Arc::new(Latch::default()),
+            throttle: std::sync::Mutex::new(self.config.throttle()),
+            queue: std::sync::Mutex::new(VecDeque::new()),
+            priority: self.config.priority,
+        };
+        Self::major(Arc::new(crew), &picked, self.config.workers).await?;
+        self.state.lock().await.last_compaction = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs();
+        self.counters.lock().unwrap().compactions += 1;
+        Ok(())
+    }
+}
+
+/// What the workers of one major compaction share
+struct Crew {
+    /// Segment manager
+    segment: Arc<Segment>,
+    /// Index manager
+    index: Arc<std::sync::Mutex<Index>>,
+    /// Service state, for pause reporting
+    state: Arc<Mutex<State>>,
+    /// Pause switch
+    gate: Arc<Gate>,
+    /// Stop signal
+    latch: Arc<Latch>,
+    /// Rate limit across all workers
+    throttle: std::sync::Mutex<Throttle>,
+    /// Picked segments not yet taken, with their live records
+    queue: std::sync::Mutex<VecDeque<(u64, Page)>>,
+    /// I/O priority of the worker threads
+    priority: Priority,
+}
+
+/// Controls a running compaction task
+pub struct Handle {
+    /// Spawned compaction loop
+    task: JoinHandle<()>,
+    /// Asks the loop to stop at the next safe point
+    latch: Arc<Latch>,
+    /// Whether a compaction pass is in progress
+    idle: watch::Receiver<bool>,
+}
+
+impl Handle {
+    /// Asks the task to stop without waiting for it
+    pub fn cancel(&self) {
+        self.latch.trip();
+    }
+    
+    /// Stops the task after the segment in progress and waits for it to exit
+    pub async fn stop(self) -> Result<()> {
+        self.latch.trip();
+        self.task.await
+            .map_err(|e| Error::Compact(format!("Compaction task failed: {}", e)))
+    }
+    
+    /// Waits until no compaction pass is in progress
+    pub async fn idle(&self) {
+        let mut idle = self.idle.clone();
+        // A closed channel means the task has exited, which is idle too
+        let _ = idle.wait_for(|busy| !busy).await;
+    }
+}
+
+/// Background compaction task that stops when dropped
+pub struct Guard {
+    /// Service driven by the task
+    compaction: Compaction,
+    /// Control over the task, taken by `stop`
+    handle: Option<Handle>,
+}
+
+impl Guard {
+    /// The running compaction service, for pausing or inspecting it
+    pub fn compaction(&self) -> &Compaction {
+        &self.compaction
+    }
+    
+    /// Stops the task after the segment in progress and waits for it to exit
+    pub async fn stop(mut self) -> Result<()> {
+        match self.handle.take() {
+            Some(handle) => handle.stop().await,
+            None => Ok(()),
+        }
+    }
+    
+    /// Waits until no compaction pass is in progress
+    pub async fn idle(&self) {
+        if let Some(handle) = &self.handle {
+            handle.idle().await;
+        }
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        // The task exits at its next safe point
+        if let Some(handle) = &self.handle {
+            handle.cancel();
+        }
     }
 }
 
@@ -276,6 +679,7 @@ impl Clone for State {
             last_compaction: self.last_compaction,
             processed: self.processed,
             removed: self.removed,
+            purged: self.purged,
         }
     }
 } 
\ No newline at end of file
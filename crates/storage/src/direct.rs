@@ -0,0 +1,153 @@
+//! Direct I/O reads (Linux)
+//!
+//! With `Builder::direct`, segments are read through handles opened
+//! with `O_DIRECT`, so reading a large store end to end does not fill
+//! the page cache and push out what the rest of the host keeps there.
+//! Direct reads must start on a block boundary and cover whole blocks,
+//! so each handle reads the blocks around what was asked into a buffer
+//! of its own, aligned to a block, and serves reads from that. Scans
+//! asking for a megabyte at a time read a megabyte; a point read costs
+//! a block or two.
+//!
+//! Writes still go through the page cache, and the kernel writes back
+//! what a direct read covers first, so records just appended read back
+//! as usual. Where a file system refuses `O_DIRECT`, handles fall back
+//! to cached reads. On other platforms every read is cached.
+
+use std::path::Path;
+use crate::Result;
+use crate::backend::Handle;
+
+/// Alignment and granularity of direct reads
+pub const BLOCK: usize = 4096;
+
+/// Opens a file for reading, bypassing the page cache where possible
+pub fn open(path: &Path) -> Result<Box<dyn Handle>> {
+    #[cfg(target_os = "linux")]
+    return Ok(Box::new(linux::Reader::open(path)?));
+    #[cfg(not(target_os = "linux"))]
+    return crate::backend::Backend::open(&crate::backend::Disk, path);
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Read, Seek, SeekFrom, Write};
+    use std::os::unix::fs::{FileExt, OpenOptionsExt};
+    use std::path::Path;
+    use crate::Result;
+    use crate::backend::Handle;
+    use super::BLOCK;
+
+    /// A file read in aligned blocks
+    pub struct Reader {
+        /// Underlying file, opened with `O_DIRECT` if the file system allows
+        file: File,
+        /// Allocation holding the blocks read, one block longer than they need
+        memory: Vec<u8>,
+        /// Where the first aligned byte sits in `memory`
+        skew: usize,
+        /// File offset of the first byte held
+        start: u64,
+        /// Bytes held
+        held: usize,
+        /// Offset of the next read
+        cursor: u64,
+    }
+
+    impl Reader {
+        /// Opens a file, with `O_DIRECT` unless its file system refuses it
+        pub fn open(path: &Path) -> Result<Self> {
+            let file = match OpenOptions::new().read(true).custom_flags(libc::O_DIRECT).open(path) {
+                Ok(file) => file,
+                Err(error) if error.raw_os_error() == Some(libc::EINVAL) => File::open(path)?,
+                Err(error) => return Err(error.into()),
+            };
+            Ok(Self { file, memory: Vec::new(), skew: 0, start: 0, held: 0, cursor: 0 })
+        }
+
+        /// Reads the blocks holding the cursor and the `wanted` bytes after it
+        fn fill(&mut self, wanted: usize) -> io::Result<()> {
+            let start = self.cursor - self.cursor % BLOCK as u64;
+            let length = ((self.cursor - start) as usize + wanted).next_multiple_of(BLOCK);
+            if self.memory.len() < length + BLOCK {
+                self.memory = vec![0; length + BLOCK];
+                self.skew = self.memory.as_ptr().align_offset(BLOCK);
+            }
+
+            self.held = 0;
+            let buffer = &mut self.memory[self.skew..self.skew + length];
+            let mut held = 0;
+            // A read ending off a block boundary has reached the end of the file
+            while held < length && held % BLOCK == 0 {
+                match self.file.read_at(&mut buffer[held..], start + held as u64) {
+                    Ok(0) => break,
+                    Ok(read) => held += read,
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                    Err(error) => return Err(error),
+                }
+            }
+            self.start = start;
+            self.held = held;
+            Ok(())
+        }
+    }
+
+    impl Read for Reader {
+        fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+            if out.is_empty() {
+                return Ok(0);
+            }
+            if self.cursor < self.start || self.cursor >= self.start + self.held as u64 {
+                self.fill(out.len())?;
+            }
+            let at = (self.cursor - self.start) as usize;
+            let count = out.len().min(self.held.saturating_sub(at));
+            if count == 0 {
+                // Past the end of the file
+                return Ok(0);
+            }
+            let held = &self.memory[self.skew + at..self.skew + at + count];
+            out[..count].copy_from_slice(held);
+            self.cursor += count as u64;
+            Ok(count)
+        }
+    }
+
+    impl Seek for Reader {
+        fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+            let (base, delta) = match position {
+                SeekFrom::Start(offset) => (offset, 0),
+                SeekFrom::Current(delta) => (self.cursor, delta),
+                SeekFrom::End(delta) => (self.file.metadata()?.len(), delta),
+            };
+            self.cursor = base.checked_add_signed(delta)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the file"))?;
+            Ok(self.cursor)
+        }
+    }
+
+    impl Write for Reader {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::Unsupported, "direct handles only read"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Handle for Reader {
+        fn sync(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn data(&self) -> Result<()> {
+            Ok(())
+        }
+
+        fn size(&self) -> Result<u64> {
+            Ok(self.file.metadata()?.len())
+        }
+    }
+}
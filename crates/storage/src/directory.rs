@@ -0,0 +1,49 @@
+//! Directory durability
+//!
+//! Syncing a file persists its contents but not the directory entry
+//! that names it. After creating or renaming a file the parent
+//! directory must be synced too, or a crash can lose the file itself.
+//! Free space is queried here as well, for the same directories.
+
+use std::fs::File;
+use std::path::Path;
+use crate::Result;
+
+/// Flushes a directory's entries to disk
+#[cfg(unix)]
+pub fn sync<P: AsRef<Path>>(path: P) -> Result<()> {
+    File::open(path.as_ref())?.sync_all()?;
+    Ok(())
+}
+
+/// Flushes a directory's entries to disk
+///
+/// Directories cannot be opened as files here; entries are durable
+/// once the file handle itself is synced.
+#[cfg(not(unix))]
+pub fn sync<P: AsRef<Path>>(_path: P) -> Result<()> {
+    Ok(())
+}
+
+/// Syncs the directory holding a file
+pub fn parent<P: AsRef<Path>>(path: P) -> Result<()> {
+    match path.as_ref().parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => sync(parent),
+        _ => sync("."),
+    }
+}
+
+/// Bytes available to unprivileged writers on the filesystem holding a path
+#[cfg(unix)]
+pub fn available<P: AsRef<Path>>(path: P) -> Result<Option<u64>> {
+    let stat = rustix::fs::statvfs(path.as_ref()).map_err(std::io::Error::from)?;
+    Ok(Some(stat.f_bavail.saturating_mul(stat.f_frsize)))
+}
+
+/// Bytes available to unprivileged writers on the filesystem holding a path
+///
+/// Not known on this platform.
+#[cfg(not(unix))]
+pub fn available<P: AsRef<Path>>(_path: P) -> Result<Option<u64>> {
+    Ok(None)
+}
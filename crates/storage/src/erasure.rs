@@ -0,0 +1,51 @@
+//! Certificates of erasure
+//!
+//! `Store::erase` removes every copy of a user it knows of and hands
+//! back a certificate saying what went. The certificate carries a
+//! SHA-256 digest over its own fields, so one kept as a record of the
+//! erasure can be checked later for having been altered.
+
+use serde::Serialize;
+use ring::digest::{digest, SHA256};
+use crate::cipher::hex;
+
+/// Record of one user erased from a store
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    /// ID of the erased user
+    pub id: u64,
+    /// When the erasure finished, in seconds since the epoch
+    pub time: u64,
+    /// Copies of the user erased, the live record and its versions
+    pub copies: u64,
+    /// Segments rewritten without the user and removed from disk
+    pub segments: Vec<u64>,
+    /// Hex SHA-256 over the fields above
+    pub digest: String,
+}
+
+impl Certificate {
+    /// Issues a certificate, sealing its fields with their digest
+    pub fn new(id: u64, time: u64, copies: u64, segments: Vec<u64>) -> Self {
+        let digest = fingerprint(id, time, copies, &segments);
+        Self { id, time, copies, segments, digest }
+    }
+    
+    /// Whether the digest still matches the fields
+    pub fn verify(&self) -> bool {
+        self.digest == fingerprint(self.id, self.time, self.copies, &self.segments)
+    }
+}
+
+/// Digest over the fields of a certificate, in a fixed layout
+fn fingerprint(id: u64, time: u64, copies: u64, segments: &[u64]) -> String {
+    let mut bytes = Vec::with_capacity(32 + segments.len() * 8);
+    bytes.extend_from_slice(&id.to_be_bytes());
+    bytes.extend_from_slice(&time.to_be_bytes());
+    bytes.extend_from_slice(&copies.to_be_bytes());
+    bytes.extend_from_slice(&(segments.len() as u64).to_be_bytes());
+    for segment in segments {
+        bytes.extend_from_slice(&segment.to_be_bytes());
+    }
+    hex(digest(&SHA256, &bytes).as_ref())
+}
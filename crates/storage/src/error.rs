@@ -5,6 +5,29 @@
 
 use thiserror::Error;
 
+/// Broad category of an error, for programmatic handling
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// The operating system reported an I/O failure
+    Io,
+    /// Stored data is damaged or not in the expected format
+    Corruption,
+    /// A requested resource does not exist
+    Missing,
+    /// A guarded write lost against a concurrent one
+    Conflict,
+    /// The caller passed an invalid argument or configuration
+    Invalid,
+    /// The operation or data version is not supported
+    Unsupported,
+    /// The store has been closed
+    Closed,
+    /// The disk is too full to accept the write
+    Capacity,
+    /// Anything else
+    Other,
+}
+
 /// Represents all possible errors in Guardian-Store
 #[derive(Error, Debug)]
 pub enum Error {
@@ -32,6 +55,13 @@ pub enum Error {
     #[error("Resource not found: {0}")]
     Missing(String),
     
+    /// User not stored because it was deleted
+    #[error("User {id} was deleted")]
+    Deleted {
+        /// User looked for
+        id: u64,
+    },
+    
     /// Operation not supported
     #[error("Operation not supported: {0}")]
     Unsupported(String),
@@ -43,4 +73,125 @@ pub enum Error {
     /// Compaction operation failed
     #[error("Compaction failed: {0}")]
     Compact(String),
-} 
\ No newline at end of file
+    
+    /// Optimistic write lost against a newer revision
+    #[error("Revision conflict on user {id}: expected {expected}, found {actual}")]
+    Conflict {
+        /// User whose write was refused
+        id: u64,
+        /// Revision the caller expected
+        expected: u64,
+        /// Revision actually stored
+        actual: u64,
+    },
+    
+    /// Another record already holds a value of a unique field
+    #[error("User {holder} already holds {field} {value:?}")]
+    Duplicate {
+        /// Unique field, as named in the model
+        field: String,
+        /// Value both records would share
+        value: String,
+        /// User holding the value
+        holder: u64,
+    },
+    
+    /// Two keys of a hashed index share a hash
+    #[error("Key {key:02x?} collides with the key of the record at {position:?}")]
+    Collision {
+        /// Key whose write was refused
+        key: Vec<u8>,
+        /// Position the other key holds
+        position: crate::model::Position,
+    },
+    
+    /// Record at a segment position could not be decoded
+    #[error("Corrupt record in segment {segment} at offset {offset}: {reason}")]
+    Corrupt {
+        /// Segment holding the record
+        segment: u64,
+        /// Byte offset of the record within the segment
+        offset: u64,
+        /// What was wrong with it
+        reason: String,
+    },
+    
+    /// Stored checksum does not match the data
+    #[error("Checksum mismatch in segment {segment} at offset {offset}: expected {expected:#010x}, found {actual:#010x}")]
+    Checksum {
+        /// Segment holding the data
+        segment: u64,
+        /// Byte offset of the checked data
+        offset: u64,
+        /// Checksum stored on disk
+        expected: u32,
+        /// Checksum computed from the data
+        actual: u32,
+    },
+    
+    /// Key is malformed
+    #[error("Invalid key {key:02x?}: {reason}")]
+    Key {
+        /// Offending key bytes
+        key: Vec<u8>,
+        /// What was wrong with it
+        reason: String,
+    },
+    
+    /// Store has been closed
+    #[error("Store is closed")]
+    Closed,
+    
+    /// Record rejected by a size limit or a validator
+    #[error("Invalid {field}: {reason}")]
+    Invalid {
+        /// Field that failed, or `record` for the record as a whole
+        field: String,
+        /// Why it was rejected
+        reason: String,
+    },
+    
+    /// A change stream broker refused or lost published changes
+    #[error("Publishing failed: {0}")]
+    Publish(String),
+    
+    /// A write would take the store past its quota
+    #[error("Quota exceeded: {used} {resource} of {limit} in use")]
+    Quota {
+        /// Limited resource, `records` or `bytes`
+        resource: String,
+        /// Amount already in use
+        used: u64,
+        /// Configured limit
+        limit: u64,
+    },
+    
+    /// Free disk space fell below the configured reserve
+    #[error("Disk nearly full: {available} bytes free, {reserve} reserved")]
+    Capacity {
+        /// Bytes free on the filesystem holding the store
+        available: u64,
+        /// Bytes the store keeps free
+        reserve: u64,
+    },
+}
+
+impl Error {
+    /// Broad category of this error
+    pub fn kind(&self) -> Kind {
+        match self {
+            Error::Storage(_) | Error::Publish(_) => Kind::Io,
+            Error::Index(_)
+            | Error::Format(_)
+            | Error::Corrupt { .. }
+            | Error::Checksum { .. } => Kind::Corruption,
+            Error::Missing(_) | Error::Deleted { .. } => Kind::Missing,
+            Error::Conflict { .. } | Error::Collision { .. } | Error::Duplicate { .. } => Kind::Conflict,
+            Error::Config(_) | Error::Key { .. } | Error::Invalid { .. } => Kind::Invalid,
+            Error::Unsupported(_) => Kind::Unsupported,
+            Error::Closed => Kind::Closed,
+            Error::Capacity { .. } | Error::Quota { .. } => Kind::Capacity,
+            Error::Time(_) | Error::Serialize(_) | Error::Compact(_) => Kind::Other,
+        }
+    }
+}
\ No newline at end of file
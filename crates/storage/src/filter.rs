@@ -0,0 +1,85 @@
+//! Filters on user fields
+//!
+//! A filter is written `field=value`, keeping users whose field equals
+//! the value, or `field~value`, keeping those whose field contains it.
+//! Filters apply to the text fields the catalog knows: name, email,
+//! city and country, compared as stored, case included, and after
+//! secret fields are opened. `Store::filter` serves exact filters from
+//! the catalog where it can and checks the rest on every user read.
+
+use std::fmt;
+use std::str::FromStr;
+use crate::{Error, Result};
+use crate::catalog;
+use crate::model::{Field, User};
+
+/// How a filter compares a field with its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Test {
+    /// The field equals the value, written `=`
+    Equals,
+    /// The field contains the value, written `~`
+    Contains,
+}
+
+/// A test of one user field against a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Filter {
+    /// Field tested
+    pub field: Field,
+    /// How it is compared
+    pub test: Test,
+    /// Value compared against
+    pub value: String,
+}
+
+impl Filter {
+    /// A filter on a field, which must be name, email, city or country
+    pub fn new(field: Field, test: Test, value: impl Into<String>) -> Result<Self> {
+        if !matches!(field, Field::Name | Field::Email | Field::City | Field::Country) {
+            return Err(Error::Invalid {
+                field: "filter".to_string(),
+                reason: format!("cannot filter on {}; use name, email, city or country", field.name()),
+            });
+        }
+        Ok(Self { field, test, value: value.into() })
+    }
+
+    /// Whether a user passes the filter
+    pub fn matches(&self, user: &User) -> bool {
+        catalog::value(user, self.field).is_some_and(|value| match self.test {
+            Test::Equals => value == self.value,
+            Test::Contains => value.contains(&self.value),
+        })
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    /// Parses `field=value` or `field~value`, splitting at the first operator
+    fn from_str(text: &str) -> Result<Self> {
+        let invalid = |reason: String| Error::Invalid { field: "filter".to_string(), reason };
+        let (at, test) = text.char_indices()
+            .find_map(|(at, symbol)| match symbol {
+                '=' => Some((at, Test::Equals)),
+                '~' => Some((at, Test::Contains)),
+                _ => None,
+            })
+            .ok_or_else(|| invalid(format!("{:?} is neither field=value nor field~value", text)))?;
+        let name = text[..at].trim();
+        let field = Field::parse(name)
+            .ok_or_else(|| invalid(format!("{:?} is not a field; use name, email, city or country", name)))?;
+        Self::new(field, test, &text[at + 1..])
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self.test {
+            Test::Equals => '=',
+            Test::Contains => '~',
+        };
+        write!(formatter, "{}{}{}", self.field.name(), symbol, self.value)
+    }
+}
@@ -0,0 +1,104 @@
+//! Garbage collection of unreferenced store files
+//!
+//! Removes segments the manifest no longer lists, superseded index
+//! tables, half-written temporary files and directories abandoned by
+//! failed compactions. Only names the store itself produces are ever
+//! considered, so unrelated files in the base directory are left alone.
+
+use std::path::{Path, PathBuf};
+use crate::Result;
+use crate::manifest::Manifest;
+use crate::segment::Segment;
+
+/// Outcome of a garbage collection pass
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// Files and directories that were (or would be) removed
+    pub paths: Vec<PathBuf>,
+    /// Total bytes reclaimed (or reclaimable)
+    pub bytes: u64,
+    /// History entries dropped (or droppable)
+    pub versions: u64,
+    /// Whether this was a dry run that removed nothing
+    pub dry: bool,
+}
+
+/// Collects garbage under a store base directory
+///
+/// With `dry` set, the report lists candidates without deleting them.
+pub fn collect<P: AsRef<Path>>(base: P, manifest: &Manifest, dry: bool) -> Result<Report> {
+    let base = base.as_ref();
+    let mut report = Report {
+        dry,
+        ..Report::default()
+    };
+
+    // Segments not referenced by the manifest
+    let segments = base.join("segments");
+    for id in Segment::discover(&segments)? {
+        if !manifest.segments.contains(&id) {
+            report.paths.push(segments.join(format!("segment_{}.dat", id)));
+        }
+    }
+
+    for entry in std::fs::read_dir(base)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+
+        if stale(&name, manifest.generation) {
+            report.paths.push(entry.path());
+        }
+    }
+
+    report.paths.sort();
+    for path in &report.paths {
+        report.bytes += size(path)?;
+    }
+
+    if !dry {
+        for path in &report.paths {
+            if path.is_dir() {
+                std::fs::remove_dir_all(path)?;
+            } else {
+                std::fs::remove_file(path)?;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Whether a base directory entry is left over from an earlier state
+fn stale(name: &str, generation: u64) -> bool {
+    // Interrupted atomic writes
+    if name.ends_with(".tmp") {
+        return true;
+    }
+
+    // Compaction scratch directories
+    if name.starts_with("temp_compact") || name.ends_with("_temp") || name.ends_with("_temp_index") {
+        return true;
+    }
+
+    // Index tables from earlier generations; a newer one is live, merged
+    // since the manifest was last written
+    name.strip_prefix("index.")
+        .and_then(|rest| rest.strip_suffix(".table"))
+        .and_then(|id| id.parse::<u64>().ok())
+        .is_some_and(|id| id < generation)
+}
+
+/// Total size of a file or directory tree
+fn size(path: &Path) -> Result<u64> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(metadata.len());
+    }
+
+    let mut total = 0;
+    for entry in std::fs::read_dir(path)? {
+        total += size(&entry?.path())?;
+    }
+    Ok(total)
+}
@@ -0,0 +1,99 @@
+//! User ID generation
+//!
+//! `Store::create` asks the store's generator for IDs, so callers no
+//! longer invent unique u64s themselves. Generators only propose IDs;
+//! the store skips any already taken.
+
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Start of snowflake time, 2024-01-01T00:00:00Z in milliseconds
+pub const EPOCH: u64 = 1_704_067_200_000;
+
+/// Bits of a snowflake ID holding the node
+const NODE: u32 = 10;
+
+/// Bits of a snowflake ID holding the per-millisecond sequence
+const SEQUENCE: u32 = 12;
+
+/// Source of fresh user IDs
+pub trait Generator: Send + Sync + fmt::Debug {
+    /// Proposes the next ID, never 0
+    fn next(&self) -> u64;
+}
+
+/// Time-ordered IDs: milliseconds since `EPOCH`, node, then sequence
+///
+/// Nodes sharing a keyspace must have distinct numbers. Up to 4096 IDs
+/// a millisecond are issued per node; beyond that, and when the clock
+/// steps back, IDs borrow from the following milliseconds so they
+/// keep increasing.
+#[derive(Debug, Default)]
+pub struct Snowflake {
+    /// Node number, below 1024
+    node: u64,
+    /// Last millisecond and sequence issued
+    state: Mutex<(u64, u64)>,
+}
+
+impl Snowflake {
+    /// Creates a generator for a node, keeping its low 10 bits
+    pub fn new(node: u16) -> Self {
+        Self {
+            node: u64::from(node) & ((1 << NODE) - 1),
+            state: Mutex::new((0, 0)),
+        }
+    }
+}
+
+impl Generator for Snowflake {
+    fn next(&self) -> u64 {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64)
+            .saturating_sub(EPOCH);
+
+        let mut state = self.state.lock().unwrap();
+        let (last, sequence) = *state;
+        *state = if now > last {
+            (now, 0)
+        } else if sequence + 1 < 1 << SEQUENCE {
+            (last, sequence + 1)
+        } else {
+            (last + 1, 0)
+        };
+
+        let (time, sequence) = *state;
+        // Only the very first millisecond of node 0 could yield 0
+        (time << (NODE + SEQUENCE) | self.node << SEQUENCE | sequence).max(1)
+    }
+}
+
+/// Consecutive IDs counting up from a start
+#[derive(Debug)]
+pub struct Monotonic {
+    /// Next ID to propose
+    next: AtomicU64,
+}
+
+impl Monotonic {
+    /// Creates a generator whose first ID is `start`, or 1 if `start` is 0
+    pub fn new(start: u64) -> Self {
+        Self {
+            next: AtomicU64::new(start.max(1)),
+        }
+    }
+}
+
+impl Default for Monotonic {
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+impl Generator for Monotonic {
+    fn next(&self) -> u64 {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,233 @@
+//! Index of superseded record versions
+//!
+//! Saves append, so every earlier version of a record stays on disk
+//! until compaction reclaims its segment. This index remembers where
+//! those versions are, keyed by user ID, version timestamp and position
+//! so a record's history reads back in write order. Entries are weak:
+//! once their segment leaves the live set they are skipped and later
+//! swept away.
+//!
+//! `Retention` says how long versions, and live records too, are kept;
+//! `Store::enforce` applies it, as does every `Store::compact`, and
+//! reports what went in a `Purge`.
+
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::Result;
+use crate::index::{Index, Operation, Shape};
+use crate::model::Position;
+
+/// Directory holding the history index inside a store
+const NAME: &str = "history";
+
+/// Length of a history key: ID, timestamp, segment and offset
+const LENGTH: usize = 32;
+
+/// What a store keeps, and for how long
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Retention {
+    /// Maximum versions kept per record (None = unlimited)
+    pub versions: Option<usize>,
+    /// Maximum age in seconds of a kept version (None = unlimited)
+    pub age: Option<u64>,
+    /// Age in seconds, by `updated`, past which live records are
+    /// deleted (None = kept)
+    #[serde(default)]
+    pub records: Option<u64>,
+    /// Whether a deleted record's history goes with it at once
+    #[serde(default)]
+    pub purge: bool,
+}
+
+/// What one retention pass purged
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Purge {
+    /// When the pass ran, in seconds since the epoch
+    pub time: u64,
+    /// Live records deleted for their age
+    pub records: u64,
+    /// Superseded versions dropped from the history
+    pub versions: u64,
+}
+
+/// One superseded version of a record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Version {
+    /// `updated` timestamp of the version
+    pub updated: u64,
+    /// Where the version is stored
+    pub position: Position,
+}
+
+/// Secondary index of superseded versions
+pub struct History {
+    /// Entries keyed by ID, timestamp and position
+    index: Index,
+}
+
+impl History {
+    /// Opens the history of a store base directory
+    pub fn new<P: AsRef<Path>>(base: P) -> Result<Self> {
+        Ok(Self {
+            index: Index::new(Self::locate(base).join("versions"))?,
+        })
+    }
+
+    /// Directory of the history for a base directory
+    pub fn locate<P: AsRef<Path>>(base: P) -> PathBuf {
+        base.as_ref().join(NAME)
+    }
+
+    /// Remembers a superseded version of a record
+    pub fn add(&mut self, id: u64, version: Version) -> Result<()> {
+        self.index.put(&key(id, &version), version.position)
+    }
+
+    /// Superseded versions of a record, oldest first
+    pub fn versions(&self, id: u64) -> Result<Vec<Version>> {
+        let mut found = Vec::new();
+        for result in self.index.after(&id.to_be_bytes()) {
+            let (key, position) = result?;
+            let (owner, updated) = split(&key);
+            if owner != id {
+                break;
+            }
+            found.push(Version { updated, position });
+        }
+        Ok(found)
+    }
+
+    /// Drops versions of one record that the policy no longer keeps
+    pub fn prune(&mut self, id: u64, retention: &Retention, live: &[u64], now: u64) -> Result<u64> {
+        let versions = self.versions(id)?;
+        let doomed = expired(&versions, retention, live, now);
+        let count = doomed.len() as u64;
+
+        let operations = doomed.into_iter()
+            .map(|version| Operation::Delete { key: key(id, version).to_vec() })
+            .collect();
+        self.index.batch(operations)?;
+        Ok(count)
+    }
+
+    /// Drops every version of a record, returning how many there were
+    pub fn forget(&mut self, id: u64) -> Result<u64> {
+        let operations = self.versions(id)?.iter()
+            .map(|version| Operation::Delete { key: key(id, version).to_vec() })
+            .collect::<Vec<_>>();
+        let count = operations.len() as u64;
+        self.index.batch(operations)?;
+        Ok(count)
+    }
+
+    /// Drops every version of the records `live` says are gone
+    pub fn purge(&mut self, live: impl Fn(u64) -> Result<bool>) -> Result<u64> {
+        let mut doomed = Vec::new();
+        // Versions of a record are adjacent, so each ID is asked about once
+        let mut owner = None;
+        for result in self.index.scan() {
+            let (key, _) = result?;
+            let (id, _) = split(&key);
+            let alive = match owner {
+                Some((held, alive)) if held == id => alive,
+                _ => {
+                    let alive = live(id)?;
+                    owner = Some((id, alive));
+                    alive
+                }
+            };
+            if !alive {
+                doomed.push(Operation::Delete { key });
+            }
+        }
+        let count = doomed.len() as u64;
+        self.index.batch(doomed)?;
+        Ok(count)
+    }
+
+    /// Drops expired versions of every record
+    ///
+    /// With `dry` set, only counts what would be dropped.
+    pub fn sweep(&mut self, retention: &Retention, live: &[u64], now: u64, dry: bool) -> Result<u64> {
+        let mut doomed = Vec::new();
+        let mut group: Vec<Version> = Vec::new();
+        let mut owner = None;
+
+        for result in self.index.scan() {
+            let (key, position) = result?;
+            let (id, updated) = split(&key);
+            if owner != Some(id) {
+                if let Some(previous) = owner {
+                    doomed.extend(expired(&group, retention, live, now).into_iter().map(|version| (previous, *version)));
+                }
+                owner = Some(id);
+                group.clear();
+            }
+            group.push(Version { updated, position });
+        }
+        if let Some(previous) = owner {
+            doomed.extend(expired(&group, retention, live, now).into_iter().map(|version| (previous, *version)));
+        }
+
+        let count = doomed.len() as u64;
+        if !dry {
+            let operations = doomed.iter()
+                .map(|(id, version)| Operation::Delete { key: key(*id, version).to_vec() })
+                .collect();
+            self.index.batch(operations)?;
+        }
+        Ok(count)
+    }
+
+    /// Flushes the index to disk
+    pub fn sync(&self) -> Result<()> {
+        self.index.sync()
+    }
+
+    /// Approximate bytes of memory held by the index
+    pub fn memory(&self) -> usize {
+        self.index.memory()
+    }
+
+    /// Entries and size of the index, counted in a full walk
+    pub fn shape(&self) -> Result<Shape> {
+        self.index.shape("history")
+    }
+
+    /// Merges the index delta into its table when above `floor` bytes
+    pub fn shrink(&mut self, floor: usize) -> Result<()> {
+        self.index.shrink(floor)
+    }
+}
+
+/// Versions, oldest first, that are reclaimed or fall outside the policy
+fn expired<'a>(versions: &'a [Version], retention: &Retention, live: &[u64], now: u64) -> Vec<&'a Version> {
+    let excess = retention.versions.map_or(0, |limit| versions.len().saturating_sub(limit));
+
+    versions.iter()
+        .enumerate()
+        .filter(|(rank, version)| {
+            *rank < excess
+                || !live.contains(&version.position.segment)
+                || retention.age.is_some_and(|age| now.saturating_sub(version.updated) > age)
+        })
+        .map(|(_, version)| version)
+        .collect()
+}
+
+/// Encodes a history key so byte order matches ID then write order
+fn key(id: u64, version: &Version) -> [u8; LENGTH] {
+    let mut key = [0u8; LENGTH];
+    key[..8].copy_from_slice(&id.to_be_bytes());
+    key[8..16].copy_from_slice(&version.updated.to_be_bytes());
+    key[16..24].copy_from_slice(&version.position.segment.to_be_bytes());
+    key[24..].copy_from_slice(&version.position.offset.to_be_bytes());
+    key
+}
+
+/// Decodes the ID and timestamp of a history key
+fn split(key: &[u8]) -> (u64, u64) {
+    let id = u64::from_be_bytes(key[..8].try_into().unwrap());
+    let updated = u64::from_be_bytes(key[8..16].try_into().unwrap());
+    (id, updated)
+}
@@ -0,0 +1,54 @@
+//! Store event hooks
+//!
+//! Applications register callbacks that run synchronously with writes,
+//! to enforce invariants, maintain derived data or publish changes.
+//! Hooks on `Save` and `Delete` run before the write and refuse it by
+//! returning an error; hooks on `Saved` and `Deleted` run once it has
+//! landed, so their errors reach the caller but the write stands.
+
+use std::sync::Arc;
+use crate::Result;
+use crate::model::User;
+
+/// Moment in a write at which hooks run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Event {
+    /// Before a user is saved, with the user as given
+    Save,
+    /// After a user is saved, with the stored revision
+    Saved,
+    /// Before a stored user is deleted
+    Delete,
+    /// After a user is deleted or expired, with its last revision
+    Deleted,
+}
+
+/// Callback run on a user when its event fires
+pub type Hook = Arc<dyn Fn(&User) -> Result<()> + Send + Sync>;
+
+/// Hooks registered on a store, in registration order
+#[derive(Default)]
+pub struct Hooks {
+    /// Every hook with the event it listens to
+    hooks: Vec<(Event, Hook)>,
+}
+
+impl Hooks {
+    /// Registers a hook for an event
+    pub fn add(&mut self, event: Event, hook: Hook) {
+        self.hooks.push((event, hook));
+    }
+
+    /// Whether any hook listens to an event
+    pub fn has(&self, event: Event) -> bool {
+        self.hooks.iter().any(|(listened, _)| *listened == event)
+    }
+
+    /// Runs the hooks of an event in order, stopping at the first error
+    pub fn fire(&self, event: Event, user: &User) -> Result<()> {
+        for (_, hook) in self.hooks.iter().filter(|(listened, _)| *listened == event) {
+            hook(user)?;
+        }
+        Ok(())
+    }
+}
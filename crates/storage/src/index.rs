@@ -1,14 +1,51 @@
 //! Custom index management using binary format
-//! 
+//!
 //! Provides fast key-value lookups using custom binary layout
-//! without external dependencies.
+//! without external dependencies. Recent writes are appended to a log
+//! and mirrored in a bounded in-memory delta; once the delta outgrows
+//! its memory budget it is merged into a sorted on-disk table. Log
+//! records can be held in a write buffer and written in bursts; the
+//! log is replayed on open, so whatever reached it survives a crash.
+//!
+//! Tables never change once written and the delta is copied on write
+//! while a `Snapshot` shares it, so a snapshot reads the index exactly
+//! as it was when taken, without holding any lock.
+//!
+//! A hashed index stores a 128-bit hash in place of each key, so long
+//! keys cost 16 bytes and its tables are written as fixed-size entries.
+//! Hashes lose key order, and two keys may in principle share one; the
+//! records a hashed index points at must name their key, so `lookup`
+//! and `claim` can tell a collision from a match.
 
-use std::collections::HashMap;
-use std::path::Path;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::collections::btree_map::Range;
+use std::ops::Bound;
+use std::iter::Peekable;
+use std::path::{Path, PathBuf};
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write, Seek, SeekFrom};
-use crate::{Error, Result};
-use crate::model::Position;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::{directory, Error, Result};
+use crate::key::{Key, Space};
+use crate::model::{Field, Position};
+use crate::table::{Cursor, Table};
+
+/// Default memory budget for the in-memory delta (64MB)
+pub const BUDGET: usize = 64 * 1024 * 1024;
+
+/// Chunk of key-position pairs in key order
+pub type Page = Vec<(Vec<u8>, Position)>;
+
+/// Fixed per-entry overhead counted against the budget
+const OVERHEAD: usize = 64;
+
+/// Version byte of a log record holding a whole batch
+const GROUP: u8 = 3;
+
+/// Bytes of a hashed key
+const HASH: usize = 16;
 
 /// Binary entry structure for index
 #[derive(Debug, Clone)]
@@ -18,6 +55,8 @@ struct Entry {
     segment: u64,
     offset: u64,
     length: u64,
+    /// False for deletion markers
+    live: bool,
 }
 
 impl Entry {
@@ -28,239 +67,591 @@ impl Entry {
             segment: position.segment,
             offset: position.offset,
             length: position.length,
+            live: true,
+        }
+    }
+
+    fn tombstone(key: &[u8]) -> Self {
+        Self {
+            live: false,
+            ..Self::new(key, Position::default())
         }
     }
-    
+
     fn unpack(data: &[u8]) -> Result<Self> {
         if data.len() < 29 { // minimum size: 1 + 4 + 8 + 8 + 8
             return Err(Error::Format("Entry data too short".to_string()));
         }
-        
-        let version = data[0];
-        if version != 1 {
-            return Err(Error::Format("Unsupported entry version".to_string()));
-        }
-        
-        let key_len = u32::from_le_bytes(data[1..5].try_into().unwrap());
-        if data.len() < (5 + key_len + 24) as usize {
+
+        // Version 1 entries are always live; version 2 adds a flag byte
+        let (live, start) = match data[0] {
+            1 => (true, 1),
+            2 => (data[1] == 1, 2),
+            _ => return Err(Error::Format("Unsupported entry version".to_string())),
+        };
+
+        let key_len = u32::from_le_bytes(data[start..start + 4].try_into().unwrap());
+        if data.len() < start + 4 + key_len as usize + 24 {
             return Err(Error::Format("Entry data incomplete".to_string()));
         }
-        
-        let key_start = 5;
+
+        let key_start = start + 4;
         let key_end = key_start + key_len as usize;
         let key = data[key_start..key_end].to_vec();
-        
+
         let pos_start = key_end;
         let segment = u64::from_le_bytes(data[pos_start..pos_start+8].try_into().unwrap());
         let offset = u64::from_le_bytes(data[pos_start+8..pos_start+16].try_into().unwrap());
         let length = u64::from_le_bytes(data[pos_start+16..pos_start+24].try_into().unwrap());
-        
+
         Ok(Self {
             key_len,
             key,
             segment,
             offset,
             length,
+            live,
         })
     }
-    
+
     fn pack(&self) -> Vec<u8> {
         let mut data = Vec::new();
-        
+
         // Version
-        data.push(1);
-        
+        data.push(2);
+
+        // Liveness flag
+        data.push(self.live as u8);
+
         // Key length
         data.extend_from_slice(&self.key_len.to_le_bytes());
-        
+
         // Key data
         data.extend_from_slice(&self.key);
-        
+
         // Position data
         data.extend_from_slice(&self.segment.to_le_bytes());
         data.extend_from_slice(&self.offset.to_le_bytes());
         data.extend_from_slice(&self.length.to_le_bytes());
-        
+
         data
     }
+
+    fn position(&self) -> Option<Position> {
+        self.live.then_some(Position {
+            segment: self.segment,
+            offset: self.offset,
+            length: self.length,
+        })
+    }
+}
+
+/// Log records held back to be written together
+#[derive(Debug)]
+struct Pending {
+    /// Length-prefixed records in log order
+    data: Vec<u8>,
+    /// When the oldest held record was queued
+    since: Instant,
 }
 
 /// Manages index operations using custom binary format
 pub struct Index {
-    /// In-memory index cache
-    cache: HashMap<Vec<u8>, Position>,
-    /// Index file path
-    path: std::path::PathBuf,
-    /// File handle
-    file: Option<File>,
+    /// In-memory delta of recent writes (`None` marks a deletion)
+    cache: Arc<BTreeMap<Vec<u8>, Option<Position>>>,
+    /// Sorted on-disk table holding everything older than the delta
+    table: Option<Arc<Table>>,
+    /// Approximate bytes held by the delta
+    usage: usize,
+    /// Memory budget for the delta before it is merged into the table
+    budget: usize,
+    /// Index log path
+    path: PathBuf,
+    /// Append-only log handle
+    file: File,
+    /// Log records not yet written, see `buffer`
+    pending: Mutex<Pending>,
+    /// Buffered bytes at which the log is written (0 writes through)
+    threshold: usize,
+    /// Age of the oldest buffered record at which the log is written
+    interval: Duration,
+    /// Whether keys are stored as their 128-bit hash
+    hashed: bool,
 }
 
 impl Index {
-    /// Creates a new index manager
+    /// Creates a new index manager with the default memory budget
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::bounded(path, BUDGET)
+    }
+
+    /// Creates a new index manager whose delta is capped at `budget` bytes
+    pub fn bounded<P: AsRef<Path>>(path: P, budget: usize) -> Result<Self> {
+        // No generation is known, so the newest table on disk is taken
+        Self::pinned(path, budget, 0)
+    }
+
+    /// Creates a new index manager on top of a known table generation
+    ///
+    /// Generation 0 means no table exists yet. Used when the manifest
+    /// already records the live table, so tables of earlier generations
+    /// left behind are never loaded. A newer table wins over it: a merge
+    /// installs its table before emptying the log and dropping the old
+    /// one, so a crash before the manifest names it leaves the newer
+    /// table, and the log replayed on top of it, as the whole index.
+    pub fn pinned<P: AsRef<Path>>(path: P, budget: usize, generation: u64) -> Result<Self> {
+        let generation = generation.max(Self::latest(path.as_ref())?.unwrap_or(0));
         let path = path.as_ref().to_path_buf();
         std::fs::create_dir_all(path.parent().unwrap())?;
-        
+
+        let fresh = !path.exists();
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)?;
+        if fresh {
+            directory::parent(&path)?;
+        }
+
         let mut index = Self {
-            cache: HashMap::new(),
+            cache: Arc::default(),
+            table: None,
+            usage: 0,
+            budget,
             path,
-            file: None,
+            file,
+            pending: Mutex::new(Pending { data: Vec::new(), since: Instant::now() }),
+            threshold: 0,
+            interval: Duration::ZERO,
+            hashed: false,
         };
-        
+
         // Load existing index data
-        index.load()?;
-        
+        index.load(generation)?;
+
         Ok(index)
     }
-    
+
+    /// Holds log records back until `bytes` of them pile up or the oldest is `interval` old
+    ///
+    /// Fewer, larger log writes cut the cost of small writes. Held
+    /// records are visible to reads at once but reach the log only at
+    /// the next change past either limit, or on `flush`, `sync`, merge or
+    /// drop; a process crash loses them. Zero bytes, the default, writes
+    /// every record through.
+    pub fn buffer(mut self, bytes: usize, interval: Duration) -> Self {
+        self.threshold = bytes;
+        self.interval = interval;
+        self
+    }
+
+    /// Stores keys as their 128-bit hash, shrinking entries to a fixed size
+    ///
+    /// Must be set on every open of a hashed index before it is used;
+    /// an index whose table is hashed turns it on by itself. Scans then
+    /// yield hashes in hash order, so namespaces and range queries do
+    /// not apply. Fails with `Error::Config` on an index already holding
+    /// keys of another width.
+    pub fn hashed(mut self) -> Result<Self> {
+        let table = self.table.as_ref().is_some_and(|table| table.count() > 0 && table.width() != Some(HASH));
+        if table || self.cache.keys().any(|key| key.len() != HASH) {
+            return Err(Error::Config(format!("Index {} holds unhashed keys", self.path.display())));
+        }
+        self.hashed = true;
+        Ok(self)
+    }
+
     /// Stores a key-position mapping
     pub fn put(&mut self, key: &[u8], position: Position) -> Result<()> {
-        let mut file = self.open()?;
-        
-        // Create entry
-        let entry = Entry::new(key, position);
-        let entry_data = entry.pack();
-        
-        // Write entry length and data
-        file.write_all(&(entry_data.len() as u32).to_le_bytes())?;
-        file.write_all(&entry_data)?;
-        file.flush()?;
-        
-        // Update cache
-        self.cache.insert(key.to_vec(), position);
-        
-        Ok(())
+        let key = self.hash(key);
+        self.append(&Entry::new(&key, position))?;
+        self.remember(key.into_owned(), Some(position));
+        self.spill()
     }
-    
+
     /// Retrieves a position for a given key
+    ///
+    /// In a hashed index this is the position of whichever key hashes
+    /// alike; `lookup` checks it is this key's.
     pub fn get(&self, key: &[u8]) -> Result<Option<Position>> {
-        // Check cache first
-        if let Some(position) = self.cache.get(key) {
-            return Ok(Some(*position));
+        self.view().get(key)
+    }
+
+    /// Retrieves a key's position, resolving hash collisions through `owns`
+    ///
+    /// `owns` tells whether the record at a position belongs to `key`,
+    /// typically by reading it from its segment. It is only consulted
+    /// by a hashed index; a position held by another key reads as absent.
+    pub fn lookup(&self, key: &[u8], owns: impl Fn(Position) -> Result<bool>) -> Result<Option<Position>> {
+        match self.get(key)? {
+            Some(position) if self.hashed && !owns(position)? => Ok(None),
+            found => Ok(found),
         }
-        
-        // Search in file
-        if let Some(file) = &self.file {
-            let mut file = file.try_clone()?;
-            file.seek(SeekFrom::Start(0))?;
-            
-            while let Ok(entry_len) = self.read_u32(&mut file) {
-                let mut entry_data = vec![0u8; entry_len as usize];
-                file.read_exact(&mut entry_data)?;
-                
-                // Parse entry
-                let entry = Entry::unpack(&entry_data)?;
-                
-                if entry.key == key {
-                    let position = Position {
-                        segment: entry.segment,
-                        offset: entry.offset,
-                        length: entry.length,
-                    };
-                    return Ok(Some(position));
+    }
+
+    /// Stores a key's position unless its hash is held by another key
+    ///
+    /// `owns` is as for `lookup`. A collision fails with
+    /// `Error::Collision` and leaves the index unchanged, rather than
+    /// letting one key silently replace the other.
+    pub fn claim(&mut self, key: &[u8], position: Position, owns: impl Fn(Position) -> Result<bool>) -> Result<()> {
+        if self.hashed {
+            if let Some(held) = self.get(key)? {
+                if !owns(held)? {
+                    return Err(Error::Collision { key: key.to_vec(), position: held });
                 }
             }
         }
-        
-        Ok(None)
+        self.put(key, position)
     }
-    
+
     /// Removes a key-position mapping
     pub fn delete(&mut self, key: &[u8]) -> Result<()> {
-        // Remove from cache
-        self.cache.remove(key);
-        
-        // TODO: Implement file-based deletion
-        // This would require rewriting the index file without the deleted entry
-        
-        Ok(())
+        let key = self.hash(key);
+        self.append(&Entry::tombstone(&key))?;
+        self.remember(key.into_owned(), None);
+        self.spill()
     }
-    
-    /// Performs batch operations for better performance
+
+    /// Applies operations atomically
+    ///
+    /// The whole batch is logged as one record, so after a crash replay
+    /// sees either all of it or none of it.
     pub fn batch(&mut self, operations: Vec<Operation>) -> Result<()> {
-        let mut file = self.open()?;
-        
-        for op in operations {
-            match op {
-                Operation::Put { key, position } => {
-                    let entry = Entry::new(&key, position);
-                    let entry_data = entry.pack();
-                    file.write_all(&(entry_data.len() as u32).to_le_bytes())?;
-                    file.write_all(&entry_data)?;
-                }
-                Operation::Delete { key } => {
-                    // TODO: Implement batch deletion
-                    self.cache.remove(&key);
-                }
-            }
+        let entries: Vec<Entry> = operations.into_iter()
+            .map(|op| match op {
+                Operation::Put { key, position } => Entry::new(&self.hash(&key), position),
+                Operation::Delete { key } => Entry::tombstone(&self.hash(&key)),
+            })
+            .collect();
+        self.group(entries)
+    }
+
+    /// Removes every entry at once, as one batch
+    pub fn clear(&mut self) -> Result<()> {
+        // Keys come back as stored, hashed or not, so they are not hashed again
+        let entries = self.scan()
+            .map(|result| result.map(|(key, _)| Entry::tombstone(&key)))
+            .collect::<Result<Vec<_>>>()?;
+        self.group(entries)
+    }
+
+    /// Logs entries as one record and applies them
+    fn group(&mut self, entries: Vec<Entry>) -> Result<()> {
+        let mut data = vec![GROUP];
+        data.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+        for entry in &entries {
+            let packed = entry.pack();
+            data.extend_from_slice(&(packed.len() as u32).to_le_bytes());
+            data.extend_from_slice(&packed);
         }
-        
-        file.flush()?;
-        Ok(())
+        self.write(&data)?;
+
+        for entry in entries {
+            let slot = entry.position();
+            self.remember(entry.key, slot);
+        }
+        self.spill()
     }
-    
-    /// Iterates over all key-position pairs
+
+    /// Iterates over all key-position pairs in key order
+    ///
+    /// A hashed index yields the hashes, in hash order.
     pub fn scan(&self) -> impl Iterator<Item = Result<(Vec<u8>, Position)>> + '_ {
-        let cache = &self.cache;
-        cache.iter().map(|(key, position)| {
-            Ok((key.clone(), *position))
-        })
+        self.view().scan()
     }
-    
-    /// Ensures the index file is open and ready for writing
-    fn open(&self) -> Result<File> {
-        if let Some(file) = &self.file {
-            Ok(file.try_clone()?)
-        } else {
-            let file = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .append(true)
-                .open(&self.path)?;
-            Ok(file)
+
+    /// Iterates over key-position pairs strictly after `key`, in key order
+    pub fn after<'a>(&'a self, key: &'a [u8]) -> impl Iterator<Item = Result<(Vec<u8>, Position)>> + 'a {
+        self.view().after(key)
+    }
+
+    /// Views the namespace of one key type, keeping other types' keys apart
+    pub fn space<K: Key>(&mut self) -> Space<'_, K> {
+        Space::new(self)
+    }
+
+    /// Collects up to `limit` entries following `from` (or from the start)
+    ///
+    /// Lets callers walk the index in bounded chunks without holding a
+    /// borrow across calls.
+    pub fn page(&self, from: Option<&[u8]>, limit: usize) -> Result<Page> {
+        self.view().page(from, limit)
+    }
+
+    /// Captures the index as it is now, for reads unaffected by later writes
+    ///
+    /// Cheap to take: the table and delta are shared, and the first
+    /// write after it copies the delta.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cache: Arc::clone(&self.cache),
+            table: self.table.clone(),
+            hashed: self.hashed,
         }
     }
-    
-    /// Loads existing index data into memory
-    fn load(&mut self) -> Result<()> {
-        if !self.path.exists() {
+
+    /// Merges the in-memory delta into a new on-disk table
+    pub fn merge(&mut self) -> Result<()> {
+        let generation = self.table.as_ref().map_or(1, |table| table.generation() + 1);
+        let target = Self::locate(&self.path, generation);
+        let table = Table::write(&target, generation, self.hashed.then_some(HASH), self.scan())?;
+
+        // The new table is durable; the log and old table can go, the
+        // latter staying readable to snapshots through their open handle
+        if let Some(old) = self.table.replace(Arc::new(table)) {
+            std::fs::remove_file(old.path())?;
+        }
+        // Buffered records are in the new table too
+        self.pending.get_mut().unwrap().data.clear();
+        self.file.set_len(0)?;
+        self.file.sync_all()?;
+        self.cache = Arc::default();
+        self.usage = 0;
+
+        Ok(())
+    }
+
+    /// Writes buffered records to the log
+    pub fn flush(&self) -> Result<()> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.data.is_empty() {
             return Ok(());
         }
-        
-        let mut file = OpenOptions::new()
-            .read(true)
-            .open(&self.path)?;
-        
+        // A failed write leaves at most a torn tail, which replay drops
+        let written = (&self.file).write_all(&pending.data);
+        pending.data.clear();
+        written?;
+        Ok(())
+    }
+
+    /// Writes buffered records and flushes the log to disk
+    pub fn sync(&self) -> Result<()> {
+        self.flush()?;
+        self.file.sync_all()?;
+        Ok(())
+    }
+
+    /// Generation of the current on-disk table (0 when none exists)
+    pub fn generation(&self) -> u64 {
+        self.table.as_deref().map_or(0, Table::generation)
+    }
+
+    /// Approximate bytes of memory held by the index
+    pub fn memory(&self) -> usize {
+        self.usage + self.table.as_deref().map_or(0, Table::memory)
+    }
+
+    /// Bytes of log records held in the write buffer
+    pub fn buffered(&self) -> usize {
+        self.pending.lock().unwrap().data.len()
+    }
+
+    /// Merges the delta into the table when it holds more than `floor` bytes
+    ///
+    /// Lets callers free memory early without rewriting the table for
+    /// a handful of entries.
+    pub fn shrink(&mut self, floor: usize) -> Result<()> {
+        if self.usage > floor {
+            self.merge()?;
+        }
+        Ok(())
+    }
+
+    /// Bytes of log not yet merged into a table, buffered records included
+    pub fn backlog(&self) -> Result<u64> {
+        let pending = self.pending.lock().unwrap().data.len() as u64;
+        Ok(self.file.metadata()?.len() + pending)
+    }
+
+    /// Memory budget for the in-memory delta
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Bytes of the index on disk: its table, log and buffered records
+    pub fn size(&self) -> Result<u64> {
+        let table = match self.table.as_deref() {
+            Some(table) => std::fs::metadata(table.path())?.len(),
+            None => 0,
+        };
+        Ok(table + self.backlog()?)
+    }
+
+    /// Counts the entries and measures the index, under `name`
+    ///
+    /// Every key is visited, so this costs a full index scan.
+    pub fn shape(&self, name: &str) -> Result<Shape> {
+        let mut entries = 0;
+        for result in self.scan() {
+            result?;
+            entries += 1;
+        }
+        Ok(Shape {
+            name: name.to_string(),
+            fields: Vec::new(),
+            entries,
+            distinct: Vec::new(),
+            bytes: self.size()?,
+            memory: self.memory(),
+        })
+    }
+
+    /// The bytes stored for a key: its hash in a hashed index, else the key
+    fn hash<'k>(&self, key: &'k [u8]) -> Cow<'k, [u8]> {
+        self.view().hash(key)
+    }
+
+    /// Borrows the delta and table for reading
+    fn view(&self) -> View<'_> {
+        View { cache: &self.cache, table: self.table.as_deref(), hashed: self.hashed }
+    }
+
+    /// Writes one entry to the log
+    fn append(&mut self, entry: &Entry) -> Result<()> {
+        self.write(&entry.pack())
+    }
+
+    /// Queues one length-prefixed record, writing the buffer once full or stale
+    ///
+    /// The buffer goes out in a single call; replay keeps every record
+    /// ahead of one a crash tore.
+    fn write(&mut self, data: &[u8]) -> Result<()> {
+        let pending = self.pending.get_mut().unwrap();
+        if pending.data.is_empty() {
+            pending.since = Instant::now();
+        }
+        pending.data.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        pending.data.extend_from_slice(data);
+        if pending.data.len() >= self.threshold || pending.since.elapsed() >= self.interval {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Records a change in the delta and tracks its memory cost
+    fn remember(&mut self, key: Vec<u8>, slot: Option<Position>) {
+        let cost = key.len() + OVERHEAD;
+        if Arc::make_mut(&mut self.cache).insert(key, slot).is_none() {
+            self.usage += cost;
+        }
+    }
+
+    /// Merges into the table once the delta exceeds its budget
+    fn spill(&mut self) -> Result<()> {
+        if self.usage > self.budget {
+            self.merge()?;
+        }
+        Ok(())
+    }
+
+    /// Path of the table file for a given generation
+    fn locate(path: &Path, generation: u64) -> PathBuf {
+        path.with_extension(format!("{}.table", generation))
+    }
+
+    /// Finds the newest table generation next to the log
+    fn latest(path: &Path) -> Result<Option<u64>> {
+        let parent = path.parent().unwrap();
+        if !parent.exists() {
+            return Ok(None);
+        }
+        let stem = path.file_name().unwrap().to_string_lossy();
+        let mut newest = None;
+
+        for entry in std::fs::read_dir(parent)? {
+            let name = entry?.file_name();
+            let name = name.to_string_lossy();
+            let generation = name.strip_prefix(stem.as_ref())
+                .and_then(|rest| rest.strip_prefix('.'))
+                .and_then(|rest| rest.strip_suffix(".table"))
+                .and_then(|id| id.parse::<u64>().ok());
+
+            if let Some(generation) = generation {
+                newest = newest.max(Some(generation));
+            }
+        }
+
+        Ok(newest)
+    }
+
+    /// Loads the given table generation and replays the log into memory
+    #[tracing::instrument(level = "info", skip(self), fields(path = %self.path.display(), entries, bytes))]
+    fn load(&mut self, generation: u64) -> Result<()> {
+        if generation > 0 {
+            let table = Table::open(Self::locate(&self.path, generation))?;
+            self.hashed = table.width() == Some(HASH);
+            self.table = Some(Arc::new(table));
+        }
+
+        let mut file = self.file.try_clone()?;
         file.seek(SeekFrom::Start(0))?;
-        
-        while let Ok(entry_len) = self.read_u32(&mut file) {
+
+        // A record cut short by a crash ends the log
+        let mut valid = 0;
+        let mut replayed = 0u64;
+        while let Ok(entry_len) = Self::read_u32(&mut file) {
             let mut entry_data = vec![0u8; entry_len as usize];
-            file.read_exact(&mut entry_data)?;
-            
-            let entry = Entry::unpack(&entry_data)?;
-            let position = Position {
-                segment: entry.segment,
-                offset: entry.offset,
-                length: entry.length,
+            if file.read_exact(&mut entry_data).is_err() {
+                break;
+            }
+
+            let entries = match entry_data.first() {
+                Some(&GROUP) => Self::ungroup(&entry_data)?,
+                _ => vec![Entry::unpack(&entry_data)?],
             };
-            
-            self.cache.insert(entry.key, position);
+            for entry in entries {
+                let slot = entry.position();
+                self.remember(entry.key, slot);
+                replayed += 1;
+            }
+            valid += 4 + entry_len as u64;
         }
-        
-        // Keep file open for future operations
-        self.file = Some(file);
-        
-        Ok(())
+        let span = tracing::Span::current();
+        span.record("entries", replayed);
+        span.record("bytes", valid);
+
+        // Drop the torn tail so new records follow the last whole one
+        if self.file.metadata()?.len() > valid {
+            self.file.set_len(valid)?;
+            self.file.sync_all()?;
+        }
+
+        self.spill()
     }
-    
+
+    /// Splits a batch record into its entries
+    fn ungroup(data: &[u8]) -> Result<Vec<Entry>> {
+        let short = || Error::Format("Batch record incomplete".to_string());
+        let count = u32::from_le_bytes(data.get(1..5).ok_or_else(short)?.try_into().unwrap());
+
+        let mut entries = Vec::with_capacity(count as usize);
+        let mut start = 5;
+        for _ in 0..count {
+            let length = u32::from_le_bytes(data.get(start..start + 4).ok_or_else(short)?.try_into().unwrap()) as usize;
+            let packed = data.get(start + 4..start + 4 + length).ok_or_else(short)?;
+            entries.push(Entry::unpack(packed)?);
+            start += 4 + length;
+        }
+        Ok(entries)
+    }
+
     /// Reads a u32 from file
-    fn read_u32(&self, file: &mut File) -> Result<u32> {
+    fn read_u32(file: &mut File) -> Result<u32> {
         let mut buf = [0u8; 4];
         file.read_exact(&mut buf)?;
         Ok(u32::from_le_bytes(buf))
     }
 }
 
+impl Drop for Index {
+    fn drop(&mut self) {
+        // Nowhere to report a failure; replay recovers what did land
+        let _ = self.flush();
+    }
+}
+
 /// Index operation types
 pub enum Operation {
     /// Put operation
@@ -277,8 +668,174 @@ pub enum Operation {
     },
 }
 
-impl Drop for Index {
-    fn drop(&mut self) {
-        // File will be closed automatically
+/// Size and cardinality of one index, see `Store::indexes`
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Shape {
+    /// Name of the index
+    pub name: String,
+    /// Fields the keys hold in order, for catalog indexes
+    pub fields: Vec<Field>,
+    /// Entries held
+    pub entries: u64,
+    /// Distinct values of each leading run of `fields`: the first
+    /// field alone, then the first two, and so on
+    pub distinct: Vec<u64>,
+    /// Bytes on disk, shared by catalog indexes kept in one file
+    pub bytes: u64,
+    /// Bytes of memory held, shared alike
+    pub memory: usize,
+}
+
+impl Shape {
+    /// Entries expected per value of the first `depth` fields
+    ///
+    /// `None` when the index does not lead with that many fields.
+    pub fn spread(&self, depth: usize) -> Option<u64> {
+        let distinct = *self.distinct.get(depth.checked_sub(1)?)?;
+        Some(self.entries.div_ceil(distinct.max(1)))
     }
-} 
\ No newline at end of file
+}
+
+/// Index contents frozen by `Index::snapshot`
+///
+/// Reads see no write made after the snapshot was taken, so a scan
+/// through one never yields a key twice or skips one that was present
+/// throughout. Positions may name records compaction has since moved;
+/// their segment files stay until garbage collection.
+#[derive(Clone)]
+pub struct Snapshot {
+    /// Delta as it was, shared with the index until its next write
+    cache: Arc<BTreeMap<Vec<u8>, Option<Position>>>,
+    /// Table as it was, readable even once merged away
+    table: Option<Arc<Table>>,
+    /// Whether keys are stored as their hash
+    hashed: bool,
+}
+
+impl Snapshot {
+    /// Retrieves the position a key had, as `Index::get`
+    pub fn get(&self, key: &[u8]) -> Result<Option<Position>> {
+        self.view().get(key)
+    }
+
+    /// Iterates over all key-position pairs in key order, as `Index::scan`
+    pub fn scan(&self) -> impl Iterator<Item = Result<(Vec<u8>, Position)>> + '_ {
+        self.view().scan()
+    }
+
+    /// Iterates over key-position pairs strictly after `key`, as `Index::after`
+    pub fn after<'a>(&'a self, key: &'a [u8]) -> impl Iterator<Item = Result<(Vec<u8>, Position)>> + 'a {
+        self.view().after(key)
+    }
+
+    /// Collects up to `limit` entries following `from`, as `Index::page`
+    pub fn page(&self, from: Option<&[u8]>, limit: usize) -> Result<Page> {
+        self.view().page(from, limit)
+    }
+
+    /// Borrows the frozen delta and table for reading
+    fn view(&self) -> View<'_> {
+        View { cache: &self.cache, table: self.table.as_deref(), hashed: self.hashed }
+    }
+}
+
+/// Borrowed delta and table, the read path shared by indexes and snapshots
+#[derive(Clone, Copy)]
+struct View<'a> {
+    /// Delta of recent writes
+    cache: &'a BTreeMap<Vec<u8>, Option<Position>>,
+    /// Table beneath the delta
+    table: Option<&'a Table>,
+    /// Whether keys are stored as their hash
+    hashed: bool,
+}
+
+impl<'a> View<'a> {
+    /// The bytes stored for a key: its hash when hashed, else the key
+    fn hash<'k>(&self, key: &'k [u8]) -> Cow<'k, [u8]> {
+        if self.hashed {
+            Cow::Owned(twox_hash::XxHash3_128::oneshot(key).to_be_bytes().to_vec())
+        } else {
+            Cow::Borrowed(key)
+        }
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Position>> {
+        let key = self.hash(key);
+        // Check the delta first; a tombstone hides older table entries
+        if let Some(slot) = self.cache.get(key.as_ref()) {
+            return Ok(*slot);
+        }
+
+        match self.table {
+            Some(table) => table.get(&key),
+            None => Ok(None),
+        }
+    }
+
+    fn scan(self) -> Merge<'a> {
+        Merge {
+            cache: self.cache.range::<[u8], _>(..).peekable(),
+            table: self.table.map(|table| table.iter().peekable()),
+        }
+    }
+
+    fn after(self, key: &'a [u8]) -> impl Iterator<Item = Result<(Vec<u8>, Position)>> + 'a {
+        let merge = Merge {
+            cache: self.cache.range::<[u8], _>((Bound::Excluded(key), Bound::Unbounded)).peekable(),
+            table: self.table.map(|table| table.seek(key).peekable()),
+        };
+
+        merge.skip_while(move |result| matches!(result, Ok((found, _)) if found.as_slice() <= key))
+    }
+
+    fn page(self, from: Option<&[u8]>, limit: usize) -> Result<Page> {
+        match from {
+            Some(key) => self.after(key).take(limit).collect(),
+            None => self.scan().take(limit).collect(),
+        }
+    }
+}
+
+/// Ordered merge of the in-memory delta over the on-disk table
+struct Merge<'a> {
+    cache: Peekable<Range<'a, Vec<u8>, Option<Position>>>,
+    table: Option<Peekable<Cursor<'a>>>,
+}
+
+impl Iterator for Merge<'_> {
+    type Item = Result<(Vec<u8>, Position)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let disk = match self.table.as_mut().and_then(|table| table.peek()) {
+                Some(Err(_)) => return self.table.as_mut().and_then(|table| table.next()),
+                Some(Ok((key, _))) => Some(key.clone()),
+                None => None,
+            };
+
+            let memory = self.cache.peek().map(|(key, _)| (*key).clone());
+
+            match (memory, disk) {
+                (None, None) => return None,
+                (Some(memory), Some(disk)) if memory > disk => {
+                    return self.table.as_mut().and_then(|table| table.next());
+                }
+                (None, Some(_)) => {
+                    return self.table.as_mut().and_then(|table| table.next());
+                }
+                (Some(memory), disk) => {
+                    // The delta shadows the table for equal keys
+                    if disk.as_ref() == Some(&memory) {
+                        self.table.as_mut().and_then(|table| table.next());
+                    }
+
+                    let (key, slot) = self.cache.next().unwrap();
+                    if let Some(position) = slot {
+                        return Some(Ok((key.clone(), *position)));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Kafka change-stream sink
+//!
+//! Publishes each change to a topic keyed by user ID, so one record's
+//! changes stay ordered within a partition. The producer is idempotent
+//! and waits for every in-sync replica, and a batch only counts as sent
+//! once each message has been acknowledged.
+
+use rdkafka::ClientConfig;
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use tokio::runtime::Runtime;
+use crate::{Error, Result};
+use crate::audit::Entry;
+use crate::model::Position;
+use crate::publish::{message, Sink};
+
+/// Sink producing changes to a Kafka topic
+pub struct Kafka {
+    /// Producer connected to the cluster
+    producer: FutureProducer,
+    /// Topic changes are produced to
+    topic: String,
+    /// Drives delivery reports while a batch is awaited
+    runtime: Runtime,
+}
+
+impl Kafka {
+    /// Connects to a cluster given as comma-separated `host:port` brokers
+    pub fn new(brokers: &str, topic: impl Into<String>) -> Result<Self> {
+        let producer = ClientConfig::new()
+            .set("bootstrap.servers", brokers)
+            .set("enable.idempotence", "true")
+            .set("acks", "all")
+            .create()
+            .map_err(failed)?;
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        Ok(Self { producer, topic: topic.into(), runtime })
+    }
+}
+
+impl Sink for Kafka {
+    fn send(&mut self, changes: &[(Position, Entry)]) -> Result<()> {
+        let mut deliveries = Vec::with_capacity(changes.len());
+        for (_, entry) in changes {
+            let payload = message(entry)?;
+            let key = entry.id.to_be_bytes();
+            let record = FutureRecord::to(&self.topic).key(&key[..]).payload(&payload);
+            // The client copies the message, so only its report is awaited
+            deliveries.push(self.producer.send_result(record).map_err(|(error, _)| failed(error))?);
+        }
+        self.runtime.block_on(async {
+            for delivery in deliveries {
+                delivery.await
+                    .map_err(|_| Error::Publish("Kafka: producer shut down".to_string()))?
+                    .map_err(|(error, _)| failed(error))?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Wraps a client error
+fn failed(error: rdkafka::error::KafkaError) -> Error {
+    Error::Publish(format!("Kafka: {}", error))
+}
@@ -0,0 +1,107 @@
+//! Typed index keys
+//!
+//! Every `Key` type owns a namespace named by a one-byte tag leading
+//! its encoded keys, so keys of different types never share bytes even
+//! when their values are equal: user 7 and order 7 land apart. A
+//! `Space` only takes and yields keys of its one type, which turns
+//! mixing types in an index into a compile error instead of a silent
+//! collision.
+//!
+//! The primary user index predates namespaces and keeps its bare
+//! little-endian IDs; namespaces are for indexes shared by several
+//! record types.
+
+use std::marker::PhantomData;
+use crate::{Error, Result};
+use crate::index::Index;
+use crate::model::Position;
+
+/// Value usable as a typed index key
+pub trait Key: Sized {
+    /// Namespace tag leading every encoded key of this type, unique per type
+    const TAG: u8;
+
+    /// Appends the key's bytes, which must sort like the keys
+    fn encode(&self, bytes: &mut Vec<u8>);
+
+    /// Decodes bytes written by `encode`
+    fn decode(bytes: &[u8]) -> Result<Self>;
+}
+
+/// User identifier in a shared index
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UserId(pub u64);
+
+impl Key for UserId {
+    const TAG: u8 = 1;
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.0.to_be_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        let id = <[u8; 8]>::try_from(bytes).map_err(|_| Error::Key {
+            key: bytes.to_vec(),
+            reason: format!("expected 8 bytes, found {}", bytes.len()),
+        })?;
+        Ok(Self(u64::from_be_bytes(id)))
+    }
+}
+
+/// Encodes a key behind its namespace tag
+pub fn encode<K: Key>(key: &K) -> Vec<u8> {
+    let mut bytes = vec![K::TAG];
+    key.encode(&mut bytes);
+    bytes
+}
+
+/// Decodes a tagged key, failing with `Error::Key` for another namespace
+pub fn decode<K: Key>(bytes: &[u8]) -> Result<K> {
+    match bytes.split_first() {
+        Some((&tag, rest)) if tag == K::TAG => K::decode(rest),
+        _ => Err(Error::Key {
+            key: bytes.to_vec(),
+            reason: format!("not in namespace {}", K::TAG),
+        }),
+    }
+}
+
+/// One key type's view of an index, see `Index::space`
+pub struct Space<'a, K> {
+    /// Index holding the namespace
+    index: &'a mut Index,
+    /// Tag leading the namespace's keys
+    tag: [u8; 1],
+    /// Key type of the namespace
+    marker: PhantomData<K>,
+}
+
+impl<'a, K: Key> Space<'a, K> {
+    /// Views the namespace of `K` in an index
+    pub fn new(index: &'a mut Index) -> Self {
+        Self { index, tag: [K::TAG], marker: PhantomData }
+    }
+
+    /// Stores a key-position mapping
+    pub fn put(&mut self, key: &K, position: Position) -> Result<()> {
+        self.index.put(&encode(key), position)
+    }
+
+    /// Retrieves the position stored for a key
+    pub fn get(&self, key: &K) -> Result<Option<Position>> {
+        self.index.get(&encode(key))
+    }
+
+    /// Removes a key-position mapping
+    pub fn delete(&mut self, key: &K) -> Result<()> {
+        self.index.delete(&encode(key))
+    }
+
+    /// Iterates over the namespace's keys and positions in key order
+    pub fn scan(&self) -> impl Iterator<Item = Result<(K, Position)>> + '_ {
+        let tag = self.tag[0];
+        self.index.after(&self.tag)
+            .take_while(move |result| !matches!(result, Ok((key, _)) if key.first() != Some(&tag)))
+            .map(|result| result.and_then(|(key, position)| Ok((decode(&key)?, position))))
+    }
+}
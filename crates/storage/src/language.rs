@@ -0,0 +1,187 @@
+//! Query language
+//!
+//! A statement reads
+//!
+//! ```text
+//! SELECT name, email WHERE email ~ "@example.com" AND country = VN LIMIT 50
+//! ```
+//!
+//! `SELECT` names the fields to return, or `*` for whole users. `WHERE`
+//! joins filters with `AND`, each a field, `=` or `~` and a value, with
+//! the meaning `Filter` gives them. Values holding spaces or any of
+//! `,=~*` are double-quoted, escaping `"` and `\` with a backslash.
+//! `LIMIT` caps how many users come back. Keywords ignore case; field
+//! names do not. A statement compiles to the filters `Store::filter`
+//! runs and the fields `Projection::pick` keeps, so anything taking
+//! text from users can offer the same queries.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::FromStr;
+use std::vec::IntoIter;
+use crate::{Error, Result};
+use crate::filter::{Filter, Test};
+use crate::model::Field;
+
+/// A parsed query
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Statement {
+    /// Fields returned, whole users when empty
+    pub fields: Vec<Field>,
+    /// Filters every user returned passes
+    pub filters: Vec<Filter>,
+    /// Most users returned, unlimited when `None`
+    pub limit: Option<usize>,
+}
+
+/// A lexical unit of a statement
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// Bare word: a keyword, a field, a number or an unquoted value
+    Word(String),
+    /// Double-quoted value, unescaped
+    Quoted(String),
+    /// One of `,`, `=`, `~` and `*`
+    Symbol(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Word(word) => write!(formatter, "{}", word),
+            Self::Quoted(text) => write!(formatter, "{:?}", text),
+            Self::Symbol(symbol) => write!(formatter, "{}", symbol),
+        }
+    }
+}
+
+/// Tokens still to parse
+type Tokens = Peekable<IntoIter<Token>>;
+
+/// Characters that stand alone as tokens
+const SYMBOLS: [char; 4] = [',', '=', '~', '*'];
+
+impl FromStr for Statement {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let mut tokens = tokens(text)?.into_iter().peekable();
+        keyword(&mut tokens, "SELECT")?;
+
+        let mut fields = Vec::new();
+        if tokens.next_if_eq(&Token::Symbol('*')).is_none() {
+            loop {
+                fields.push(field(&mut tokens)?);
+                if tokens.next_if_eq(&Token::Symbol(',')).is_none() {
+                    break;
+                }
+            }
+        }
+
+        let mut filters = Vec::new();
+        if optional(&mut tokens, "WHERE") {
+            loop {
+                let field = field(&mut tokens)?;
+                let test = match tokens.next() {
+                    Some(Token::Symbol('=')) => Test::Equals,
+                    Some(Token::Symbol('~')) => Test::Contains,
+                    token => return Err(unexpected(token, "= or ~")),
+                };
+                let value = match tokens.next() {
+                    Some(Token::Word(value) | Token::Quoted(value)) => value,
+                    token => return Err(unexpected(token, "a value")),
+                };
+                filters.push(Filter::new(field, test, value)?);
+                if !optional(&mut tokens, "AND") {
+                    break;
+                }
+            }
+        }
+
+        let mut limit = None;
+        if optional(&mut tokens, "LIMIT") {
+            let number = match tokens.next() {
+                Some(Token::Word(number)) => number,
+                token => return Err(unexpected(token, "a number")),
+            };
+            limit = Some(number.parse().map_err(|_| invalid(format!("{:?} is not a limit", number)))?);
+        }
+
+        match tokens.next() {
+            None => Ok(Self { fields, filters, limit }),
+            token => Err(unexpected(token, "the end of the query")),
+        }
+    }
+}
+
+/// Splits a statement into tokens
+fn tokens(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut characters = text.chars().peekable();
+    while let Some(&next) = characters.peek() {
+        if next.is_whitespace() {
+            characters.next();
+        } else if SYMBOLS.contains(&next) {
+            characters.next();
+            tokens.push(Token::Symbol(next));
+        } else if next == '"' {
+            characters.next();
+            let mut quoted = String::new();
+            loop {
+                match characters.next() {
+                    Some('"') => break,
+                    Some('\\') => match characters.next() {
+                        Some(escaped @ ('"' | '\\')) => quoted.push(escaped),
+                        _ => return Err(invalid("only \\\" and \\\\ are escapes".to_string())),
+                    },
+                    Some(character) => quoted.push(character),
+                    None => return Err(invalid("a quoted value is not closed".to_string())),
+                }
+            }
+            tokens.push(Token::Quoted(quoted));
+        } else {
+            let mut word = String::new();
+            while let Some(character) = characters.next_if(|&c| !c.is_whitespace() && c != '"' && !SYMBOLS.contains(&c)) {
+                word.push(character);
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Takes a keyword the statement must have next
+fn keyword(tokens: &mut Tokens, name: &str) -> Result<()> {
+    if optional(tokens, name) {
+        Ok(())
+    } else {
+        Err(unexpected(tokens.next(), name))
+    }
+}
+
+/// Takes a keyword if it comes next
+fn optional(tokens: &mut Tokens, name: &str) -> bool {
+    tokens.next_if(|token| matches!(token, Token::Word(word) if word.eq_ignore_ascii_case(name))).is_some()
+}
+
+/// Takes a field name
+fn field(tokens: &mut Tokens) -> Result<Field> {
+    match tokens.next() {
+        Some(Token::Word(name)) => Field::parse(&name)
+            .ok_or_else(|| invalid(format!("{:?} is not a field", name))),
+        token => Err(unexpected(token, "a field")),
+    }
+}
+
+/// Error for a token other than the one expected
+fn unexpected(token: Option<Token>, expected: &str) -> Error {
+    match token {
+        Some(token) => invalid(format!("expected {}, found {}", expected, token)),
+        None => invalid(format!("expected {}, found the end of the query", expected)),
+    }
+}
+
+/// Error for a statement that does not parse
+fn invalid(reason: String) -> Error {
+    Error::Invalid { field: "query".to_string(), reason }
+}
@@ -0,0 +1,122 @@
+//! Record layouts of earlier schema versions
+//!
+//! Archived records are not self-describing, so every layout a store
+//! may still hold on disk is kept here verbatim together with its
+//! conversion into the current model.
+
+use rkyv::{Archive, Serialize, Deserialize};
+use guardian_macros::Record;
+use crate::{Error, Result};
+use crate::model::{self, Position};
+use crate::segment::Segment;
+
+/// Reads a record written under an older schema as a current `User`
+///
+/// Upgraded records start at revision 1.
+pub fn read(segment: &Segment, position: Position, schema: u32) -> Result<model::User> {
+    match schema {
+        1 => Ok(segment.former::<first::User>(position)?.into()),
+        2 => Ok(segment.former::<second::User>(position)?.into()),
+        _ => Err(Error::Unsupported(format!("Record schema {}", schema))),
+    }
+}
+
+/// Schema 1: locations without coordinates
+pub mod first {
+    use super::*;
+
+    /// Location as written by schema 1
+    #[derive(Archive, Serialize, Deserialize, Debug, Clone, Record)]
+    #[archive(check_bytes)]
+    pub struct Location {
+        /// Street address
+        pub street: String,
+        /// City name
+        pub city: String,
+        /// Country code
+        pub country: String,
+        /// Postal code
+        pub postal: String,
+    }
+
+    /// User as written by schema 1
+    #[derive(Archive, Serialize, Deserialize, Debug, Clone, Record)]
+    #[archive(check_bytes)]
+    pub struct User {
+        /// Unique user identifier
+        pub id: u64,
+        /// User's display name
+        pub name: String,
+        /// User's email address
+        pub email: String,
+        /// User's geographical location
+        pub location: Location,
+        /// User's profile information
+        pub profile: Option<model::Profile>,
+        /// Account creation timestamp
+        pub created: u64,
+        /// Last update timestamp
+        pub updated: u64,
+    }
+
+    impl From<User> for model::User {
+        fn from(user: User) -> Self {
+            Self {
+                id: user.id,
+                name: user.name,
+                email: user.email,
+                location: model::Location {
+                    street: user.location.street,
+                    city: user.location.city,
+                    country: user.location.country,
+                    postal: user.location.postal,
+                    point: None,
+                },
+                profile: user.profile,
+                created: user.created,
+                updated: user.updated,
+                revision: 1,
+            }
+        }
+    }
+}
+
+/// Schema 2: users without revisions
+pub mod second {
+    use super::*;
+
+    /// User as written by schema 2
+    #[derive(Archive, Serialize, Deserialize, Debug, Clone, Record)]
+    #[archive(check_bytes)]
+    pub struct User {
+        /// Unique user identifier
+        pub id: u64,
+        /// User's display name
+        pub name: String,
+        /// User's email address
+        pub email: String,
+        /// User's geographical location
+        pub location: model::Location,
+        /// User's profile information
+        pub profile: Option<model::Profile>,
+        /// Account creation timestamp
+        pub created: u64,
+        /// Last update timestamp
+        pub updated: u64,
+    }
+
+    impl From<User> for model::User {
+        fn from(user: User) -> Self {
+            Self {
+                id: user.id,
+                name: user.name,
+                email: user.email,
+                location: user.location,
+                profile: user.profile,
+                created: user.created,
+                updated: user.updated,
+                revision: 1,
+            }
+        }
+    }
+}
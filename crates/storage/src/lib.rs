@@ -7,15 +7,57 @@
 pub mod model;
 pub mod segment;
 pub mod index;
+pub mod key;
+pub mod table;
 pub mod sdk;
 pub mod compaction;
+pub mod throttle;
+pub mod manifest;
+pub mod garbage;
+pub mod timeline;
+pub mod atlas;
+pub mod catalog;
+pub mod filter;
+pub mod language;
+pub mod migration;
+pub mod history;
+pub mod audit;
+pub mod legacy;
+pub mod directory;
+pub mod backend;
+pub mod direct;
+#[cfg(feature = "uring")]
+pub mod uring;
+pub mod testing;
+pub mod validator;
+pub mod generator;
+pub mod hook;
+pub mod publish;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod codec;
+pub mod cipher;
+pub mod erasure;
+pub mod probe;
+pub mod registry;
+pub mod writer;
+pub mod replica;
+pub mod shard;
+pub mod raw;
+#[cfg(feature = "resp")]
+pub mod resp;
+#[cfg(feature = "parquet")]
+pub mod parquet;
 pub mod error;
 
-pub use error::Error;
-pub use sdk::Store;
+pub use error::{Error, Kind};
+pub use sdk::{Builder, Durability, Pinned, Quarantine, Store};
 
 /// Result type for Guardian-Store operations
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Re-export commonly used types
-pub use model::{User, Location, Profile, Position}; 
\ No newline at end of file
+pub use model::{User, Location, Point, Profile, Position, Field, Projection};
+pub use segment::Compression; 
\ No newline at end of file
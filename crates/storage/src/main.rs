@@ -2,9 +2,19 @@
 //! 
 //! Provides command-line interface for administrative operations
 
-use clap::{Parser, Subcommand};
-use guardian_store::{Store, User, Location, Profile};
-use std::path::PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
+use guardian_store::{Store, User, Location, Profile, Field, Projection};
+use guardian_store::compaction::Config;
+use guardian_store::filter::Filter;
+use guardian_store::language::Statement;
+use guardian_store::migration;
+use guardian_store::registry::Change;
+use guardian_store::manifest::Quota;
+use guardian_store::segment::Segment;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 #[derive(Parser)]
 #[command(name = "guardian-store")]
@@ -14,15 +24,42 @@ struct Cli {
     #[arg(short, long, default_value = "./data")]
     path: PathBuf,
     
+    /// Output format for get, scan and status
+    #[arg(short, long, global = true, value_enum, default_value_t = Format::Table)]
+    output: Format,
+    
     #[command(subcommand)]
     command: Commands,
 }
 
+/// How query results are printed
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// JSON for scripts
+    Json,
+    /// Aligned columns for humans
+    Table,
+    /// Comma-separated values with a header row
+    Csv,
+}
+
+/// Column names of a user row
+const COLUMNS: [&str; 15] = [
+    "id", "name", "email", "street", "city", "country", "postal",
+    "latitude", "longitude", "age", "job", "interests", "created", "updated", "revision",
+];
+
 #[derive(Subcommand)]
 enum Commands {
     /// Show system status
     Status,
     
+    /// Check store health, failing when segments are damaged
+    Health,
+    
+    /// Show the memory the store holds, in bytes
+    Memory,
+    
     /// Query a record by ID
     Get {
         /// Record ID
@@ -30,13 +67,39 @@ enum Commands {
     },
     
     /// Create a new record
+    ///
+    /// Without positional arguments or with `--json -`, the full record
+    /// is read as JSON from stdin.
     Create {
         /// Record ID
-        id: u64,
+        #[arg(requires_all = ["name", "email"], conflicts_with = "json")]
+        id: Option<u64>,
         /// User name
-        name: String,
+        name: Option<String>,
         /// Email address
-        email: String,
+        email: Option<String>,
+        /// Full record as JSON ("-" reads stdin)
+        #[arg(long)]
+        json: Option<String>,
+    },
+    
+    /// Update an existing record
+    ///
+    /// Either changes single fields of the stored record, or replaces it
+    /// with a full JSON record given via `--json` or stdin.
+    Update {
+        /// Record ID
+        #[arg(conflicts_with = "json")]
+        id: Option<u64>,
+        /// New user name
+        #[arg(long, requires = "id")]
+        name: Option<String>,
+        /// New email address
+        #[arg(long, requires = "id")]
+        email: Option<String>,
+        /// Full record as JSON ("-" reads stdin)
+        #[arg(long)]
+        json: Option<String>,
     },
     
     /// Delete a record
@@ -45,88 +108,436 @@ enum Commands {
         id: u64,
     },
     
+    /// Erase a record and every earlier version of it from disk
+    Erase {
+        /// Record ID
+        id: u64,
+    },
+    
+    /// Print a segment file's header, footer and records, for debugging damaged files
+    ///
+    /// Reads the file alone, without opening the store.
+    #[command(name = "dump-segment")]
+    Dump {
+        /// Segment ID
+        #[arg(long)]
+        id: u64,
+        /// Also hexdump each record's stored payload
+        #[arg(long)]
+        hex: bool,
+    },
+    
+    /// Plan or apply rewriting records in a newer schema version
+    ///
+    /// Other commands refuse a store whose records predate this build's
+    /// schema until they are migrated. An interrupted `--apply` resumes
+    /// where it stopped when run again.
+    Migrate {
+        /// Schema version to move records to
+        #[arg(long, value_name = "VERSION")]
+        to: u32,
+        /// Only show what would be rewritten, and roughly how long it takes
+        #[arg(long, conflicts_with = "apply", required_unless_present = "apply")]
+        plan: bool,
+        /// Rewrite the records
+        #[arg(long)]
+        apply: bool,
+    },
+    
+    /// Time fsync on the store's device and show the group commit window it suggests
+    Probe,
+    
     /// Trigger compaction
-    Compact,
+    Compact {
+        /// Rewrite every sealed segment holding dead records
+        #[arg(long)]
+        major: bool,
+        /// Show which segments would be rewritten without touching them
+        #[arg(long = "dry-run")]
+        dry: bool,
+        /// Rewrite only this segment; repeat for several
+        #[arg(long = "segment", conflicts_with = "major")]
+        segments: Vec<u64>,
+        /// Segments rewritten in parallel
+        #[arg(long, default_value_t = 1)]
+        workers: usize,
+    },
+    
+    /// Scan all records, or only a few of them
+    Scan {
+        /// Only records passing a filter, `field=value` or `field~value`; repeatable
+        #[arg(long = "where", value_name = "FILTER", conflicts_with_all = ["head", "tail", "sample"])]
+        filters: Vec<Filter>,
+        /// Most records passing the filters to show
+        #[arg(long, value_name = "N", requires = "filters")]
+        limit: Option<usize>,
+        /// Only the first N records in key order
+        #[arg(long, value_name = "N", conflicts_with_all = ["tail", "sample"])]
+        head: Option<usize>,
+        /// Only the last N records in key order
+        #[arg(long, value_name = "N", conflicts_with = "sample")]
+        tail: Option<usize>,
+        /// A random sample of N records, shown in key order
+        #[arg(long, value_name = "N")]
+        sample: Option<usize>,
+        /// Seed picking the sample, random by default
+        #[arg(long, requires = "sample")]
+        seed: Option<u64>,
+    },
+    
+    /// Run a query such as `SELECT name, email WHERE country = VN LIMIT 10`
+    Query {
+        /// Statement to run
+        statement: Statement,
+    },
+    
+    /// List stored IDs, one per line, reading only the index
+    Keys,
     
-    /// Scan all records
-    Scan,
+    /// Show the recorded field layout of a schema version
+    Schema {
+        /// Schema version, the store's own by default
+        version: Option<u32>,
+    },
+    
+    /// Drop sealed segments older than the expiry age
+    Expire {
+        /// New expiry age in seconds to store before expiring
+        #[arg(long)]
+        age: Option<u64>,
+    },
+    
+    /// Limit live records and their stored bytes; omitted limits are lifted
+    Quota {
+        /// Most live records
+        #[arg(long)]
+        records: Option<u64>,
+        /// Most stored bytes of live records
+        #[arg(long)]
+        bytes: Option<u64>,
+    },
+    
+    /// Clone the store, sharing sealed segments through hard links
+    Fork {
+        /// Directory of the new store, which must not exist yet
+        target: PathBuf,
+    },
+    
+    /// Export all records to a Parquet file for analytics
+    #[cfg(feature = "parquet")]
+    Export {
+        /// Destination file
+        target: PathBuf,
+    },
+    
+    /// Serve the raw key-value API to Redis clients
+    #[cfg(feature = "resp")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:6379")]
+        address: String,
+    },
+    
+    /// Rebuild the secondary indexes from the stored records
+    #[command(name = "rebuild-index")]
+    Rebuild {
+        /// Compare the rebuilt indexes entry by entry with the old ones
+        #[arg(long)]
+        verify: bool,
+    },
+    
+    /// Remove files no longer referenced by the manifest
+    Gc {
+        /// List candidates without deleting them
+        #[arg(long = "dry-run")]
+        dry: bool,
+    },
+    
+    /// Measure write and read throughput on this hardware
+    ///
+    /// Writes go through the write queue from concurrent producers, then
+    /// every record is read back. Needs an empty store.
+    Bench {
+        /// Records to write
+        #[arg(long, default_value_t = 100_000)]
+        writes: u64,
+        /// Concurrent producers and readers
+        #[arg(long, default_value_t = 8)]
+        threads: usize,
+        /// Payload bytes per record
+        #[arg(long = "value-size", default_value_t = 512)]
+        size: usize,
+    },
+}
+
+/// Outcome of one benchmark phase
+#[derive(serde::Serialize)]
+struct Summary {
+    /// Phase measured
+    phase: &'static str,
+    /// Operations completed
+    operations: u64,
+    /// Wall-clock duration in seconds
+    seconds: f64,
+    /// Operations per second
+    throughput: f64,
+    /// Median latency in microseconds
+    p50: u64,
+    /// 90th percentile latency in microseconds
+    p90: u64,
+    /// 99th percentile latency in microseconds
+    p99: u64,
+    /// Slowest operation in microseconds
+    max: u64,
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
     
+    // Forensics must work on stores that no longer open
+    if let Commands::Dump { id, hex } = cli.command {
+        return dump(&cli.path, id, hex, cli.output);
+    }
+    // Planning must not open the store, since opening is what migrates it
+    if let Commands::Migrate { to, apply, .. } = cli.command {
+        return migrate(&cli.path, to, apply, cli.output);
+    }
+    
     // Initialize store
-    let mut store = Store::new(&cli.path)?;
+    let store = Store::builder().path(&cli.path).hold(true).open()?;
     
     match cli.command {
         Commands::Status => {
             let stats = store.stats()?;
-            println!("Guardian-Store Status:");
-            println!("  Records: {}", stats.records);
-            println!("  Segments: {}", stats.segments);
+            match cli.output {
+                Format::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+                format => render(format, &["records", "size", "segments", "written", "deleted", "bytes", "compactions"], &[
+                    vec![
+                        stats.records.to_string(),
+                        stats.size.to_string(),
+                        stats.segments.to_string(),
+                        stats.written.to_string(),
+                        stats.deleted.to_string(),
+                        stats.bytes.to_string(),
+                        stats.compactions.to_string(),
+                    ],
+                ]),
+            }
+        }
+        
+        Commands::Memory => {
+            let memory = store.memory();
+            match cli.output {
+                Format::Json => println!("{}", serde_json::to_string_pretty(&memory)?),
+                format => render(format, &["index", "secondary", "cache", "buffer", "total"], &[
+                    vec![
+                        memory.index.to_string(),
+                        memory.secondary.to_string(),
+                        memory.cache.to_string(),
+                        memory.buffer.to_string(),
+                        memory.total().to_string(),
+                    ],
+                ]),
+            }
+        }
+        
+        Commands::Health => {
+            let health = store.health()?;
+            let list = |ids: &[u64]| ids.iter().map(u64::to_string).collect::<Vec<_>>().join(" ");
+            match cli.output {
+                Format::Json => println!("{}", serde_json::to_string_pretty(&health)?),
+                format => render(format, &["synced", "backlog", "compactions", "pending", "corrupt", "available"], &[
+                    vec![
+                        health.synced.map(|time| time.to_string()).unwrap_or_default(),
+                        health.backlog.to_string(),
+                        health.compactions.to_string(),
+                        list(&health.pending),
+                        list(&health.corrupt),
+                        health.available.map(|bytes| bytes.to_string()).unwrap_or_default(),
+                    ],
+                ]),
+            }
+            if !health.healthy() {
+                return Err(format!("Damaged segments: {}", list(&health.corrupt)).into());
+            }
         }
         
         Commands::Get { id } => {
-            match store.find(id)? {
-                Some(user) => {
-                    println!("User ID: {}", user.id);
-                    println!("Name: {}", user.name);
-                    println!("Email: {}", user.email);
-                    println!("Location: {} {}, {}", user.location.street, user.location.city, user.location.country);
-                    if let Some(profile) = user.profile {
-                        println!("Age: {}", profile.age);
-                        println!("Job: {}", profile.job);
-                        println!("Interests: {}", profile.interests.join(", "));
+            let user = store.find(id)?;
+            match (cli.output, user) {
+                (Format::Json, user) => println!("{}", serde_json::to_string_pretty(&user)?),
+                (format, Some(user)) => render(format, &COLUMNS, &[row(&user)]),
+                (_, None) => eprintln!("User with ID {} not found", id),
+            }
+        }
+        
+        Commands::Create { id, name, email, json } => {
+            let now = now()?;
+            let mut user = match (id, name, email) {
+                (Some(id), Some(name), Some(email)) => User {
+                    id,
+                    name,
+                    email,
+                    location: Location {
+                        street: "Default Street".to_string(),
+                        city: "Default City".to_string(),
+                        country: "Default Country".to_string(),
+                        postal: "00000".to_string(),
+                        point: None,
+                    },
+                    profile: None,
+                    created: now,
+                    updated: now,
+                    revision: 0,
+                },
+                _ => parse(json)?,
+            };
+            
+            // Fill in timestamps the JSON record left out
+            if user.created == 0 {
+                user.created = now;
+            }
+            if user.updated == 0 {
+                user.updated = user.created;
+            }
+            
+            store.save(&user)?;
+            println!("User created successfully with ID: {}", user.id);
+        }
+        
+        Commands::Update { id, name, email, json } => {
+            let user = match id {
+                Some(id) => {
+                    let mut user = store.fetch(id)?;
+                    if let Some(name) = name {
+                        user.name = name;
                     }
+                    if let Some(email) = email {
+                        user.email = email;
+                    }
+                    user
                 }
                 None => {
-                    println!("User with ID {} not found", id);
+                    let mut user = parse(json)?;
+                    let existing = store.find(user.id)?
+                        .ok_or_else(|| format!("User with ID {} not found", user.id))?;
+                    // Creation time belongs to the stored record unless given
+                    if user.created == 0 {
+                        user.created = existing.created;
+                    }
+                    // Without a revision the record replaces whatever is stored
+                    if user.revision == 0 {
+                        user.revision = existing.revision;
+                    }
+                    user
                 }
+            };
+            
+            // Refuse to overwrite a change made since the record was read
+            let user = User { updated: now()?, ..user };
+            let revision = store.commit(&user, user.revision)?;
+            println!("User with ID {} updated successfully (revision {})", user.id, revision);
+        }
+        
+        Commands::Delete { id } => {
+            if store.delete(id)?.is_some() {
+                println!("User with ID {} deleted successfully", id);
+            } else {
+                eprintln!("User with ID {} not found", id);
             }
         }
         
-        Commands::Create { id, name, email } => {
-            let location = Location {
-                street: "Default Street".to_string(),
-                city: "Default City".to_string(),
-                country: "Default Country".to_string(),
-                postal: "00000".to_string(),
+        Commands::Erase { id } => {
+            let certificate = store.erase(id)?;
+            println!("{}", serde_json::to_string_pretty(&certificate)?);
+        }
+        
+        Commands::Dump { .. } => unreachable!("dumped before opening the store"),
+        Commands::Migrate { .. } => unreachable!("migrated before opening the store"),
+        
+        Commands::Probe => {
+            let latency = guardian_store::probe::fsync(&cli.path, guardian_store::probe::ROUNDS)?;
+            println!("fsync median {:?}, worst {:?} over {} rounds", latency.median, latency.worst, latency.rounds);
+            println!("Suggested window: {:?}", latency.window());
+        }
+        
+        Commands::Compact { major, dry, segments, workers } => {
+            let mut config = Config {
+                throttle: false,
+                workers,
+                ..Config::default()
             };
+            if major {
+                config.threshold = 0.0;
+                config.limit = usize::MAX;
+            }
             
-            let user = User {
-                id,
-                name,
-                email,
-                location,
-                profile: None,
-                created: std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)?
-                    .as_secs(),
-                updated: std::time::SystemTime::now()
-                    .duration_since(std::time::UNIX_EPOCH)?
-                    .as_secs(),
+            let tallies = store.tallies();
+            let picked = if segments.is_empty() {
+                store.compaction(config.clone()).plan()
+            } else {
+                segments.clone()
             };
             
-            store.save(&user)?;
-            println!("User created successfully with ID: {}", id);
-        }
-        
-        Commands::Delete { id } => {
-            store.delete(id)?;
-            println!("User with ID {} deleted successfully", id);
+            if dry {
+                println!("Segments to rewrite: {}", picked.len());
+                for id in &picked {
+                    let tally = tallies.get(id).copied().unwrap_or_default();
+                    println!("  segment {}: {} live, {} dead ({:.1}% dead)", id, tally.live, tally.dead, tally.ratio() * 100.0);
+                }
+            } else {
+                let state = if segments.is_empty() {
+                    store.compact(config)?
+                } else {
+                    store.rewrite(&segments, config)?
+                };
+                
+                println!("Compaction completed:");
+                println!("  Processed: {}", state.processed);
+                println!("  Removed: {}", state.removed);
+                println!("  Segments rewritten: {}", picked.len());
+                println!("  Purged: {} records, {} versions", state.purged.records, state.purged.versions);
+            }
         }
         
-        Commands::Compact => {
-            println!("Compaction not yet implemented in CLI");
+        Commands::Keys => {
+            for key in store.keys() {
+                println!("{}", key?);
+            }
         }
         
-        Commands::Scan => {
-            println!("Scanning all records...");
+        Commands::Scan { filters, limit, head, tail, sample, seed } => {
+            let mut rows = Vec::new();
             let mut count = 0;
-            for result in store.scan() {
+            let users: Box<dyn Iterator<Item = guardian_store::Result<User>>> = match (head, tail, sample) {
+                _ if !filters.is_empty() => Box::new(store.filter(&filters, limit.unwrap_or(usize::MAX))?.into_iter().map(Ok)),
+                (Some(limit), _, _) => Box::new(store.scan().take(limit)),
+                (_, Some(limit), _) => Box::new(store.tail(limit)?.into_iter().map(Ok)),
+                (_, _, Some(limit)) => {
+                    let seed = match seed {
+                        Some(seed) => seed,
+                        None => std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_nanos() as u64,
+                    };
+                    Box::new(store.sample(limit, seed)?.into_iter().map(Ok))
+                }
+                _ => Box::new(store.scan()),
+            };
+            
+            // JSON is streamed so large stores never sit in memory
+            if cli.output == Format::Json {
+                println!("[");
+            }
+            for result in users {
                 match result {
+                    Ok(user) if cli.output == Format::Json => {
+                        if count > 0 {
+                            println!(",");
+                        }
+                        print!("  {}", serde_json::to_string(&user)?);
+                        count += 1;
+                    }
                     Ok(user) => {
-                        println!("ID: {}, Name: {}, Email: {}", user.id, user.name, user.email);
+                        rows.push(row(&user));
                         count += 1;
                     }
                     Err(e) => {
@@ -134,9 +545,499 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     }
                 }
             }
-            println!("Total records: {}", count);
+            
+            match cli.output {
+                Format::Json => {
+                    if count > 0 {
+                        println!();
+                    }
+                    println!("]");
+                }
+                Format::Table => {
+                    render(Format::Table, &COLUMNS, &rows);
+                    println!("Total records: {}", count);
+                }
+                Format::Csv => render(Format::Csv, &COLUMNS, &rows),
+            }
+        }
+        
+        Commands::Query { statement } => {
+            let users = store.filter(&statement.filters, statement.limit.unwrap_or(usize::MAX))?;
+            match cli.output {
+                Format::Json if statement.fields.is_empty() => println!("{}", serde_json::to_string_pretty(&users)?),
+                Format::Json => {
+                    let projections: Vec<Projection> = users.iter()
+                        .map(|user| Projection::pick(user, &statement.fields))
+                        .collect();
+                    println!("{}", serde_json::to_string_pretty(&projections)?);
+                }
+                format => {
+                    let mut header = vec!["id"];
+                    for column in statement.fields.iter().flat_map(|&field| columns(field)) {
+                        if !header.contains(column) {
+                            header.push(column);
+                        }
+                    }
+                    if statement.fields.is_empty() {
+                        header = COLUMNS.to_vec();
+                    }
+                    let picks: Vec<usize> = header.iter()
+                        .filter_map(|name| COLUMNS.iter().position(|column| column == name))
+                        .collect();
+                    let rows: Vec<Vec<String>> = users.iter()
+                        .map(|user| {
+                            let cells = row(user);
+                            picks.iter().map(|&at| cells[at].clone()).collect()
+                        })
+                        .collect();
+                    render(format, &header, &rows);
+                    if format == Format::Table {
+                        println!("Total records: {}", rows.len());
+                    }
+                }
+            }
+        }
+        
+        Commands::Schema { version } => {
+            let manifest = store.manifest();
+            let version = version.unwrap_or(manifest.schema);
+            let layout = manifest.registry.get(version)
+                .ok_or_else(|| format!("Schema {} is not recorded", version))?;
+            match cli.output {
+                Format::Json => println!("{}", serde_json::to_string_pretty(layout)?),
+                format => {
+                    let rows: Vec<Vec<String>> = layout.structs.iter()
+                        .flat_map(|(name, members)| members.iter().map(move |member| {
+                            vec![name.clone(), member.name.clone(), member.kind.clone()]
+                        }))
+                        .collect();
+                    render(format, &["struct", "field", "type"], &rows);
+                }
+            }
+        }
+        
+        Commands::Quota { records, bytes } => {
+            store.quota(Quota { records, bytes })?;
+            let stats = store.stats()?;
+            let limit = |limit: Option<u64>| limit.map_or("unlimited".to_string(), |limit| limit.to_string());
+            println!("Records: {} of {}", stats.records, limit(records));
+            println!("Bytes: {} of {}", stats.size, limit(bytes));
+        }
+        
+        Commands::Expire { age } => {
+            if age.is_some() {
+                store.expiry(age)?;
+            }
+            let expired = store.expire()?;
+            println!("Expired segments: {}", expired.len());
+            for id in &expired {
+                println!("  segment {}", id);
+            }
+        }
+        
+        Commands::Fork { target } => {
+            store.fork(&target)?;
+            println!("Forked store into {}", target.display());
+        }
+        
+        #[cfg(feature = "parquet")]
+        Commands::Export { target } => {
+            let rows = store.export(&target)?;
+            println!("Exported {} records to {}", rows, target.display());
+        }
+        
+        #[cfg(feature = "resp")]
+        Commands::Serve { address } => {
+            let runtime = tokio::runtime::Runtime::new()?;
+            runtime.block_on(async {
+                let listener = tokio::net::TcpListener::bind(&address).await?;
+                println!("Serving RESP on {}", listener.local_addr()?);
+                let server = guardian_store::resp::Server::new(Arc::new(store));
+                server.serve(listener).await
+            })?;
+        }
+        
+        Commands::Rebuild { verify } => {
+            let rebuild = store.rebuild(verify, |records, total| {
+                eprint!("\rIndexed {} of {} records", records, total);
+            })?;
+            eprintln!();
+            if cli.output == Format::Json {
+                println!("{}", serde_json::to_string_pretty(&rebuild)?);
+            } else if verify {
+                let rows: Vec<Vec<String>> = rebuild.checks.iter()
+                    .map(|check| vec![check.name.clone(), check.kept.to_string(), check.stale.to_string(), check.missing.to_string()])
+                    .collect();
+                render(cli.output, &["index", "kept", "stale", "missing"], &rows);
+            }
+            if !rebuild.matched() {
+                return Err("rebuilt indexes differ from the old ones".into());
+            }
+        }
+        
+        Commands::Gc { dry } => {
+            let report = store.collect(dry)?;
+            for path in &report.paths {
+                println!("{} {}", if dry { "Would remove" } else { "Removed" }, path.display());
+            }
+            println!("Reclaimed: {} bytes in {} entries{}", report.bytes, report.paths.len(), if dry { " (dry run)" } else { "" });
+            println!("Expired history versions: {}", report.versions);
+        }
+        
+        Commands::Bench { writes, threads, size } => {
+            let summaries = bench(store, writes, threads.max(1), size)?;
+            match cli.output {
+                Format::Json => println!("{}", serde_json::to_string_pretty(&summaries)?),
+                format => {
+                    let rows: Vec<Vec<String>> = summaries.iter()
+                        .map(|summary| vec![
+                            summary.phase.to_string(),
+                            summary.operations.to_string(),
+                            format!("{:.3}", summary.seconds),
+                            format!("{:.0}", summary.throughput),
+                            summary.p50.to_string(),
+                            summary.p90.to_string(),
+                            summary.p99.to_string(),
+                            summary.max.to_string(),
+                        ])
+                        .collect();
+                    render(format, &["phase", "ops", "seconds", "ops/s", "p50 us", "p90 us", "p99 us", "max us"], &rows);
+                }
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// Prints what a segment file holds, as `dump-segment` does
+fn dump(path: &Path, id: u64, hex: bool, format: Format) -> Result<(), Box<dyn std::error::Error>> {
+    let segment = Segment::new(path.join("segments"))?;
+    let walk = segment.walk(id)?;
+    if format == Format::Json {
+        println!("{}", serde_json::to_string_pretty(&walk)?);
+        return Ok(());
+    }
+    
+    if format == Format::Table {
+        match segment.header(id) {
+            Ok(header) => println!(
+                "Header: segment {}, schema {}, key {}, created {}, {} records, {} bytes",
+                header.id, header.schema, header.key, header.created, header.records, header.bytes
+            ),
+            Err(e) => println!("Header: unreadable ({})", e),
+        }
+        match walk.footer {
+            Some(footer) => println!(
+                "Footer: {} records, {} bytes, checksum {:08x}",
+                footer.records, footer.bytes, footer.checksum
+            ),
+            None => println!("Footer: none"),
+        }
+        match walk.dictionary {
+            Some(length) => println!("Dictionary: {} bytes", length),
+            None => println!("Dictionary: none"),
+        }
+    }
+    
+    let columns = ["offset", "length", "flags", "checksum"];
+    let rows: Vec<Vec<String>> = walk.entries.iter()
+        .map(|entry| vec![
+            entry.offset.to_string(),
+            entry.length.to_string(),
+            match (entry.packed, entry.shared) {
+                (true, _) => "lz4".to_string(),
+                (_, true) => "dictionary".to_string(),
+                _ => "-".to_string(),
+            },
+            format!("{:08x}", entry.checksum),
+        ])
+        .collect();
+    if hex {
+        let map = segment.map(id)?;
+        for (entry, row) in walk.entries.iter().zip(&rows) {
+            println!("{}", row.join("  "));
+            let payload = &map[entry.offset as usize + 4..(entry.offset + 4 + entry.length) as usize];
+            for (line, chunk) in payload.chunks(16).enumerate() {
+                let bytes: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+                let text: String = chunk.iter()
+                    .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+                    .collect();
+                println!("  {:08x}  {:<47}  |{}|", line * 16, bytes.join(" "), text);
+            }
         }
+    } else {
+        render(format, &columns, &rows);
     }
     
+    if format == Format::Table {
+        println!("Records: {}, ending at {} of {} bytes", walk.entries.len(), walk.end, walk.limit);
+        if walk.end < walk.limit {
+            println!("Damaged: {} bytes after offset {} hold no whole record", walk.limit - walk.end, walk.end);
+        }
+    }
     Ok(())
-} 
\ No newline at end of file
+}
+
+/// Shows the plan moving a store's records to a schema, then runs it if asked
+fn migrate(path: &Path, to: u32, apply: bool, format: Format) -> Result<(), Box<dyn std::error::Error>> {
+    let plan = migration::plan(path, to)?;
+    let rows: Vec<Vec<String>> = plan.segments.iter()
+        .map(|rewrite| vec![rewrite.segment.to_string(), rewrite.records.to_string(), rewrite.bytes.to_string()])
+        .collect();
+    match format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&plan)?),
+        Format::Csv => render(Format::Csv, &["segment", "records", "bytes"], &rows),
+        Format::Table if plan.current() => println!("Records are already in schema {}", plan.to),
+        Format::Table => {
+            println!(
+                "Schema {} -> {}: {} records in {} segments, {} bytes, about {}s",
+                plan.from, plan.to, plan.records, plan.segments.len(), plan.bytes, plan.seconds,
+            );
+            if plan.done > 0 {
+                println!("Resuming after {} records already rewritten", plan.done);
+            }
+            for change in &plan.changes {
+                match change {
+                    Change::Added { path, kind } => println!("  added {}: {}", path, kind),
+                    Change::Removed { path, kind } => println!("  removed {}: {}", path, kind),
+                    Change::Retyped { path, from, to } => println!("  retyped {}: {} -> {}", path, from, to),
+                }
+            }
+            render(Format::Table, &["segment", "records", "bytes"], &rows);
+        }
+    }
+    if !apply || plan.current() {
+        return Ok(());
+    }
+    
+    // Opening without hold rewrites the records, resuming any earlier run
+    let start = Instant::now();
+    let store = Store::new(path)?;
+    store.migrate(to)?;
+    store.close()?;
+    if format == Format::Table {
+        println!("Migrated {} records to schema {} in {:.2?}", plan.records, to, start.elapsed());
+    }
+    Ok(())
+}
+
+/// Current time in seconds since the Unix epoch
+fn now() -> Result<u64, Box<dyn std::error::Error>> {
+    Ok(std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs())
+}
+
+/// Writes `writes` records from `threads` producers, then reads them all back
+fn bench(store: Store, writes: u64, threads: usize, size: usize) -> Result<Vec<Summary>, Box<dyn std::error::Error>> {
+    if store.count()? > 0 {
+        return Err("Bench needs an empty store; point --path at a scratch directory".into());
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(threads)
+        .enable_all()
+        .build()?;
+    
+    // Each producer waits for its write, so latency includes the group commit
+    let started = Instant::now();
+    let (store, latencies) = runtime.block_on(async {
+        let writer = Arc::new(store.writer(threads * 64)?);
+        let tasks: Vec<_> = (0..threads as u64)
+            .map(|thread| {
+                let writer = Arc::clone(&writer);
+                tokio::spawn(async move {
+                    let mut latencies = Vec::new();
+                    for id in (1 + thread..=writes).step_by(threads) {
+                        let begun = Instant::now();
+                        writer.save(sample(id, size)).await?;
+                        latencies.push(begun.elapsed());
+                    }
+                    Ok::<_, guardian_store::Error>(latencies)
+                })
+            })
+            .collect();
+        
+        let mut latencies = Vec::with_capacity(writes as usize);
+        for task in tasks {
+            latencies.extend(task.await??);
+        }
+        let writer = Arc::into_inner(writer).ok_or("Write queue still shared")?;
+        Ok::<_, Box<dyn std::error::Error>>((writer.close().await?, latencies))
+    })?;
+    let write = summarize("write", latencies, started.elapsed());
+    
+    let started = Instant::now();
+    let latencies = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads as u64)
+            .map(|thread| {
+                let store = &store;
+                scope.spawn(move || {
+                    let mut latencies = Vec::new();
+                    for id in (1 + thread..=writes).step_by(threads) {
+                        let begun = Instant::now();
+                        store.find(id)
+                            .map_err(|e| e.to_string())?
+                            .ok_or_else(|| format!("Record {} missing after its write", id))?;
+                        latencies.push(begun.elapsed());
+                    }
+                    Ok::<_, String>(latencies)
+                })
+            })
+            .collect();
+        
+        let mut latencies = Vec::with_capacity(writes as usize);
+        for handle in handles {
+            latencies.extend(handle.join().map_err(|_| "Reader panicked")??);
+        }
+        Ok::<_, Box<dyn std::error::Error>>(latencies)
+    })?;
+    let read = summarize("read", latencies, started.elapsed());
+    
+    Ok(vec![write, read])
+}
+
+/// A benchmark record carrying `size` payload bytes
+fn sample(id: u64, size: usize) -> User {
+    User {
+        id,
+        name: format!("Bench {}", id),
+        email: format!("bench{}@example.com", id),
+        location: Location {
+            street: String::new(),
+            city: String::new(),
+            country: String::new(),
+            postal: String::new(),
+            point: None,
+        },
+        profile: Some(Profile {
+            age: 0,
+            job: "x".repeat(size),
+            interests: Vec::new(),
+        }),
+        created: 0,
+        updated: 0,
+        revision: 0,
+    }
+}
+
+/// Throughput and latency percentiles of one phase
+fn summarize(phase: &'static str, mut latencies: Vec<Duration>, elapsed: Duration) -> Summary {
+    latencies.sort_unstable();
+    let percentile = |rank: f64| latencies
+        .get(((latencies.len() as f64 * rank).ceil() as usize).saturating_sub(1))
+        .map_or(0, |latency| latency.as_micros() as u64);
+    let seconds = elapsed.as_secs_f64();
+    Summary {
+        phase,
+        operations: latencies.len() as u64,
+        seconds,
+        throughput: if seconds > 0.0 { latencies.len() as f64 / seconds } else { 0.0 },
+        p50: percentile(0.50),
+        p90: percentile(0.90),
+        p99: percentile(0.99),
+        max: percentile(1.0),
+    }
+}
+
+/// Parses a JSON user record from the argument, or stdin when absent or "-"
+fn parse(json: Option<String>) -> Result<User, Box<dyn std::error::Error>> {
+    let text = match json {
+        Some(text) if text != "-" => text,
+        _ => {
+            let mut text = String::new();
+            std::io::stdin().read_to_string(&mut text)?;
+            text
+        }
+    };
+    
+    let user = serde_json::from_str(&text)
+        .map_err(|e| format!("Invalid user record: {}", e))?;
+    Ok(user)
+}
+
+/// Flattens a user into cells matching `COLUMNS`
+fn row(user: &User) -> Vec<String> {
+    let (age, job, interests) = match &user.profile {
+        Some(profile) => (profile.age.to_string(), profile.job.clone(), profile.interests.join(";")),
+        None => Default::default(),
+    };
+    let (latitude, longitude) = match &user.location.point {
+        Some(point) => (point.latitude.to_string(), point.longitude.to_string()),
+        None => Default::default(),
+    };
+    
+    vec![
+        user.id.to_string(),
+        user.name.clone(),
+        user.email.clone(),
+        user.location.street.clone(),
+        user.location.city.clone(),
+        user.location.country.clone(),
+        user.location.postal.clone(),
+        latitude,
+        longitude,
+        age,
+        job,
+        interests,
+        user.created.to_string(),
+        user.updated.to_string(),
+        user.revision.to_string(),
+    ]
+}
+
+/// Columns of a user row showing a field
+fn columns(field: Field) -> &'static [&'static str] {
+    match field {
+        Field::Name => &["name"],
+        Field::Email => &["email"],
+        Field::Location => &["street", "city", "country", "postal", "latitude", "longitude"],
+        Field::City => &["city"],
+        Field::Country => &["country"],
+        Field::Profile => &["age", "job", "interests"],
+        Field::Created => &["created"],
+        Field::Updated => &["updated"],
+        Field::Revision => &["revision"],
+    }
+}
+
+/// Prints rows as an aligned table or as CSV
+fn render(format: Format, header: &[&str], rows: &[Vec<String>]) {
+    if format == Format::Csv {
+        println!("{}", header.join(","));
+        for row in rows {
+            let cells: Vec<String> = row.iter().map(|cell| escape(cell)).collect();
+            println!("{}", cells.join(","));
+        }
+        return;
+    }
+    
+    let mut widths: Vec<usize> = header.iter().map(|name| name.len()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    
+    let line = |cells: Vec<String>| {
+        let padded: Vec<String> = cells.iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", padded.join("  ").trim_end());
+    };
+    
+    line(header.iter().map(|name| name.to_uppercase()).collect());
+    for row in rows {
+        line(row.clone());
+    }
+}
+
+/// Quotes a CSV cell when it holds separators, quotes or line breaks
+fn escape(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
@@ -0,0 +1,197 @@
+//! Store manifest
+//!
+//! Describes the durable state of a store in a single small file that
+//! is replaced atomically, so readers never observe a half-written one.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::{directory, Error, Result};
+use crate::segment::Tally;
+use crate::history::Retention;
+use crate::registry::Registry;
+
+/// Manifest file name inside the store base directory
+const NAME: &str = "MANIFEST";
+
+/// Current manifest format version
+pub const VERSION: u32 = 1;
+
+/// Durable description of a store
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Manifest {
+    /// Manifest format version
+    pub version: u32,
+    /// Live segment identifiers in ascending order
+    pub segments: Vec<u64>,
+    /// Generation of the current index table
+    pub generation: u64,
+    /// Schema version of newly written records
+    #[serde(default = "schema")]
+    pub schema: u32,
+    /// Rewrite of records stored under an older schema under way, if any
+    #[serde(default)]
+    pub upgrade: Option<Upgrade>,
+    /// Highest segment identifier already rewritten by compaction
+    #[serde(default)]
+    pub watermark: u64,
+    /// Live/dead record counts per segment
+    #[serde(default)]
+    pub tallies: BTreeMap<u64, Tally>,
+    /// Live segments ended with a footer
+    #[serde(default)]
+    pub sealed: Vec<u64>,
+    /// How many superseded versions are kept per record
+    #[serde(default)]
+    pub retention: Retention,
+    /// Lifetime activity counters
+    #[serde(default)]
+    pub counters: Counters,
+    /// Age in seconds after which sealed segments are dropped whole
+    #[serde(default)]
+    pub expiry: Option<u64>,
+    /// Name of the codec every record is serialized with
+    #[serde(default = "codec")]
+    pub codec: String,
+    /// Field layouts of every schema version the store has held
+    #[serde(default)]
+    pub registry: Registry,
+    /// Highest sequence value reserved; none above it was ever handed out
+    #[serde(default)]
+    pub sequence: u64,
+    /// Limits on what the store may hold
+    #[serde(default)]
+    pub quota: Quota,
+    /// Secondary index backfill under way, if any
+    #[serde(default)]
+    pub backfill: Option<Backfill>,
+    /// Live/dead record counts per segment of the raw bucket
+    #[serde(default)]
+    pub raw: BTreeMap<u64, Tally>,
+    /// Fingerprints of the keys secret fields were sealed under, by
+    /// number from 1; the last one seals new records
+    #[serde(default)]
+    pub keys: Vec<String>,
+}
+
+/// Progress of an online secondary index backfill, see `Store::backfill`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Backfill {
+    /// Primary key of the last record indexed, `None` before the first
+    pub watermark: Option<Vec<u8>>,
+    /// Records indexed so far
+    pub records: u64,
+}
+
+/// Limits on what a store may hold, unlimited where `None`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// Most live records
+    pub records: Option<u64>,
+    /// Most stored bytes of live records
+    pub bytes: Option<u64>,
+}
+
+/// Lifetime activity of a store, persisted with the manifest
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Counters {
+    /// Records written, including replacements
+    pub written: u64,
+    /// Records deleted
+    pub deleted: u64,
+    /// Bytes of record data written
+    pub bytes: u64,
+    /// Compaction passes completed
+    pub compactions: u64,
+}
+
+/// Schema version assumed for manifests written before it was recorded
+fn schema() -> u32 {
+    1
+}
+
+/// Codec assumed for manifests written before it was recorded
+fn codec() -> String {
+    "rkyv".to_string()
+}
+
+/// Progress of rewriting records of an older schema, see `migration`
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Upgrade {
+    /// Primary key of the last record rewritten, `None` before the first
+    pub watermark: Option<Vec<u8>>,
+    /// Records rewritten so far
+    pub records: u64,
+    /// Active segment when the upgrade began; it and later ones hold only rewritten records
+    pub segment: u64,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            segments: Vec::new(),
+            generation: 0,
+            schema: schema(),
+            upgrade: None,
+            watermark: 0,
+            tallies: BTreeMap::new(),
+            sealed: Vec::new(),
+            retention: Retention::default(),
+            counters: Counters::default(),
+            expiry: None,
+            codec: codec(),
+            registry: Registry::default(),
+            sequence: 0,
+            quota: Quota::default(),
+            backfill: None,
+            raw: BTreeMap::new(),
+            keys: Vec::new(),
+        }
+    }
+}
+
+impl Manifest {
+    /// Loads the manifest from a store base directory, if present
+    pub fn load<P: AsRef<Path>>(base: P) -> Result<Option<Self>> {
+        let path = Self::locate(base);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let data = std::fs::read(&path)?;
+        let manifest: Self = serde_json::from_slice(&data)
+            .map_err(|e| Error::Format(format!("Manifest parse failed: {}", e)))?;
+
+        if manifest.version > VERSION {
+            return Err(Error::Unsupported(format!("Manifest version {}", manifest.version)));
+        }
+
+        Ok(Some(manifest))
+    }
+
+    /// Persists the manifest atomically via write, fsync and rename
+    pub fn save<P: AsRef<Path>>(&self, base: P) -> Result<()> {
+        let path = Self::locate(&base);
+        let temp = path.with_extension("tmp");
+
+        let data = serde_json::to_vec_pretty(self)
+            .map_err(|e| Error::Serialize(format!("Manifest serialization failed: {}", e)))?;
+
+        let mut file = File::create(&temp)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        drop(file);
+
+        std::fs::rename(&temp, &path)?;
+        directory::parent(&path)?;
+        Ok(())
+    }
+
+    /// Path of the manifest file for a base directory
+    pub fn locate<P: AsRef<Path>>(base: P) -> PathBuf {
+        base.as_ref().join(NAME)
+    }
+}
@@ -0,0 +1,118 @@
+//! Schema migration plans
+//!
+//! Records written under an older schema are rewritten in the current
+//! layout when a store opens, a page at a time in key order, with the
+//! progress kept in the manifest so an interrupted upgrade resumes
+//! where it stopped. That costs as much as copying every live record,
+//! so `plan` describes the work from the manifest alone, without
+//! opening the store, and `Builder::hold` keeps stores from upgrading
+//! until the plan is applied by opening one without it.
+
+use std::path::Path;
+use serde::Serialize;
+use crate::{Error, Result};
+use crate::manifest::Manifest;
+use crate::model::SCHEMA;
+use crate::registry::{Change, Registry};
+
+/// Bytes of records rewritten per second assumed by estimates
+///
+/// A conservative figure for one thread reading, converting and
+/// appending records; real upgrades on fast disks run well above it.
+pub const RATE: u64 = 32 * 1024 * 1024;
+
+/// Work moving a store's records to a schema version
+#[derive(Debug, Clone, Serialize)]
+pub struct Migration {
+    /// Schema the records are in
+    pub from: u32,
+    /// Schema they move to
+    pub to: u32,
+    /// How fields differ between the two
+    pub changes: Vec<Change>,
+    /// Segments whose live records are rewritten
+    pub segments: Vec<Rewrite>,
+    /// Records still to rewrite
+    pub records: u64,
+    /// Stored bytes of those records
+    pub bytes: u64,
+    /// Records an interrupted upgrade already rewrote
+    pub done: u64,
+    /// Estimated time to apply, in seconds, at `RATE`
+    pub seconds: u64,
+}
+
+/// A segment holding records to rewrite
+#[derive(Debug, Clone, Serialize)]
+pub struct Rewrite {
+    /// Segment identifier
+    pub segment: u64,
+    /// Live records in it
+    pub records: u64,
+    /// Stored bytes of those records
+    pub bytes: u64,
+}
+
+impl Migration {
+    /// Whether the records are already in the target schema
+    pub fn current(&self) -> bool {
+        self.from == self.to
+    }
+}
+
+/// Plans moving the records of the store at `base` to schema `target`
+///
+/// Only the manifest is read, and the store need not open. Records
+/// move only to the schema this build writes, `SCHEMA`, so any other
+/// target fails with `Error::Unsupported`, as does a store whose
+/// manifest is newer than this build. Fails with `Error::Config` when
+/// there is no manifest; opening the store once writes one.
+pub fn plan(base: &Path, target: u32) -> Result<Migration> {
+    let manifest = Manifest::load(base)?
+        .ok_or_else(|| Error::Config(format!("No store manifest in {}", base.display())))?;
+    let from = manifest.schema;
+    if target != SCHEMA || from > SCHEMA {
+        return Err(Error::Unsupported(format!(
+            "Records of schema {} cannot move to schema {}; this build writes schema {}",
+            from, target, SCHEMA,
+        )));
+    }
+
+    let mut registry = manifest.registry.clone();
+    registry.merge(&Registry::builtin())?;
+    let changes = registry.changes(from, target)?;
+
+    let mut segments = Vec::new();
+    let upgrade = manifest.upgrade.clone().unwrap_or_default();
+    if from < target {
+        for &segment in &manifest.segments {
+            // Segments an interrupted upgrade appended to hold only rewritten records
+            if upgrade.segment > 0 && segment >= upgrade.segment {
+                continue;
+            }
+            let Some(tally) = manifest.tallies.get(&segment).filter(|tally| tally.live > 0) else {
+                continue;
+            };
+            let mut bytes = tally.bytes;
+            // Manifests from before live bytes were tallied count the whole file
+            if bytes == 0 {
+                let path = base.join("segments").join(format!("segment_{}.dat", segment));
+                bytes = std::fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0);
+            }
+            segments.push(Rewrite { segment, records: tally.live, bytes });
+        }
+    }
+
+    let records = segments.iter().map(|rewrite| rewrite.records).sum();
+    let bytes = segments.iter().map(|rewrite| rewrite.bytes).sum();
+    Ok(Migration {
+        from,
+        to: target,
+        changes,
+        segments,
+        records,
+        bytes,
+        done: upgrade.records,
+        seconds: bytes.div_ceil(RATE),
+    })
+}
@@ -1,13 +1,45 @@
 //! Data models for Guardian-Store
 //! 
 //! All structs follow the single-word identifier manifesto and
-//! support zero-copy serialization with rkyv.
+//! support zero-copy serialization with rkyv. User records also
+//! derive serde so they can be exchanged as JSON.
 
-use rkyv::{Archive, Serialize, Deserialize};
+use rkyv::{Archive, Serialize, Deserialize, Infallible};
+use guardian_macros::Record;
+
+/// Layout version of records written by this build
+///
+/// Bumped whenever an archived model changes shape; older layouts
+/// live in `legacy` and are rewritten when a store is opened.
+pub const SCHEMA: u32 = 3;
+
+/// Represents a point on the Earth's surface in degrees.
+/// Original concept: "Geo Coordinate"
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Record)]
+#[archive(check_bytes)]
+pub struct Point {
+    /// Latitude in degrees, -90 to 90
+    pub latitude: f64,
+    /// Longitude in degrees, -180 to 180
+    pub longitude: f64,
+}
+
+impl Point {
+    /// Great-circle distance to another point in meters (haversine)
+    pub fn distance(&self, other: &Point) -> f64 {
+        let (a, b) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let dlat = b - a;
+        let dlon = (other.longitude - self.longitude).to_radians();
+        
+        let h = (dlat / 2.0).sin().powi(2) + a.cos() * b.cos() * (dlon / 2.0).sin().powi(2);
+        2.0 * crate::atlas::RADIUS * h.sqrt().min(1.0).asin()
+    }
+}
 
 /// Represents a user's geographical location.
 /// Original concept: "User Address"
-#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Debug, Clone, Record)]
+#[archive(check_bytes)]
 pub struct Location {
     /// Street address
     pub street: String,
@@ -17,11 +49,15 @@ pub struct Location {
     pub country: String,
     /// Postal code
     pub postal: String,
+    /// Coordinates, when known
+    #[serde(default)]
+    pub point: Option<Point>,
 }
 
 /// Represents user profile information.
 /// Original concept: "User Profile"
-#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Debug, Clone, Default, Record)]
+#[archive(check_bytes)]
 pub struct Profile {
     /// User's age
     pub age: u32,
@@ -33,27 +69,191 @@ pub struct Profile {
 
 /// Represents a system user entity.
 /// Original concept: "User Account"
-#[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+#[derive(Archive, Serialize, Deserialize, serde::Serialize, serde::Deserialize, Debug, Clone, Record)]
+#[archive(check_bytes)]
 pub struct User {
     /// Unique user identifier
     pub id: u64,
     /// User's display name
     pub name: String,
-    /// User's email address
+    /// User's email address, unique across the store and sealed at rest
+    #[index(unique)]
+    #[secret]
     pub email: String,
     /// User's geographical location
     pub location: Location,
     /// User's profile information (optional for schema evolution)
+    #[serde(default)]
     pub profile: Option<Profile>,
     /// Account creation timestamp
+    #[serde(default)]
     pub created: u64,
     /// Last update timestamp
+    #[serde(default)]
     pub updated: u64,
+    /// Write counter assigned by the store, starting at 1
+    #[serde(default)]
+    pub revision: u64,
+}
+
+/// Names a field of a user, for reading records partially or looking them up.
+/// Original concept: "Column"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub enum Field {
+    /// `User::name`
+    Name,
+    /// `User::email`
+    Email,
+    /// `User::location`
+    Location,
+    /// `User::location.city`
+    City,
+    /// `User::location.country`
+    Country,
+    /// `User::profile`
+    Profile,
+    /// `User::created`
+    Created,
+    /// `User::updated`
+    Updated,
+    /// `User::revision`
+    Revision,
+}
+
+impl Field {
+    /// The field a `User` field name refers to, as listed in `User::FIELDS`
+    pub fn named(name: &str) -> Option<Self> {
+        match name {
+            "name" => Some(Self::Name),
+            "email" => Some(Self::Email),
+            "location" => Some(Self::Location),
+            "profile" => Some(Self::Profile),
+            "created" => Some(Self::Created),
+            "updated" => Some(Self::Updated),
+            "revision" => Some(Self::Revision),
+            _ => None,
+        }
+    }
+
+    /// The field a name from `name` refers to, `city` and `country` included
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "city" => Some(Self::City),
+            "country" => Some(Self::Country),
+            name => Self::named(name),
+        }
+    }
+
+    /// Name of the field, `city` and `country` for those of the location
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Name => "name",
+            Self::Email => "email",
+            Self::Location => "location",
+            Self::City => "city",
+            Self::Country => "country",
+            Self::Profile => "profile",
+            Self::Created => "created",
+            Self::Updated => "updated",
+            Self::Revision => "revision",
+        }
+    }
+}
+
+/// The requested fields of a user; the rest stay `None`.
+/// Original concept: "Projected Row"
+#[derive(serde::Serialize, Debug, Clone, Default)]
+pub struct Projection {
+    /// Unique user identifier, always present
+    pub id: u64,
+    /// User's display name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// User's email address
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// User's geographical location
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<Location>,
+    /// City of the user's location
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub city: Option<String>,
+    /// Country code of the user's location
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub country: Option<String>,
+    /// User's profile, also `None` when the user has none
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<Profile>,
+    /// Account creation timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created: Option<u64>,
+    /// Last update timestamp
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated: Option<u64>,
+    /// Write counter assigned by the store
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<u64>,
+}
+
+impl Projection {
+    /// Copies the requested fields out of an archived user
+    ///
+    /// Only those fields are deserialized, so a large profile costs
+    /// nothing unless it is asked for.
+    pub fn select(user: &ArchivedUser, fields: &[Field]) -> Self {
+        let mut projection = Self {
+            id: user.id,
+            ..Self::default()
+        };
+        // Deserializing with `Infallible` cannot fail
+        for field in fields {
+            match field {
+                Field::Name => projection.name = Some(user.name.to_string()),
+                Field::Email => projection.email = Some(user.email.to_string()),
+                Field::Location => {
+                    projection.location = Some(user.location.deserialize(&mut Infallible).unwrap());
+                }
+                Field::City => projection.city = Some(user.location.city.to_string()),
+                Field::Country => projection.country = Some(user.location.country.to_string()),
+                Field::Profile => {
+                    projection.profile = user.profile.as_ref()
+                        .map(|profile| profile.deserialize(&mut Infallible).unwrap());
+                }
+                Field::Created => projection.created = Some(user.created),
+                Field::Updated => projection.updated = Some(user.updated),
+                Field::Revision => projection.revision = Some(user.revision),
+            }
+        }
+        projection
+    }
+
+    /// Copies the requested fields out of a user already read
+    pub fn pick(user: &User, fields: &[Field]) -> Self {
+        let mut projection = Self {
+            id: user.id,
+            ..Self::default()
+        };
+        for field in fields {
+            match field {
+                Field::Name => projection.name = Some(user.name.clone()),
+                Field::Email => projection.email = Some(user.email.clone()),
+                Field::Location => projection.location = Some(user.location.clone()),
+                Field::City => projection.city = Some(user.location.city.clone()),
+                Field::Country => projection.country = Some(user.location.country.clone()),
+                Field::Profile => projection.profile = user.profile.clone(),
+                Field::Created => projection.created = Some(user.created),
+                Field::Updated => projection.updated = Some(user.updated),
+                Field::Revision => projection.revision = Some(user.revision),
+            }
+        }
+        projection
+    }
 }
 
 /// Represents a data record position in storage.
 /// Original concept: "Storage Location"
-#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Archive, Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[archive(check_bytes)]
 pub struct Position {
     /// Segment identifier
     pub segment: u64,
@@ -66,6 +266,7 @@ pub struct Position {
 /// Represents metadata for a storage segment.
 /// Original concept: "Segment Metadata"
 #[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+#[archive(check_bytes)]
 pub struct Metadata {
     /// Segment identifier
     pub id: u64,
@@ -77,11 +278,17 @@ pub struct Metadata {
     pub bytes: u64,
     /// Schema version for this segment
     pub schema: u32,
+    /// Number of the key secret fields are sealed under, 0 for none
+    ///
+    /// Fills what was padding after `schema`, which rkyv writes as
+    /// zeros, so headers from before it read as 0.
+    pub key: u32,
 }
 
 /// Represents a storage segment header.
 /// Original concept: "Segment Header"
 #[derive(Archive, Serialize, Deserialize, Debug, Clone)]
+#[archive(check_bytes)]
 pub struct Header {
     /// Magic number for validation
     pub magic: u32,
@@ -90,23 +297,3 @@ pub struct Header {
     /// Checksum for integrity
     pub checksum: u64,
 }
-
-impl Default for Profile {
-    fn default() -> Self {
-        Self {
-            age: 0,
-            job: String::new(),
-            interests: Vec::new(),
-        }
-    }
-}
-
-impl Default for Position {
-    fn default() -> Self {
-        Self {
-            segment: 0,
-            offset: 0,
-            length: 0,
-        }
-    }
-} 
\ No newline at end of file
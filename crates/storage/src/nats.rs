@@ -0,0 +1,62 @@
+//! NATS JetStream change-stream sink
+//!
+//! Publishes each change to a subject covered by a JetStream stream.
+//! Every message carries its audit position as `Nats-Msg-Id`, so the
+//! server drops duplicates a restarted publisher resends, and a batch
+//! only counts as sent once the stream has acknowledged each message.
+
+use async_nats::HeaderMap;
+use async_nats::jetstream::{self, Context};
+use tokio::runtime::Runtime;
+use crate::{Error, Result};
+use crate::audit::Entry;
+use crate::model::Position;
+use crate::publish::{message, Sink};
+
+/// Sink publishing changes to a JetStream subject
+pub struct Nats {
+    /// JetStream handle on the connection
+    context: Context,
+    /// Subject changes are published to
+    subject: String,
+    /// Runs the connection in the background
+    runtime: Runtime,
+}
+
+impl Nats {
+    /// Connects to a server, such as `nats://localhost:4222`
+    pub fn new(url: &str, subject: impl Into<String>) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        let client = runtime.block_on(async_nats::connect(url)).map_err(failed)?;
+        Ok(Self { context: jetstream::new(client), subject: subject.into(), runtime })
+    }
+}
+
+impl Sink for Nats {
+    fn send(&mut self, changes: &[(Position, Entry)]) -> Result<()> {
+        self.runtime.block_on(async {
+            let mut acknowledgements = Vec::with_capacity(changes.len());
+            for (position, entry) in changes {
+                let mut headers = HeaderMap::new();
+                headers.insert("Nats-Msg-Id", format!("{}-{}", position.segment, position.offset).as_str());
+                let acknowledgement = self.context
+                    .publish_with_headers(self.subject.clone(), headers, message(entry)?.into())
+                    .await
+                    .map_err(failed)?;
+                acknowledgements.push(acknowledgement);
+            }
+            for acknowledgement in acknowledgements {
+                acknowledgement.await.map_err(failed)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Wraps a client error
+fn failed(error: impl std::fmt::Display) -> Error {
+    Error::Publish(format!("NATS: {}", error))
+}
@@ -0,0 +1,387 @@
+//! Parquet export
+//!
+//! Writes users as a flat Parquet file, one column per field, so
+//! analytics engines such as DuckDB or Spark can query a store's data.
+//! The writer is self-contained: values are plain-encoded and
+//! uncompressed, each column chunk is a single data page, and rows are
+//! flushed in bounded row groups so exports stream in constant memory.
+
+use std::io::Write;
+use crate::Result;
+use crate::model::User;
+
+/// File magic at both ends of a Parquet file
+const MAGIC: &[u8; 4] = b"PAR1";
+
+/// Rows buffered per row group
+pub const ROWS: usize = 64 * 1024;
+
+/// Physical type of a column, with its Parquet type code
+#[derive(Debug, Clone, Copy)]
+enum Physical {
+    /// 32-bit integer
+    Int32 = 1,
+    /// 64-bit integer
+    Int64 = 2,
+    /// IEEE double
+    Double = 5,
+    /// Length-prefixed bytes
+    Bytes = 6,
+}
+
+/// Logical annotations (Parquet converted types) used by the export
+mod converted {
+    pub const UTF8: i32 = 0;
+    pub const UINT32: i32 = 13;
+    pub const UINT64: i32 = 14;
+    pub const JSON: i32 = 19;
+}
+
+/// Column layout: name, physical type, whether it may be null, annotation
+const SCHEMA: [(&str, Physical, bool, i32); 15] = [
+    ("id", Physical::Int64, false, converted::UINT64),
+    ("name", Physical::Bytes, false, converted::UTF8),
+    ("email", Physical::Bytes, false, converted::UTF8),
+    ("street", Physical::Bytes, false, converted::UTF8),
+    ("city", Physical::Bytes, false, converted::UTF8),
+    ("country", Physical::Bytes, false, converted::UTF8),
+    ("postal", Physical::Bytes, false, converted::UTF8),
+    ("latitude", Physical::Double, true, -1),
+    ("longitude", Physical::Double, true, -1),
+    ("age", Physical::Int32, true, converted::UINT32),
+    ("job", Physical::Bytes, true, converted::UTF8),
+    ("interests", Physical::Bytes, true, converted::JSON),
+    ("created", Physical::Int64, false, converted::UINT64),
+    ("updated", Physical::Int64, false, converted::UINT64),
+    ("revision", Physical::Int64, false, converted::UINT64),
+];
+
+/// Values of one column in the current row group
+#[derive(Debug, Default)]
+struct Column {
+    /// Plain-encoded non-null values
+    values: Vec<u8>,
+    /// Whether each row holds a value
+    defined: Vec<bool>,
+}
+
+impl Column {
+    /// Appends a value, or a null when `None`
+    fn push(&mut self, value: Option<&[u8]>) {
+        self.defined.push(value.is_some());
+        if let Some(value) = value {
+            self.values.extend_from_slice(value);
+        }
+    }
+
+    /// Appends a length-prefixed byte string
+    fn text(&mut self, value: Option<&str>) {
+        self.defined.push(value.is_some());
+        if let Some(value) = value {
+            self.values.extend_from_slice(&(value.len() as u32).to_le_bytes());
+            self.values.extend_from_slice(value.as_bytes());
+        }
+    }
+}
+
+/// Where a written column chunk lies
+#[derive(Debug, Clone, Copy)]
+struct Chunk {
+    /// Offset of its data page header
+    offset: u64,
+    /// Bytes including the page header
+    size: u64,
+}
+
+/// A flushed row group
+#[derive(Debug)]
+struct Group {
+    /// Column chunks in schema order
+    chunks: Vec<Chunk>,
+    /// Rows in the group
+    rows: u64,
+}
+
+/// Streams users into a Parquet file
+pub struct Writer<W: Write> {
+    /// Destination
+    out: W,
+    /// Bytes written so far
+    offset: u64,
+    /// Buffered columns of the current row group
+    columns: Vec<Column>,
+    /// Row groups already written
+    groups: Vec<Group>,
+}
+
+impl<W: Write> Writer<W> {
+    /// Starts a Parquet file
+    pub fn new(mut out: W) -> Result<Self> {
+        out.write_all(MAGIC)?;
+        Ok(Self {
+            out,
+            offset: MAGIC.len() as u64,
+            columns: SCHEMA.iter().map(|_| Column::default()).collect(),
+            groups: Vec::new(),
+        })
+    }
+
+    /// Appends one user as a row
+    pub fn push(&mut self, user: &User) -> Result<()> {
+        let point = user.location.point;
+        let profile = user.profile.as_ref();
+        let interests = profile.map(|profile| serde_json::to_string(&profile.interests).unwrap_or_default());
+
+        let [id, name, email, street, city, country, postal, latitude, longitude, age, job, list, created, updated, revision] = &mut self.columns[..] else {
+            unreachable!("one column per schema entry");
+        };
+        id.push(Some(&user.id.to_le_bytes()));
+        name.text(Some(&user.name));
+        email.text(Some(&user.email));
+        street.text(Some(&user.location.street));
+        city.text(Some(&user.location.city));
+        country.text(Some(&user.location.country));
+        postal.text(Some(&user.location.postal));
+        latitude.push(point.map(|point| point.latitude.to_le_bytes()).as_ref().map(|bytes| &bytes[..]));
+        longitude.push(point.map(|point| point.longitude.to_le_bytes()).as_ref().map(|bytes| &bytes[..]));
+        age.push(profile.map(|profile| profile.age.to_le_bytes()).as_ref().map(|bytes| &bytes[..]));
+        job.text(profile.map(|profile| profile.job.as_str()));
+        list.text(interests.as_deref());
+        created.push(Some(&user.created.to_le_bytes()));
+        updated.push(Some(&user.updated.to_le_bytes()));
+        revision.push(Some(&user.revision.to_le_bytes()));
+
+        if self.columns[0].defined.len() >= ROWS {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the buffered rows as a row group
+    fn flush(&mut self) -> Result<()> {
+        let rows = self.columns[0].defined.len();
+        if rows == 0 {
+            return Ok(());
+        }
+
+        let mut chunks = Vec::with_capacity(SCHEMA.len());
+        for (column, &(_, _, optional, _)) in self.columns.iter_mut().zip(SCHEMA.iter()) {
+            let mut body = Vec::new();
+            if optional {
+                let levels = levels(&column.defined);
+                body.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+                body.extend_from_slice(&levels);
+            }
+            body.extend_from_slice(&column.values);
+
+            let mut header = Compact::new();
+            header.int(1, 0); // DATA_PAGE
+            header.int(2, body.len() as i32);
+            header.int(3, body.len() as i32);
+            header.open(5);
+            header.int(1, rows as i32);
+            header.int(2, 0); // PLAIN
+            header.int(3, 3); // RLE
+            header.int(4, 3); // RLE
+            header.close();
+            let header = header.finish();
+
+            self.out.write_all(&header)?;
+            self.out.write_all(&body)?;
+            let size = (header.len() + body.len()) as u64;
+            chunks.push(Chunk { offset: self.offset, size });
+            self.offset += size;
+
+            *column = Column::default();
+        }
+
+        self.groups.push(Group { chunks, rows: rows as u64 });
+        Ok(())
+    }
+
+    /// Writes the remaining rows and the footer, returning the row count
+    pub fn finish(mut self) -> Result<u64> {
+        self.flush()?;
+        let total: u64 = self.groups.iter().map(|group| group.rows).sum();
+
+        let mut meta = Compact::new();
+        meta.int(1, 1);
+        meta.list(2, STRUCT, SCHEMA.len() + 1);
+        meta.element();
+        meta.binary(4, b"schema");
+        meta.int(5, SCHEMA.len() as i32);
+        meta.close();
+        for &(name, physical, optional, annotation) in &SCHEMA {
+            meta.element();
+            meta.int(1, physical as i32);
+            meta.int(3, i32::from(optional));
+            meta.binary(4, name.as_bytes());
+            if annotation >= 0 {
+                meta.int(6, annotation);
+            }
+            meta.close();
+        }
+        meta.long(3, total as i64);
+        meta.list(4, STRUCT, self.groups.len());
+        for group in &self.groups {
+            meta.element();
+            meta.list(1, STRUCT, group.chunks.len());
+            for (chunk, &(name, physical, _, _)) in group.chunks.iter().zip(SCHEMA.iter()) {
+                meta.element();
+                meta.long(2, chunk.offset as i64);
+                meta.open(3);
+                meta.int(1, physical as i32);
+                meta.list(2, I32, 2);
+                meta.item(0); // PLAIN
+                meta.item(3); // RLE
+                meta.list(3, BINARY, 1);
+                meta.text(name.as_bytes());
+                meta.int(4, 0); // UNCOMPRESSED
+                meta.long(5, group.rows as i64);
+                meta.long(6, chunk.size as i64);
+                meta.long(7, chunk.size as i64);
+                meta.long(9, chunk.offset as i64);
+                meta.close();
+                meta.close();
+            }
+            meta.long(2, group.chunks.iter().map(|chunk| chunk.size).sum::<u64>() as i64);
+            meta.long(3, group.rows as i64);
+            meta.close();
+        }
+        meta.binary(6, b"guardian-store");
+        let meta = meta.finish();
+
+        self.out.write_all(&meta)?;
+        self.out.write_all(&(meta.len() as u32).to_le_bytes())?;
+        self.out.write_all(MAGIC)?;
+        self.out.flush()?;
+        Ok(total)
+    }
+}
+
+/// Encodes definition levels of width 1 as RLE runs
+fn levels(defined: &[bool]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut rest = defined;
+    while let Some(&first) = rest.first() {
+        let run = rest.iter().take_while(|&&value| value == first).count();
+        varint(&mut out, (run as u64) << 1);
+        out.push(u8::from(first));
+        rest = &rest[run..];
+    }
+    out
+}
+
+/// Appends an unsigned LEB128 varint
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Compact protocol type of a 32-bit integer
+const I32: u8 = 5;
+/// Compact protocol type of a 64-bit integer
+const I64: u8 = 6;
+/// Compact protocol type of a byte string
+const BINARY: u8 = 8;
+/// Compact protocol type of a list
+const LIST: u8 = 9;
+/// Compact protocol type of a struct
+const STRUCT: u8 = 12;
+
+/// Thrift compact protocol encoder for the footer and page headers
+struct Compact {
+    /// Encoded bytes
+    out: Vec<u8>,
+    /// Last field id of each open struct
+    last: Vec<i16>,
+}
+
+impl Compact {
+    /// Starts encoding a top-level struct
+    fn new() -> Self {
+        Self {
+            out: Vec::new(),
+            last: vec![0],
+        }
+    }
+
+    /// Writes a field header
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last.last_mut().unwrap();
+        let delta = id - *last;
+        if (1..=15).contains(&delta) {
+            self.out.push((delta as u8) << 4 | kind);
+        } else {
+            self.out.push(kind);
+            varint(&mut self.out, u64::from(((id << 1) ^ (id >> 15)) as u16));
+        }
+        *last = id;
+    }
+
+    /// Writes a 32-bit integer field
+    fn int(&mut self, id: i16, value: i32) {
+        self.field(id, I32);
+        self.item(value);
+    }
+
+    /// Writes a 64-bit integer field
+    fn long(&mut self, id: i16, value: i64) {
+        self.field(id, I64);
+        varint(&mut self.out, ((value << 1) ^ (value >> 63)) as u64);
+    }
+
+    /// Writes a byte string field
+    fn binary(&mut self, id: i16, value: &[u8]) {
+        self.field(id, BINARY);
+        self.text(value);
+    }
+
+    /// Writes a list header for `len` elements of `kind`
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.field(id, LIST);
+        if len < 15 {
+            self.out.push((len as u8) << 4 | kind);
+        } else {
+            self.out.push(0xF0 | kind);
+            varint(&mut self.out, len as u64);
+        }
+    }
+
+    /// Writes a 32-bit integer list element
+    fn item(&mut self, value: i32) {
+        varint(&mut self.out, ((value << 1) ^ (value >> 31)) as u32 as u64);
+    }
+
+    /// Writes a byte string list element
+    fn text(&mut self, value: &[u8]) {
+        varint(&mut self.out, value.len() as u64);
+        self.out.extend_from_slice(value);
+    }
+
+    /// Opens a struct field
+    fn open(&mut self, id: i16) {
+        self.field(id, STRUCT);
+        self.last.push(0);
+    }
+
+    /// Opens a struct list element
+    fn element(&mut self) {
+        self.last.push(0);
+    }
+
+    /// Closes the innermost struct
+    fn close(&mut self) {
+        self.out.push(0);
+        self.last.pop();
+    }
+
+    /// Closes the top-level struct and returns the bytes
+    fn finish(mut self) -> Vec<u8> {
+        self.out.push(0);
+        self.out
+    }
+}
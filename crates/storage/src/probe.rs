@@ -0,0 +1,86 @@
+//! Device micro-benchmarks
+//!
+//! Measures what the disk under a store charges for an fsync, so the
+//! write queue can pick its group-commit window from the device rather
+//! than from a guess. A window about as long as one fsync lets writes
+//! arriving during a flush share the next one: throughput grows with the
+//! producers while a lone write waits at most one more fsync.
+//!
+//! The probe writes and syncs a scratch file ending in `.tmp` next to
+//! the store's files and removes it after; one left by a crash is
+//! removed by garbage collection like any other temporary file.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+use crate::Result;
+
+/// Fsyncs timed by a probe at open
+pub const ROUNDS: usize = 16;
+
+/// Bytes written before each fsync, one page
+const BLOCK: usize = 4096;
+
+/// Shortest window picked; below it the wait costs more than it groups
+const FLOOR: Duration = Duration::from_micros(100);
+
+/// Longest window picked, however slow the device
+const CEILING: Duration = Duration::from_millis(10);
+
+/// Name of the scratch file
+const SCRATCH: &str = "probe.tmp";
+
+/// Fsync latency measured on a device
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Latency {
+    /// Fsyncs timed
+    pub rounds: usize,
+    /// Median time of one write and fsync
+    pub median: Duration,
+    /// Slowest write and fsync
+    pub worst: Duration,
+}
+
+impl Latency {
+    /// Group-commit window for this device: the median fsync, within bounds
+    pub fn window(&self) -> Duration {
+        self.median.clamp(FLOOR, CEILING)
+    }
+}
+
+/// Times `rounds` page writes each followed by an fsync in `dir`
+///
+/// The first fsync, which also settles the new file's metadata, is not
+/// counted.
+pub fn fsync<P: AsRef<Path>>(dir: P, rounds: usize) -> Result<Latency> {
+    let path = dir.as_ref().join(SCRATCH);
+    let outcome = time(&path, rounds.max(1));
+    let removed = std::fs::remove_file(&path);
+    let latency = outcome?;
+    removed?;
+    Ok(latency)
+}
+
+/// Writes and syncs the scratch file, timing each round
+fn time(path: &Path, rounds: usize) -> Result<Latency> {
+    let mut file = OpenOptions::new().create(true).write(true).truncate(true).open(path)?;
+    let block = [0u8; BLOCK];
+    file.write_all(&block)?;
+    file.sync_all()?;
+    
+    let mut times = Vec::with_capacity(rounds);
+    for _ in 0..rounds {
+        let started = Instant::now();
+        file.write_all(&block)?;
+        file.sync_data()?;
+        times.push(started.elapsed());
+    }
+    times.sort_unstable();
+    Ok(Latency {
+        rounds,
+        median: times[rounds / 2],
+        worst: times[rounds - 1],
+    })
+}
@@ -0,0 +1,138 @@
+//! Change-stream publishing
+//!
+//! A `Publisher` forwards the store's change stream, its audit log, to
+//! a message broker through a `Sink`. The position of the last entry a
+//! sink acknowledged is persisted under the store, so a restarted
+//! publisher resumes where it stopped. Changes are delivered at least
+//! once: a crash or failure after a send but before the cursor is saved
+//! repeats that batch.
+//!
+//! Sinks for Kafka and NATS JetStream live behind the `kafka` and
+//! `nats` features.
+
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use crate::{directory, Error, Result, Store};
+use crate::audit::Entry;
+use crate::model::Position;
+
+/// Directory holding publish cursors inside a store
+const NAME: &str = "publish";
+
+/// Changes read and sent per batch by default
+const BATCH: usize = 256;
+
+/// Destination of published changes
+pub trait Sink {
+    /// Sends changes in order, returning once the broker holds all of them
+    ///
+    /// Positions identify entries uniquely within a store, for brokers
+    /// that deduplicate.
+    fn send(&mut self, changes: &[(Position, Entry)]) -> Result<()>;
+}
+
+/// Forwards a store's changes to a sink, resuming after restarts
+pub struct Publisher<S> {
+    /// Where changes go
+    sink: S,
+    /// File holding the cursor
+    path: PathBuf,
+    /// Position of the last change the sink acknowledged
+    cursor: Option<Position>,
+    /// Changes sent per batch
+    batch: usize,
+}
+
+impl<S: Sink> Publisher<S> {
+    /// Opens the publisher `name` of a store base directory at its saved cursor
+    ///
+    /// Each name keeps its own cursor, so several sinks can follow one
+    /// store. Names must be plain file names.
+    pub fn new<P: AsRef<Path>>(base: P, name: &str, sink: S) -> Result<Self> {
+        if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+            return Err(Error::Config(format!("Invalid publisher name {:?}", name)));
+        }
+        let path = base.as_ref().join(NAME).join(name);
+        let cursor = match std::fs::read(&path) {
+            Ok(bytes) => Some(decode(&bytes)?),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error.into()),
+        };
+        Ok(Self { sink, path, cursor, batch: BATCH })
+    }
+
+    /// Sets how many changes are sent per batch
+    pub fn batch(mut self, size: usize) -> Self {
+        self.batch = size.max(1);
+        self
+    }
+
+    /// Position of the last change the sink acknowledged
+    pub fn cursor(&self) -> Option<Position> {
+        self.cursor
+    }
+
+    /// The sink changes are sent to
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Sends every change after the cursor and returns how many went out
+    ///
+    /// The cursor is saved after each acknowledged batch. On error the
+    /// failed batch is retried from its start by the next call.
+    pub fn pump(&mut self, store: &Store) -> Result<usize> {
+        let mut sent = 0;
+        loop {
+            let changes = store.changes(self.cursor, self.batch)?;
+            let Some(&(last, _)) = changes.last() else { break };
+            self.sink.send(&changes)?;
+            self.save(last)?;
+            sent += changes.len();
+        }
+        Ok(sent)
+    }
+
+    /// Persists the cursor atomically via write, fsync and rename
+    fn save(&mut self, cursor: Position) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temp = self.path.with_extension("tmp");
+        let mut file = File::create(&temp)?;
+        file.write_all(&encode(cursor))?;
+        file.sync_all()?;
+        drop(file);
+
+        std::fs::rename(&temp, &self.path)?;
+        directory::parent(&self.path)?;
+        self.cursor = Some(cursor);
+        Ok(())
+    }
+}
+
+/// Serializes a change as the JSON message sinks publish
+pub fn message(entry: &Entry) -> Result<Vec<u8>> {
+    serde_json::to_vec(entry)
+        .map_err(|e| Error::Serialize(format!("Change serialization failed: {}", e)))
+}
+
+/// Encodes a cursor as big-endian segment and offset
+fn encode(cursor: Position) -> [u8; 16] {
+    let mut bytes = [0u8; 16];
+    bytes[..8].copy_from_slice(&cursor.segment.to_be_bytes());
+    bytes[8..].copy_from_slice(&cursor.offset.to_be_bytes());
+    bytes
+}
+
+/// Decodes a saved cursor
+fn decode(bytes: &[u8]) -> Result<Position> {
+    let bytes = <[u8; 16]>::try_from(bytes)
+        .map_err(|_| Error::Format(format!("Publish cursor of {} bytes", bytes.len())))?;
+    Ok(Position {
+        segment: u64::from_be_bytes(bytes[..8].try_into().unwrap()),
+        offset: u64::from_be_bytes(bytes[8..].try_into().unwrap()),
+        ..Position::default()
+    })
+}
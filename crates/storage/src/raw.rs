@@ -0,0 +1,285 @@
+//! Raw key-value access
+//!
+//! `Store::raw` hands out a `Raw` view storing plain byte values under
+//! plain byte keys, so the engine can back data models other than
+//! `User`. Raw entries live apart from users, in a segment log and an
+//! index of their own under `raw/`, so user scans, secondary indexes
+//! and compaction never see them. Values take the store's compression
+//! but skip its codec.
+//!
+//! Each record holds its key ahead of the value, so a record names the
+//! key it belongs to. `Raw::compact` reclaims the space of overwritten
+//! and deleted values.
+
+use std::collections::BTreeMap;
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
+use std::sync::MutexGuard;
+use crate::{Error, Result};
+use crate::compaction::Compaction;
+use crate::index::Index;
+use crate::model::Position;
+use crate::segment::{Segment, Tally};
+
+/// Directory holding raw entries inside a store
+const NAME: &str = "raw";
+
+/// Entries read per index page by `range`
+const PAGE: usize = 256;
+
+/// Raw segments and the index over their keys, owned by a store
+pub struct Bucket {
+    /// Directory of the bucket
+    base: PathBuf,
+    /// Log of key-value records
+    segment: Segment,
+    /// Positions of the current record of every key
+    index: Index,
+}
+
+impl Bucket {
+    /// Opens the bucket of a store base directory
+    ///
+    /// Live counts come from the index; `dead` carries the dead counts
+    /// last persisted, which the index cannot tell.
+    pub fn open<P: AsRef<Path>>(base: P, segment: impl FnOnce(Segment) -> Segment, dead: &BTreeMap<u64, Tally>) -> Result<Self> {
+        let base = Self::locate(base);
+        let index = Index::new(base.join("index"))?;
+        let live = Segment::discover(&base.join("segments"))?;
+        let mut tallies: BTreeMap<u64, Tally> = live.iter()
+            .map(|&id| (id, Tally { dead: dead.get(&id).map_or(0, |tally| tally.dead), ..Tally::default() }))
+            .collect();
+        for result in index.scan() {
+            let (_, position) = result?;
+            if let Some(tally) = tallies.get_mut(&position.segment) {
+                tally.live += 1;
+                tally.bytes += position.length;
+            }
+        }
+        let segment = segment(Segment::restore(base.join("segments"), live, tallies, Vec::new())?);
+        Ok(Self { base, segment, index })
+    }
+
+    /// Directory of the bucket for a base directory
+    pub fn locate<P: AsRef<Path>>(base: P) -> PathBuf {
+        base.as_ref().join(NAME)
+    }
+
+    /// Live/dead record counts per segment, for the manifest
+    pub fn tallies(&self) -> BTreeMap<u64, Tally> {
+        self.segment.tallies()
+    }
+
+    /// Flushes segments and index to disk
+    pub fn sync(&self) -> Result<()> {
+        self.segment.sync()?;
+        self.index.sync()
+    }
+
+    /// Seals the active segment and flushes the index
+    pub fn seal(&self) -> Result<()> {
+        self.segment.seal()?;
+        self.index.sync()
+    }
+
+    /// Approximate bytes of memory held by the index
+    pub fn memory(&self) -> usize {
+        self.index.memory()
+    }
+}
+
+/// Byte-keyed view of a store, see `Store::raw`
+pub struct Raw<'a> {
+    /// Entries of the store
+    bucket: MutexGuard<'a, Bucket>,
+    /// Whether every write is fsynced before it returns
+    durable: bool,
+}
+
+impl<'a> Raw<'a> {
+    /// Views a store's bucket
+    pub(crate) fn new(bucket: MutexGuard<'a, Bucket>, durable: bool) -> Self {
+        Self { bucket, durable }
+    }
+
+    /// Stores a value under a key, replacing any held
+    ///
+    /// Fails with `Error::Invalid` for an empty key.
+    pub fn put(&mut self, key: &[u8], value: &[u8]) -> Result<()> {
+        if key.is_empty() {
+            return Err(Error::Invalid { field: "key".to_string(), reason: "empty".to_string() });
+        }
+        let record = encode(key, value)?;
+        let position = self.bucket.segment.push(vec![record], u64::MAX)?[0];
+        let old = self.bucket.index.get(key)?;
+        self.bucket.index.put(key, position)?;
+        if let Some(old) = old {
+            self.bucket.segment.retire(old);
+        }
+        self.flush()
+    }
+
+    /// Retrieves the value under a key
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.bucket.index.get(key)? {
+            Some(position) => Ok(Some(self.read(key, position)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes a key, returning whether it was present
+    pub fn delete(&mut self, key: &[u8]) -> Result<bool> {
+        let Some(old) = self.bucket.index.get(key)? else {
+            return Ok(false);
+        };
+        self.bucket.index.delete(key)?;
+        self.bucket.segment.retire(old);
+        self.flush()?;
+        Ok(true)
+    }
+
+    /// Iterates over the keys within `range` and their values, in key order
+    ///
+    /// Keys compare as bytes, so `b"user/".as_slice()..b"user0".as_slice()`
+    /// covers every key starting `user/`.
+    pub fn range<R: RangeBounds<[u8]>>(&self, range: R) -> impl Iterator<Item = Result<(Vec<u8>, Vec<u8>)>> + '_ {
+        let owned = |bound: Bound<&[u8]>| bound.map(<[u8]>::to_vec);
+        let (start, end) = (owned(range.start_bound()), owned(range.end_bound()));
+        // The index starts strictly after a key, so an included start is looked up alone
+        let first = match &start {
+            Bound::Included(key) => self.bucket.index.get(key).transpose().map(|found| (key.clone(), found)),
+            _ => None,
+        };
+        let from = match start {
+            Bound::Included(key) | Bound::Excluded(key) => key,
+            Bound::Unbounded => Vec::new(),
+        };
+        let entries = first.into_iter()
+            .map(|(key, found)| found.map(|position| (key, position)))
+            .chain(Walk { index: &self.bucket.index, from: Some(from), page: Vec::new().into_iter(), done: false })
+            .take_while(move |entry| match (entry, &end) {
+                (Ok((key, _)), Bound::Included(end)) => key <= end,
+                (Ok((key, _)), Bound::Excluded(end)) => key < end,
+                _ => true,
+            });
+        entries.map(|entry| {
+            let (key, position) = entry?;
+            let value = self.read(&key, position)?;
+            Ok((key, value))
+        })
+    }
+
+    /// Rewrites segments where dead records pass `threshold` of the total
+    ///
+    /// Live records move to the active segment and the emptied segments
+    /// are deleted. Returns how many segments were reclaimed.
+    pub fn compact(&mut self, threshold: f64) -> Result<usize> {
+        let bucket = &mut *self.bucket;
+        let picked = Compaction::pick(&bucket.segment.tallies(), threshold, usize::MAX, bucket.segment.current());
+        if picked.is_empty() {
+            return Ok(0);
+        }
+        let mut survivors = Vec::new();
+        for result in bucket.index.scan() {
+            let (key, position) = result?;
+            if picked.contains(&position.segment) {
+                survivors.push((key, position));
+            }
+        }
+        for (key, position) in &survivors {
+            let record = bucket.segment.bytes(*position)?;
+            let moved = bucket.segment.push(vec![record], u64::MAX)?[0];
+            bucket.index.put(key, moved)?;
+        }
+        bucket.sync()?;
+        bucket.segment.release(&picked);
+        for id in &picked {
+            std::fs::remove_file(bucket.base.join("segments").join(format!("segment_{}.dat", id)))?;
+        }
+        Ok(picked.len())
+    }
+
+    /// Reads a value, checking its record belongs to `key`
+    fn read(&self, key: &[u8], position: Position) -> Result<Vec<u8>> {
+        let record = self.bucket.segment.bytes(position)?;
+        let (held, value) = decode(&record).ok_or_else(|| Error::Corrupt {
+            segment: position.segment,
+            offset: position.offset,
+            reason: "raw record too short".to_string(),
+        })?;
+        if held != key {
+            return Err(Error::Corrupt {
+                segment: position.segment,
+                offset: position.offset,
+                reason: "raw record of another key".to_string(),
+            });
+        }
+        Ok(value.to_vec())
+    }
+
+    /// Fsyncs the write just made when the store is durable
+    fn flush(&self) -> Result<()> {
+        if self.durable {
+            self.bucket.sync()?;
+        }
+        Ok(())
+    }
+}
+
+/// Index entries strictly after a key, a page at a time
+struct Walk<'a> {
+    /// Index walked
+    index: &'a Index,
+    /// Last key returned
+    from: Option<Vec<u8>>,
+    /// Rest of the current page
+    page: std::vec::IntoIter<(Vec<u8>, Position)>,
+    /// Set once the index is exhausted or failed
+    done: bool,
+}
+
+impl Iterator for Walk<'_> {
+    type Item = Result<(Vec<u8>, Position)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(entry) = self.page.next() {
+            self.from = Some(entry.0.clone());
+            return Some(Ok(entry));
+        }
+        if self.done {
+            return None;
+        }
+        match self.index.page(self.from.as_deref(), PAGE) {
+            Ok(page) if page.is_empty() => {
+                self.done = true;
+                None
+            }
+            Ok(page) => {
+                self.page = page.into_iter();
+                self.next()
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Lays out a record as key length, key and value
+fn encode(key: &[u8], value: &[u8]) -> Result<Vec<u8>> {
+    let length = u32::try_from(key.len())
+        .map_err(|_| Error::Invalid { field: "key".to_string(), reason: format!("{} bytes", key.len()) })?;
+    let mut record = Vec::with_capacity(4 + key.len() + value.len());
+    record.extend_from_slice(&length.to_le_bytes());
+    record.extend_from_slice(key);
+    record.extend_from_slice(value);
+    Ok(record)
+}
+
+/// Splits a record into its key and value
+fn decode(record: &[u8]) -> Option<(&[u8], &[u8])> {
+    let length = u32::from_le_bytes(record.get(..4)?.try_into().ok()?) as usize;
+    let key = record.get(4..4 + length)?;
+    Some((key, &record[4 + length..]))
+}
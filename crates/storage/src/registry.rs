@@ -0,0 +1,157 @@
+//! Schema registry
+//!
+//! Records the field layout of every schema version a store has held,
+//! keyed by the number stamped in the manifest and segment headers.
+//! Layouts come from the `Record` derive on the model and on the
+//! legacy types, and are persisted with the manifest: a store keeps
+//! describing its records after the build that wrote them is gone, and
+//! a model changed without bumping `SCHEMA` is caught when it opens.
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use crate::{Error, Result};
+use crate::legacy::{first, second};
+use crate::model::{self, SCHEMA};
+
+/// One field of a recorded struct
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Member {
+    /// Field name
+    pub name: String,
+    /// Declared type, without module paths
+    pub kind: String,
+}
+
+/// Field layouts of one schema version, by struct name
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Layout {
+    /// Fields of each struct in declaration order
+    pub structs: BTreeMap<String, Vec<Member>>,
+}
+
+impl Layout {
+    /// Adds a struct as listed by its `Record` derive
+    fn with(mut self, name: &str, fields: &[(&str, &str)]) -> Self {
+        let members = fields.iter()
+            .map(|&(name, kind)| Member { name: name.to_string(), kind: kind.to_string() })
+            .collect();
+        self.structs.insert(name.to_string(), members);
+        self
+    }
+
+    /// Fields of a struct, if this version has it
+    pub fn fields(&self, name: &str) -> Option<&[Member]> {
+        self.structs.get(name).map(Vec::as_slice)
+    }
+}
+
+/// How a field differs between two schema versions
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// Field only in the newer version, as `Struct.field`
+    Added { path: String, kind: String },
+    /// Field only in the older version
+    Removed { path: String, kind: String },
+    /// Field in both with different declared types
+    Retyped { path: String, from: String, to: String },
+}
+
+/// Layouts of schema versions, by version number
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Registry {
+    /// Layout of each known version
+    versions: BTreeMap<u32, Layout>,
+}
+
+impl Registry {
+    /// Layouts of every version this build can read
+    pub fn builtin() -> Self {
+        let mut versions = BTreeMap::new();
+        versions.insert(1, Layout::default()
+            .with("User", first::User::FIELDS)
+            .with("Location", first::Location::FIELDS)
+            .with("Profile", model::Profile::FIELDS));
+        versions.insert(2, Layout::default()
+            .with("User", second::User::FIELDS)
+            .with("Location", model::Location::FIELDS)
+            .with("Point", model::Point::FIELDS)
+            .with("Profile", model::Profile::FIELDS));
+        versions.insert(SCHEMA, Layout::default()
+            .with("User", model::User::FIELDS)
+            .with("Location", model::Location::FIELDS)
+            .with("Point", model::Point::FIELDS)
+            .with("Profile", model::Profile::FIELDS));
+        Self { versions }
+    }
+
+    /// Layout of a version, if recorded
+    pub fn get(&self, version: u32) -> Option<&Layout> {
+        self.versions.get(&version)
+    }
+
+    /// Recorded versions in ascending order
+    pub fn versions(&self) -> impl Iterator<Item = u32> + '_ {
+        self.versions.keys().copied()
+    }
+
+    /// Records the versions of `other` missing here
+    ///
+    /// Returns whether anything was added. Fails with `Error::Format`
+    /// when a version is already recorded with a different layout,
+    /// since records of that version could no longer be told apart.
+    pub fn merge(&mut self, other: &Registry) -> Result<bool> {
+        let mut added = false;
+        for (&version, layout) in &other.versions {
+            match self.versions.get(&version) {
+                Some(recorded) if recorded != layout => {
+                    return Err(Error::Format(format!(
+                        "Schema {} is recorded with a different layout; bump the schema version",
+                        version,
+                    )));
+                }
+                Some(_) => {}
+                None => {
+                    self.versions.insert(version, layout.clone());
+                    added = true;
+                }
+            }
+        }
+        Ok(added)
+    }
+
+    /// Field changes from one version to another
+    ///
+    /// Fails with `Error::Unsupported` when either version is unknown.
+    pub fn changes(&self, from: u32, to: u32) -> Result<Vec<Change>> {
+        let layout = |version: u32| self.get(version)
+            .ok_or_else(|| Error::Unsupported(format!("Record schema {}", version)));
+        let (old, new) = (layout(from)?, layout(to)?);
+        let empty = Vec::new();
+
+        let mut changes = Vec::new();
+        let names = old.structs.keys().chain(new.structs.keys()).collect::<std::collections::BTreeSet<_>>();
+        for name in names {
+            let before = old.structs.get(name).unwrap_or(&empty);
+            let after = new.structs.get(name).unwrap_or(&empty);
+            for member in before {
+                let path = format!("{}.{}", name, member.name);
+                match after.iter().find(|other| other.name == member.name) {
+                    None => changes.push(Change::Removed { path, kind: member.kind.clone() }),
+                    Some(other) if other.kind != member.kind => changes.push(Change::Retyped {
+                        path,
+                        from: member.kind.clone(),
+                        to: other.kind.clone(),
+                    }),
+                    Some(_) => {}
+                }
+            }
+            for member in after {
+                if !before.iter().any(|other| other.name == member.name) {
+                    let path = format!("{}.{}", name, member.name);
+                    changes.push(Change::Added { path, kind: member.kind.clone() });
+                }
+            }
+        }
+        Ok(changes)
+    }
+}
@@ -0,0 +1,180 @@
+//! Snapshot streams for seeding replicas
+//!
+//! `Store::ship` writes a consistent snapshot of a store to any byte
+//! stream, a file or a socket alike, and `Store::seed` loads one into
+//! an empty store. The stream is a run of length-delimited `Chunk`
+//! frames: a head naming the format and schema, one frame per record
+//! and an end frame carrying the record count and a CRC-32 over the
+//! records. Records travel as rkyv archives whatever codec either
+//! store uses, and keep their revisions.
+//!
+//! A stream cut short or damaged fails the import once it is noticed,
+//! after earlier records have landed; discard the half-seeded store.
+
+use std::io::{Read, Write};
+use guardian_macros::frame;
+use crate::{Error, Result};
+use crate::codec::{Codec, Rkyv};
+use crate::model::{User, SCHEMA};
+use crate::segment::MAXSIZE;
+
+/// Magic bytes opening every snapshot stream
+const MAGIC: &[u8; 4] = b"GSNP";
+
+/// Version of the stream format
+pub const VERSION: u32 = 1;
+
+/// Chunk kind opening the stream
+const HEAD: u8 = 0;
+
+/// Chunk kind holding one record
+const RECORD: u8 = 1;
+
+/// Chunk kind closing the stream
+const END: u8 = 2;
+
+// One frame of a snapshot stream, preceded on the wire by its length
+#[frame]
+pub struct Chunk {
+    kind: u8,
+    data: rest,
+}
+
+/// Writes a snapshot stream
+pub struct Sender<W> {
+    /// Where frames go
+    out: W,
+    /// Checksum over the record payloads sent so far
+    digest: crc32fast::Hasher,
+    /// Records sent so far
+    count: u64,
+    /// Frame being assembled, reused across records
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Sender<W> {
+    /// Starts a stream by writing its head
+    ///
+    /// `sequence` is the last value the source store's sequence handed
+    /// out, so a replica never repeats one.
+    pub fn new(out: W, sequence: u64) -> Result<Self> {
+        let mut sender = Self { out, digest: crc32fast::Hasher::new(), count: 0, buffer: Vec::new() };
+        let mut head = MAGIC.to_vec();
+        head.extend_from_slice(&VERSION.to_be_bytes());
+        head.extend_from_slice(&SCHEMA.to_be_bytes());
+        head.extend_from_slice(&sequence.to_be_bytes());
+        sender.send(HEAD, &head)?;
+        Ok(sender)
+    }
+
+    /// Writes one record
+    pub fn push(&mut self, user: &User) -> Result<()> {
+        let data = Rkyv.encode(user)?;
+        self.digest.update(&data);
+        self.count += 1;
+        self.send(RECORD, &data)
+    }
+
+    /// Writes the end frame and flushes, returning the records sent
+    pub fn finish(mut self) -> Result<u64> {
+        let mut end = self.count.to_be_bytes().to_vec();
+        end.extend_from_slice(&self.digest.clone().finalize().to_be_bytes());
+        self.send(END, &end)?;
+        self.out.flush()?;
+        Ok(self.count)
+    }
+
+    /// Writes a frame as its length, kind and data in one call
+    fn send(&mut self, kind: u8, data: &[u8]) -> Result<()> {
+        let length = u32::try_from(1 + data.len())
+            .map_err(|_| Error::Format(format!("Snapshot chunk of {} bytes", data.len())))?;
+        self.buffer.clear();
+        self.buffer.extend_from_slice(&length.to_be_bytes());
+        self.buffer.push(kind);
+        self.buffer.extend_from_slice(data);
+        self.out.write_all(&self.buffer)?;
+        Ok(())
+    }
+}
+
+/// Reads a snapshot stream
+pub struct Receiver<R> {
+    /// Frames coming in
+    reader: ChunkReader<R>,
+    /// Checksum over the record payloads received so far
+    digest: crc32fast::Hasher,
+    /// Records received so far
+    count: u64,
+    /// Last sequence value the source handed out
+    sequence: u64,
+    /// Set once the end frame has been verified
+    done: bool,
+}
+
+impl<R: Read> Receiver<R> {
+    /// Opens a stream, checking its head
+    ///
+    /// Fails with `Error::Format` for anything but a snapshot stream and
+    /// `Error::Unsupported` for another format version or schema.
+    pub fn new(input: R) -> Result<Self> {
+        // No record outgrows a segment
+        let mut reader = ChunkReader::new(input).limit(MAXSIZE as usize);
+        let chunk = reader.read()?
+            .ok_or_else(|| Error::Format("Empty snapshot stream".to_string()))?;
+        let data = chunk.data();
+        if chunk.kind() != HEAD || data.len() != 20 || &data[..4] != MAGIC {
+            return Err(Error::Format("Not a snapshot stream".to_string()));
+        }
+        let version = u32::from_be_bytes(data[4..8].try_into().unwrap());
+        let schema = u32::from_be_bytes(data[8..12].try_into().unwrap());
+        let sequence = u64::from_be_bytes(data[12..].try_into().unwrap());
+        if version != VERSION {
+            return Err(Error::Unsupported(format!("Snapshot stream version {}", version)));
+        }
+        if schema != SCHEMA {
+            return Err(Error::Unsupported(format!("Snapshot of schema {}, expected {}", schema, SCHEMA)));
+        }
+        Ok(Self { reader, digest: crc32fast::Hasher::new(), count: 0, sequence, done: false })
+    }
+
+    /// Last sequence value the source store handed out
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Next record, or `None` once the end frame checks out
+    ///
+    /// Fails with `Error::Format` when the stream ends early or its
+    /// count or checksum disagree with the records received.
+    pub fn pull(&mut self) -> Result<Option<User>> {
+        if self.done {
+            return Ok(None);
+        }
+        let chunk = self.reader.read()?
+            .ok_or_else(|| Error::Format(format!("Snapshot stream ended after {} records", self.count)))?;
+        let data = chunk.data();
+        match chunk.kind() {
+            RECORD => {
+                let user = Rkyv.decode(data)?;
+                self.digest.update(data);
+                self.count += 1;
+                Ok(Some(user))
+            }
+            END => {
+                let end = <[u8; 12]>::try_from(data)
+                    .map_err(|_| Error::Format(format!("Snapshot end of {} bytes", data.len())))?;
+                let count = u64::from_be_bytes(end[..8].try_into().unwrap());
+                let digest = u32::from_be_bytes(end[8..].try_into().unwrap());
+                if count != self.count || digest != self.digest.clone().finalize() {
+                    return Err(Error::Format(format!(
+                        "Snapshot stream of {} records does not match its end frame of {}",
+                        self.count, count
+                    )));
+                }
+                self.done = true;
+                Ok(None)
+            }
+            kind => Err(Error::Format(format!("Unknown snapshot chunk kind {}", kind))),
+        }
+    }
+}
@@ -0,0 +1,268 @@
+//! Redis protocol frontend
+//!
+//! `Server` speaks RESP, the Redis wire protocol, over TCP and maps
+//! `GET`, `SET`, `DEL`, `EXISTS` and `SCAN` onto the raw key-value API,
+//! so existing Redis clients can keep persistent key-value data in a
+//! store. `PING`, `QUIT` and the `COMMAND` probe clients send on
+//! connect are answered too; anything else is refused with an error
+//! reply. Options such as `EX` or `NX` on `SET` are not supported.
+//!
+//! `SCAN` walks keys in order. Its cursors are small numbers naming the
+//! last key a connection returned, so they only hold on the connection
+//! that received them.
+
+use std::collections::HashMap;
+use std::ops::Bound;
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use crate::{Error, Result, Store};
+use crate::raw::Raw;
+use crate::segment::MAXSIZE;
+
+/// Most arguments a command may carry
+const ARGUMENTS: usize = 1024 * 1024;
+
+/// Keys `SCAN` returns per call unless `COUNT` says otherwise
+const COUNT: usize = 10;
+
+/// Store shared by every connection
+type Shared = Arc<Store>;
+
+/// RESP server over a store
+pub struct Server {
+    /// Store every connection works on
+    store: Shared,
+}
+
+/// Reply to a command
+enum Reply {
+    /// Status line such as `OK`
+    Status(&'static str),
+    /// Error line
+    Error(String),
+    /// Signed number
+    Integer(i64),
+    /// Byte string, or the null bulk string
+    Bulk(Option<Vec<u8>>),
+    /// Nested replies
+    Array(Vec<Reply>),
+}
+
+/// Connection state kept between commands
+#[derive(Default)]
+struct Session {
+    /// Last key returned under each open `SCAN` cursor
+    cursors: HashMap<u64, Vec<u8>>,
+    /// Cursor number handed out next, never 0
+    next: u64,
+}
+
+impl Server {
+    /// Serves a store, shared with whoever else holds it
+    pub fn new(store: Shared) -> Self {
+        Self { store }
+    }
+
+    /// Accepts connections until the listener fails
+    ///
+    /// Each connection runs as a task of its own; commands hold the
+    /// store's raw entries locked for their duration.
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let store = Arc::clone(&self.store);
+            tokio::spawn(async move {
+                if let Err(error) = connect(stream, store).await {
+                    tracing::debug!(%error, "resp connection closed");
+                }
+            });
+        }
+    }
+}
+
+/// Answers commands on one connection until the client leaves
+async fn connect(stream: TcpStream, store: Shared) -> Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut session = Session::default();
+    let mut out = Vec::new();
+    while let Some(command) = read(&mut reader).await? {
+        let quit = command.first().is_some_and(|name| name.eq_ignore_ascii_case(b"QUIT"));
+        let reply = if quit {
+            Reply::Status("OK")
+        } else {
+            run(&store, &mut session, &command)
+        };
+        out.clear();
+        write(&mut out, &reply);
+        writer.write_all(&out).await?;
+        if quit {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Reads one command as an array of bulk strings, `None` at a clean end
+async fn read<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Result<Option<Vec<Vec<u8>>>> {
+    let Some(line) = header(reader).await? else {
+        return Ok(None);
+    };
+    let count = length(&line, b'*', ARGUMENTS)?;
+    let mut arguments = Vec::with_capacity(count);
+    for _ in 0..count {
+        let line = header(reader).await?
+            .ok_or_else(|| Error::Format("Command cut short".to_string()))?;
+        let size = length(&line, b'$', MAXSIZE as usize)?;
+        let mut argument = vec![0u8; size + 2];
+        reader.read_exact(&mut argument).await?;
+        if !argument.ends_with(b"\r\n") {
+            return Err(Error::Format("Bulk string not ended by CRLF".to_string()));
+        }
+        argument.truncate(size);
+        arguments.push(argument);
+    }
+    Ok(Some(arguments))
+}
+
+/// Reads a line without its CRLF, `None` at a clean end
+async fn header<R: AsyncBufReadExt + Unpin>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    // A header is short; anything longer is not RESP
+    let read = reader.take(64).read_until(b'\n', &mut line).await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\r\n") {
+        return Err(Error::Format("Header line not ended by CRLF".to_string()));
+    }
+    line.truncate(line.len() - 2);
+    Ok(Some(line))
+}
+
+/// Parses a header such as `*3` or `$5`, up to `limit`
+fn length(line: &[u8], kind: u8, limit: usize) -> Result<usize> {
+    let digits = line.strip_prefix(&[kind])
+        .ok_or_else(|| Error::Format(format!("Expected '{}' header", kind as char)))?;
+    std::str::from_utf8(digits).ok()
+        .and_then(|digits| digits.parse::<usize>().ok())
+        .filter(|&length| length <= limit)
+        .ok_or_else(|| Error::Format(format!("Bad length in '{}' header", kind as char)))
+}
+
+/// Runs a command against the store
+fn run(store: &Shared, session: &mut Session, command: &[Vec<u8>]) -> Reply {
+    let Some((name, arguments)) = command.split_first() else {
+        return Reply::Error("ERR empty command".to_string());
+    };
+    let name = String::from_utf8_lossy(name).to_ascii_uppercase();
+    let outcome = store.raw().and_then(|mut raw| match (name.as_str(), arguments) {
+        ("PING", []) => Ok(Reply::Status("PONG")),
+        ("PING", [message]) => Ok(Reply::Bulk(Some(message.clone()))),
+        ("COMMAND", _) => Ok(Reply::Array(Vec::new())),
+        ("GET", [key]) => Ok(Reply::Bulk(raw.get(key)?)),
+        ("SET", [key, value]) => raw.put(key, value).map(|()| Reply::Status("OK")),
+        ("DEL", keys) if !keys.is_empty() => {
+            let mut deleted = 0;
+            for key in keys {
+                deleted += i64::from(raw.delete(key)?);
+            }
+            Ok(Reply::Integer(deleted))
+        }
+        ("EXISTS", keys) if !keys.is_empty() => {
+            let mut found = 0;
+            for key in keys {
+                found += i64::from(raw.get(key)?.is_some());
+            }
+            Ok(Reply::Integer(found))
+        }
+        ("SCAN", [cursor, options @ ..]) => scan(&raw, session, cursor, options),
+        ("PING" | "GET" | "SET" | "DEL" | "EXISTS" | "SCAN", _) => {
+            Ok(Reply::Error(format!("ERR wrong number of arguments for '{}' command", name.to_lowercase())))
+        }
+        _ => Ok(Reply::Error(format!("ERR unknown command '{}'", name.to_lowercase()))),
+    });
+    outcome.unwrap_or_else(|error| Reply::Error(format!("ERR {}", error)))
+}
+
+/// Answers `SCAN cursor [MATCH pattern] [COUNT count]`
+fn scan(raw: &Raw, session: &mut Session, cursor: &[u8], options: &[Vec<u8>]) -> Result<Reply> {
+    let invalid = || Error::Invalid { field: "cursor".to_string(), reason: String::from_utf8_lossy(cursor).into_owned() };
+    let cursor = std::str::from_utf8(cursor).ok().and_then(|cursor| cursor.parse::<u64>().ok()).ok_or_else(invalid)?;
+    let from = match cursor {
+        0 => None,
+        cursor => Some(session.cursors.remove(&cursor).ok_or_else(invalid)?),
+    };
+
+    let mut pattern = None;
+    let mut count = COUNT;
+    for pair in options.chunks(2) {
+        match pair {
+            [option, value] if option.eq_ignore_ascii_case(b"MATCH") => pattern = Some(value.as_slice()),
+            [option, value] if option.eq_ignore_ascii_case(b"COUNT") => {
+                count = std::str::from_utf8(value).ok()
+                    .and_then(|value| value.parse::<usize>().ok())
+                    .filter(|&count| count > 0)
+                    .ok_or_else(|| Error::Invalid { field: "count".to_string(), reason: String::from_utf8_lossy(value).into_owned() })?;
+            }
+            _ => return Ok(Reply::Error("ERR syntax error".to_string())),
+        }
+    }
+
+    // Like Redis, COUNT bounds the keys looked at, not those returned;
+    // one more is read to tell whether the scan is over
+    let start = from.as_deref().map_or(Bound::Unbounded, Bound::Excluded);
+    let mut seen = Vec::with_capacity(count + 1);
+    for entry in raw.range((start, Bound::Unbounded)).take(count + 1) {
+        seen.push(entry?.0);
+    }
+    let next = if seen.len() > count {
+        seen.truncate(count);
+        session.next += 1;
+        session.cursors.insert(session.next, seen[count - 1].clone());
+        session.next
+    } else {
+        0
+    };
+    let keys = seen.into_iter()
+        .filter(|key| pattern.is_none_or(|pattern| glob(pattern, key)))
+        .map(|key| Reply::Bulk(Some(key)))
+        .collect();
+    Ok(Reply::Array(vec![Reply::Bulk(Some(next.to_string().into_bytes())), Reply::Array(keys)]))
+}
+
+/// Whether a key matches a pattern where `*` stands for any run of
+/// bytes, `?` for any one byte and `\` escapes the next
+fn glob(pattern: &[u8], key: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => key.is_empty(),
+        Some((b'*', rest)) => (0..=key.len()).any(|skip| glob(rest, &key[skip..])),
+        Some((b'?', rest)) => key.split_first().is_some_and(|(_, key)| glob(rest, key)),
+        Some((b'\\', [escaped, rest @ ..])) | Some((escaped, rest)) => {
+            key.split_first().is_some_and(|(first, key)| first == escaped && glob(rest, key))
+        }
+    }
+}
+
+/// Appends a reply in wire form
+fn write(out: &mut Vec<u8>, reply: &Reply) {
+    match reply {
+        Reply::Status(status) => out.extend_from_slice(format!("+{}\r\n", status).as_bytes()),
+        // Lines must not break inside an error
+        Reply::Error(message) => out.extend_from_slice(format!("-{}\r\n", message.replace(['\r', '\n'], " ")).as_bytes()),
+        Reply::Integer(value) => out.extend_from_slice(format!(":{}\r\n", value).as_bytes()),
+        Reply::Bulk(None) => out.extend_from_slice(b"$-1\r\n"),
+        Reply::Bulk(Some(bytes)) => {
+            out.extend_from_slice(format!("${}\r\n", bytes.len()).as_bytes());
+            out.extend_from_slice(bytes);
+            out.extend_from_slice(b"\r\n");
+        }
+        Reply::Array(items) => {
+            out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+            for item in items {
+                write(out, item);
+            }
+        }
+    }
+}
@@ -3,161 +3,3178 @@
 //! Provides a clean abstraction over segment and index operations
 //! with zero-copy data access and schema evolution support.
 
-use std::path::Path;
-use std::collections::HashMap;
-use rkyv::Archive;
-use crate::{Error, Result};
-use crate::segment::Segment;
-use crate::index::{Index, Operation};
-use crate::model::User;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::ops::Deref;
+use std::pin::Pin;
+use std::ptr::NonNull;
+use std::task::{Context, Poll};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use crate::{directory, Error, Kind, Result};
+use crate::segment::{Compression, Segment, Tally, CUTOFF, MAXSIZE};
+use crate::backend::{Backend, Disk};
+use crate::validator::Validator;
+use crate::generator::{Generator, Snowflake};
+use crate::hook::{Event, Hooks};
+use crate::codec::{Codec, Rkyv};
+use crate::cipher::{self, Cipher, Master, Sealed};
+use crate::registry::Registry;
+use crate::writer::Writer;
+use memmap2::Mmap;
+use futures_core::Stream;
+use tokio::sync::Notify;
+use rkyv::AlignedVec;
+use crate::index::{Index, Operation, Shape, Snapshot, BUDGET};
+use crate::manifest::{Backfill, Counters, Manifest, Quota, Upgrade};
+use crate::timeline::Timeline;
+use crate::atlas::Atlas;
+use crate::catalog::{self, Catalog, Plan};
+use crate::filter::{Filter, Test};
+use crate::history::{History, Purge, Retention, Version};
+use crate::audit::{Action, Audit, Entry};
+use crate::legacy;
+use crate::garbage::{self, Report};
+use crate::erasure::Certificate;
+use crate::probe;
+use crate::compaction::{Compaction, Config, Guard, State};
+use crate::raw::{Bucket, Raw};
+use crate::replica::{Receiver, Sender};
+use crate::model::{ArchivedUser, Field, Projection, User, Point, Position, SCHEMA};
+
+/// Number of index entries fetched per scan page
+const PAGE: usize = 1024;
+
+/// Users a stream hands out before yielding to the executor
+const BURST: usize = 64;
+
+/// Sequence values reserved per manifest write
+const RESERVE: u64 = 1024;
+
+/// Records `seed` writes per batch
+const SEED: usize = 256;
 
 /// Main storage interface for Guardian-Store
+///
+/// Reads and writes take `&self`, so one store can be shared between
+/// threads through an `Arc`. Writes are serialized by a write lock
+/// reads never take; a read waits only for the index or secondary
+/// index it consults, each locked on its own and briefly. Hooks run
+/// under the write lock and must not write to the store themselves.
 pub struct Store {
-    /// Segment manager
-    segment: Segment,
-    /// Index manager
-    index: Index,
-    /// Schema version cache
-    schema_cache: HashMap<u64, u32>,
+    /// Base storage path
+    base: PathBuf,
+    /// Segment manager, shared with compaction
+    segment: Arc<Segment>,
+    /// Index manager, shared with compaction
+    index: Arc<Mutex<Index>>,
+    /// Last persisted description of the store
+    manifest: RwLock<Manifest>,
+    /// Lifetime activity, shared with compaction
+    counters: Arc<Mutex<Counters>>,
+    /// Wakes compaction early when the disk runs low
+    urgent: Arc<Notify>,
+    /// Free bytes below which writes are refused
+    reserve: u64,
+    /// Memory in bytes the store sheds down to after writes, if capped
+    budget: Option<usize>,
+    /// Largest archived record accepted, in bytes
+    limit: u64,
+    /// Checks every record must pass before it is written
+    validators: Vec<Arc<dyn Validator>>,
+    /// Proposes IDs for `create`
+    generator: Arc<dyn Generator>,
+    /// Callbacks run around saves and deletes
+    hooks: Hooks,
+    /// Secondary indexes on record timestamps
+    timeline: RwLock<Timeline>,
+    /// Secondary index on record coordinates
+    atlas: RwLock<Atlas>,
+    /// Secondary index on attribute values
+    catalog: RwLock<Catalog>,
+    /// Superseded versions of records
+    history: RwLock<History>,
+    /// Trail of saves and deletes, when auditing is enabled
+    audit: Option<RwLock<Audit>>,
+    /// Entries stored through `raw`
+    bucket: Mutex<Bucket>,
+    /// Keys sealing secret fields, if encrypted
+    cipher: Option<Arc<Cipher>>,
+    /// Who audited operations are attributed to
+    actor: String,
+    /// When writes reach stable storage
+    durability: Durability,
+    /// When the store last fsynced its files, in seconds since the epoch
+    synced: Mutex<Option<u64>>,
+    /// Next sequence value to hand out
+    next: Mutex<u64>,
+    /// Set once the store has been closed
+    closed: AtomicBool,
+    /// Serializes writes; reads never take it
+    writing: Mutex<()>,
+    /// How long the write queue waits for more writes to commit together
+    window: Duration,
+}
+
+/// When writes are forced to stable storage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Durability {
+    /// Writes reach the OS on return and disk on `close` or OS flush
+    #[default]
+    Buffered,
+    /// Every write is fsynced before it returns
+    Sync,
+}
+
+/// Options for opening a store
+#[derive(Debug, Clone)]
+pub struct Builder {
+    /// Base storage path
+    path: Option<PathBuf>,
+    /// Size in bytes at which segments rotate
+    segment: u64,
+    /// Whether segment files reserve their full size when started
+    preallocate: bool,
+    /// Whether segments are read around the page cache
+    direct: bool,
+    /// Memory budget in bytes of the index delta
+    cache: usize,
+    /// Index log bytes held back before a write (0 writes through)
+    buffer: usize,
+    /// Longest an index log record is held back
+    interval: Duration,
+    /// When writes reach stable storage
+    durability: Durability,
+    /// Encoding of newly written records
+    compression: Compression,
+    /// Size in bytes below which records are stored uncompressed
+    cutoff: usize,
+    /// Where segment files are read and written
+    backend: Arc<dyn Backend>,
+    /// How records are serialized
+    codec: Arc<dyn Codec>,
+    /// Master key sealing secret fields, if encrypted
+    cipher: Option<Master>,
+    /// Earlier master keys still sealing some segments
+    former: Vec<Master>,
+    /// Free bytes below which writes are refused
+    reserve: u64,
+    /// Largest archived record accepted, in bytes
+    limit: u64,
+    /// Checks every record must pass before it is written
+    validators: Vec<Arc<dyn Validator>>,
+    /// Proposes IDs for `create`
+    generator: Arc<dyn Generator>,
+    /// Actor to audit operations as, if auditing
+    audit: Option<String>,
+    /// Memory in bytes the store sheds down to after writes, if capped
+    budget: Option<usize>,
+    /// Whether opening checks the last segment for a torn tail
+    recovery: bool,
+    /// Whether missing secondary indexes are built after opening
+    backfill: bool,
+    /// Whether opening refuses records of an older schema instead of rewriting them
+    hold: bool,
+    /// How long the write queue waits for more writes to commit together
+    window: Duration,
+    /// Whether opening picks the window from a measured fsync
+    tune: bool,
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self {
+            path: None,
+            segment: MAXSIZE,
+            preallocate: false,
+            direct: false,
+            cache: BUDGET,
+            buffer: 0,
+            interval: Duration::ZERO,
+            durability: Durability::default(),
+            compression: Compression::default(),
+            cutoff: CUTOFF,
+            backend: Arc::new(Disk),
+            codec: Arc::new(Rkyv),
+            cipher: None,
+            former: Vec::new(),
+            reserve: 0,
+            limit: u64::MAX,
+            validators: Vec::new(),
+            generator: Arc::new(Snowflake::default()),
+            audit: None,
+            budget: None,
+            recovery: false,
+            backfill: false,
+            hold: false,
+            window: Duration::ZERO,
+            tune: false,
+        }
+    }
+}
+
+impl Builder {
+    /// Sets the base storage path (required)
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+    
+    /// Sets the size in bytes at which segments rotate
+    pub fn segment(mut self, bytes: u64) -> Self {
+        self.segment = bytes;
+        self
+    }
+    
+    /// Reserves disk space for each segment up to its rotation size when it starts
+    ///
+    /// Appends then fill blocks already allocated instead of growing the
+    /// file a write at a time, which keeps segments contiguous and spares
+    /// the file system a metadata update per append. The space reserved
+    /// is held until the segment is sealed, however little of it is
+    /// used. Linux only; elsewhere it does nothing. Off by default.
+    pub fn preallocate(mut self, enabled: bool) -> Self {
+        self.preallocate = enabled;
+        self
+    }
+    
+    /// Reads segments with `O_DIRECT`, keeping them out of the page cache
+    ///
+    /// For hosts shared with other work: a scan or compaction of a large
+    /// store then leaves the cache to them, at the price of reading from
+    /// the device every time. Zero-copy reads, `Store::pin` and
+    /// `Store::archived`, map segments and still go through the cache.
+    /// Linux only; elsewhere it does nothing. Off by default.
+    pub fn direct(mut self, enabled: bool) -> Self {
+        self.direct = enabled;
+        self
+    }
+    
+    /// Sets the memory budget in bytes of the index delta
+    pub fn cache(mut self, bytes: usize) -> Self {
+        self.cache = bytes;
+        self
+    }
+    
+    /// Buffers index log writes, see `Index::buffer`
+    ///
+    /// Off by default. While on, a process crash loses index updates
+    /// still held back, up to `bytes` or `interval` old, and the
+    /// records they pointed at. `Durability::Sync` still writes through
+    /// on every write.
+    pub fn buffer(mut self, bytes: usize, interval: Duration) -> Self {
+        self.buffer = bytes;
+        self.interval = interval;
+        self
+    }
+    
+    /// Sets when writes reach stable storage
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+    
+    /// Sets the encoding of newly written records
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+    
+    /// Sets the archived size in bytes below which records are stored uncompressed
+    ///
+    /// Compressing a small record costs more time than it saves space,
+    /// and LZ4 rarely shrinks one. Records compression would grow are
+    /// stored uncompressed whatever their size. Defaults to `CUTOFF`.
+    pub fn cutoff(mut self, bytes: usize) -> Self {
+        self.cutoff = bytes;
+        self
+    }
+    
+    /// Sets the backend segment files are read and written through
+    pub fn backend(mut self, backend: Arc<dyn Backend>) -> Self {
+        self.backend = backend;
+        self
+    }
+    
+    /// Sets the free disk space in bytes below which writes are refused
+    ///
+    /// Zero, the default, disables the check.
+    pub fn reserve(mut self, bytes: u64) -> Self {
+        self.reserve = bytes;
+        self
+    }
+    
+    /// Sets the largest archived record accepted, in bytes
+    pub fn limit(mut self, bytes: u64) -> Self {
+        self.limit = bytes;
+        self
+    }
+    
+    /// Sets how records are serialized, rkyv by default
+    ///
+    /// The codec is recorded when the store is created; opening it
+    /// later with another codec fails with `Error::Config`. Only rkyv
+    /// records are read in place; others are rebuilt for archived views.
+    pub fn codec(mut self, codec: Arc<dyn Codec>) -> Self {
+        self.codec = codec;
+        self
+    }
+    
+    /// Encrypts fields marked `#[secret]` under a 32-byte master key
+    ///
+    /// Once a store has seen a key, opening it without that key fails
+    /// with `Error::Config`; after `Store::rewrap`, the key is the new
+    /// one. Records written before the key was first given stay
+    /// readable and are sealed as compaction or `Store::rewrite` writes
+    /// them anew. Snapshot streams from `Store::ship` carry secret
+    /// fields in the clear.
+    pub fn cipher(mut self, key: [u8; 32]) -> Self {
+        self.cipher = Some(Master(key));
+        self
+    }
+    
+    /// Adds a key `Store::rewrap` replaced, for segments still sealed under it
+    ///
+    /// Opening fails with `Error::Config` while a segment is sealed
+    /// under an earlier key not given here; `Store::stale` lists them.
+    pub fn former(mut self, key: [u8; 32]) -> Self {
+        self.former.push(Master(key));
+        self
+    }
+    
+    /// Adds a check every saved record must pass
+    ///
+    /// Validators run in the order they were added.
+    pub fn validator(mut self, validator: Arc<dyn Validator>) -> Self {
+        self.validators.push(validator);
+        self
+    }
+    
+    /// Sets how `Store::create` picks IDs (default: `Snowflake` node 0)
+    pub fn generator(mut self, generator: Arc<dyn Generator>) -> Self {
+        self.generator = generator;
+        self
+    }
+    
+    /// Audits every save and delete, attributing them to `actor`
+    ///
+    /// Entries go to the store's audit log and read back with
+    /// `Store::audit`; `Store::actor` changes who later ones name.
+    pub fn audit(mut self, actor: impl Into<String>) -> Self {
+        self.audit = Some(actor.into());
+        self
+    }
+    
+    /// Caps the memory the store holds, see `Store::memory`
+    ///
+    /// After a write that leaves the store over budget, it writes out
+    /// the index buffer, drops cached dictionaries and merges index
+    /// deltas into their tables until back under. Table fence keys stay
+    /// resident, so a budget below them is exceeded regardless.
+    pub fn budget(mut self, bytes: usize) -> Self {
+        self.budget = Some(bytes);
+        self
+    }
+    
+    /// Checks the last segment for a torn tail when opening
+    ///
+    /// A crash can cut off the records a store was appending, or, when
+    /// writes were not synced, lose records the index already points
+    /// at. With recovery on, opening truncates the last segment after
+    /// its last whole record and drops index entries past that point,
+    /// so their keys read as absent instead of failing as corrupt. Off
+    /// by default: the check scans the whole index.
+    pub fn recovery(mut self, enabled: bool) -> Self {
+        self.recovery = enabled;
+        self
+    }
+    
+    /// Builds missing secondary indexes after opening instead of during it
+    ///
+    /// A store from before an index existed has it filled from every
+    /// record. Off by default, opening does so before it returns; with
+    /// backfill on, opening returns at once and `Store::backfill` fills
+    /// the indexes a page at a time, as a `Writer` does between writes.
+    /// Queries meanwhile also read the records not yet reached, so
+    /// their results stay whole.
+    pub fn backfill(mut self, enabled: bool) -> Self {
+        self.backfill = enabled;
+        self
+    }
+    
+    /// Refuses to open a store holding records of an older schema
+    ///
+    /// Opening otherwise rewrites such records in the current layout
+    /// before it returns, which takes as long as copying the store.
+    /// With hold on, opening fails with `Error::Unsupported` instead,
+    /// so the rewrite can be planned with `migration::plan` and run
+    /// when chosen by opening without hold. Off by default.
+    pub fn hold(mut self, enabled: bool) -> Self {
+        self.hold = enabled;
+        self
+    }
+    
+    /// Sets the group-commit window of the write queue, see `Store::writer`
+    ///
+    /// Once a write is queued the worker waits this long for others to
+    /// join it, then commits all of them with one flush. Zero, the
+    /// default, commits whatever is queued at once.
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+    
+    /// Picks the group-commit window from the device when opening
+    ///
+    /// With `Durability::Sync`, opening times a few fsyncs next to the
+    /// store's files, see `probe::fsync`, and uses their median, within
+    /// bounds, as the window instead of the one set. Other durabilities
+    /// flush nothing per write, so the window set is kept. Off by default.
+    pub fn tune(mut self, enabled: bool) -> Self {
+        self.tune = enabled;
+        self
+    }
+    
+    /// Opens the store with these options
+    pub fn open(self) -> Result<Store> {
+        Store::open(self)
+    }
 }
 
 impl Store {
-    /// Creates a new store instance
+    /// Creates a new store instance with default options
     pub fn new<P: AsRef<Path>>(base: P) -> Result<Self> {
-        let base = base.as_ref();
+        Self::builder().path(base).open()
+    }
+    
+    /// Starts configuring a store
+    pub fn builder() -> Builder {
+        Builder::default()
+    }
+    
+    /// Opens a store as described by a builder
+    fn open(options: Builder) -> Result<Self> {
+        if options.segment == 0 {
+            return Err(Error::Config("Segment size must be positive".to_string()));
+        }
+        let base = options.path
+            .ok_or_else(|| Error::Config("Store path not set".to_string()))?;
+        let base = base.as_path();
         let segment_path = base.join("segments");
         let index_path = base.join("index");
         
-        let segment = Segment::new(segment_path)?;
-        let index = Index::new(index_path)?;
+        let (segment, index, manifest) = match Manifest::load(base)? {
+            Some(mut manifest) => {
+                let index = Index::pinned(index_path, options.cache, manifest.generation)?
+                    .buffer(options.buffer, options.interval);
+                // A merge the manifest missed before a crash is recorded now
+                if index.generation() != manifest.generation {
+                    manifest.generation = index.generation();
+                    manifest.save(base)?;
+                }
+                let mut tallies = manifest.tallies.clone();
+                // Tallies saved before live bytes were tracked count them once
+                if tallies.values().any(|tally| tally.live > 0 && tally.bytes == 0) {
+                    for result in index.scan() {
+                        let (_, position) = result?;
+                        if let Some(tally) = tallies.get_mut(&position.segment) {
+                            tally.bytes += position.length;
+                        }
+                    }
+                }
+                let segment = Segment::restore(segment_path, manifest.segments.clone(), tallies, manifest.sealed.clone())?;
+                (segment, index, manifest)
+            }
+            None => {
+                // No manifest yet: infer state from the directory once
+                let index = Index::bounded(index_path, options.cache)?
+                    .buffer(options.buffer, options.interval);
+                let mut tallies = BTreeMap::new();
+                for result in index.scan() {
+                    let (_, position) = result?;
+                    let tally = tallies.entry(position.segment).or_insert_with(Tally::default);
+                    tally.live += 1;
+                    tally.bytes += position.length;
+                }
+                
+                // Records found without a manifest predate schema tracking
+                let (schema, codec) = if tallies.is_empty() {
+                    (SCHEMA, options.codec.name())
+                } else {
+                    (1, Rkyv.name())
+                };
+                let live = Segment::discover(&segment_path)?;
+                let segment = Segment::restore(segment_path, live, tallies, Vec::new())?;
+                let manifest = Manifest {
+                    segments: segment.list(),
+                    generation: index.generation(),
+                    schema,
+                    codec: codec.to_string(),
+                    registry: Registry::builtin(),
+                    tallies: segment.tallies(),
+                    ..Manifest::default()
+                };
+                manifest.save(base)?;
+                (segment, index, manifest)
+            }
+        };
+        
+        if manifest.codec != options.codec.name() {
+            return Err(Error::Config(format!(
+                "Store was written with the {} codec, not {}",
+                manifest.codec,
+                options.codec.name(),
+            )));
+        }
+        
+        // Layouts are recorded once; a changed one means SCHEMA was not bumped
+        let mut manifest = manifest;
+        if manifest.registry.merge(&Registry::builtin())? {
+            manifest.save(base)?;
+        }
         
-        Ok(Self {
-            segment,
-            index,
-            schema_cache: HashMap::new(),
-        })
+        let cipher = match &options.cipher {
+            Some(master) => {
+                if manifest.keys.is_empty() {
+                    // The catalog holds secret values in the clear until rebuilt blind
+                    let catalog = Catalog::locate(base);
+                    if catalog.exists() {
+                        std::fs::remove_dir_all(catalog)?;
+                    }
+                    manifest.keys.push(cipher::fingerprint(&master.0));
+                    manifest.save(base)?;
+                }
+                Some(Arc::new(unlock(&manifest, &segment, master, &options.former)?))
+            }
+            None => match manifest.keys.last() {
+                Some(held) => return Err(Error::Config(format!("Store is encrypted with key {}; none was given", held))),
+                None => None,
+            },
+        };
+        let codec: Arc<dyn Codec> = match &cipher {
+            Some(cipher) => Arc::new(Sealed::new(options.codec, Arc::clone(cipher))),
+            None => options.codec,
+        };
+        
+        // Stores predating a secondary index get it backfilled once
+        let fresh = !Timeline::locate(base).exists()
+            || !Atlas::locate(base).exists()
+            || !Catalog::ready(base);
+        
+        let bucket = Bucket::open(base, |bucket| bucket
+            .capacity(options.segment)
+            .compression(options.compression)
+            .cutoff(options.cutoff)
+            .backend(Arc::clone(&options.backend)), &manifest.raw)?;
+        let segment = segment
+            .capacity(options.segment)
+            .preallocate(options.preallocate)
+            .direct(options.direct)
+            .compression(options.compression)
+            .cutoff(options.cutoff)
+            .backend(options.backend)
+            .codec(codec)
+            .key(cipher.as_ref().map_or(0, |cipher| cipher.version()));
+        
+        let window = if options.tune && options.durability == Durability::Sync {
+            let latency = probe::fsync(base, probe::ROUNDS)?;
+            tracing::info!(median = ?latency.median, worst = ?latency.worst, "fsync probed");
+            latency.window()
+        } else {
+            options.window
+        };
+        
+        let store = Self {
+            base: base.to_path_buf(),
+            segment: Arc::new(segment),
+            index: Arc::new(Mutex::new(index)),
+            counters: Arc::new(Mutex::new(manifest.counters)),
+            urgent: Arc::new(Notify::new()),
+            reserve: options.reserve,
+            budget: options.budget,
+            limit: options.limit,
+            validators: options.validators,
+            generator: options.generator,
+            hooks: Hooks::default(),
+            next: Mutex::new(manifest.sequence + 1),
+            manifest: RwLock::new(manifest),
+            timeline: RwLock::new(Timeline::new(base)?),
+            atlas: RwLock::new(Atlas::new(base)?),
+            catalog: RwLock::new(Catalog::new(base)?.cipher(cipher.clone())),
+            history: RwLock::new(History::new(base)?),
+            audit: options.audit.is_some().then(|| Audit::new(base).map(RwLock::new)).transpose()?,
+            bucket: Mutex::new(bucket),
+            cipher,
+            actor: options.audit.unwrap_or_default(),
+            durability: options.durability,
+            synced: Mutex::new(None),
+            closed: AtomicBool::new(false),
+            writing: Mutex::new(()),
+            window,
+        };
+        
+        if options.recovery {
+            store.recover()?;
+        }
+        let schema = store.manifest.read().unwrap().schema;
+        if schema < SCHEMA {
+            if options.hold {
+                return Err(Error::Unsupported(format!(
+                    "Store holds records of schema {}; migrate them to schema {} first",
+                    schema, SCHEMA,
+                )));
+            }
+            store.upgrade()?;
+        }
+        if fresh {
+            let mut manifest = store.manifest.write().unwrap();
+            manifest.backfill = Some(Backfill::default());
+            manifest.save(base)?;
+        }
+        if !options.backfill {
+            while !store.backfill(PAGE)? {}
+        }
+        
+        Ok(store)
     }
     
     /// Saves a user to storage
-    pub fn save(&mut self, user: &User) -> Result<()> {
-        // Append to segment
-        let position = self.segment.append(user)?;
-        
-        // Update index
-        let key = user.id.to_le_bytes();
-        self.index.put(&key, position)?;
-        
-        Ok(())
+    ///
+    /// The stored copy gets the next revision; `user.revision` is ignored.
+    /// Fails with `Error::Invalid` when a validator refuses the user or
+    /// its record exceeds the size limit, and with `Error::Duplicate`
+    /// when another user holds its value of a unique field.
+    ///
+    /// Returns the version replaced, `None` for a new user, which saves
+    /// a read in read-modify-write flows. A replaced record that can no
+    /// longer be read is `None` too, and is overwritten as revision 0.
+    pub fn save(&self, user: &User) -> Result<Option<User>> {
+        Ok(self.write(user, Expect::Any)?.1)
+    }
+    
+    /// Saves a user only if its stored revision is still `expected`
+    ///
+    /// Pass 0 to require that the user does not exist yet. Returns the
+    /// new revision, or `Error::Conflict` when another write got there
+    /// first, so read-modify-write cycles can retry instead of losing
+    /// updates. A user recreated after a delete starts over at 1, as
+    /// does one whose stored record can no longer be read.
+    pub fn commit(&self, user: &User, expected: u64) -> Result<u64> {
+        Ok(self.write(user, Expect::Revision(expected))?.0)
+    }
+    
+    /// Saves a new user under a generated ID and returns the ID
+    ///
+    /// `user.id` is ignored. IDs the generator proposes that are already
+    /// taken are skipped, so a new user never replaces another, even one
+    /// saved under the same ID while this runs.
+    pub fn create(&self, user: &User) -> Result<u64> {
+        self.check()?;
+        loop {
+            let id = self.generator.next();
+            if self.contains(id)? {
+                continue;
+            }
+            // Another write may take the ID after the probe
+            match self.commit(&User { id, ..user.clone() }, 0) {
+                Ok(_) => return Ok(id),
+                Err(Error::Conflict { .. }) => continue,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+    
+    /// Hands out the next value of the store's sequence, starting at 1
+    ///
+    /// Values are reserved in blocks recorded in the manifest before
+    /// any is returned, so none repeats even after a crash; a crash
+    /// only skips the rest of its block. Closing the store releases
+    /// the unused values.
+    pub fn sequence(&self) -> Result<u64> {
+        self.check()?;
+        let mut next = self.next.lock().unwrap();
+        let mut manifest = self.manifest.write().unwrap();
+        if *next > manifest.sequence {
+            manifest.sequence = *next + RESERVE - 1;
+            if let Err(error) = manifest.save(&self.base) {
+                manifest.sequence = *next - 1;
+                return Err(error);
+            }
+        }
+        *next += 1;
+        Ok(*next - 1)
     }
     
     /// Finds a user by ID and deserializes to owned value
+    #[tracing::instrument(level = "debug", skip(self), fields(segment, offset, bytes))]
     pub fn find(&self, id: u64) -> Result<Option<User>> {
+        self.check()?;
         let key = id.to_le_bytes();
         
         // Look up position in index
-        let position = match self.index.get(&key)? {
+        let position = match self.index().get(&key)? {
             Some(pos) => pos,
             None => return Ok(None),
         };
         
         // Read and deserialize from segment
-        let user = self.segment.read::<User>(position)?;
+        trace(position);
+        let user = self.segment.read(position)?;
         Ok(Some(user))
     }
     
-    /// Deletes a user by ID
-    pub fn delete(&mut self, id: u64) -> Result<()> {
+    /// Finds a user by ID that must be stored
+    ///
+    /// Where `find` returns `None`, fails with `Error::Deleted` when the
+    /// user is known to have been deleted, and with `Error::Missing`
+    /// otherwise. A user is known deleted while its history keeps a
+    /// version or, in an audited store, while the audit trail records
+    /// the delete; one erased or purged from an unaudited store reads
+    /// as never stored.
+    pub fn fetch(&self, id: u64) -> Result<User> {
+        self.find(id)?.ok_or_else(|| self.absent(id))
+    }
+    
+    /// Finds a user by ID as an archived view, without deserializing it
+    ///
+    /// The returned guard maps the user's segment and keeps the map, so
+    /// the view stays valid however long it is held: should compaction
+    /// move the record and garbage collection delete its file, the
+    /// guard still shows it as it was when pinned.
+    #[tracing::instrument(level = "debug", skip(self), fields(segment, offset, bytes))]
+    pub fn pin(&self, id: u64) -> Result<Option<Pinned>> {
+        self.check()?;
+        let Some(position) = self.index().get(&id.to_le_bytes())? else {
+            return Ok(None);
+        };
+        trace(position);
+        
+        let map = self.segment.map(position.segment)?;
+        let mut buffer = AlignedVec::new();
+        let mut scratch = AlignedVec::new();
+        let user = NonNull::from(self.segment.archived(&map, position, &mut buffer, &mut scratch)?);
+        Ok(Some(Pinned { user, map, buffer, scratch }))
+    }
+    
+    /// Checks whether a user is stored, consulting only the index
+    ///
+    /// Unlike `find`, no segment is read and nothing is deserialized.
+    pub fn contains(&self, id: u64) -> Result<bool> {
+        self.check()?;
+        Ok(self.index().get(&id.to_le_bytes())?.is_some())
+    }
+    
+    /// Deletes a user by ID, returning the user removed
+    ///
+    /// Deleting a user that is not stored changes nothing and returns
+    /// `None`, so deletes can be retried safely. A stored record that
+    /// can no longer be read is deleted all the same, and returns `None`
+    /// too.
+    #[tracing::instrument(level = "debug", skip(self), fields(segment, offset, bytes))]
+    pub fn delete(&self, id: u64) -> Result<Option<User>> {
+        let _writing = self.writing()?;
+        self.remove(id)
+    }
+    
+    /// Deletes a user by ID, the write lock held
+    fn remove(&self, id: u64) -> Result<Option<User>> {
         let key = id.to_le_bytes();
-        self.index.delete(&key)?;
-        Ok(())
+        let previous = {
+            let mut index = self.index();
+            let old = index.get(&key)?;
+            if let Some(old) = old {
+                trace(old);
+                // Unreadable records can still be deleted, unseen by hooks
+                if self.hooks.has(Event::Delete) {
+                    if let Ok(user) = self.segment.read(old) {
+                        self.hooks.fire(Event::Delete, &user)?;
+                    }
+                }
+            }
+            let previous = old.and_then(|old| self.retire(old));
+            index.delete(&key)?;
+            if old.is_some() {
+                self.tick(|counters| counters.deleted += 1);
+            }
+            (old.is_some(), previous)
+        };
+        let (removed, previous) = previous;
+        
+        self.reindex(previous.as_ref(), None)?;
+        if removed {
+            let revision = previous.as_ref().map_or(0, |(_, user)| user.revision);
+            self.note(Action::Delete, &[(id, revision)])?;
+        }
+        self.flush()?;
+        self.shed()?;
+        self.record()?;
+        if let Some((_, user)) = &previous {
+            self.hooks.fire(Event::Deleted, user)?;
+        }
+        Ok(previous.map(|(_, user)| user))
     }
     
-    /// Updates a user (delete + save)
-    pub fn update(&mut self, user: &User) -> Result<()> {
-        self.delete(user.id)?;
-        self.save(user)?;
-        Ok(())
+    /// Updates a stored user, continuing its revision sequence
+    ///
+    /// Unlike `save`, never creates a user: fails as `fetch` would
+    /// when none is stored under `user.id`. Returns the version
+    /// replaced, as `save` does.
+    pub fn update(&self, user: &User) -> Result<Option<User>> {
+        Ok(self.write(user, Expect::Present)?.1)
     }
     
     /// Performs batch save operations
-    pub fn batch(&mut self, users: &[User]) -> Result<()> {
-        let mut operations = Vec::with_capacity(users.len());
+    ///
+    /// Records are serialized up front and written with one call per
+    /// segment, then the index takes all their positions in one group
+    /// record. The batch is atomic: after a failure or crash either every
+    /// user in it is visible or none is, and replaced versions are only
+    /// retired once the index holds the new ones. Unique fields are
+    /// checked against the batch's final values before anything is
+    /// written.
+    #[tracing::instrument(level = "debug", skip_all, fields(records = users.len(), bytes))]
+    pub fn batch(&self, users: &[User]) -> Result<()> {
+        let _writing = self.writing()?;
+        self.room()?;
+        for user in users {
+            self.vet(user)?;
+        }
+        self.place(users)
+    }
+    
+    /// Saves users already vetted as one batch, firing no `Event::Save` hooks
+    ///
+    /// Lets the write queue run each user's hooks once, then retry a
+    /// failed batch user by user without running them again.
+    pub(crate) fn land(&self, users: &[User]) -> Result<()> {
+        if users.is_empty() {
+            return Ok(());
+        }
+        let _writing = self.writing()?;
+        self.room()?;
+        self.place(users)
+    }
+    
+    /// Runs the validators and `Event::Save` hooks over a user about to be saved
+    pub(crate) fn vet(&self, user: &User) -> Result<()> {
+        self.validate(user)?;
+        self.hooks.fire(Event::Save, user)
+    }
+    
+    /// Writes a batch of vetted users, the write lock held
+    fn place(&self, users: &[User]) -> Result<()> {
+        self.claim(users)?;
+        let mut pending: HashMap<u64, usize> = HashMap::with_capacity(users.len());
+        let mut stored: Vec<User> = Vec::with_capacity(users.len());
+        let mut replaced = Vec::with_capacity(users.len());
+        let mut earlier = Vec::with_capacity(users.len());
+        let mut index = self.index();
         
         for user in users {
-            let position = self.segment.append(user)?;
-            let key = user.id.to_le_bytes();
+            // Replace an earlier version in this batch, or the stored one
+            let slot = pending.get(&user.id).copied();
+            let previous = match slot {
+                Some(_) => None,
+                None => index.get(&user.id.to_le_bytes())?
+                    .map(|old| (old, self.segment.read(old).ok())),
+            };
+            let revision = match slot {
+                Some(slot) => stored[slot].revision,
+                None => previous.as_ref()
+                    .and_then(|(_, previous)| previous.as_ref())
+                    .map_or(0, |previous| previous.revision),
+            };
             
-            operations.push(Operation::Put {
-                key: key.to_vec(),
-                position,
+            pending.insert(user.id, stored.len());
+            stored.push(User {
+                revision: revision + 1,
+                ..user.clone()
             });
+            replaced.push(previous);
+            earlier.push(slot);
         }
         
-        self.index.batch(operations)?;
+        let added = replaced.iter().zip(&earlier)
+            .filter(|(previous, slot)| previous.is_none() && slot.is_none())
+            .count();
+        self.fits(added as u64)?;
+        let positions = self.segment.admit(&stored, self.limit)?;
+        let operations = stored.iter().zip(&positions)
+            .map(|(user, position)| Operation::Put {
+                key: user.id.to_le_bytes().to_vec(),
+                position: *position,
+            })
+            .collect();
+        index.batch(operations)?;
+        drop(index);
+        let bytes = positions.iter().map(|position| position.length).sum::<u64>();
+        tracing::Span::current().record("bytes", bytes);
+        self.tick(|counters| {
+            counters.written += positions.len() as u64;
+            counters.bytes += bytes;
+        });
+        
+        // In order, so a user repeated in the batch keeps only its last version
+        for ((user, previous), slot) in stored.iter().zip(replaced).zip(earlier) {
+            // Positions of earlier entries are only known now
+            let previous = match slot {
+                Some(slot) => {
+                    self.segment.retire(positions[slot]);
+                    Some((positions[slot], stored[slot].clone()))
+                }
+                None => previous.and_then(|(old, previous)| {
+                    self.segment.retire(old);
+                    previous.map(|previous| (old, previous))
+                }),
+            };
+            self.reindex(previous.as_ref(), Some(user))?;
+        }
+        let written = stored.iter().map(|user| (user.id, user.revision)).collect::<Vec<_>>();
+        self.note(Action::Save, &written)?;
+        self.flush()?;
+        self.shed()?;
+        self.record()?;
+        for user in &stored {
+            self.hooks.fire(Event::Saved, user)?;
+        }
         Ok(())
     }
     
     /// Scans all users in the store
+    ///
+    /// Walks a snapshot of the index taken when the scan starts, in key
+    /// order one page at a time, without taking the index lock again.
+    /// Writes and compaction running meanwhile never make it yield a
+    /// user twice or skip one present throughout. Each page is read in disk
+    /// order with readahead, which keeps cold scans sequential, and
+    /// handed out in key order.
     pub fn scan(&self) -> impl Iterator<Item = Result<User>> + '_ {
-        self.index.scan().map(|result| {
-            result.and_then(|(key, position)| {
-                // Convert key back to ID
-                if key.len() != 8 {
-                    return Err(Error::Format("Invalid key length".to_string()));
+        Scan::new(self)
+    }
+    
+    /// Streams all users in the store, for async callers
+    ///
+    /// Reads like `scan`, but yields to the executor every few users so
+    /// streaming a large store to a client never starves other tasks.
+    /// Each step still reads from disk on the calling thread.
+    pub fn stream(&self) -> impl Stream<Item = Result<User>> + '_ {
+        Flow {
+            scan: Scan::new(self),
+            burst: 0,
+        }
+    }
+    
+    /// Iterates over stored user IDs
+    ///
+    /// Only the index is read, never a segment file, so this is cheap
+    /// for existence audits or building external indexes. IDs come in
+    /// index order, which follows their little-endian key bytes rather
+    /// than their numeric value.
+    pub fn keys(&self) -> impl Iterator<Item = Result<u64>> + '_ {
+        Entries::new(self).map(|entry| entry.and_then(|(key, _)| match <[u8; 8]>::try_from(key.as_slice()) {
+            Ok(id) => Ok(u64::from_le_bytes(id)),
+            Err(_) => Err(Error::Key {
+                reason: format!("expected 8 bytes, found {}", key.len()),
+                key,
+            }),
+        }))
+    }
+    
+    /// Last `count` users in index order, those a scan would end with
+    ///
+    /// The whole index is walked, but only the users returned are read.
+    pub fn tail(&self, count: usize) -> Result<Vec<User>> {
+        self.check()?;
+        let mut last = VecDeque::with_capacity(count);
+        for entry in Entries::new(self) {
+            last.push_back(entry?);
+            if last.len() > count {
+                last.pop_front();
+            }
+        }
+        last.into_iter().map(|(key, position)| self.load(&key, position)).collect()
+    }
+    
+    /// A uniform random sample of `count` users, in index order
+    ///
+    /// Reservoir sampling over one walk of the index, so every user is
+    /// as likely to be picked whatever the size of the store, and only
+    /// those picked are read. The same `seed` picks the same users from
+    /// the same index.
+    pub fn sample(&self, count: usize, seed: u64) -> Result<Vec<User>> {
+        self.check()?;
+        let mut state = seed;
+        let mut picked = Vec::with_capacity(count);
+        for (seen, entry) in Entries::new(self).enumerate() {
+            let entry = entry?;
+            if picked.len() < count {
+                picked.push(entry);
+                continue;
+            }
+            let slot = (mix(&mut state) % (seen as u64 + 1)) as usize;
+            if slot < count {
+                picked[slot] = entry;
+            }
+        }
+        picked.sort_unstable_by(|(left, _), (right, _)| left.cmp(right));
+        picked.into_iter().map(|(key, position)| self.load(&key, position)).collect()
+    }
+    
+    /// Scans all users as archived views, without deserializing them
+    ///
+    /// Segments are memory-mapped and each record is validated where it
+    /// lies, so analytics passes over many records avoid allocating per
+    /// record. Each view lives until the next call to `Archives::advance`.
+    pub fn archived(&self) -> Archives<'_> {
+        Archives {
+            entries: Entries::new(self),
+            maps: HashMap::new(),
+            buffer: AlignedVec::new(),
+            scratch: AlignedVec::new(),
+        }
+    }
+    
+    /// Scans all users, skipping records that fail to decode
+    ///
+    /// Damaged records are set aside in the iterator's quarantine
+    /// instead of being yielded; other errors, such as I/O failures,
+    /// are still yielded so the caller can decide whether to go on.
+    pub fn salvage(&self) -> Salvage<'_> {
+        Salvage {
+            entries: Entries::new(self),
+            quarantine: Quarantine::default(),
+        }
+    }
+    
+    /// Users created within `from..=to` (seconds since the epoch), oldest first
+    ///
+    /// Served from the timeline, so only matching records are read.
+    pub fn created(&self, from: u64, to: u64) -> Result<Vec<User>> {
+        self.check()?;
+        let mut entries = self.timeline.read().unwrap().created(from, to)?;
+        entries.extend(self.stragglers(|user| (from..=to).contains(&user.created))?
+            .iter().map(|user| (user.created, user.id)));
+        entries.sort_unstable();
+        entries.dedup();
+        self.resolve(entries, |user| user.created)
+    }
+    
+    /// Users updated at or after `since` (seconds since the epoch), oldest first
+    pub fn updated(&self, since: u64) -> Result<Vec<User>> {
+        self.check()?;
+        let mut entries = self.timeline.read().unwrap().updated(since)?;
+        entries.extend(self.stragglers(|user| user.updated >= since)?
+            .iter().map(|user| (user.updated, user.id)));
+        entries.sort_unstable();
+        entries.dedup();
+        self.resolve(entries, |user| user.updated)
+    }
+    
+    /// Users within `radius` meters of a point, closest first
+    ///
+    /// Only users whose location carries coordinates can match.
+    pub fn near(&self, latitude: f64, longitude: f64, radius: f64) -> Result<Vec<User>> {
+        self.check()?;
+        let center = Point { latitude, longitude };
+        
+        let mut ids = self.atlas.read().unwrap().near(center, radius)?;
+        ids.extend(self.stragglers(|user| user.location.point.is_some())?.iter().map(|user| user.id));
+        ids.sort_unstable();
+        ids.dedup();
+        
+        let mut found = Vec::new();
+        for id in ids {
+            let Some(user) = self.find(id)? else { continue };
+            // The atlas returns whole cells; keep only the circle
+            if let Some(point) = user.location.point {
+                let distance = center.distance(&point);
+                if distance <= radius {
+                    found.push((distance, user));
                 }
-                
-                let _id = u64::from_le_bytes(key.try_into().unwrap());
-                
-                // Read user data
-                let user = self.segment.read::<User>(position)?;
-                Ok(user)
-            })
+            }
+        }
+        
+        found.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(found.into_iter().map(|(_, user)| user).collect())
+    }
+    
+    /// Users whose `field` equals any of `values`, by ascending ID
+    ///
+    /// Served from the catalog, so only matching records are read, and
+    /// a user matching several values is returned once. Only
+    /// `Field::Country`, leading the country and city index, and the
+    /// unique fields, `Field::Email`, are served; other fields fail with
+    /// `Error::Unsupported`. Planned like `query`, across all values.
+    pub fn lookup(&self, field: Field, values: &[&str]) -> Result<Vec<User>> {
+        self.check()?;
+        let first = values.first().copied().unwrap_or_default();
+        let plan = self.catalog.read().unwrap().plan(&[(field, first)], values.len(), self.usage().0)?;
+        self.select(plan, || self.catalog.read().unwrap().find(field, values), |user| {
+            catalog::value(user, field).is_some_and(|value| values.contains(&value))
         })
     }
     
-    /// Gets storage statistics
-    pub fn stats(&self) -> Result<Stats> {
+    /// Users matching every `(field, value)` term, by ascending ID
+    ///
+    /// Served from a composite index of the catalog when the terms'
+    /// fields lead it, so `[(Country, "VN")]` and `[(Country, "VN"),
+    /// (City, "Hanoi")]` share the country and city index, or from a
+    /// scan when `plan` expects too many matches. Other terms fail with
+    /// `Error::Unsupported`.
+    pub fn query(&self, terms: &[(Field, &str)]) -> Result<Vec<User>> {
+        self.check()?;
+        self.select(self.plan(terms)?, || self.catalog.read().unwrap().query(terms), |user| {
+            terms.iter().all(|&(field, value)| catalog::value(user, field) == Some(value))
+        })
+    }
+    
+    /// Up to `limit` users passing every filter
+    ///
+    /// Exact filters the catalog can serve together, as `query` would,
+    /// narrow what is read; the other filters are checked on each user
+    /// read from there. Failing that, the store is scanned, stopping
+    /// once `limit` users pass. Users come by ascending ID when served
+    /// from the catalog and in index order when scanned, and which ones
+    /// are kept when more than `limit` pass follows that order.
+    pub fn filter(&self, filters: &[Filter], limit: usize) -> Result<Vec<User>> {
+        self.check()?;
+        let terms: Vec<(Field, &str)> = filters.iter()
+            .filter(|filter| filter.test == Test::Equals)
+            .map(|filter| (filter.field, filter.value.as_str()))
+            .collect();
+        let passes = |user: &User| filters.iter().all(|filter| filter.matches(user));
+        
+        let served = if terms.is_empty() {
+            None
+        } else {
+            match self.query(&terms) {
+                Ok(users) => Some(users),
+                Err(Error::Unsupported(_)) => None,
+                Err(e) => return Err(e),
+            }
+        };
+        if let Some(users) = served {
+            return Ok(users.into_iter().filter(|user| passes(user)).take(limit).collect());
+        }
+        let mut found = Vec::new();
+        for user in self.scan() {
+            if found.len() >= limit {
+                break;
+            }
+            let user = user?;
+            if passes(&user) {
+                found.push(user);
+            }
+        }
+        Ok(found)
+    }
+    
+    /// How `query` would read the users matching `terms`
+    ///
+    /// Scans every record once the statistics last gathered by
+    /// `indexes` expect more than a tenth of them to match, and reads
+    /// through the catalog otherwise or before any were gathered.
+    pub fn plan(&self, terms: &[(Field, &str)]) -> Result<Plan> {
+        self.check()?;
+        self.catalog.read().unwrap().plan(terms, 1, self.usage().0)
+    }
+    
+    /// Size and cardinality of every index
+    ///
+    /// Each index is walked in full to count its entries and, for the
+    /// catalog, distinct values, so this costs a read of every index.
+    /// The catalog statistics are kept to plan later lookups.
+    pub fn indexes(&self) -> Result<Vec<Shape>> {
+        self.check()?;
+        let mut shapes = vec![self.index().shape("primary")?];
+        shapes.extend(self.catalog.read().unwrap().shapes()?);
+        shapes.extend(self.timeline.read().unwrap().shapes()?);
+        shapes.push(self.atlas.read().unwrap().shape()?);
+        shapes.push(self.history.read().unwrap().shape()?);
+        Ok(shapes)
+    }
+    
+    /// Indexes up to `limit` more records into the secondary indexes being backfilled
+    ///
+    /// Returns whether the backfill is done, and at once when none is
+    /// under way. Records are taken in key order after a watermark kept
+    /// in the manifest, so an interrupted backfill resumes where it
+    /// stopped. Writes meanwhile index their records as usual; a record
+    /// indexed twice lands on the same entries.
+    pub fn backfill(&self, limit: usize) -> Result<bool> {
+        let _writing = self.writing()?;
+        self.fill(limit)
+    }
+    
+    /// Rebuilds every secondary index from the records the primary index points at
+    ///
+    /// Drops the timeline, atlas and catalog, then fills them again a
+    /// page at a time like a backfill, calling `progress` with the
+    /// records indexed so far and the total after each page. Writes
+    /// wait until it is done. With `verify`, the indexes as they were
+    /// are kept aside and compared entry by entry with the rebuilt ones,
+    /// see `Check`.
+    pub fn rebuild(&self, verify: bool, mut progress: impl FnMut(u64, u64)) -> Result<Rebuild> {
+        let _writing = self.writing()?;
         let mut total = 0u64;
-        let segments = 0u64;
+        for result in self.index().scan() {
+            result?;
+            total += 1;
+        }
+        let old = if verify { self.snapshots() } else { Vec::new() };
+        
+        self.timeline.write().unwrap().clear()?;
+        self.atlas.write().unwrap().clear()?;
+        self.catalog.write().unwrap().clear()?;
+        self.manifest.write().unwrap().backfill = Some(Backfill::default());
+        self.persist()?;
+        loop {
+            let done = self.fill(PAGE)?;
+            let records = self.manifest.read().unwrap().backfill.as_ref().map_or(total, |backfill| backfill.records);
+            progress(records, total);
+            if done {
+                break;
+            }
+        }
+        tracing::info!(records = total, "secondary indexes rebuilt");
+        
+        let mut checks = Vec::with_capacity(old.len());
+        for ((name, before), (_, after)) in old.into_iter().zip(self.snapshots()) {
+            checks.push(Check::compare(name, &before, &after)?);
+        }
+        Ok(Rebuild { records: total, checks })
+    }
+    
+    /// Every secondary index as it is now, by name
+    fn snapshots(&self) -> Vec<(&'static str, Snapshot)> {
+        let mut snapshots = self.timeline.read().unwrap().snapshots();
+        snapshots.extend(self.atlas.read().unwrap().snapshots());
+        snapshots.extend(self.catalog.read().unwrap().snapshots());
+        snapshots
+    }
+    
+    /// Backfills up to `limit` more records, the write lock held
+    fn fill(&self, limit: usize) -> Result<bool> {
+        let Some(mut backfill) = self.manifest.read().unwrap().backfill.clone() else {
+            return Ok(true);
+        };
+        let page = self.index().page(backfill.watermark.as_deref(), limit.max(1))?;
+        let done = page.len() < limit.max(1);
+        if let Some((last, _)) = page.last() {
+            backfill.watermark = Some(last.clone());
+        }
+        backfill.records += page.len() as u64;
+        
+        for (key, position) in page {
+            let user = self.load(&key, position)?;
+            self.timeline.write().unwrap().insert(&user)?;
+            self.atlas.write().unwrap().insert(&user)?;
+            self.catalog.write().unwrap().insert(&user)?;
+        }
+        // Entries must be durable before the watermark passes them
+        self.timeline.read().unwrap().sync()?;
+        self.atlas.read().unwrap().sync()?;
+        self.catalog.read().unwrap().sync()?;
         
-        // Count records and segments
-        for result in self.index.scan() {
+        if done {
+            tracing::info!(records = backfill.records, "secondary index backfill done");
+        }
+        self.manifest.write().unwrap().backfill = (!done).then_some(backfill);
+        self.persist()?;
+        self.shed()?;
+        Ok(done)
+    }
+    
+    /// Number of live records
+    ///
+    /// Counted from the index alone; no record is read.
+    pub fn count(&self) -> Result<u64> {
+        self.check()?;
+        let mut total = 0u64;
+        for result in self.index().scan() {
             result?;
             total += 1;
         }
+        Ok(total)
+    }
+    
+    /// Number of live records accepted by `filter`
+    ///
+    /// The filter sees archived records in place, so nothing is
+    /// deserialized into an owned `User`.
+    pub fn census(&self, filter: impl Fn(&ArchivedUser) -> bool) -> Result<u64> {
+        let mut total = 0u64;
+        self.visit(|user| {
+            if filter(user) {
+                total += 1;
+            }
+        })?;
+        Ok(total)
+    }
+    
+    /// Min, max and mean of a numeric field over the live records
+    ///
+    /// `field` reads the value from an archived record and returns `None`
+    /// for records lacking it, e.g. `|user| user.profile.as_ref().map(|p| p.age as f64)`.
+    pub fn aggregate(&self, field: impl Fn(&ArchivedUser) -> Option<f64>) -> Result<Summary> {
+        let mut summary = Summary::default();
+        self.visit(|user| {
+            if let Some(value) = field(user) {
+                summary.add(value);
+            }
+        })?;
+        Ok(summary)
+    }
+    
+    /// Reads only the given fields of a user
+    ///
+    /// The record is validated and read in place; fields not asked for
+    /// are never deserialized.
+    pub fn project(&self, id: u64, fields: &[Field]) -> Result<Option<Projection>> {
+        self.check()?;
+        let Some(position) = self.index().get(&id.to_le_bytes())? else {
+            return Ok(None);
+        };
+        let projection = self.segment.view(position, |user| Projection::select(user, fields))?;
+        Ok(Some(projection))
+    }
+    
+    /// Earlier versions of a user, oldest first
+    ///
+    /// Versions stay available until compaction reclaims their segment
+    /// or the retention policy drops them. A deleted user keeps the
+    /// history it had, including its last version.
+    pub fn history(&self, id: u64) -> Result<Vec<User>> {
+        self.check()?;
+        let live = self.segment.list();
+        
+        let mut versions = Vec::new();
+        for version in self.history.read().unwrap().versions(id)? {
+            if !live.contains(&version.position.segment) {
+                continue;
+            }
+            // A version unreadable mid-compaction is as good as reclaimed
+            if let Ok(user) = self.segment.read(version.position) {
+                versions.push(user);
+            }
+        }
+        Ok(versions)
+    }
+    
+    /// The version of a user current at `time`, for looking back
+    ///
+    /// That is the last version written, among the `history` and the
+    /// live record, whose `updated` is at or before `time`; `None` when
+    /// every version kept is newer. Versions reclaimed by compaction or
+    /// retention are gone, so looking far back may find nothing. Deletes
+    /// are only known to an audited store: there, a user whose last
+    /// audited operation at or before `time` is a delete reads as
+    /// `None`, where otherwise its last version would be returned.
+    pub fn recall(&self, id: u64, time: u64) -> Result<Option<User>> {
+        let mut versions = self.history(id)?;
+        versions.extend(self.find(id)?);
+        if let Some(audit) = &self.audit {
+            let last = audit.read().unwrap().entries(id)?.into_iter().rev().find(|entry| entry.time <= time);
+            if last.is_some_and(|entry| entry.action == Action::Delete) {
+                return Ok(None);
+            }
+        }
+        // Among versions stamped alike, the later revision wins
+        Ok(versions.into_iter()
+            .filter(|user| user.updated <= time)
+            .max_by_key(|user| (user.updated, user.revision)))
+    }
+    
+    /// Audit trail of a user, oldest first
+    ///
+    /// Lists every save and delete since auditing was enabled, including
+    /// those of users deleted since. Fails with `Error::Config` when the
+    /// store was opened without `Builder::audit`.
+    pub fn audit(&self, id: u64) -> Result<Vec<Entry>> {
+        self.check()?;
+        self.audit.as_ref()
+            .ok_or_else(|| Error::Config("Auditing is not enabled".to_string()))?
+            .read().unwrap()
+            .entries(id)
+    }
+    
+    /// Up to `limit` audit entries written after `cursor`, in write order
+    ///
+    /// The audit log doubles as the store's change stream: pass the
+    /// position of the last entry handled to continue after it, or
+    /// `None` to start from the beginning. Fails with `Error::Config`
+    /// when the store was opened without `Builder::audit`.
+    pub fn changes(&self, cursor: Option<Position>, limit: usize) -> Result<Vec<(Position, Entry)>> {
+        self.check()?;
+        self.audit.as_ref()
+            .ok_or_else(|| Error::Config("Auditing is not enabled".to_string()))?
+            .read().unwrap()
+            .since(cursor, limit)
+    }
+    
+    /// Sets who later audited operations are attributed to
+    pub fn actor(&mut self, actor: impl Into<String>) {
+        self.actor = actor.into();
+    }
+    
+    /// Registers a hook run synchronously whenever `event` fires
+    ///
+    /// Hooks run in registration order. An error from a `Save` or
+    /// `Delete` hook refuses the write; one from a `Saved` or `Deleted`
+    /// hook is returned after the write has landed. Batches run `Save`
+    /// hooks on every user before writing any, and expired records fire
+    /// `Deleted` only. The write queue runs `Save` hooks once per write,
+    /// even when a failed batch is retried user by user.
+    pub fn on(&mut self, event: Event, hook: impl Fn(&User) -> Result<()> + Send + Sync + 'static) {
+        self.hooks.add(event, Arc::new(hook));
+    }
+    
+    /// Sets how many earlier versions are kept per record, and how long
+    /// records themselves are
+    ///
+    /// The policy is stored in the manifest. Histories are trimmed on
+    /// their record's next write, and deleted with it when `purge` is
+    /// set; `collect` trims all of them, and `enforce` applies the whole
+    /// policy.
+    pub fn retain(&self, retention: Retention) -> Result<()> {
+        let _writing = self.writing()?;
+        self.manifest.write().unwrap().retention = retention;
+        self.persist()
+    }
+    
+    /// Sets the age in seconds after which sealed segments expire
+    ///
+    /// The policy is stored in the manifest and applied by `expire`;
+    /// `None` keeps segments forever.
+    pub fn expiry(&self, age: Option<u64>) -> Result<()> {
+        let _writing = self.writing()?;
+        self.manifest.write().unwrap().expiry = age;
+        self.persist()
+    }
+    
+    /// Sets limits on live records and their stored bytes
+    ///
+    /// The quota is stored in the manifest. Saves that would add records
+    /// beyond the limit fail with `Error::Quota`, as does any save once
+    /// the live bytes have reached theirs; the write crossing the byte
+    /// limit still lands. Deletes are always allowed.
+    pub fn quota(&self, quota: Quota) -> Result<()> {
+        let _writing = self.writing()?;
+        self.manifest.write().unwrap().quota = quota;
+        self.persist()
+    }
+    
+    /// Drops sealed segments older than the expiry age
+    ///
+    /// Meant for append-mostly data such as time series: whole segments
+    /// leave the live set and every index entry still pointing into them
+    /// is purged, without rewriting any record. Files are removed by the
+    /// next `collect`. Returns the expired segment identifiers.
+    pub fn expire(&self) -> Result<Vec<u64>> {
+        let _writing = self.writing()?;
+        let Some(age) = self.manifest.read().unwrap().expiry else {
+            return Ok(Vec::new());
+        };
+        let cutoff = now()?.saturating_sub(age);
+        let active = self.segment.current();
+        
+        let mut expired = Vec::new();
+        for id in self.segment.list() {
+            if id != active && self.segment.modified(id)? < cutoff {
+                expired.push(id);
+            }
+        }
+        if expired.is_empty() {
+            return Ok(expired);
+        }
         
-        // TODO: Implement segment counting from filesystem
+        let mut purged = Vec::new();
+        let mut gone = Vec::new();
+        let mut dropped = Vec::new();
+        let mut from = None;
+        loop {
+            let page = self.index().page(from.as_deref(), PAGE)?;
+            let Some((last, _)) = page.last() else { break };
+            from = Some(last.clone());
+            
+            for (key, position) in page {
+                if !expired.contains(&position.segment) {
+                    continue;
+                }
+                // Unreadable records have no secondary entries to drop
+                if let Ok(user) = self.segment.read(position) {
+                    self.timeline.write().unwrap().remove(&user)?;
+                    self.atlas.write().unwrap().remove(&user)?;
+                    self.catalog.write().unwrap().remove(&user)?;
+                    gone.push((user.id, user.revision));
+                    if self.hooks.has(Event::Deleted) {
+                        dropped.push(user);
+                    }
+                }
+                purged.push(Operation::Delete { key });
+            }
+        }
         
+        let count = purged.len() as u64;
+        self.index().batch(purged)?;
+        self.tick(|counters| counters.deleted += count);
+        self.note(Action::Delete, &gone)?;
+        self.segment.release(&expired);
+        self.flush()?;
+        self.shed()?;
+        self.persist()?;
+        for user in &dropped {
+            self.hooks.fire(Event::Deleted, user)?;
+        }
+        Ok(expired)
+    }
+    
+    /// Gets storage statistics
+    ///
+    /// Live records come from the per-segment tallies and activity from
+    /// the lifetime counters, so nothing is scanned. Counters reach disk
+    /// with the manifest; a crash may lose the most recent increments.
+    pub fn stats(&self) -> Result<Stats> {
+        self.check()?;
+        let counters = *self.counters.lock().unwrap();
+        let (records, size) = self.usage();
         Ok(Stats {
-            records: total,
-            segments,
+            records,
+            size,
+            quota: self.manifest.read().unwrap().quota,
+            segments: self.segment.list().len() as u64,
+            written: counters.written,
+            deleted: counters.deleted,
+            bytes: counters.bytes,
+            compactions: counters.compactions,
         })
     }
     
-    /// Migrates data to a new schema version
-    pub fn migrate(&self, _target_schema: u32) -> Result<()> {
-        // TODO: Implement schema migration logic
-        // This would involve:
-        // 1. Reading all records
-        // 2. Converting to new schema
-        // 3. Writing back with new schema version
-        // 4. Updating metadata
-        
-        Err(Error::Unsupported("Schema migration not yet implemented".to_string()))
+    /// Reports the memory the store holds, by part
+    ///
+    /// Counts what the store itself keeps resident; mapped segment
+    /// pages belong to the OS page cache and are not included.
+    pub fn memory(&self) -> Memory {
+        // The index is let go first: the history may be locked while waiting for it
+        let (index, buffer) = {
+            let index = self.index();
+            (index.memory(), index.buffered())
+        };
+        let audit = self.audit.as_ref().map_or(0, |audit| audit.read().unwrap().memory());
+        Memory {
+            index,
+            secondary: self.timeline.read().unwrap().memory() + self.atlas.read().unwrap().memory() + self.catalog.read().unwrap().memory() + self.history.read().unwrap().memory() + audit + self.bucket.lock().unwrap().memory(),
+            cache: self.segment.memory(),
+            buffer,
+            budget: self.budget,
+        }
     }
-}
+    
+    /// Reports the state of the store for readiness and liveness probes
+    ///
+    /// Reads every live segment's header, and the footer of sealed ones,
+    /// so cost grows with the number of segments but not with the number
+    /// of records.
+    pub fn health(&self) -> Result<Health> {
+        self.check()?;
+        let corrupt = self.segment.list().into_iter()
+            .filter(|id| self.segment.verify(*id).is_err())
+            .collect();
+        let pending = Compaction::pick(&self.segment.tallies(), Config::default().threshold, usize::MAX, self.segment.current());
+        
+        Ok(Health {
+            synced: *self.synced.lock().unwrap(),
+            backlog: self.index().backlog()?,
+            compactions: self.counters.lock().unwrap().compactions,
+            pending,
+            corrupt,
+            available: directory::available(&self.base)?,
+        })
+    }
+    
+    /// Creates a compaction service over this store's own segments and index
+    pub fn compaction(&self, config: Config) -> Compaction {
+        Compaction::new(config, Arc::clone(&self.segment), Arc::clone(&self.index))
+            .counters(Arc::clone(&self.counters))
+            .urgent(Arc::clone(&self.urgent))
+    }
+    
+    /// Runs compaction over this store in the background
+    ///
+    /// Must be called inside a Tokio runtime. The task stops when the
+    /// returned guard is dropped; drop it before closing the store.
+    pub fn schedule(&self, config: Config) -> Result<Guard> {
+        self.check()?;
+        self.compaction(config).spawn()
+    }
+    
+    /// Runs one compaction pass to completion, blocking until it ends
+    ///
+    /// Minor then major compaction, as `Compaction::trigger` would, on
+    /// a private runtime so callers need no Tokio setup. The moved
+    /// records and the manifest are synced before it returns. Inside a
+    /// Tokio runtime this fails with `Error::Config`; use `compaction`
+    /// and `trigger` there instead.
+    ///
+    /// The retention policy is enforced first, so the pass reclaims
+    /// what it purged; the state reports it in `purged`.
+    pub fn compact(&self, config: Config) -> Result<State> {
+        let _writing = self.writing()?;
+        let purged = self.police()?;
+        let mut state = self.block(config, None)?;
+        state.purged = purged;
+        Ok(state)
+    }
+    
+    /// Applies the retention policy, see `retain`
+    ///
+    /// Deletes live records whose `updated` is older than the policy's
+    /// record age, as `delete` would, then drops the versions it no
+    /// longer keeps, and with `purge` set every version of deleted
+    /// records. Returns what went.
+    pub fn enforce(&self) -> Result<Purge> {
+        let _writing = self.writing()?;
+        self.police()
+    }
+    
+    /// Applies the retention policy, the write lock held
+    fn police(&self) -> Result<Purge> {
+        let retention = self.manifest.read().unwrap().retention;
+        let time = now()?;
+        let mut purge = Purge { time, ..Purge::default() };
+        
+        if let Some(age) = retention.records {
+            let cutoff = time.saturating_sub(age);
+            let mut doomed = Vec::new();
+            let mut from = None;
+            loop {
+                let page = self.index().page(from.as_deref(), PAGE)?;
+                let Some((last, _)) = page.last() else { break };
+                from = Some(last.clone());
+                for (_, position) in page {
+                    // Unreadable records are left for whoever finds them
+                    let Ok((id, updated)) = self.segment.view(position, |user| (user.id, user.updated)) else { continue };
+                    if updated < cutoff {
+                        doomed.push(id);
+                    }
+                }
+            }
+            for &id in &doomed {
+                self.remove(id)?;
+            }
+            purge.records = doomed.len() as u64;
+        }
+        
+        if retention.purge {
+            let index = Arc::clone(&self.index);
+            purge.versions += self.history.write().unwrap().purge(|id| Ok(index.lock().unwrap().get(&id.to_le_bytes())?.is_some()))?;
+        }
+        let segments = self.manifest.read().unwrap().segments.clone();
+        purge.versions += self.history.write().unwrap().sweep(&retention, &segments, time, false)?;
+        self.history.read().unwrap().sync()?;
+        tracing::info!(records = purge.records, versions = purge.versions, "retention enforced");
+        Ok(purge)
+    }
+    
+    /// Erases a user for good, returning a certificate of erasure
+    ///
+    /// Beyond `delete`, drops every version the history keeps of the
+    /// user, rewrites each segment that held one of them or the live
+    /// record, and removes the old files, so the user's bytes are gone
+    /// from disk rather than merely unreachable. The current segment is
+    /// ended first when it holds any, and every index is merged into a
+    /// new table, so no log holds the user's ID, timestamps, location or
+    /// values either. An audit trail, when kept, still records the
+    /// delete. Copies the store no longer tracks, such as versions
+    /// retention already dropped, go when compaction next reaches their
+    /// segment. Like `rewrite`, this fails with `Error::Config` inside a
+    /// Tokio runtime, before anything changes; should the rewrite fail,
+    /// the history keeps every copy, so erasing again picks them all up.
+    pub fn erase(&self, id: u64) -> Result<Certificate> {
+        let _writing = self.writing()?;
+        blocking()?;
+        let live = self.segment.list();
+        let mut versions = self.history.read().unwrap().versions(id)?;
+        let position = self.index().get(&id.to_le_bytes())?;
+        if let Some(position) = position {
+            // The live record joins the history, so a retry still finds it
+            let updated = self.segment.view(position, |user| user.updated).unwrap_or_default();
+            versions.push(Version { updated, position });
+        }
+        // Versions in segments compaction already reclaimed are gone
+        versions.retain(|version| live.contains(&version.position.segment));
+        let mut segments = versions.iter().map(|version| version.position.segment).collect::<Vec<_>>();
+        let copies = segments.len() as u64;
+        
+        segments.sort_unstable();
+        segments.dedup();
+        if segments.contains(&self.segment.current()) {
+            self.segment.roll()?;
+        }
+        self.remove(id)?;
+        {
+            let mut history = self.history.write().unwrap();
+            for &version in &versions {
+                history.add(id, version)?;
+            }
+            history.sync()?;
+        }
+        self.block(Config { throttle: false, ..Config::default() }, Some(&segments))?;
+        
+        self.history.write().unwrap().forget(id)?;
+        self.merge()?;
+        garbage::collect(&self.base, &self.manifest.read().unwrap(), false)?;
+        
+        let certificate = Certificate::new(id, now()?, copies, segments);
+        tracing::info!(id, copies, digest = %certificate.digest, "user erased");
+        Ok(certificate)
+    }
+    
+    /// Rewrites exactly the given segments, blocking until done
+    ///
+    /// Like `compact`, but through `Compaction::segments`: no minor
+    /// pass, and the segments are rewritten whatever their dead ratio.
+    pub fn rewrite(&self, ids: &[u64], config: Config) -> Result<State> {
+        let _writing = self.writing()?;
+        self.block(config, Some(ids))
+    }
+    
+    /// Seals secret fields under a new master key from now on
+    ///
+    /// New records are sealed under `key`, in segments of their own.
+    /// Records sealed under earlier keys stay readable, and are sealed
+    /// anew as compaction moves them, or at once by passing `stale` to
+    /// `rewrite`. Until then, reopening the store needs the earlier
+    /// keys too, see `Builder::former`. The catalog's blind indexes are
+    /// filled again under the new key before this returns. Compaction
+    /// may keep running meanwhile.
+    ///
+    /// Fails with `Error::Config` on a store opened without a key and
+    /// with `Error::Invalid` for a key the store has used before.
+    pub fn rewrap(&self, key: [u8; 32]) -> Result<()> {
+        let _writing = self.writing()?;
+        let Some(cipher) = self.cipher.clone() else {
+            return Err(Error::Config("Store has no key to rewrap; open it with Builder::cipher".to_string()));
+        };
+        let fingerprint = cipher::fingerprint(&key);
+        if self.manifest.read().unwrap().keys.contains(&fingerprint) {
+            return Err(Error::Invalid { field: "key".to_string(), reason: format!("{} was used before", fingerprint) });
+        }
+        
+        // The new key is recorded before anything is sealed under it
+        let version = {
+            let mut manifest = self.manifest.write().unwrap();
+            manifest.keys.push(fingerprint);
+            manifest.backfill = Some(Backfill::default());
+            manifest.keys.len() as u32
+        };
+        self.persist()?;
+        // Sealing moves to the new key before segments name it: records
+        // compaction seals in between land in a segment naming an older
+        // key, which `stale` still lists, never the other way round
+        cipher.rotate(version, &key);
+        self.segment.rekey(version)?;
+        self.catalog.write().unwrap().forget()?;
+        while !self.fill(PAGE)? {}
+        tracing::info!(version, "secret fields rewrapped");
+        Ok(())
+    }
+    
+    /// Segments holding records not sealed under the current key
+    ///
+    /// Segments written before the store had a key count too. Pass
+    /// them to `rewrite` to seal their records under the current key.
+    pub fn stale(&self) -> Result<Vec<u64>> {
+        self.check()?;
+        let current = self.cipher.as_ref().map_or(0, |cipher| cipher.version());
+        let active = self.segment.current();
+        let mut stale = Vec::new();
+        for id in self.segment.list() {
+            if id != active && self.segment.header(id)?.key < current {
+                stale.push(id);
+            }
+        }
+        Ok(stale)
+    }
+    
+    /// Runs a compaction pass on a private runtime, then syncs and persists
+    ///
+    /// Callers hold the write lock.
+    fn block(&self, config: Config, ids: Option<&[u64]>) -> Result<State> {
+        blocking()?;
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+        let compaction = self.compaction(config);
+        match ids {
+            Some(ids) => runtime.block_on(compaction.segments(ids))?,
+            None => runtime.block_on(compaction.trigger())?,
+        }
+        let state = runtime.block_on(compaction.state());
+        
+        self.segment.sync()?;
+        self.index().sync()?;
+        self.persist()?;
+        Ok(state)
+    }
+    
+    /// Byte-keyed access to the store, for data models besides `User`
+    ///
+    /// Raw entries are kept apart from users: scans, queries and
+    /// compaction pass them by, and `Raw::compact` reclaims their
+    /// space. Writes follow the store's durability.
+    /// The view holds the entries locked until it is dropped.
+    pub fn raw(&self) -> Result<Raw<'_>> {
+        self.check()?;
+        Ok(Raw::new(self.bucket.lock().unwrap(), self.durability == Durability::Sync))
+    }
+    
+    /// Moves the store behind an asynchronous write queue
+    ///
+    /// Up to `depth` writes wait in the queue; producers beyond that
+    /// wait for room. A worker on the Tokio blocking pool commits queued
+    /// saves in batches, and `Writer::find` sees queued writes before
+    /// they commit. `Writer::close` commits what is left and hands the
+    /// store back. Outside a Tokio runtime this fails with
+    /// `Error::Config`, closing the store.
+    pub fn writer(self, depth: usize) -> Result<Writer> {
+        self.check()?;
+        Writer::spawn(self, depth)
+    }
+    
+    /// Group-commit window of the write queue, as set or tuned when opening
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+    
+    /// Closes the store
+    ///
+    /// Seals the active segment, fsyncs the index and persists the
+    /// manifest. Every later operation fails with `Error::Closed`.
+    pub fn close(&self) -> Result<()> {
+        let _writing = self.writing.lock().unwrap();
+        if self.closed.load(Ordering::Acquire) {
+            return Ok(());
+        }
+        
+        self.segment.seal()?;
+        self.index().sync()?;
+        self.timeline.read().unwrap().sync()?;
+        self.atlas.read().unwrap().sync()?;
+        self.catalog.read().unwrap().sync()?;
+        self.history.read().unwrap().sync()?;
+        if let Some(audit) = &self.audit {
+            audit.read().unwrap().seal()?;
+        }
+        self.bucket.lock().unwrap().seal()?;
+        *self.synced.lock().unwrap() = Some(now()?);
+        let next = *self.next.lock().unwrap();
+        self.manifest.write().unwrap().sequence = next - 1;
+        self.persist()?;
+        
+        self.closed.store(true, Ordering::Release);
+        Ok(())
+    }
+    
+    /// Creates a copy-on-write clone of the store at `path`
+    ///
+    /// Sealed segments and index tables never change once written, so
+    /// the clone hard-links them and copies everything else; across
+    /// filesystems they are copied too. Each store only ever writes to
+    /// files of its own, so the two diverge freely. Open the clone with
+    /// `Store::new` or a `Builder`. Fails if `path` already exists.
+    pub fn fork<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let _writing = self.writing()?;
+        let target = path.as_ref();
+        if target.exists() {
+            return Err(Error::Config(format!("Fork target {} already exists", target.display())));
+        }
+        
+        self.segment.sync()?;
+        self.timeline.read().unwrap().sync()?;
+        self.atlas.read().unwrap().sync()?;
+        self.catalog.read().unwrap().sync()?;
+        self.history.read().unwrap().sync()?;
+        if let Some(audit) = &self.audit {
+            audit.read().unwrap().sync()?;
+        }
+        self.bucket.lock().unwrap().sync()?;
+        self.persist()?;
+        
+        // Holding the index keeps compaction from moving records meanwhile
+        let index = self.index();
+        index.sync()?;
+        let active = self.segment.current();
+        let segments = self.segment.list();
+        let audit = Audit::locate(&self.base);
+        let raw = Bucket::locate(&self.base);
+        replicate(&self.base, target, &|path: &Path| {
+            let name = path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+            if name.ends_with(".tmp") {
+                return Carry::Skip;
+            }
+            // Audit and raw segments are not the store's, nor retired by its compaction
+            if path.starts_with(&audit) || path.starts_with(&raw) {
+                return Carry::Copy;
+            }
+            if name.ends_with(".table") {
+                return Carry::Link;
+            }
+            let segment = name.strip_prefix("segment_")
+                .and_then(|rest| rest.strip_suffix(".dat"))
+                .and_then(|id| id.parse::<u64>().ok());
+            match segment {
+                Some(id) if !segments.contains(&id) => Carry::Skip,
+                Some(id) if id != active => Carry::Link,
+                _ => Carry::Copy,
+            }
+        })?;
+        drop(index);
+        directory::parent(target)
+    }
+    
+    /// Streams a consistent snapshot of every record to `out`
+    ///
+    /// Records are read as by `scan`, from a snapshot of the index, so
+    /// writes and compaction may go on meanwhile. The stream suits any
+    /// `Write`, such as a file or a socket to a replica, which loads it
+    /// with `seed`. Wrap unbuffered writers in a `BufWriter`. Returns
+    /// the records sent.
+    pub fn ship<W: Write>(&self, out: W) -> Result<u64> {
+        self.check()?;
+        let next = *self.next.lock().unwrap();
+        let mut sender = Sender::new(out, next - 1)?;
+        for user in self.scan() {
+            sender.push(&user?)?;
+        }
+        sender.finish()
+    }
+    
+    /// Loads a stream written by `ship` into this empty store
+    ///
+    /// Records keep their IDs and revisions, and the sequence resumes
+    /// past the source's. Validators and hooks are skipped, as the
+    /// source already ran them. Fails with `Error::Config` when the
+    /// store holds records, and with `Error::Format` when the stream is
+    /// damaged or cut short; records before the damage stay written, so
+    /// discard the store in that case. Returns the records loaded.
+    pub fn seed<R: Read>(&self, input: R) -> Result<u64> {
+        let _writing = self.writing()?;
+        if self.index().scan().next().is_some() {
+            return Err(Error::Config("Seeding needs an empty store".to_string()));
+        }
+        let mut receiver = Receiver::new(input)?;
+        let mut users = Vec::with_capacity(SEED);
+        let mut total = 0;
+        while let Some(user) = receiver.pull()? {
+            users.push(user);
+            if users.len() == SEED {
+                total += self.plant(&users)?;
+                users.clear();
+            }
+        }
+        total += self.plant(&users)?;
+        let mut next = self.next.lock().unwrap();
+        *next = (*next).max(receiver.sequence() + 1);
+        Ok(total)
+    }
+    
+    /// Exports every record to a Parquet file at `path`
+    ///
+    /// Writes one column per user field, optional fields as nullable
+    /// columns and interests as a JSON string, so the file can be
+    /// queried directly from DuckDB or Spark. Returns the rows written.
+    #[cfg(feature = "parquet")]
+    pub fn export<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        self.check()?;
+        let file = std::fs::File::create(path)?;
+        let mut writer = crate::parquet::Writer::new(std::io::BufWriter::new(file))?;
+        for user in self.scan() {
+            writer.push(&user?)?;
+        }
+        writer.finish()
+    }
+    
+    /// Removes files the manifest no longer references
+    ///
+    /// With `dry` set, only reports what would be removed.
+    pub fn collect(&self, dry: bool) -> Result<Report> {
+        let _writing = self.writing()?;
+        self.record()?;
+        
+        let manifest = self.manifest.read().unwrap().clone();
+        let versions = self.history.write().unwrap().sweep(&manifest.retention, &manifest.segments, now()?, dry)?;
+        let mut report = garbage::collect(&self.base, &manifest, dry)?;
+        report.versions = versions;
+        Ok(report)
+    }
+    
+    /// Returns a copy of the last persisted manifest
+    pub fn manifest(&self) -> Manifest {
+        self.manifest.read().unwrap().clone()
+    }
+    
+    /// Live/dead record counts per segment
+    pub fn tallies(&self) -> BTreeMap<u64, Tally> {
+        self.segment.tallies()
+    }
+    
+    /// Fsyncs everything a write touched when durability requires it
+    fn flush(&self) -> Result<()> {
+        if self.durability == Durability::Sync {
+            self.segment.sync()?;
+            self.index().sync()?;
+            self.timeline.read().unwrap().sync()?;
+            self.atlas.read().unwrap().sync()?;
+            self.catalog.read().unwrap().sync()?;
+            self.history.read().unwrap().sync()?;
+            if let Some(audit) = &self.audit {
+                audit.read().unwrap().sync()?;
+            }
+            *self.synced.lock().unwrap() = Some(now()?);
+        }
+        Ok(())
+    }
+    
+    /// Persists the manifest when the segment set or index table changed
+    fn record(&self) -> Result<()> {
+        let segments = self.segment.list();
+        let generation = self.index().generation();
+        
+        let stale = {
+            let manifest = self.manifest.read().unwrap();
+            manifest.segments != segments || manifest.generation != generation
+        };
+        
+        if stale {
+            self.persist()?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Writes the current segment set, index generation, tallies and sealed segments to the manifest
+    ///
+    /// Everything is read before the manifest is locked, as the index
+    /// may be held by a reader waiting on the manifest.
+    fn persist(&self) -> Result<()> {
+        let segments = self.segment.list();
+        let generation = self.index().generation();
+        let tallies = self.segment.tallies();
+        let sealed = self.segment.sealed();
+        let counters = *self.counters.lock().unwrap();
+        let raw = self.bucket.lock().unwrap().tallies();
+        
+        let mut manifest = self.manifest.write().unwrap();
+        manifest.segments = segments;
+        manifest.generation = generation;
+        manifest.tallies = tallies;
+        manifest.sealed = sealed;
+        manifest.counters = counters;
+        manifest.raw = raw;
+        manifest.save(&self.base)
+    }
+    
+    /// Bumps the lifetime counters
+    fn tick(&self, bump: impl FnOnce(&mut Counters)) {
+        bump(&mut self.counters.lock().unwrap());
+    }
+    
+    /// Appends `(id, revision)` pairs to the audit log, if auditing
+    fn note(&self, action: Action, records: &[(u64, u64)]) -> Result<()> {
+        let Some(audit) = &self.audit else { return Ok(()) };
+        let time = now()?;
+        let entries = records.iter()
+            .map(|&(id, revision)| Entry { id, actor: self.actor.clone(), time, action, revision })
+            .collect::<Vec<_>>();
+        audit.write().unwrap().append(&entries)
+    }
+    
+    /// Writes seeded records as they are, revisions included
+    fn plant(&self, users: &[User]) -> Result<u64> {
+        if users.is_empty() {
+            return Ok(0);
+        }
+        self.room()?;
+        self.fits(users.len() as u64)?;
+        let positions = self.segment.admit(users, self.limit)?;
+        let operations = users.iter().zip(&positions)
+            .map(|(user, position)| Operation::Put {
+                key: user.id.to_le_bytes().to_vec(),
+                position: *position,
+            })
+            .collect();
+        self.index().batch(operations)?;
+        let bytes = positions.iter().map(|position| position.length).sum::<u64>();
+        self.tick(|counters| {
+            counters.written += positions.len() as u64;
+            counters.bytes += bytes;
+        });
+        for user in users {
+            self.reindex(None, Some(user))?;
+        }
+        let written = users.iter().map(|user| (user.id, user.revision)).collect::<Vec<_>>();
+        self.note(Action::Save, &written)?;
+        self.flush()?;
+        self.shed()?;
+        self.record()?;
+        Ok(users.len() as u64)
+    }
+    
+    /// Moves secondary index entries from a record's previous version to its current one
+    ///
+    /// The previous version joins the record's history.
+    fn reindex(&self, previous: Option<&(Position, User)>, current: Option<&User>) -> Result<()> {
+        let retention = self.manifest.read().unwrap().retention;
+        if let Some((position, previous)) = previous {
+            self.timeline.write().unwrap().remove(previous)?;
+            self.atlas.write().unwrap().remove(previous)?;
+            self.catalog.write().unwrap().remove(previous)?;
+            
+            if current.is_none() && retention.purge {
+                // A deleted record's history goes with it
+                self.history.write().unwrap().forget(previous.id)?;
+            } else {
+                let version = Version { updated: previous.updated, position: *position };
+                self.history.write().unwrap().add(previous.id, version)?;
+                self.history.write().unwrap().prune(previous.id, &retention, &self.segment.list(), now()?)?;
+            }
+        }
+        if let Some(current) = current {
+            self.timeline.write().unwrap().insert(current)?;
+            self.atlas.write().unwrap().insert(current)?;
+            self.catalog.write().unwrap().insert(current)?;
+        }
+        Ok(())
+    }
+    
+    /// Truncates a torn tail off the last segment and forgets records lost with it
+    ///
+    /// Sealed segments are skipped; their footer vouches for them.
+    /// Secondary index entries of lost records are left, as after any
+    /// crash, for queries to discard.
+    fn recover(&self) -> Result<()> {
+        let Some(last) = self.segment.list().pop() else {
+            return Ok(());
+        };
+        if self.segment.sealed().contains(&last) {
+            return Ok(());
+        }
+        let mut held = Vec::new();
+        for result in self.index().scan() {
+            let (key, position) = result?;
+            if position.segment == last {
+                held.push((key, position));
+            }
+        }
+        let Some(from) = held.iter().map(|(_, position)| position.offset).min() else {
+            return Ok(());
+        };
+        
+        let end = self.segment.trim(last, from)?;
+        let lost: Vec<_> = held.into_iter()
+            .filter(|(_, position)| position.offset + 4 + position.length > end)
+            .collect();
+        if lost.is_empty() {
+            return Ok(());
+        }
+        tracing::warn!(segment = last, end, lost = lost.len(), "dropping index entries past the torn tail");
+        for (key, position) in &lost {
+            self.index().delete(key)?;
+            self.segment.retire(*position);
+        }
+        self.index().sync()?;
+        self.persist()
+    }
+    
+    /// Rewrites records stored under an older schema in the current layout
+    ///
+    /// Records are taken a page at a time in key order after a watermark
+    /// kept in the manifest, so an interrupted upgrade resumes where it
+    /// stopped. Records the watermark has not passed but that already
+    /// sit in a segment the upgrade appended to were rewritten by a run
+    /// cut short before it saved its progress.
+    fn upgrade(&self) -> Result<()> {
+        let (schema, upgrade) = {
+            let manifest = self.manifest.read().unwrap();
+            (manifest.schema, manifest.upgrade.clone())
+        };
+        let mut upgrade = upgrade.unwrap_or_else(|| Upgrade { segment: self.segment.current(), ..Upgrade::default() });
+        loop {
+            let page = self.index().page(upgrade.watermark.as_deref(), PAGE)?;
+            let Some((last, _)) = page.last() else { break };
+            upgrade.watermark = Some(last.clone());
+            
+            for (key, position) in page {
+                if position.segment >= upgrade.segment {
+                    continue;
+                }
+                let user = legacy::read(&self.segment, position, schema)?;
+                let moved = self.segment.append(&user)?;
+                self.segment.retire(position);
+                self.index().put(&key, moved)?;
+                upgrade.records += 1;
+            }
+            // Rewritten records must be durable before the watermark passes them
+            self.segment.sync()?;
+            self.index().sync()?;
+            tracing::info!(records = upgrade.records, "upgrading records to schema {}", SCHEMA);
+            self.manifest.write().unwrap().upgrade = Some(upgrade.clone());
+            self.persist()?;
+        }
+        
+        let mut manifest = self.manifest.write().unwrap();
+        manifest.schema = SCHEMA;
+        manifest.upgrade = None;
+        drop(manifest);
+        self.persist()
+    }
+    
+    /// Appends the next revision of a user, checking the current one as `expect` says
+    ///
+    /// Returns the new revision and the version replaced, if readable.
+    #[tracing::instrument(name = "save", level = "debug", skip_all, fields(id = user.id, segment, offset, bytes))]
+    fn write(&self, user: &User, expect: Expect) -> Result<(u64, Option<User>)> {
+        let _writing = self.writing()?;
+        self.room()?;
+        self.vet(user)?;
+        self.claim(std::slice::from_ref(user))?;
+        let key = user.id.to_le_bytes();
+        
+        // Check and replace under one index lock so writers can't interleave
+        let (user, previous) = {
+            let mut index = self.index();
+            let old = index.get(&key)?;
+            // A record that can no longer be read counts as revision 0, as in `batch`
+            let revision = old
+                .and_then(|old| self.segment.view(old, |stored| stored.revision).ok())
+                .unwrap_or(0);
+            self.fits(u64::from(old.is_none()))?;
+            
+            match expect {
+                Expect::Revision(expected) if expected != revision => {
+                    return Err(Error::Conflict {
+                        id: user.id,
+                        expected,
+                        actual: revision,
+                    });
+                }
+                Expect::Present if old.is_none() => return Err(self.absent(user.id)),
+                _ => {}
+            }
+            
+            let user = User { revision: revision + 1, ..user.clone() };
+            let position = self.segment.admit(std::slice::from_ref(&user), self.limit)?[0];
+            trace(position);
+            let previous = old.and_then(|old| self.retire(old));
+            index.put(&key, position)?;
+            self.tick(|counters| {
+                counters.written += 1;
+                counters.bytes += position.length;
+            });
+            (user, previous)
+        };
+        
+        self.reindex(previous.as_ref(), Some(&user))?;
+        self.note(Action::Save, &[(user.id, user.revision)])?;
+        self.flush()?;
+        self.shed()?;
+        self.record()?;
+        self.hooks.fire(Event::Saved, &user)?;
+        Ok((user.revision, previous.map(|(_, previous)| previous)))
+    }
+    
+    /// Reads the users a lookup matches, following its plan
+    ///
+    /// `keep` is checked either way: on a scan it is the filter, and
+    /// behind the catalog it guards against entries left by a crash.
+    /// Records a backfill has not reached are matched by `keep` too.
+    fn select(&self, plan: Plan, ids: impl FnOnce() -> Result<Vec<u64>>, keep: impl Fn(&User) -> bool) -> Result<Vec<User>> {
+        let mut users = Vec::new();
+        match plan {
+            Plan::Index => {
+                let mut ids = ids()?;
+                ids.extend(self.stragglers(&keep)?.iter().map(|user| user.id));
+                ids.sort_unstable();
+                ids.dedup();
+                for id in ids {
+                    let Some(user) = self.find(id)? else { continue };
+                    if keep(&user) {
+                        users.push(user);
+                    }
+                }
+            }
+            Plan::Scan => {
+                for user in self.scan() {
+                    let user = user?;
+                    if keep(&user) {
+                        users.push(user);
+                    }
+                }
+                users.sort_unstable_by_key(|user| user.id);
+            }
+        }
+        Ok(users)
+    }
+    
+    /// Refuses users taking a unique value another user holds
+    ///
+    /// Only the last version of each user in `users` counts, so a
+    /// batch may hand a value from one user to another. Holders are
+    /// read back, as catalog entries can outlive their record after a
+    /// crash. Runs before anything is written, under the write lock, so
+    /// no other save can take the value meanwhile.
+    fn claim(&self, users: &[User]) -> Result<()> {
+        let latest = users.iter().map(|user| (user.id, user)).collect::<HashMap<_, _>>();
+        let mut taken = HashMap::new();
+        for user in latest.values() {
+            for (field, value) in catalog::unique(user) {
+                if let Some(holder) = taken.insert((field, value), user.id) {
+                    return Err(duplicate(field, value, holder));
+                }
+            }
+        }
+        
+        for user in latest.values() {
+            for (field, value) in catalog::unique(user) {
+                let holders = self.catalog.read().unwrap().find(field, &[value])?;
+                for holder in holders {
+                    // Users in the batch are judged by their new values
+                    if holder == user.id || latest.contains_key(&holder) {
+                        continue;
+                    }
+                    let held = self.find(holder)?
+                        .is_some_and(|stored| catalog::value(&stored, field) == Some(value));
+                    if held {
+                        return Err(duplicate(field, value, holder));
+                    }
+                }
+            }
+        }
+        
+        // Holders a backfill has not reached are missing from the catalog
+        let stragglers = self.stragglers(|stored| {
+            !latest.contains_key(&stored.id) && catalog::unique(stored).any(|pair| taken.contains_key(&pair))
+        })?;
+        for stored in &stragglers {
+            if let Some((field, value)) = catalog::unique(stored).find(|pair| taken.contains_key(pair)) {
+                return Err(duplicate(field, value, stored.id));
+            }
+        }
+        Ok(())
+    }
+    
+    /// Records a backfill has not reached yet that `keep` accepts
+    ///
+    /// Queries add these to what the secondary indexes return, so they
+    /// stay whole while a backfill runs. Empty when none is under way.
+    fn stragglers(&self, keep: impl Fn(&User) -> bool) -> Result<Vec<User>> {
+        let Some(backfill) = self.manifest.read().unwrap().backfill.clone() else {
+            return Ok(Vec::new());
+        };
+        let mut users = Vec::new();
+        let mut from = backfill.watermark;
+        loop {
+            let page = self.index().page(from.as_deref(), PAGE)?;
+            let Some((last, _)) = page.last() else { break };
+            from = Some(last.clone());
+            
+            for (key, position) in page {
+                let user = self.load(&key, position)?;
+                if keep(&user) {
+                    users.push(user);
+                }
+            }
+        }
+        Ok(users)
+    }
+    
+    /// Error for a user not stored: `Error::Deleted` if it ever was, as far as is known
+    fn absent(&self, id: u64) -> Error {
+        let audited = self.audit.as_ref()
+            .and_then(|audit| audit.read().unwrap().entries(id).ok())
+            .is_some_and(|entries| entries.last().is_some_and(|entry| entry.action == Action::Delete));
+        let versioned = self.history.read().unwrap().versions(id).is_ok_and(|versions| !versions.is_empty());
+        if audited || versioned {
+            Error::Deleted { id }
+        } else {
+            Error::Missing(format!("User {}", id))
+        }
+    }
+    
+    /// Retires a replaced version and returns it when still readable
+    fn retire(&self, old: Position) -> Option<(Position, User)> {
+        let previous = self.segment.read(old).ok();
+        self.segment.retire(old);
+        previous.map(|user| (old, user))
+    }
+    
+    /// Loads the users behind timeline entries
+    ///
+    /// Entries whose record no longer carries that timestamp are skipped,
+    /// which guards against a timeline left behind by a crash.
+    fn resolve(&self, entries: Vec<(u64, u64)>, stamp: fn(&User) -> u64) -> Result<Vec<User>> {
+        let mut users = Vec::with_capacity(entries.len());
+        for (time, id) in entries {
+            if let Some(user) = self.find(id)? {
+                if stamp(&user) == time {
+                    users.push(user);
+                }
+            }
+        }
+        Ok(users)
+    }
+    
+    /// Calls `each` with every live record in archived form
+    fn visit(&self, mut each: impl FnMut(&ArchivedUser)) -> Result<()> {
+        self.check()?;
+        let mut from = None;
+        loop {
+            let page = self.index().page(from.as_deref(), PAGE)?;
+            let Some((last, _)) = page.last() else { break };
+            from = Some(last.clone());
+            
+            for (_, position) in page {
+                self.segment.view(position, &mut each)?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Locks the index
+    fn index(&self) -> MutexGuard<'_, Index> {
+        self.index.lock().unwrap()
+    }
+    
+    /// Takes the write lock, failing with `Error::Closed` once the store has been closed
+    fn writing(&self) -> Result<MutexGuard<'_, ()>> {
+        let writing = self.writing.lock().unwrap();
+        self.check()?;
+        Ok(writing)
+    }
+    
+    /// Reads the user stored at an index position
+    fn load(&self, key: &[u8], position: Position) -> Result<User> {
+        // Keys are little-endian user IDs
+        if key.len() != 8 {
+            return Err(Error::Key {
+                key: key.to_vec(),
+                reason: format!("expected 8 bytes, found {}", key.len()),
+            });
+        }
+        
+        self.segment.read(position)
+    }
+    
+    /// Fails with `Error::Closed` once the store has been closed
+    fn check(&self) -> Result<()> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(Error::Closed);
+        }
+        Ok(())
+    }
+    
+    /// Runs every validator over a record about to be written
+    fn validate(&self, user: &User) -> Result<()> {
+        for validator in &self.validators {
+            validator.validate(user)?;
+        }
+        Ok(())
+    }
+    
+    /// Frees memory until the store is back under its budget
+    ///
+    /// Cheapest first: writing the index buffer and dropping cached
+    /// dictionaries cost little, while a merge rewrites a whole table,
+    /// so only deltas above a sixteenth of the budget are merged.
+    fn shed(&self) -> Result<()> {
+        let Some(budget) = self.budget else {
+            return Ok(());
+        };
+        if self.memory().total() <= budget {
+            return Ok(());
+        }
+        self.index().flush()?;
+        self.segment.evict();
+        
+        let floor = budget / 16;
+        if self.memory().total() > budget {
+            self.index().shrink(floor)?;
+        }
+        if self.memory().total() > budget {
+            self.timeline.write().unwrap().shrink(floor)?;
+            self.atlas.write().unwrap().shrink(floor)?;
+            self.catalog.write().unwrap().shrink(floor)?;
+            self.history.write().unwrap().shrink(floor)?;
+            if let Some(audit) = &self.audit {
+                audit.write().unwrap().shrink(floor)?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Merges every index delta into its table, emptying the logs, and records the new table
+    fn merge(&self) -> Result<()> {
+        self.index().shrink(0)?;
+        self.timeline.write().unwrap().shrink(0)?;
+        self.atlas.write().unwrap().shrink(0)?;
+        self.catalog.write().unwrap().shrink(0)?;
+        self.history.write().unwrap().shrink(0)?;
+        self.record()
+    }
+    
+    /// Live records and their stored bytes, from the segment tallies
+    fn usage(&self) -> (u64, u64) {
+        self.segment.tallies().values()
+            .fold((0, 0), |(records, bytes), tally| (records + tally.live, bytes + tally.bytes))
+    }
+    
+    /// Refuses a write adding `added` records that the quota has no room for
+    fn fits(&self, added: u64) -> Result<()> {
+        let quota = self.manifest.read().unwrap().quota;
+        if quota == Quota::default() {
+            return Ok(());
+        }
+        let (records, bytes) = self.usage();
+        if let Some(limit) = quota.records {
+            if added > 0 && records + added > limit {
+                return Err(Error::Quota { resource: "records".to_string(), used: records, limit });
+            }
+        }
+        if let Some(limit) = quota.bytes {
+            if bytes >= limit {
+                return Err(Error::Quota { resource: "bytes".to_string(), used: bytes, limit });
+            }
+        }
+        Ok(())
+    }
+    
+    /// Refuses writes once free disk space drops below the reserve
+    ///
+    /// Also wakes a scheduled compaction, which may free whole segments.
+    fn room(&self) -> Result<()> {
+        if self.reserve == 0 {
+            return Ok(());
+        }
+        match directory::available(&self.base)? {
+            Some(available) if available < self.reserve => {
+                self.urgent.notify_one();
+                Err(Error::Capacity { available, reserve: self.reserve })
+            }
+            _ => Ok(()),
+        }
+    }
+    
+    /// Checks that records are in schema `target`
+    ///
+    /// Records of an older schema are rewritten as a store opens, see
+    /// `migration`, so an open store's records are always in `SCHEMA`.
+    /// Fails with `Error::Unsupported` for any other target, since this
+    /// build writes no other layout.
+    pub fn migrate(&self, target: u32) -> Result<()> {
+        self.check()?;
+        let schema = self.manifest.read().unwrap().schema;
+        if target != schema {
+            return Err(Error::Unsupported(format!(
+                "Records are in schema {} and this build cannot rewrite them to schema {}",
+                schema, target,
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Records where a record lies on the current span
+fn trace(position: Position) {
+    let span = tracing::Span::current();
+    span.record("segment", position.segment);
+    span.record("offset", position.offset);
+    span.record("bytes", position.length);
+}
+
+/// What a write requires of the revision stored before it
+#[derive(Debug, Clone, Copy)]
+enum Expect {
+    /// Nothing: the user may or may not exist
+    Any,
+    /// Exactly this revision, 0 for no user
+    Revision(u64),
+    /// Any revision: the user must exist
+    Present,
+}
+
+/// How `fork` carries a file over to the clone
+enum Carry {
+    /// Share the file through a hard link
+    Link,
+    /// Give the clone its own copy
+    Copy,
+    /// Leave the file behind
+    Skip,
+}
+
+/// Recreates a directory tree, linking or copying each file as `choose` says
+fn replicate(from: &Path, to: &Path, choose: &dyn Fn(&Path) -> Carry) -> Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let entry = entry?;
+        let source = entry.path();
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            replicate(&source, &target, choose)?;
+            continue;
+        }
+        match choose(&source) {
+            Carry::Link if std::fs::hard_link(&source, &target).is_ok() => {}
+            Carry::Link | Carry::Copy => {
+                std::fs::copy(&source, &target)?;
+                std::fs::File::open(&target)?.sync_all()?;
+            }
+            Carry::Skip => {}
+        }
+    }
+    directory::sync(to)
+}
+
+/// Cipher over the keys given for an encrypted store
+///
+/// `master` must be the key the manifest names last, and every earlier
+/// key some segment is still sealed under must be among `former`;
+/// otherwise this fails with `Error::Config`.
+fn unlock(manifest: &Manifest, segment: &Segment, master: &Master, former: &[Master]) -> Result<Cipher> {
+    let current = manifest.keys.len() as u32;
+    let given = cipher::fingerprint(&master.0);
+    if manifest.keys.last() != Some(&given) {
+        return Err(Error::Config(format!("Store is encrypted with key {}, not {}", manifest.keys[current as usize - 1], given)));
+    }
+    
+    let cipher = Cipher::new(current, &master.0);
+    let mut held = vec![current];
+    for master in former {
+        let fingerprint = cipher::fingerprint(&master.0);
+        let version = manifest.keys.iter().position(|known| *known == fingerprint)
+            .ok_or_else(|| Error::Config(format!("Key {} was never used by the store", fingerprint)))? as u32 + 1;
+        cipher.admit(version, &master.0);
+        held.push(version);
+    }
+    for id in segment.list() {
+        // Damaged segments are left to verification to report
+        let Ok(header) = segment.header(id) else { continue };
+        if header.key != 0 && !held.contains(&header.key) {
+            return Err(Error::Config(format!(
+                "Segment {} is sealed under key {}, which was not given",
+                id,
+                manifest.keys.get(header.key as usize - 1).map_or("unknown", String::as_str),
+            )));
+        }
+    }
+    Ok(cipher)
+}
+
+/// Error for a unique value already held by `holder`
+fn duplicate(field: Field, value: &str, holder: u64) -> Error {
+    Error::Duplicate { field: field.name().to_string(), value: value.to_string(), holder }
+}
+
+/// Fails with `Error::Config` inside a Tokio runtime, where blocking compaction cannot run
+fn blocking() -> Result<()> {
+    if tokio::runtime::Handle::try_current().is_ok() {
+        return Err(Error::Config("Blocking compaction cannot run inside a Tokio runtime".to_string()));
+    }
+    Ok(())
+}
+
+/// Current time in seconds since the Unix epoch
+fn now() -> Result<u64> {
+    Ok(std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs())
+}
+
+/// Next value of a SplitMix64 sequence, for sampling rather than secrets
+fn mix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut value = *state;
+    value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    value ^ (value >> 31)
+}
 
 /// Storage statistics
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize)]
 pub struct Stats {
     /// Total number of records
     pub records: u64,
+    /// Stored bytes of the live records
+    pub size: u64,
+    /// Limits on records and bytes
+    pub quota: Quota,
     /// Total number of segments
     pub segments: u64,
+    /// Records written over the store's lifetime
+    pub written: u64,
+    /// Records deleted over the store's lifetime
+    pub deleted: u64,
+    /// Bytes of record data written over the store's lifetime
+    pub bytes: u64,
+    /// Compaction passes completed over the store's lifetime
+    pub compactions: u64,
+}
+
+impl Stats {
+    /// Adds another store's statistics, as for shards of one store
+    ///
+    /// A limit missing on either side stays missing.
+    pub fn merge(&mut self, other: &Stats) {
+        self.records += other.records;
+        self.size += other.size;
+        self.quota.records = self.quota.records.zip(other.quota.records).map(|(a, b)| a + b);
+        self.quota.bytes = self.quota.bytes.zip(other.quota.bytes).map(|(a, b)| a + b);
+        self.segments += other.segments;
+        self.written += other.written;
+        self.deleted += other.deleted;
+        self.bytes += other.bytes;
+        self.compactions += other.compactions;
+    }
+}
+
+/// Memory held by a store, in bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Memory {
+    /// Primary index delta and table fence keys
+    pub index: usize,
+    /// Secondary, audit and raw index deltas and fence keys
+    pub secondary: usize,
+    /// Cached segment dictionaries and the dictionary sample
+    pub cache: usize,
+    /// Index log records held in the write buffer
+    pub buffer: usize,
+    /// Budget the store sheds down to, if capped
+    pub budget: Option<usize>,
+}
+
+impl Memory {
+    /// Bytes held across all parts
+    pub fn total(&self) -> usize {
+        self.index + self.secondary + self.cache + self.buffer
+    }
+}
+
+/// Outcome of `Store::rebuild`
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Rebuild {
+    /// Records indexed
+    pub records: u64,
+    /// Each secondary index compared with its old self, when verified
+    pub checks: Vec<Check>,
+}
+
+impl Rebuild {
+    /// Whether every rebuilt index holds exactly what the old one did
+    pub fn matched(&self) -> bool {
+        self.checks.iter().all(|check| check.stale == 0 && check.missing == 0)
+    }
+}
+
+/// One secondary index compared entry by entry with its old self
+///
+/// Stale entries were left by records since changed or lost, as after
+/// a crash; missing ones were never written, as when an index was
+/// damaged. Either way the rebuilt index is the one to trust.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct Check {
+    /// Name of the index
+    pub name: String,
+    /// Entries in both
+    pub kept: u64,
+    /// Entries only the old index held
+    pub stale: u64,
+    /// Entries only the rebuilt index holds
+    pub missing: u64,
+}
+
+impl Check {
+    /// Walks both indexes in key order, counting where they differ
+    fn compare(name: &str, before: &Snapshot, after: &Snapshot) -> Result<Self> {
+        let mut check = Check { name: name.to_string(), kept: 0, stale: 0, missing: 0 };
+        let mut before = before.scan().peekable();
+        let mut after = after.scan().peekable();
+        loop {
+            let order = match (before.peek(), after.peek()) {
+                (None, None) => return Ok(check),
+                (Some(Ok((old, _))), Some(Ok((new, _)))) => old.cmp(new),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (Some(Err(_)), _) => return Err(before.next().unwrap().unwrap_err()),
+                (_, Some(Err(_))) => return Err(after.next().unwrap().unwrap_err()),
+            };
+            match order {
+                std::cmp::Ordering::Less => {
+                    check.stale += 1;
+                    before.next();
+                }
+                std::cmp::Ordering::Greater => {
+                    check.missing += 1;
+                    after.next();
+                }
+                std::cmp::Ordering::Equal => {
+                    check.kept += 1;
+                    before.next();
+                    after.next();
+                }
+            }
+        }
+    }
+}
+
+/// Readiness report of a store
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Health {
+    /// When the store last fsynced its files, in seconds since the epoch
+    pub synced: Option<u64>,
+    /// Bytes of index log not yet merged into a table
+    pub backlog: u64,
+    /// Compaction passes completed over the store's lifetime
+    pub compactions: u64,
+    /// Segments with enough dead records to be compacted
+    pub pending: Vec<u64>,
+    /// Live segments whose header is missing or damaged
+    pub corrupt: Vec<u64>,
+    /// Bytes free on the filesystem holding the store, when known
+    pub available: Option<u64>,
+}
+
+impl Health {
+    /// Whether every live segment is intact
+    pub fn healthy(&self) -> bool {
+        self.corrupt.is_empty()
+    }
+}
+
+/// Aggregate of a numeric field
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize)]
+pub struct Summary {
+    /// Number of records carrying the field
+    pub count: u64,
+    /// Smallest value, if any record carried the field
+    pub min: Option<f64>,
+    /// Largest value, if any record carried the field
+    pub max: Option<f64>,
+    /// Sum of all values
+    pub sum: f64,
+}
+
+impl Summary {
+    /// Folds one value into the summary
+    pub fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+    }
+    
+    /// Arithmetic mean, if any record carried the field
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum / self.count as f64)
+    }
 }
 
 impl Drop for Store {
     fn drop(&mut self) {
-        // Resources will be cleaned up automatically
+        // Best-effort clean shutdown; call `close` to observe failures
+        let _ = self.close();
+    }
+}
+
+/// Iterator over live index entries, paging through the index
+struct Entries<'a> {
+    /// Store being scanned
+    store: &'a Store,
+    /// Index as it was when the scan started
+    snapshot: Snapshot,
+    /// Last key handed out, where the next page starts
+    from: Option<Vec<u8>>,
+    /// Entries of the current page
+    buffer: std::vec::IntoIter<(Vec<u8>, Position)>,
+    /// Set once the index is exhausted
+    done: bool,
+    /// Error to report before anything else
+    fault: Option<Error>,
+    /// Span the scan runs in, counting entries and their bytes
+    span: tracing::Span,
+    /// Entries handed out so far
+    entries: u64,
+    /// Record bytes behind those entries
+    bytes: u64,
+}
+
+impl<'a> Entries<'a> {
+    /// Starts at the first key of a store's index
+    fn new(store: &'a Store) -> Self {
+        Self {
+            store,
+            snapshot: store.index().snapshot(),
+            from: None,
+            buffer: Vec::new().into_iter(),
+            done: false,
+            // A closed store yields its error once and nothing else
+            fault: store.check().err(),
+            span: tracing::debug_span!("scan", entries = 0u64, bytes = 0u64),
+            entries: 0,
+            bytes: 0,
+        }
+    }
+}
+
+impl Iterator for Entries<'_> {
+    type Item = Result<(Vec<u8>, Position)>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(fault) = self.fault.take() {
+            self.done = true;
+            return Some(Err(fault));
+        }
+        
+        loop {
+            if let Some(entry) = self.buffer.next() {
+                return Some(Ok(entry));
+            }
+            
+            if self.done {
+                return None;
+            }
+            
+            let _entered = self.span.enter();
+            let page = match self.snapshot.page(self.from.as_deref(), PAGE) {
+                Ok(page) => page,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+            
+            self.entries += page.len() as u64;
+            self.bytes += page.iter().map(|(_, position)| position.length).sum::<u64>();
+            self.span.record("entries", self.entries);
+            self.span.record("bytes", self.bytes);
+            
+            self.done = page.len() < PAGE;
+            self.from = page.last().map(|(key, _)| key.clone());
+            self.buffer = page.into_iter();
+        }
+    }
+}
+
+/// Users of a store in key order, read a page at a time in disk order
+struct Scan<'a> {
+    /// Store being scanned
+    store: &'a Store,
+    /// Index entries still to read
+    entries: Entries<'a>,
+    /// Users of the current page
+    ready: std::vec::IntoIter<Result<User>>,
+}
+
+impl<'a> Scan<'a> {
+    /// Starts a scan at the first key
+    fn new(store: &'a Store) -> Self {
+        Self {
+            store,
+            entries: Entries::new(store),
+            ready: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl Iterator for Scan<'_> {
+    type Item = Result<User>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(user) = self.ready.next() {
+            return Some(user);
+        }
+        
+        let page: Vec<_> = self.entries.by_ref().take(PAGE).collect();
+        if page.is_empty() {
+            return None;
+        }
+        let _entered = self.entries.span.enter();
+        
+        // Only well-formed keys are fetched; the rest fail in `load`
+        let positions: Vec<Position> = page.iter()
+            .filter_map(|entry| match entry {
+                Ok((key, position)) if key.len() == 8 => Some(*position),
+                _ => None,
+            })
+            .collect();
+        let mut users = self.store.segment.fetch(&positions).into_iter();
+        let page: Vec<_> = page.into_iter()
+            .map(|entry| entry.and_then(|(key, position)| match key.len() {
+                8 => users.next().unwrap_or_else(|| self.store.load(&key, position)),
+                _ => self.store.load(&key, position),
+            }))
+            .collect();
+        
+        self.ready = page.into_iter();
+        self.ready.next()
+    }
+}
+
+/// Scan handed out as a stream, yielding to the executor between bursts
+struct Flow<'a> {
+    /// Underlying scan
+    scan: Scan<'a>,
+    /// Users handed out since the last yield
+    burst: usize,
+}
+
+impl Stream for Flow<'_> {
+    type Item = Result<User>;
+    
+    fn poll_next(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.burst == BURST {
+            self.burst = 0;
+            context.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        self.burst += 1;
+        Poll::Ready(self.scan.next())
+    }
+}
+
+/// Records a lossy scan set aside
+#[derive(Debug, Default)]
+pub struct Quarantine {
+    /// Damaged records in scan order
+    pub records: Vec<Damage>,
+}
+
+impl Quarantine {
+    /// Positions of the damaged records
+    pub fn positions(&self) -> Vec<Position> {
+        self.records.iter().map(|damage| damage.position).collect()
     }
-} 
\ No newline at end of file
+}
+
+/// A record that could not be decoded
+#[derive(Debug)]
+pub struct Damage {
+    /// User the index maps to the record
+    pub id: u64,
+    /// Where the record is stored
+    pub position: Position,
+    /// Why it could not be read
+    pub error: Error,
+}
+
+/// Lossy scan over stored users, see `Store::salvage`
+pub struct Salvage<'a> {
+    /// Index entries still to visit
+    entries: Entries<'a>,
+    /// Records skipped so far
+    quarantine: Quarantine,
+}
+
+impl Salvage<'_> {
+    /// Records skipped so far
+    pub fn quarantine(&self) -> &Quarantine {
+        &self.quarantine
+    }
+    
+    /// Ends the scan, keeping its quarantine
+    pub fn finish(self) -> Quarantine {
+        self.quarantine
+    }
+}
+
+impl Iterator for Salvage<'_> {
+    type Item = Result<User>;
+    
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (key, position) = match self.entries.next()? {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e)),
+            };
+            
+            match self.entries.store.load(&key, position) {
+                Err(error) if error.kind() == Kind::Corruption => {
+                    let id = key.as_slice().try_into().map_or(0, u64::from_le_bytes);
+                    self.quarantine.records.push(Damage { id, position, error });
+                }
+                result => return Some(result),
+            }
+        }
+    }
+}
+
+/// Zero-copy scan over stored users, see `Store::archived`
+///
+/// Not an `Iterator`: each view borrows the scan's own buffers.
+pub struct Archives<'a> {
+    /// Index entries still to visit
+    entries: Entries<'a>,
+    /// Mapped segments by identifier
+    maps: HashMap<u64, Mmap>,
+    /// Buffer for records that cannot be read in place
+    buffer: AlignedVec,
+    /// Buffer for archives rebuilt by the codec
+    scratch: AlignedVec,
+}
+
+/// An archived user pinned in memory, from `Store::pin`
+pub struct Pinned {
+    /// The view, pointing into one of the buffers below
+    user: NonNull<ArchivedUser>,
+    /// Mapped segment holding records read in place
+    map: Mmap,
+    /// Record copied or decompressed out of the map
+    buffer: AlignedVec,
+    /// Archive rebuilt by a codec that cannot read in place
+    scratch: AlignedVec,
+}
+
+impl Deref for Pinned {
+    type Target = ArchivedUser;
+    
+    fn deref(&self) -> &ArchivedUser {
+        // Safety: the view was validated when pinned and points into
+        // the map or a buffer's heap allocation, which moving the guard
+        // leaves in place and which is never written again
+        unsafe { self.user.as_ref() }
+    }
+}
+
+impl std::fmt::Debug for Pinned {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pinned")
+            .field("id", &self.id)
+            .field("mapped", &self.map.len())
+            .field("copied", &(self.buffer.len() + self.scratch.len()))
+            .finish()
+    }
+}
+
+impl Archives<'_> {
+    /// Borrows the next stored user
+    pub fn advance(&mut self) -> Option<Result<&ArchivedUser>> {
+        match self.entries.next()? {
+            Ok((_, position)) => Some(self.view(position)),
+            Err(e) => Some(Err(e)),
+        }
+    }
+    
+    /// Validates the record at a position, mapping its segment as needed
+    fn view(&mut self, position: Position) -> Result<&ArchivedUser> {
+        // The active segment grows after it is mapped
+        let end = position.offset + 4 + position.length;
+        let stale = self.maps.get(&position.segment).is_none_or(|map| (map.len() as u64) < end);
+        if stale {
+            let map = self.entries.store.segment.map(position.segment)?;
+            self.maps.insert(position.segment, map);
+        }
+        
+        let maps = &self.maps;
+        self.entries.store.segment.archived(&maps[&position.segment], position, &mut self.buffer, &mut self.scratch)
+    }
+}
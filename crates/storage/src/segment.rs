@@ -2,20 +2,166 @@
 //! 
 //! Handles immutable segment files for efficient data storage
 //! with automatic segment rotation when size limits are reached.
+//! A segment that fills and rotates ends with a footer holding its
+//! record count, its length and a checksum of everything after the
+//! header, so a sealed segment can be validated without decoding it.
+//!
+//! Appends are written at the active segment's tail, kept in memory,
+//! rather than after seeking to the end of the file each time.
 
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write, Seek, SeekFrom};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::{BufReader, Read, Write, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
-use rkyv::{to_bytes, Archive, Deserialize, Infallible};
+use std::sync::{Arc, Mutex, MutexGuard};
+use memmap2::Mmap;
+use rkyv::{to_bytes, AlignedVec, Archive, CheckBytes, Deserialize, Infallible};
+use rkyv::validation::validators::DefaultValidator;
 use crate::{Error, Result};
-use crate::model::{Position, Header, Metadata};
+use crate::backend::{Backend, Disk, Handle};
+use crate::direct;
+use crate::codec::{Codec, Rkyv};
+use crate::model::{ArchivedUser, Position, Header, Metadata, User, SCHEMA};
 
 /// Magic number for segment file validation
 const MAGIC: u32 = 0x47535452; // "GSTR"
 
-/// Maximum segment size in bytes (256MB)
-const MAXSIZE: u64 = 256 * 1024 * 1024;
+/// Magic number closing the footer of a sealed segment
+const SEAL: u32 = 0x47534546; // "GSEF"
+
+/// Footer size: record count + data length + checksum + magic
+const FOOTER: u64 = 8 + 8 + 4 + 4;
+
+/// Default maximum segment size in bytes (256MB)
+pub const MAXSIZE: u64 = 256 * 1024 * 1024;
+
+/// Bytes read ahead when fetching records in disk order (1MB)
+const READAHEAD: usize = 1024 * 1024;
+
+/// Length-prefix bit marking a compressed record
+const PACKED: u32 = 1 << 31;
+
+/// Length-prefix bit marking a record compressed against its segment's dictionary
+const SHARED: u32 = 1 << 30;
+
+/// Length-prefix bits holding the record length
+const LENGTH: u32 = !(PACKED | SHARED);
+
+/// Default size in bytes below which records are stored uncompressed
+pub const CUTOFF: usize = 64;
+
+/// Bytes of recent records kept to seed the next segment's dictionary
+const SAMPLE: usize = 4 * 1024;
+
+/// Alignment of record payloads, so mapped archives can be read in place
+const ALIGN: u64 = 16;
+
+/// How record payloads are encoded on disk
+///
+/// Each record carries its own flag, so segments may mix encodings and
+/// the setting can change between opens.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Records are stored as archived
+    #[default]
+    None,
+    /// Records are LZ4 block-compressed
+    Lz4,
+    /// Records are LZ4-compressed against a per-segment dictionary
+    ///
+    /// Each new segment starts with a sample of recently written
+    /// records, so strings many records share, such as cities and
+    /// countries, are stored once per segment and referenced after.
+    Dictionary,
+}
+
+/// Live and dead record counts for one segment
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub struct Tally {
+    /// Records still referenced by the index
+    pub live: u64,
+    /// Records superseded or deleted
+    pub dead: u64,
+    /// Stored bytes of the live records
+    #[serde(default)]
+    pub bytes: u64,
+}
+
+impl Tally {
+    /// Fraction of records in the segment that are dead
+    pub fn ratio(&self) -> f64 {
+        let total = self.live + self.dead;
+        if total == 0 {
+            0.0
+        } else {
+            self.dead as f64 / total as f64
+        }
+    }
+}
+
+/// Summary ending a sealed segment
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Footer {
+    /// Records written to the segment
+    pub records: u64,
+    /// Length of the segment before the footer
+    pub bytes: u64,
+    /// CRC-32 of the bytes between the header and the footer
+    pub checksum: u32,
+}
+
+impl Footer {
+    /// Encodes the footer as little-endian fields ending in its magic
+    fn encode(&self) -> [u8; FOOTER as usize] {
+        let mut bytes = [0u8; FOOTER as usize];
+        bytes[..8].copy_from_slice(&self.records.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.bytes.to_le_bytes());
+        bytes[16..20].copy_from_slice(&self.checksum.to_le_bytes());
+        bytes[20..].copy_from_slice(&SEAL.to_le_bytes());
+        bytes
+    }
+    
+    /// Decodes a footer, or `None` when the magic is missing
+    fn decode(bytes: &[u8; FOOTER as usize]) -> Option<Self> {
+        if u32::from_le_bytes(bytes[20..].try_into().unwrap()) != SEAL {
+            return None;
+        }
+        Some(Self {
+            records: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            bytes: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+            checksum: u32::from_le_bytes(bytes[16..20].try_into().unwrap()),
+        })
+    }
+}
+
+/// A record found walking a segment file, see `Segment::walk`
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    /// Offset of the record's length prefix
+    pub offset: u64,
+    /// Stored length of the payload
+    pub length: u64,
+    /// Whether the payload is LZ4-compressed on its own
+    pub packed: bool,
+    /// Whether the payload is compressed against the segment's dictionary
+    pub shared: bool,
+    /// CRC-32 of the payload as stored
+    pub checksum: u32,
+}
+
+/// Layout of a segment file as read from its bytes alone
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Walk {
+    /// Footer ending the file, if one does
+    pub footer: Option<Footer>,
+    /// Length of the dictionary after the header, if the segment has one
+    pub dictionary: Option<u64>,
+    /// Records in file order
+    pub entries: Vec<Entry>,
+    /// End of the last whole record
+    pub end: u64,
+    /// Where records should end: the footer's start, or the file's end
+    pub limit: u64,
+}
 
 /// Manages segment-based storage operations
 pub struct Segment {
@@ -24,18 +170,60 @@ pub struct Segment {
     /// Current active segment ID
     current: Arc<Mutex<u64>>,
     /// Current segment file handle
-    file: Arc<Mutex<Option<File>>>,
+    file: Arc<Mutex<Option<Box<dyn Handle>>>>,
     /// Current segment metadata
     metadata: Arc<Mutex<Metadata>>,
+    /// Identifiers of segment files written so far
+    live: Arc<Mutex<Vec<u64>>>,
+    /// Live/dead record counts per segment
+    tallies: Arc<Mutex<BTreeMap<u64, Tally>>>,
+    /// Size in bytes at which the active segment rotates
+    capacity: u64,
+    /// Encoding of newly appended records
+    compression: Compression,
+    /// Where segment files are read and written
+    backend: Arc<dyn Backend>,
+    /// How records are serialized
+    codec: Arc<dyn Codec>,
+    /// Tail of recently archived records, the next dictionary
+    sample: Arc<Mutex<Vec<u8>>>,
+    /// Dictionaries of segments read or written so far
+    dictionaries: Arc<Mutex<HashMap<u64, Arc<Vec<u8>>>>>,
+    /// Segments ended with a footer
+    sealed: Arc<Mutex<BTreeSet<u64>>>,
+    /// Checksum so far of the active segment, unknown after a failed write
+    digest: Arc<Mutex<Option<crc32fast::Hasher>>>,
+    /// Held for a whole append, rotation included
+    appending: Arc<Mutex<()>>,
+    /// Size in bytes below which records skip compression
+    cutoff: usize,
+    /// Whether new segment files reserve their capacity up front
+    preallocate: bool,
+    /// Whether segments are read around the page cache
+    direct: bool,
 }
 
 impl Segment {
     /// Creates a new segment manager
     pub fn new<P: AsRef<Path>>(base: P) -> Result<Self> {
+        let live = Self::discover(base.as_ref())?;
+        Self::restore(base, live, BTreeMap::new(), Vec::new())
+    }
+    
+    /// Creates a segment manager from a known list of live segments
+    ///
+    /// Used when the manifest already describes the store, so stray
+    /// files in the directory are never mistaken for live segments.
+    pub fn restore<P: AsRef<Path>>(
+        base: P,
+        live: Vec<u64>,
+        tallies: BTreeMap<u64, Tally>,
+        sealed: Vec<u64>,
+    ) -> Result<Self> {
         let base = base.as_ref().to_path_buf();
         std::fs::create_dir_all(&base)?;
         
-        let current = Self::find_next(&base)?;
+        let current = live.iter().max().copied().unwrap_or(0) + 1;
         let metadata = Metadata {
             id: current,
             created: std::time::SystemTime::now()
@@ -43,7 +231,8 @@ impl Segment {
                 .as_secs(),
             records: 0,
             bytes: 0,
-            schema: 1,
+            schema: SCHEMA,
+            key: 0,
         };
         
         Ok(Self {
@@ -51,94 +240,859 @@ impl Segment {
             current: Arc::new(Mutex::new(current)),
             file: Arc::new(Mutex::new(None)),
             metadata: Arc::new(Mutex::new(metadata)),
+            live: Arc::new(Mutex::new(live)),
+            tallies: Arc::new(Mutex::new(tallies)),
+            capacity: MAXSIZE,
+            compression: Compression::None,
+            backend: Arc::new(Disk),
+            codec: Arc::new(Rkyv),
+            sample: Arc::new(Mutex::new(Vec::new())),
+            dictionaries: Arc::new(Mutex::new(HashMap::new())),
+            sealed: Arc::new(Mutex::new(sealed.into_iter().collect())),
+            digest: Arc::new(Mutex::new(None)),
+            appending: Arc::new(Mutex::new(())),
+            cutoff: CUTOFF,
+            preallocate: false,
+            direct: false,
         })
     }
     
-    /// Appends data to the current segment
-    pub fn append<T>(&self, data: &T) -> Result<Position>
-    where
-        T: rkyv::Serialize<rkyv::ser::serializers::AllocSerializer<1024>>,
-    {
-        let mut file = self.open()?;
-        let mut metadata = self.metadata.lock().unwrap();
-        
-        // Check if we need to rotate to a new segment
-        if metadata.bytes >= MAXSIZE {
+    /// Sets the size at which the active segment rotates
+    pub fn capacity(mut self, bytes: u64) -> Self {
+        self.capacity = bytes;
+        self
+    }
+    
+    /// Sets the encoding of newly appended records
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+    
+    /// Sets the size in bytes below which records are stored uncompressed
+    pub fn cutoff(mut self, bytes: usize) -> Self {
+        self.cutoff = bytes;
+        self
+    }
+    
+    /// Reserves each new segment file's capacity on disk when it starts
+    ///
+    /// The file's length still follows its records, but appends land in
+    /// blocks already allocated, so the file system neither grows it
+    /// piecemeal nor scatters it. Sealing frees what was not used.
+    pub fn preallocate(mut self, enabled: bool) -> Self {
+        self.preallocate = enabled;
+        self
+    }
+    
+    /// Reads segments with direct I/O, see `direct`
+    ///
+    /// Reads then skip the backend; writes still go through it.
+    pub fn direct(mut self, enabled: bool) -> Self {
+        self.direct = enabled;
+        self
+    }
+    
+    /// Sets the backend segment files are read and written through
+    pub fn backend(mut self, backend: Arc<dyn Backend>) -> Self {
+        self.backend = backend;
+        self
+    }
+    
+    /// Sets how records are serialized
+    ///
+    /// Every record in the segments must use the same codec.
+    pub fn codec(mut self, codec: Arc<dyn Codec>) -> Self {
+        self.codec = codec;
+        self
+    }
+    
+    /// Sets the number of the key records are sealed under, named in
+    /// the header of every segment started
+    pub fn key(self, version: u32) -> Self {
+        self.metadata.lock().unwrap().key = version;
+        self
+    }
+    
+    /// Moves to a new key number, starting a new segment unless none
+    /// has been started under the old one
+    ///
+    /// Records must be encoded under the new key from now on, so every
+    /// segment holds records of the key its header names.
+    pub fn rekey(&self, version: u32) -> Result<()> {
+        let _appending = self.appending.lock().unwrap();
+        self.cut()?;
+        self.metadata.lock().unwrap().key = version;
+        Ok(())
+    }
+    
+    /// Ends the current segment, so it can be rewritten like any other
+    ///
+    /// Does nothing while no segment has been started.
+    pub fn roll(&self) -> Result<()> {
+        let _appending = self.appending.lock().unwrap();
+        self.cut()
+    }
+    
+    /// Rotates unless the current segment is still untouched
+    fn cut(&self) -> Result<()> {
+        if self.file.lock().unwrap().is_some() || self.metadata.lock().unwrap().records > 0 {
             self.rotate()?;
-            file = self.open()?;
-            metadata = self.metadata.lock().unwrap();
         }
+        Ok(())
+    }
+    
+    /// Appends a user to the current segment
+    pub fn append(&self, user: &User) -> Result<Position> {
+        Ok(self.extend(std::slice::from_ref(user))?[0])
+    }
+    
+    /// Appends many records, one write per segment they land in
+    ///
+    /// All records are serialized before anything is written, and each
+    /// segment's share goes out as a single buffer. Positions come back
+    /// in input order.
+    pub fn extend(&self, users: &[User]) -> Result<Vec<Position>> {
+        self.admit(users, u64::from(LENGTH))
+    }
+    
+    /// Appends many records, refusing all of them if one is too large
+    ///
+    /// `limit` bounds each record's encoded size before compression.
+    /// Nothing is written when a record exceeds it.
+    pub fn admit(&self, users: &[User], limit: u64) -> Result<Vec<Position>> {
+        let encoded = users.iter()
+            .map(|user| self.codec.encode(user))
+            .collect::<Result<Vec<_>>>()?;
+        self.push(encoded, limit)
+    }
+    
+    /// Appends records already encoded, refusing all if one is too large
+    ///
+    /// Used directly, this bypasses the codec; the bytes must be what
+    /// the codec would have produced.
+    pub fn push(&self, encoded: Vec<Vec<u8>>, limit: u64) -> Result<Vec<Position>> {
+        for (slot, bytes) in encoded.iter().enumerate() {
+            // The length prefix must also fit the record compressed
+            let stored = lz4_flex::block::get_maximum_output_size(bytes.len()) as u64 + 4;
+            if bytes.len() as u64 > limit || stored > u64::from(LENGTH) {
+                return Err(Error::Invalid {
+                    field: "record".to_string(),
+                    reason: format!("record {} encodes to {} bytes, over the limit of {}", slot, bytes.len(), limit),
+                });
+            }
+        }
+        if self.compression == Compression::Dictionary {
+            self.learn(&encoded);
+        }
+        let mut positions = Vec::with_capacity(encoded.len());
+        let mut pending = encoded.into_iter().peekable();
+        let mut landed = Vec::new();
         
-        // Serialize data
-        let bytes = to_bytes::<_, 1024>(data)
-            .map_err(|e| Error::Serialize(format!("Serialization failed: {:?}", e)))?;
+        // Records only count as live once the whole batch is on disk;
+        // those written before a failure are dead space for compaction
+        let written = self.write(&mut pending, &mut positions, &mut landed);
+        let mut tallies = self.tallies.lock().unwrap();
+        for (id, count) in landed {
+            let tally = tallies.entry(id).or_default();
+            match written {
+                Ok(()) => tally.live += count,
+                Err(_) => tally.dead += count,
+            }
+        }
+        if written.is_ok() {
+            for position in &positions {
+                tallies.entry(position.segment).or_default().bytes += position.length;
+            }
+        }
+        written.map(|()| positions)
+    }
+    
+    /// Writes encoded records, one buffer per segment they land in
+    ///
+    /// Pushes each record's position and, per segment written, its id
+    /// and record count.
+    fn write(
+        &self,
+        pending: &mut std::iter::Peekable<std::vec::IntoIter<Vec<u8>>>,
+        positions: &mut Vec<Position>,
+        landed: &mut Vec<(u64, u64)>,
+    ) -> Result<()> {
+        // Concurrent appenders must not both see the segment full and rotate twice
+        let _appending = self.appending.lock().unwrap();
+        while pending.peek().is_some() {
+            // Check if we need to rotate to a new segment
+            let full = self.metadata.lock().unwrap().bytes >= self.capacity;
+            if full {
+                self.rotate()?;
+            }
+            
+            let mut file_guard = self.open()?;
+            let file = file_guard.as_mut().unwrap();
+            let mut metadata = self.metadata.lock().unwrap();
+            let start = metadata.bytes;
+            let dictionary = self.dictionaries.lock().unwrap().get(&metadata.id).cloned();
+            
+            // Take records until this segment is full, but always at least one
+            let mut buffer = Vec::new();
+            let mut count = 0u64;
+            while let Some(bytes) = pending.next_if(|_| count == 0 || start + (buffer.len() as u64) < self.capacity) {
+                let (bytes, flag) = self.pack(bytes, dictionary.as_deref());
+                // Pad so the payload after the length prefix starts aligned
+                let end = start + buffer.len() as u64;
+                let pad = ((ALIGN - (end + 4) % ALIGN) % ALIGN) as usize;
+                buffer.resize(buffer.len() + pad, 0);
+                
+                positions.push(Position {
+                    segment: metadata.id,
+                    offset: start + buffer.len() as u64,
+                    length: bytes.len() as u64,
+                });
+                buffer.extend_from_slice(&(bytes.len() as u32 | flag).to_le_bytes());
+                buffer.extend_from_slice(&bytes);
+                count += 1;
+            }
+            
+            let written = file.put(start, &buffer).and_then(|()| Ok(file.flush()?));
+            let mut digest = self.digest.lock().unwrap();
+            match (&written, digest.as_mut()) {
+                (Ok(()), Some(hasher)) => hasher.update(&buffer),
+                // What reached the file is unknown, so it is reread when sealing
+                (Err(_), _) => *digest = None,
+                _ => {}
+            }
+            drop(digest);
+            if written.is_err() {
+                // Later records go after whatever part of this one landed
+                if let Ok(size) = file.size() {
+                    metadata.bytes = metadata.bytes.max(size);
+                }
+            }
+            written?;
+            
+            // Update metadata
+            metadata.records += count;
+            metadata.bytes = start + buffer.len() as u64;
+            landed.push((metadata.id, count));
+        }
+        Ok(())
+    }
+    
+    /// Applies the configured compression to an archived record
+    ///
+    /// Without a dictionary for the target segment, dictionary mode
+    /// falls back to plain LZ4. Records under the cutoff, and those
+    /// compression would not shrink, are stored as archived with no
+    /// flag set, so they read back in place.
+    fn pack(&self, bytes: Vec<u8>, dictionary: Option<&Vec<u8>>) -> (Vec<u8>, u32) {
+        if bytes.len() < self.cutoff {
+            return (bytes, 0);
+        }
+        let (packed, flag) = match (self.compression, dictionary) {
+            (Compression::None, _) => return (bytes, 0),
+            (Compression::Dictionary, Some(dictionary)) => {
+                (lz4_flex::block::compress_prepend_size_with_dict(&bytes, dictionary), SHARED)
+            }
+            (Compression::Lz4 | Compression::Dictionary, _) => (lz4_flex::compress_prepend_size(&bytes), PACKED),
+        };
+        if packed.len() < bytes.len() {
+            (packed, flag)
+        } else {
+            (bytes, 0)
+        }
+    }
+    
+    /// Keeps the tail of newly archived records as the next dictionary
+    fn learn(&self, records: &[Vec<u8>]) {
+        let mut sample = self.sample.lock().unwrap();
+        for record in records {
+            sample.extend_from_slice(record);
+        }
+        let excess = sample.len().saturating_sub(SAMPLE);
+        sample.drain(..excess);
+    }
+    
+    /// Loads the dictionary stored after a segment's header
+    fn dictionary(&self, id: u64) -> Result<Arc<Vec<u8>>> {
+        if let Some(dictionary) = self.dictionaries.lock().unwrap().get(&id) {
+            return Ok(Arc::clone(dictionary));
+        }
         
-        // Get current position
-        let offset = file.seek(SeekFrom::End(0))?;
+        let truncated = |e: std::io::Error| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => Error::Corrupt {
+                segment: id,
+                offset: 0,
+                reason: "dictionary truncated".to_string(),
+            },
+            _ => Error::Storage(e),
+        };
+        let mut file = self.reader(id)?;
+        let mut length = [0u8; 4];
+        file.read_exact(&mut length).map_err(truncated)?;
+        file.seek(SeekFrom::Current(i64::from(u32::from_le_bytes(length))))?;
+        file.read_exact(&mut length).map_err(truncated)?;
+        let mut dictionary = vec![0u8; u32::from_le_bytes(length) as usize];
+        file.read_exact(&mut dictionary).map_err(truncated)?;
         
-        // Write data length and data
-        file.write_all(&(bytes.len() as u32).to_le_bytes())?;
-        file.write_all(&bytes)?;
-        file.flush()?;
+        let dictionary = Arc::new(dictionary);
+        self.dictionaries.lock().unwrap().insert(id, Arc::clone(&dictionary));
+        Ok(dictionary)
+    }
+    
+    /// Finds the dictionary stored after the header of a mapped segment
+    fn lexicon(map: &[u8]) -> Option<&[u8]> {
+        let word = |at: usize| Some(u32::from_le_bytes(map.get(at..at + 4)?.try_into().unwrap()) as usize);
+        let start = 4 + word(0)?;
+        let length = word(start)?;
+        map.get(start + 4..start + 4 + length)
+    }
+    
+    /// Reads the user at a position
+    pub fn read(&self, position: Position) -> Result<User> {
+        let data = self.bytes(position)?;
+        self.codec.decode(&data).map_err(damaged(position))
+    }
+    
+    /// Reads the users at many positions, in the order given
+    ///
+    /// Positions are visited by segment and offset through one buffered
+    /// handle per segment, so each file is read front to back with
+    /// readahead instead of seeking for every record. Each position gets
+    /// its own outcome; one bad record does not fail the others.
+    pub fn fetch(&self, positions: &[Position]) -> Vec<Result<User>> {
+        let mut order: Vec<usize> = (0..positions.len()).collect();
+        order.sort_unstable_by_key(|&slot| (positions[slot].segment, positions[slot].offset));
         
-        // Update metadata
-        metadata.records += 1;
-        metadata.bytes = file.seek(SeekFrom::End(0))?;
+        // The open segment with its reader, and the offset the reader is at
+        let mut open: Option<(u64, BufReader<Box<dyn Handle>>)> = None;
+        let mut cursor = None;
+        let mut fetched = Vec::with_capacity(positions.len());
+        for slot in order {
+            let position = positions[slot];
+            if open.as_ref().map(|(id, _)| *id) != Some(position.segment) {
+                open = self.reader(position.segment).ok()
+                    .map(|file| (position.segment, BufReader::with_capacity(READAHEAD, file)));
+                cursor = None;
+            }
+            
+            let data = match &mut open {
+                Some((_, reader)) => {
+                    // Skipping forward keeps what the buffer already holds
+                    let placed = match cursor {
+                        Some(at) if at <= position.offset => reader.seek_relative((position.offset - at) as i64),
+                        _ => reader.seek(SeekFrom::Start(position.offset)).map(|_| ()),
+                    };
+                    let data = placed.map_err(Error::from).and_then(|()| self.record(reader, position));
+                    cursor = data.is_ok().then_some(position.offset + 4 + position.length);
+                    data
+                }
+                // A segment that cannot be opened reports its error per record
+                None => self.bytes(position),
+            };
+            fetched.push((slot, data.and_then(|data| self.codec.decode(&data).map_err(damaged(position)))));
+        }
         
-        Ok(Position {
-            segment: metadata.id,
-            offset,
-            length: bytes.len() as u64,
-        })
+        fetched.sort_unstable_by_key(|&(slot, _)| slot);
+        fetched.into_iter().map(|(_, user)| user).collect()
+    }
+    
+    /// Borrows the user at a position in archived form
+    ///
+    /// The record is validated first, so damaged bytes surface as
+    /// `Error::Corrupt` instead of being trusted.
+    pub fn view<R>(&self, position: Position, visit: impl FnOnce(&ArchivedUser) -> R) -> Result<R> {
+        let data = self.bytes(position)?;
+        let mut scratch = AlignedVec::new();
+        let archived = self.codec.view(&data, &mut scratch).map_err(damaged(position))?;
+        Ok(visit(archived))
     }
     
-    /// Reads data from a specific position
-    pub fn read<T>(&self, position: Position) -> Result<T>
+    /// Reads a record rkyv archived under an earlier layout
+    ///
+    /// Older schemas predate pluggable codecs, so their records are
+    /// always rkyv archives whatever codec the segment is set to.
+    pub fn former<T>(&self, position: Position) -> Result<T>
     where
         T: Archive,
-        T::Archived: Deserialize<T, Infallible>,
+        T::Archived: Deserialize<T, Infallible> + for<'a> CheckBytes<DefaultValidator<'a>>,
     {
-        let segment_path = self.base.join(format!("segment_{}.dat", position.segment));
-        let mut file = File::open(segment_path)?;
+        let data = self.bytes(position)?;
+        let archived = rkyv::check_archived_root::<T>(&data)
+            .map_err(|e| Error::Corrupt {
+                segment: position.segment,
+                offset: position.offset,
+                reason: format!("invalid archive: {}", e),
+            })?;
+        archived.deserialize(&mut Infallible)
+            .map_err(|e| Error::Serialize(format!("Deserialization error: {:?}", e)))
+    }
+    
+    /// Maps a segment file into memory for zero-copy reads
+    ///
+    /// The map is a snapshot of the file; records appended later need
+    /// a fresh one. It reads the local file directly, not through the
+    /// backend.
+    pub fn map(&self, id: u64) -> Result<Mmap> {
+        let file = std::fs::File::open(self.base.join(format!("segment_{}.dat", id)))?;
+        // Safety: segment files are only appended to or unlinked, and
+        // only truncated by `trim` before the store hands out any map,
+        // so mapped bytes stay valid while the map lives
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(map)
+    }
+    
+    /// When a segment file was last written, in seconds since the epoch
+    ///
+    /// For a sealed segment this is when it was sealed, so it bounds
+    /// the age of every record inside.
+    pub fn modified(&self, id: u64) -> Result<u64> {
+        let metadata = std::fs::metadata(self.base.join(format!("segment_{}.dat", id)))?;
+        Ok(metadata.modified()?
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs())
+    }
+    
+    /// Borrows the archived user at a position from a mapped segment
+    ///
+    /// Aligned, uncompressed rkyv records are validated in place; others
+    /// are copied or decompressed into `buffer`, and codecs that cannot
+    /// read in place rebuild the archive in `scratch`. Callers reuse both.
+    pub fn archived<'b>(
+        &self,
+        map: &'b [u8],
+        position: Position,
+        buffer: &'b mut AlignedVec,
+        scratch: &'b mut AlignedVec,
+    ) -> Result<&'b ArchivedUser> {
+        let corrupt = |reason: String| Error::Corrupt {
+            segment: position.segment,
+            offset: position.offset,
+            reason,
+        };
+        
+        let start = position.offset as usize;
+        let prefix = map.get(start..start + 4)
+            .ok_or_else(|| corrupt("record truncated".to_string()))?;
+        let prefix = u32::from_le_bytes(prefix.try_into().unwrap());
+        let length = (prefix & LENGTH) as usize;
+        if length as u64 != position.length {
+            return Err(corrupt(format!("length {} where the index expects {}", length, position.length)));
+        }
+        let data = map.get(start + 4..start + 4 + length)
+            .ok_or_else(|| corrupt("record truncated".to_string()))?;
+        
+        let data: &'b [u8] = if prefix & (PACKED | SHARED) != 0 {
+            let dictionary = match prefix & SHARED {
+                0 => &[][..],
+                _ => Self::lexicon(map).ok_or_else(|| corrupt("dictionary truncated".to_string()))?,
+            };
+            let (size, body) = lz4_flex::block::uncompressed_size(data)
+                .map_err(|e| corrupt(format!("decompression failed: {}", e)))?;
+            buffer.clear();
+            buffer.resize(size, 0);
+            lz4_flex::block::decompress_into_with_dict(body, buffer.as_mut_slice(), dictionary)
+                .map_err(|e| corrupt(format!("decompression failed: {}", e)))?;
+            buffer.as_slice()
+        } else if !(data.as_ptr() as usize).is_multiple_of(ALIGN as usize) {
+            // Records written before payloads were aligned
+            buffer.clear();
+            buffer.extend_from_slice(data);
+            buffer.as_slice()
+        } else {
+            data
+        };
+        
+        self.codec.view(data, scratch).map_err(damaged(position))
+    }
+    
+    /// Reads the raw bytes of the record at a position
+    ///
+    /// The bytes are decompressed but not decoded.
+    pub fn bytes(&self, position: Position) -> Result<Vec<u8>> {
+        let mut file = self.reader(position.segment)?;
         
         // Seek to position
         file.seek(SeekFrom::Start(position.offset))?;
+        self.record(&mut file, position)
+    }
+    
+    /// Reads the raw bytes of a record from a reader placed at its start
+    fn record(&self, file: &mut dyn Read, position: Position) -> Result<Vec<u8>> {
+        let corrupt = |reason: String| Error::Corrupt {
+            segment: position.segment,
+            offset: position.offset,
+            reason,
+        };
+        let truncated = |e: std::io::Error| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => corrupt("record truncated".to_string()),
+            _ => Error::Storage(e),
+        };
         
         // Read length
         let mut length_bytes = [0u8; 4];
-        file.read_exact(&mut length_bytes)?;
-        let length = u32::from_le_bytes(length_bytes) as usize;
+        file.read_exact(&mut length_bytes).map_err(truncated)?;
+        let prefix = u32::from_le_bytes(length_bytes);
+        let length = (prefix & LENGTH) as usize;
+        if length as u64 != position.length {
+            return Err(corrupt(format!("length {} where the index expects {}", length, position.length)));
+        }
         
         // Read data
         let mut data = vec![0u8; length];
-        file.read_exact(&mut data)?;
+        file.read_exact(&mut data).map_err(truncated)?;
+        
+        if prefix & PACKED != 0 {
+            data = lz4_flex::decompress_size_prepended(&data)
+                .map_err(|e| corrupt(format!("decompression failed: {}", e)))?;
+        } else if prefix & SHARED != 0 {
+            let dictionary = self.dictionary(position.segment)?;
+            data = lz4_flex::block::decompress_size_prepended_with_dict(&data, &dictionary)
+                .map_err(|e| corrupt(format!("decompression failed: {}", e)))?;
+        }
+        Ok(data)
+    }
+    
+    /// Seals the active segment
+    ///
+    /// Rewrites the header with the final record and byte counts, fsyncs
+    /// the file and releases the handle. A later append reopens it.
+    pub fn seal(&self) -> Result<()> {
+        let mut file_guard = self.file.lock().unwrap();
+        
+        if let Some(file) = file_guard.as_mut() {
+            let header = Header {
+                magic: MAGIC,
+                metadata: self.metadata.lock().unwrap().clone(),
+                checksum: 0,
+            };
+            
+            // Archived headers are fixed-size, so rewriting in place is safe
+            let header_bytes = to_bytes::<_, 1024>(&header)
+                .map_err(|e| Error::Serialize(format!("Header serialization failed: {:?}", e)))?;
+            
+            file.put(4, &header_bytes)?;
+            if self.preallocate {
+                file.release()?;
+            }
+            file.sync()?;
+        }
+        
+        *file_guard = None;
+        Ok(())
+    }
+    
+    /// Flushes written records of the active segment to disk
+    pub fn sync(&self) -> Result<()> {
+        if let Some(file) = self.file.lock().unwrap().as_ref() {
+            file.data()?;
+        }
+        Ok(())
+    }
+    
+    /// Approximate bytes held by cached dictionaries and the dictionary sample
+    pub fn memory(&self) -> usize {
+        let cached: usize = self.dictionaries.lock().unwrap().values().map(|dictionary| dictionary.len()).sum();
+        cached + self.sample.lock().unwrap().len()
+    }
+    
+    /// Drops cached dictionaries of segments no longer written to
+    ///
+    /// They are read back from their segment on the next read.
+    pub fn evict(&self) {
+        let current = self.metadata.lock().unwrap().id;
+        self.dictionaries.lock().unwrap().retain(|id, _| *id == current);
+    }
+    
+    /// Lists the identifiers of live segment files in ascending order
+    pub fn list(&self) -> Vec<u64> {
+        self.live.lock().unwrap().clone()
+    }
+    
+    /// Marks the record at a position as superseded or deleted
+    pub fn retire(&self, position: Position) {
+        let mut tallies = self.tallies.lock().unwrap();
+        let tally = tallies.entry(position.segment).or_default();
+        tally.live = tally.live.saturating_sub(1);
+        tally.dead += 1;
+        tally.bytes = tally.bytes.saturating_sub(position.length);
+    }
+    
+    /// Snapshot of live/dead counts and live bytes per segment
+    pub fn tallies(&self) -> BTreeMap<u64, Tally> {
+        self.tallies.lock().unwrap().clone()
+    }
+    
+    /// Removes segments from the live set once their records moved elsewhere
+    ///
+    /// Files stay on disk until garbage collection, so readers holding
+    /// an old position never see them vanish mid-read.
+    pub fn release(&self, ids: &[u64]) {
+        self.live.lock().unwrap().retain(|id| !ids.contains(id));
+        let mut tallies = self.tallies.lock().unwrap();
+        let mut dictionaries = self.dictionaries.lock().unwrap();
+        let mut sealed = self.sealed.lock().unwrap();
+        for id in ids {
+            tallies.remove(id);
+            dictionaries.remove(id);
+            sealed.remove(id);
+        }
+    }
+    
+    /// Lists the identifiers of segments ended with a footer
+    pub fn sealed(&self) -> Vec<u64> {
+        self.sealed.lock().unwrap().iter().copied().collect()
+    }
+    
+    /// Reads the footer of a sealed segment, `None` for one still open
+    ///
+    /// Fails as corrupt when the footer is missing or does not end the file.
+    pub fn footer(&self, id: u64) -> Result<Option<Footer>> {
+        if !self.sealed.lock().unwrap().contains(&id) {
+            return Ok(None);
+        }
+        let corrupt = |offset: u64, reason: String| Error::Corrupt {
+            segment: id,
+            offset,
+            reason,
+        };
+        
+        let mut file = self.reader(id)?;
+        let size = file.size()?;
+        let Some(start) = size.checked_sub(FOOTER) else {
+            return Err(corrupt(0, "footer truncated".to_string()));
+        };
+        let mut bytes = [0u8; FOOTER as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut bytes)?;
+        let footer = Footer::decode(&bytes)
+            .ok_or_else(|| corrupt(start, "footer missing".to_string()))?;
+        if footer.bytes != start {
+            return Err(corrupt(start, format!("footer covers {} bytes of {}", footer.bytes, start)));
+        }
+        Ok(Some(footer))
+    }
+    
+    /// Checks a segment like `verify`, then a sealed segment's checksum
+    ///
+    /// Unlike `verify` this reads the whole file, so it suits jobs that
+    /// are about to read the segment anyway.
+    pub fn check(&self, id: u64) -> Result<()> {
+        self.verify(id)?;
+        let Some(footer) = self.footer(id)? else {
+            return Ok(());
+        };
+        let mut file = self.reader(id)?;
+        let (start, actual) = digest(file.as_mut(), footer.bytes)?;
+        if actual != footer.checksum {
+            return Err(Error::Checksum {
+                segment: id,
+                offset: start,
+                expected: footer.checksum,
+                actual,
+            });
+        }
+        Ok(())
+    }
+    
+    /// Checks that a segment file starts with a valid header of its own
+    ///
+    /// A sealed segment must also end with a footer that agrees with
+    /// the header; its checksum is left to `check`.
+    pub fn verify(&self, id: u64) -> Result<()> {
+        self.header(id).map(|_| ())
+    }
+    
+    /// Metadata from the header of a segment, checked like `verify`
+    ///
+    /// The active segment's header only carries its final counts once
+    /// sealed.
+    pub fn header(&self, id: u64) -> Result<Metadata> {
+        let corrupt = |reason: String| Error::Corrupt {
+            segment: id,
+            offset: 0,
+            reason,
+        };
+        let truncated = |e: std::io::Error| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => corrupt("header truncated".to_string()),
+            _ => Error::Storage(e),
+        };
+        
+        let mut file = self.reader(id)?;
+        let mut length = [0u8; 4];
+        file.read_exact(&mut length).map_err(truncated)?;
+        let mut data = vec![0u8; u32::from_le_bytes(length) as usize];
+        file.read_exact(&mut data).map_err(truncated)?;
+        
+        let mut aligned = AlignedVec::new();
+        aligned.extend_from_slice(&data);
+        let header = rkyv::check_archived_root::<Header>(&aligned)
+            .map_err(|e| corrupt(format!("invalid header: {}", e)))?;
+        if header.magic != MAGIC {
+            return Err(corrupt(format!("bad magic {:#x}", header.magic)));
+        }
+        if header.metadata.id != id {
+            return Err(corrupt(format!("header names segment {}", header.metadata.id)));
+        }
+        if let Some(footer) = self.footer(id)? {
+            if footer.records != header.metadata.records {
+                return Err(corrupt(format!(
+                    "footer counts {} records, header {}",
+                    footer.records, header.metadata.records
+                )));
+            }
+        }
+        header.metadata.deserialize(&mut Infallible)
+            .map_err(|e| Error::Serialize(format!("Header deserialization failed: {:?}", e)))
+    }
+    
+    /// Cuts the torn tail off a segment no longer written to
+    ///
+    /// Walks the records from `from`, the offset of one the index points
+    /// at, and truncates the file after the last whole one. A record is
+    /// whole when its length prefix fits in the file; the last must also
+    /// decode, since a crash can leave zeros where its bytes should be.
+    /// Returns the new file length. Like `map`, it works on the local
+    /// file directly, not through the backend.
+    pub fn trim(&self, id: u64, from: u64) -> Result<u64> {
+        let map = self.map(id)?;
+        let size = map.len() as u64;
+        let mut records = Vec::new();
+        let mut offset = from;
+        while let Some(prefix) = map.get(offset as usize..offset as usize + 4) {
+            let length = u64::from(u32::from_le_bytes(prefix.try_into().unwrap()) & LENGTH);
+            let end = offset + 4 + length;
+            if length == 0 || end > size {
+                break;
+            }
+            records.push(Position { segment: id, offset, length });
+            offset = end + (ALIGN - (end + 4) % ALIGN) % ALIGN;
+        }
+        drop(map);
         
-        // Deserialize using unsafe method for now
-        unsafe {
-            let archived = rkyv::archived_root::<T>(&data);
-            let value = archived.deserialize(&mut Infallible)
-                .map_err(|e| Error::Serialize(format!("Deserialization error: {:?}", e)))?;
-            Ok(value)
+        while let Some(&last) = records.last() {
+            if self.read(last).is_ok() {
+                break;
+            }
+            records.pop();
+        }
+        let end = records.last().map_or(from, |last| last.offset + 4 + last.length);
+        if end < size {
+            let file = std::fs::OpenOptions::new()
+                .write(true)
+                .open(self.base.join(format!("segment_{}.dat", id)))?;
+            file.set_len(end)?;
+            file.sync_all()?;
         }
+        Ok(end)
     }
     
-    /// Ensures the current segment file is open
-    fn open(&self) -> Result<File> {
+    /// Walks the records of a segment file from its raw bytes
+    ///
+    /// For forensics: nothing is decoded and neither the index nor the
+    /// manifest is consulted, so it works on files a store refuses.
+    /// Whether a dictionary follows the header is not recorded, so both
+    /// layouts are tried: the one whose records reach the limit, and
+    /// number what the header counts, is kept, or failing that the one
+    /// getting furthest. Only a dictionary can precede shared records. The walk stops at the first
+    /// record that is empty or runs past the limit; `end` falling short
+    /// of `limit` marks where the damage starts.
+    pub fn walk(&self, id: u64) -> Result<Walk> {
+        let map = self.map(id)?;
+        let word = |at: u64| Some(u32::from_le_bytes(map.get(at as usize..at as usize + 4)?.try_into().unwrap()));
+        let header = 4 + u64::from(word(0).ok_or_else(|| Error::Corrupt {
+            segment: id,
+            offset: 0,
+            reason: "header truncated".to_string(),
+        })?);
+        
+        let size = map.len() as u64;
+        let footer = size.checked_sub(FOOTER)
+            .and_then(|start| Footer::decode(map[start as usize..].try_into().unwrap()))
+            .filter(|footer| footer.bytes + FOOTER == size);
+        let limit = footer.map_or(size, |footer| footer.bytes);
+        
+        let plain = Self::entries(&map, header, limit);
+        let shared = word(header)
+            .map(u64::from)
+            .filter(|length| header + 4 + length <= limit)
+            .map(|length| (length, Self::entries(&map, header + 4 + length, limit)));
+        
+        // The header counts the records once sealed; only a dictionary precedes shared records
+        let records = self.header(id).ok().map(|header| header.records).filter(|records| *records > 0);
+        let fits = |(entries, end): &(Vec<Entry>, u64)| {
+            *end == limit && records.is_none_or(|records| entries.len() as u64 == records)
+        };
+        let (dictionary, (entries, end)) = match shared {
+            Some((length, walked)) if plain.0.iter().any(|entry| entry.shared)
+                || (!fits(&plain) && (fits(&walked) || walked.1 > plain.1)) => (Some(length), walked),
+            _ => (None, plain),
+        };
+        Ok(Walk { footer, dictionary, entries, end, limit })
+    }
+    
+    /// Records laid out from `from` up to `limit`, and where they end
+    fn entries(map: &[u8], from: u64, limit: u64) -> (Vec<Entry>, u64) {
+        let mut entries = Vec::new();
+        let mut end = from;
+        loop {
+            let offset = end + (ALIGN - (end + 4) % ALIGN) % ALIGN;
+            let Some(prefix) = map.get(offset as usize..offset as usize + 4) else { break };
+            let prefix = u32::from_le_bytes(prefix.try_into().unwrap());
+            let length = u64::from(prefix & LENGTH);
+            if length == 0 || offset + 4 + length > limit {
+                break;
+            }
+            entries.push(Entry {
+                offset,
+                length,
+                packed: prefix & PACKED != 0,
+                shared: prefix & SHARED != 0,
+                checksum: crc32fast::hash(&map[offset as usize + 4..(offset + 4 + length) as usize]),
+            });
+            end = offset + 4 + length;
+        }
+        (entries, end)
+    }
+    
+    /// Identifier of the active segment
+    pub fn current(&self) -> u64 {
+        *self.current.lock().unwrap()
+    }
+    
+    /// Opens a segment file for reading, directly if so configured
+    fn reader(&self, id: u64) -> Result<Box<dyn Handle>> {
+        let path = self.base.join(format!("segment_{}.dat", id));
+        if self.direct {
+            direct::open(&path)
+        } else {
+            self.backend.open(&path)
+        }
+    }
+
+    
+    /// Ensures the current segment file is open and returns it locked
+    fn open(&self) -> Result<MutexGuard<'_, Option<Box<dyn Handle>>>> {
         let mut file_guard = self.file.lock().unwrap();
         
         if file_guard.is_none() {
-            let current = *self.current.lock().unwrap();
+            let mut current = self.current.lock().unwrap();
+            
+            // Never append to a leftover file: it may hold a torn header,
+            // or records the index still points at
+            if !self.live.lock().unwrap().contains(&current) {
+                while self.base.join(format!("segment_{}.dat", *current)).exists() {
+                    *current += 1;
+                }
+                self.metadata.lock().unwrap().id = *current;
+            }
+            let current = *current;
             let path = self.base.join(format!("segment_{}.dat", current));
             
-            let mut file = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .read(true)
-                .open(&path)?;
+            let mut file = self.backend.create(&path)?;
             
             // Write header if file is new
-            if file.metadata()?.len() == 0 {
-                let metadata = self.metadata.lock().unwrap();
+            if file.size()? == 0 {
+                let mut metadata = self.metadata.lock().unwrap();
                 let header = Header {
                     magic: MAGIC,
                     metadata: metadata.clone(),
@@ -150,21 +1104,68 @@ impl Segment {
                 
                 file.write_all(&(header_bytes.len() as u32).to_le_bytes())?;
                 file.write_all(&header_bytes)?;
+                metadata.bytes = 4 + header_bytes.len() as u64;
+                let mut hasher = crc32fast::Hasher::new();
+                
+                // Records in this segment are compressed against it for good
+                if self.compression == Compression::Dictionary {
+                    // Small segments get a proportionally small dictionary
+                    let sample = self.sample.lock().unwrap();
+                    let keep = sample.len().min(self.capacity as usize / 16);
+                    let dictionary = sample[sample.len() - keep..].to_vec();
+                    drop(sample);
+                    file.write_all(&(dictionary.len() as u32).to_le_bytes())?;
+                    file.write_all(&dictionary)?;
+                    metadata.bytes += 4 + dictionary.len() as u64;
+                    hasher.update(&(dictionary.len() as u32).to_le_bytes());
+                    hasher.update(&dictionary);
+                    self.dictionaries.lock().unwrap().insert(current, Arc::new(dictionary));
+                }
+                drop(metadata);
+                if self.preallocate {
+                    file.reserve(self.capacity)?;
+                }
+                file.sync()?;
+                self.backend.directory(&self.base)?;
+                *self.digest.lock().unwrap() = Some(hasher);
+                
+                let mut live = self.live.lock().unwrap();
+                if !live.contains(&current) {
+                    live.push(current);
+                }
             }
             
             *file_guard = Some(file);
         }
         
-        Ok(file_guard.as_mut().unwrap().try_clone()?)
+        Ok(file_guard)
+    }
+    
+    /// Ends the active segment with its footer
+    ///
+    /// The checksum kept while writing is used when every write
+    /// succeeded; otherwise the file is read back.
+    fn finish(&self) -> Result<u64> {
+        let mut file_guard = self.open()?;
+        let file = file_guard.as_mut().unwrap();
+        let metadata = self.metadata.lock().unwrap().clone();
+        let bytes = metadata.bytes;
+        let checksum = match self.digest.lock().unwrap().take() {
+            Some(hasher) => hasher.finalize(),
+            None => digest(file.as_mut(), bytes)?.1,
+        };
+        
+        let footer = Footer { records: metadata.records, bytes, checksum };
+        file.put(bytes, &footer.encode())?;
+        Ok(metadata.id)
     }
     
     /// Rotates to a new segment
     fn rotate(&self) -> Result<()> {
-        // Close current file
-        {
-            let mut file_guard = self.file.lock().unwrap();
-            *file_guard = None;
-        }
+        // Close the full segment with its footer, then seal it
+        let id = self.finish()?;
+        self.seal()?;
+        self.sealed.lock().unwrap().insert(id);
         
         // Increment segment ID
         let mut current_guard = self.current.lock().unwrap();
@@ -182,9 +1183,9 @@ impl Segment {
         Ok(())
     }
     
-    /// Finds the next available segment ID
-    fn find_next(base: &Path) -> Result<u64> {
-        let mut max_id = 0u64;
+    /// Collects segment identifiers from file names in a directory
+    pub fn discover(base: &Path) -> Result<Vec<u64>> {
+        let mut ids = Vec::new();
         
         if base.exists() {
             for entry in std::fs::read_dir(base)? {
@@ -192,16 +1193,49 @@ impl Segment {
                 let name = entry.file_name();
                 let name_str = name.to_string_lossy();
                 
-                if name_str.starts_with("segment_") && name_str.ends_with(".dat") {
-                    if let Some(id_str) = name_str.strip_prefix("segment_").and_then(|s| s.strip_suffix(".dat")) {
-                        if let Ok(id) = id_str.parse::<u64>() {
-                            max_id = max_id.max(id);
-                        }
+                if let Some(id_str) = name_str.strip_prefix("segment_").and_then(|s| s.strip_suffix(".dat")) {
+                    if let Ok(id) = id_str.parse::<u64>() {
+                        ids.push(id);
                     }
                 }
             }
         }
         
-        Ok(max_id + 1)
+        ids.sort_unstable();
+        Ok(ids)
+    }
+}
+
+/// Computes the CRC-32 of a segment from the end of its header up to `end`
+///
+/// Returns where the checksummed bytes start along with the checksum.
+fn digest(file: &mut dyn Handle, end: u64) -> Result<(u64, u32)> {
+    let mut length = [0u8; 4];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut length)?;
+    let start = 4 + u32::from_le_bytes(length) as u64;
+    
+    file.seek(SeekFrom::Start(start))?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut chunk = vec![0u8; 64 * 1024];
+    let mut left = end.saturating_sub(start);
+    while left > 0 {
+        let size = chunk.len().min(left as usize);
+        file.read_exact(&mut chunk[..size])?;
+        hasher.update(&chunk[..size]);
+        left -= size as u64;
     }
-} 
\ No newline at end of file
+    Ok((start, hasher.finalize()))
+}
+
+/// Reports a record its codec rejects as corrupt at its position
+fn damaged(position: Position) -> impl Fn(Error) -> Error {
+    move |error| match error {
+        Error::Serialize(reason) => Error::Corrupt {
+            segment: position.segment,
+            offset: position.offset,
+            reason,
+        },
+        other => other,
+    }
+}
@@ -0,0 +1,353 @@
+//! Sharded stores
+//!
+//! `Shards` fans one logical store over several stores, each in a
+//! directory of its own, so their segments and indexes can sit on
+//! separate disks. Users are placed by consistent hashing: every shard
+//! owns `POINTS` points on a hash ring, derived from its directory name,
+//! and a user belongs to the shard owning the first point at or after
+//! the hash of its ID. Adding a shard thus claims about a share of the
+//! users from each other shard and moves nothing else; `rebalance`
+//! carries them over.
+//!
+//! Each shard is a full `Store` with its own compaction. Writes touching
+//! several shards are not atomic across them: a failed `batch` may have
+//! landed on some shards only.
+//!
+//! Reads spanning shards scatter to every shard on a thread of its own
+//! and gather the answers. Each shard returns its part in order, so
+//! ordered results are merged as they come rather than sorted anew.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::path::Path;
+use std::sync::mpsc;
+use twox_hash::XxHash3_128;
+use crate::{Builder, Error, Field, Point, Result, Store, User};
+use crate::compaction::{Config, Guard, State};
+use crate::sdk::Stats;
+
+/// Ring points per shard, evening out the share each one owns
+const POINTS: u32 = 64;
+
+/// Records moved per batch by `rebalance`
+const BATCH: usize = 256;
+
+/// Hash ring placing user IDs on shards
+#[derive(Debug, Clone)]
+pub struct Ring {
+    /// Points in ascending order with the shard owning each
+    points: Vec<(u64, usize)>,
+}
+
+impl Ring {
+    /// Builds the ring of shards with these names, owned by their position
+    pub fn new(names: &[&str]) -> Self {
+        let mut points = Vec::with_capacity(names.len() * POINTS as usize);
+        for (shard, name) in names.iter().enumerate() {
+            for point in 0..POINTS {
+                let mut bytes = name.as_bytes().to_vec();
+                bytes.push(0);
+                bytes.extend_from_slice(&point.to_be_bytes());
+                points.push((hash(&bytes), shard));
+            }
+        }
+        points.sort_unstable();
+        Self { points }
+    }
+
+    /// Shard owning a user ID
+    pub fn locate(&self, id: u64) -> usize {
+        let hash = hash(&id.to_be_bytes());
+        let at = self.points.partition_point(|&(point, _)| point < hash);
+        // Past the last point the ring wraps around to the first
+        self.points.get(at).or(self.points.first()).map_or(0, |&(_, shard)| shard)
+    }
+}
+
+/// One logical store spread over several shard stores
+pub struct Shards {
+    /// Shard stores in the order they were opened
+    stores: Vec<Store>,
+    /// Placement of IDs on shards
+    ring: Ring,
+}
+
+impl Shards {
+    /// Opens a store per directory with default options
+    pub fn new<P: AsRef<Path>>(paths: &[P]) -> Result<Self> {
+        Self::open(paths, Store::builder)
+    }
+
+    /// Opens a store per directory, each configured by `builder`
+    ///
+    /// Shards are named by their directory's last component, which must
+    /// be distinct; reopening with the same names places every user as
+    /// before, whatever their order.
+    pub fn open<P: AsRef<Path>>(paths: &[P], builder: impl Fn() -> Builder) -> Result<Self> {
+        if paths.is_empty() {
+            return Err(Error::Config("At least one shard is required".to_string()));
+        }
+        let mut names = Vec::with_capacity(paths.len());
+        for path in paths {
+            let name = path.as_ref().file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| Error::Config(format!("Shard path {} has no name", path.as_ref().display())))?;
+            names.push(name);
+        }
+        if names.iter().collect::<HashSet<_>>().len() != names.len() {
+            return Err(Error::Config(format!("Shard names {:?} are not distinct", names)));
+        }
+
+        let ring = Ring::new(&names);
+        let stores = paths.iter()
+            .map(|path| builder().path(path).open())
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { stores, ring })
+    }
+
+    /// Number of shards
+    pub fn len(&self) -> usize {
+        self.stores.len()
+    }
+
+    /// Whether there are no shards, which `open` never allows
+    pub fn is_empty(&self) -> bool {
+        self.stores.is_empty()
+    }
+
+    /// Shard store at a position, for per-shard operations
+    pub fn shard(&self, at: usize) -> Option<&Store> {
+        self.stores.get(at)
+    }
+
+    /// Position of the shard owning a user ID
+    pub fn locate(&self, id: u64) -> usize {
+        self.ring.locate(id)
+    }
+
+    /// Saves a user to its shard, returning the version replaced
+    pub fn save(&self, user: &User) -> Result<Option<User>> {
+        let at = self.locate(user.id);
+        self.stores[at].save(user)
+    }
+
+    /// Saves users, one batch per shard
+    ///
+    /// Each shard's batch is atomic; the whole is not.
+    pub fn batch(&self, users: &[User]) -> Result<()> {
+        let mut groups = vec![Vec::new(); self.stores.len()];
+        for user in users {
+            groups[self.locate(user.id)].push(user.clone());
+        }
+        for (store, group) in self.stores.iter().zip(groups) {
+            if !group.is_empty() {
+                store.batch(&group)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Retrieves a user from its shard
+    pub fn find(&self, id: u64) -> Result<Option<User>> {
+        self.stores[self.locate(id)].find(id)
+    }
+
+    /// Checks whether a user exists
+    pub fn contains(&self, id: u64) -> Result<bool> {
+        self.stores[self.locate(id)].contains(id)
+    }
+
+    /// Deletes a user from its shard, returning the user removed
+    pub fn delete(&self, id: u64) -> Result<Option<User>> {
+        let at = self.locate(id);
+        self.stores[at].delete(id)
+    }
+
+    /// Calls `each` with every user, reading all shards at once
+    ///
+    /// Users arrive in no particular order, at most `BATCH` ahead of
+    /// `each` per shard. An error from `each` or any shard stops the
+    /// scan and is returned.
+    pub fn scan(&self, mut each: impl FnMut(User) -> Result<()>) -> Result<()> {
+        std::thread::scope(|scope| {
+            let (sender, receiver) = mpsc::sync_channel(BATCH);
+            for store in &self.stores {
+                let sender = sender.clone();
+                scope.spawn(move || {
+                    for user in store.scan() {
+                        let failed = user.is_err();
+                        // A closed channel means the scan was abandoned
+                        if sender.send(user).is_err() || failed {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+            // Returning drops the receiver, which stops the senders
+            for user in receiver {
+                each(user?)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Number of live records across shards, counted side by side
+    pub fn count(&self) -> Result<u64> {
+        Ok(self.scatter(Store::count)?.into_iter().sum())
+    }
+
+    /// Users created within `from..=to`, oldest first, see `Store::created`
+    pub fn created(&self, from: u64, to: u64) -> Result<Vec<User>> {
+        let parts = self.scatter(|store| store.created(from, to))?;
+        Ok(merge(parts, |user| (user.created, user.id)))
+    }
+
+    /// Users updated at or after `since`, oldest first, see `Store::updated`
+    pub fn updated(&self, since: u64) -> Result<Vec<User>> {
+        let parts = self.scatter(|store| store.updated(since))?;
+        Ok(merge(parts, |user| (user.updated, user.id)))
+    }
+
+    /// Users within `radius` meters of a point, closest first, see `Store::near`
+    pub fn near(&self, latitude: f64, longitude: f64, radius: f64) -> Result<Vec<User>> {
+        let center = Point { latitude, longitude };
+        let parts = self.scatter(|store| store.near(latitude, longitude, radius))?;
+        // Bits of a non-negative float sort like the float
+        Ok(merge(parts, |user| user.location.point.map(|point| center.distance(&point).to_bits())))
+    }
+
+    /// Users whose `field` equals any of `values`, by ascending ID
+    ///
+    /// Asks every shard, see `Store::lookup`.
+    pub fn lookup(&self, field: Field, values: &[&str]) -> Result<Vec<User>> {
+        let parts = self.scatter(|store| store.lookup(field, values))?;
+        Ok(merge(parts, |user| user.id))
+    }
+
+    /// Users matching every `(field, value)` term, by ascending ID
+    ///
+    /// Asks every shard, see `Store::query`.
+    pub fn query(&self, terms: &[(Field, &str)]) -> Result<Vec<User>> {
+        let parts = self.scatter(|store| store.query(terms))?;
+        Ok(merge(parts, |user| user.id))
+    }
+
+    /// Statistics summed over every shard
+    ///
+    /// Quotas add up too; a shard without a limit leaves the total
+    /// without one.
+    pub fn stats(&self) -> Result<Stats> {
+        let mut shards = self.stores.iter().map(Store::stats);
+        let mut total = shards.next().transpose()?
+            .ok_or_else(|| Error::Config("At least one shard is required".to_string()))?;
+        for stats in shards {
+            total.merge(&stats?);
+        }
+        Ok(total)
+    }
+
+    /// Runs one compaction pass on every shard in turn, see `Store::compact`
+    pub fn compact(&self, config: Config) -> Result<Vec<State>> {
+        self.stores.iter().map(|store| store.compact(config.clone())).collect()
+    }
+
+    /// Runs compaction over every shard in the background, see `Store::schedule`
+    ///
+    /// Each shard gets a task of its own, so shards on separate disks
+    /// compact side by side. Drop the guards before closing.
+    pub fn schedule(&self, config: Config) -> Result<Vec<Guard>> {
+        self.stores.iter().map(|store| store.schedule(config.clone())).collect()
+    }
+
+    /// Moves every user held by a shard other than its owner to the owner
+    ///
+    /// Needed after shards are added or removed. Users are saved on
+    /// their owner before they leave the old shard, so a crash midway
+    /// leaves a stray copy, never a loss; the next run drops copies
+    /// whose owner already holds the user. Returns how many users moved.
+    pub fn rebalance(&self) -> Result<u64> {
+        let mut moved = 0;
+        for at in 0..self.stores.len() {
+            let mut strays = Vec::new();
+            for id in self.stores[at].keys() {
+                let id = id?;
+                if self.locate(id) != at {
+                    strays.push(id);
+                }
+            }
+            for chunk in strays.chunks(BATCH) {
+                let mut users = Vec::with_capacity(chunk.len());
+                for &id in chunk {
+                    if !self.contains(id)? {
+                        users.extend(self.stores[at].find(id)?);
+                    }
+                }
+                self.batch(&users)?;
+                for &id in chunk {
+                    self.stores[at].delete(id)?;
+                }
+                moved += users.len() as u64;
+            }
+        }
+        Ok(moved)
+    }
+
+    /// Closes every shard
+    pub fn close(&self) -> Result<()> {
+        for store in &self.stores {
+            store.close()?;
+        }
+        Ok(())
+    }
+
+    /// Runs `each` on every shard at once, returning the answers by shard
+    ///
+    /// The first shard to fail fails the whole.
+    fn scatter<T: Send>(&self, each: impl Fn(&Store) -> Result<T> + Sync) -> Result<Vec<T>> {
+        if let [store] = self.stores.as_slice() {
+            return Ok(vec![each(store)?]);
+        }
+        std::thread::scope(|scope| {
+            let each = &each;
+            let handles = self.stores.iter()
+                .map(|store| scope.spawn(move || each(store)))
+                .collect::<Vec<_>>();
+            handles.into_iter()
+                .map(|handle| handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+                .collect()
+        })
+    }
+}
+
+/// Merges lists each ordered by `key` into one ordered list
+///
+/// Ties go to the earlier list.
+fn merge<K: Ord>(parts: Vec<Vec<User>>, key: impl Fn(&User) -> K) -> Vec<User> {
+    let total = parts.iter().map(Vec::len).sum();
+    let mut lists = parts.into_iter().map(Vec::into_iter).collect::<Vec<_>>();
+    let mut heads = Vec::with_capacity(lists.len());
+    let mut heap = BinaryHeap::with_capacity(lists.len());
+    for (at, list) in lists.iter_mut().enumerate() {
+        let head = list.next();
+        if let Some(user) = &head {
+            heap.push(Reverse((key(user), at)));
+        }
+        heads.push(head);
+    }
+
+    let mut users = Vec::with_capacity(total);
+    while let Some(Reverse((_, at))) = heap.pop() {
+        users.extend(heads[at].take());
+        heads[at] = lists[at].next();
+        if let Some(user) = &heads[at] {
+            heap.push(Reverse((key(user), at)));
+        }
+    }
+    users
+}
+
+/// Position of bytes on the ring, stable across releases and platforms
+fn hash(bytes: &[u8]) -> u64 {
+    XxHash3_128::oneshot(bytes) as u64
+}
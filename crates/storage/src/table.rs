@@ -0,0 +1,493 @@
+//! Sorted on-disk index table
+//!
+//! Stores key-position pairs in sorted, fixed-size blocks. Only the
+//! first key of every block (its fence) is kept in memory, so a lookup
+//! costs one binary search plus a single block read.
+//!
+//! Tables whose keys all share one width, such as hashed keys, can be
+//! written as flat arrays of fixed-size entries instead. The offset of
+//! every entry is then known, so no fences are kept and a lookup binary
+//! searches the file itself.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Read, Write, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::{directory, Error, Result};
+use crate::model::Position;
+
+/// Magic number for table file validation
+const MAGIC: u32 = 0x47494458; // "GIDX"
+
+/// Magic number of a table of fixed-size entries
+const FIXED: u32 = 0x47494446; // "GIDF"
+
+/// Header size of a fixed table: magic + generation + key width
+const HEAD: u64 = 4 + 8 + 4;
+
+/// Bytes of an entry after its key: segment + offset + length
+const TAIL: usize = 8 + 8 + 8;
+
+/// Target block size in bytes
+const BLOCKSIZE: usize = 4096;
+
+/// Footer size: fence offset + fence count + entry count + magic
+const FOOTER: u64 = 8 + 8 + 8 + 4;
+
+/// First key of a block and where the block lives
+#[derive(Debug, Clone)]
+struct Fence {
+    key: Vec<u8>,
+    offset: u64,
+    length: u32,
+}
+
+/// Immutable sorted table of key-position pairs
+pub struct Table {
+    /// Table file path
+    path: PathBuf,
+    /// Generation number of this table
+    generation: u64,
+    /// Number of entries
+    count: u64,
+    /// Sparse in-memory fence keys, empty for a fixed table
+    fences: Vec<Fence>,
+    /// Width of every key, for a table of fixed-size entries
+    width: Option<usize>,
+    /// File handle for block reads
+    file: Mutex<File>,
+}
+
+impl Table {
+    /// Writes a new table from entries sorted by key
+    ///
+    /// With a `width`, every key must be that long and the table is
+    /// written as fixed-size entries. The file is written under a
+    /// temporary name, synced, and renamed into place so a crash never
+    /// leaves a partial table behind.
+    pub fn write<P, I>(path: P, generation: u64, width: Option<usize>, entries: I) -> Result<Self>
+    where
+        P: AsRef<Path>,
+        I: IntoIterator<Item = Result<(Vec<u8>, Position)>>,
+    {
+        let path = path.as_ref().to_path_buf();
+        let temp = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&temp)?);
+        if let Some(width) = width {
+            let count = Self::flat(&mut writer, generation, width, entries)?;
+            Self::install(writer, &temp, &path)?;
+            return Ok(Self {
+                file: Mutex::new(File::open(&path)?),
+                path,
+                generation,
+                count,
+                fences: Vec::new(),
+                width: Some(width),
+            });
+        }
+
+        writer.write_all(&MAGIC.to_le_bytes())?;
+        writer.write_all(&generation.to_le_bytes())?;
+
+        let mut offset = 4 + 8u64;
+        let mut fences = Vec::new();
+        let mut block = Vec::with_capacity(BLOCKSIZE);
+        let mut first: Option<Vec<u8>> = None;
+        let mut count = 0u64;
+
+        for entry in entries {
+            let (key, position) = entry?;
+            if first.is_none() {
+                first = Some(key.clone());
+            }
+
+            block.extend_from_slice(&(key.len() as u32).to_le_bytes());
+            block.extend_from_slice(&key);
+            block.extend_from_slice(&position.segment.to_le_bytes());
+            block.extend_from_slice(&position.offset.to_le_bytes());
+            block.extend_from_slice(&position.length.to_le_bytes());
+            count += 1;
+
+            if block.len() >= BLOCKSIZE {
+                let key = first.take().unwrap_or_default();
+                offset = Self::seal(&mut writer, &mut block, key, offset, &mut fences)?;
+            }
+        }
+
+        if !block.is_empty() {
+            let key = first.take().unwrap_or_default();
+            offset = Self::seal(&mut writer, &mut block, key, offset, &mut fences)?;
+        }
+
+        // Fence region followed by the fixed-size footer
+        for fence in &fences {
+            writer.write_all(&(fence.key.len() as u32).to_le_bytes())?;
+            writer.write_all(&fence.key)?;
+            writer.write_all(&fence.offset.to_le_bytes())?;
+            writer.write_all(&fence.length.to_le_bytes())?;
+        }
+        writer.write_all(&offset.to_le_bytes())?;
+        writer.write_all(&(fences.len() as u64).to_le_bytes())?;
+        writer.write_all(&count.to_le_bytes())?;
+        writer.write_all(&MAGIC.to_le_bytes())?;
+        Self::install(writer, &temp, &path)?;
+
+        let file = File::open(&path)?;
+        Ok(Self {
+            path,
+            generation,
+            count,
+            fences,
+            width: None,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Writes the header, entries and footer of a fixed table, returning the entry count
+    fn flat<I>(writer: &mut BufWriter<File>, generation: u64, width: usize, entries: I) -> Result<u64>
+    where
+        I: IntoIterator<Item = Result<(Vec<u8>, Position)>>,
+    {
+        writer.write_all(&FIXED.to_le_bytes())?;
+        writer.write_all(&generation.to_le_bytes())?;
+        writer.write_all(&(width as u32).to_le_bytes())?;
+
+        let mut count = 0u64;
+        for entry in entries {
+            let (key, position) = entry?;
+            if key.len() != width {
+                return Err(Error::Key {
+                    key,
+                    reason: format!("fixed table holds {}-byte keys", width),
+                });
+            }
+            writer.write_all(&key)?;
+            writer.write_all(&position.segment.to_le_bytes())?;
+            writer.write_all(&position.offset.to_le_bytes())?;
+            writer.write_all(&position.length.to_le_bytes())?;
+            count += 1;
+        }
+        writer.write_all(&count.to_le_bytes())?;
+        writer.write_all(&FIXED.to_le_bytes())?;
+        Ok(count)
+    }
+
+    /// Syncs a written temporary table and renames it into place
+    fn install(writer: BufWriter<File>, temp: &Path, path: &Path) -> Result<()> {
+        let file = writer.into_inner().map_err(|e| Error::Storage(e.into_error()))?;
+        file.sync_all()?;
+        drop(file);
+        std::fs::rename(temp, path)?;
+        directory::parent(path)?;
+        Ok(())
+    }
+
+    /// Opens an existing table, loading only its fence keys
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new().read(true).open(&path)?;
+
+        let size = file.metadata()?.len();
+        if size < HEAD + 12 {
+            return Err(Error::Index("Table file too short".to_string()));
+        }
+
+        let mut head = [0u8; 12];
+        file.read_exact(&mut head)?;
+        let magic = u32::from_le_bytes(head[0..4].try_into().unwrap());
+        let generation = u64::from_le_bytes(head[4..12].try_into().unwrap());
+        if magic == FIXED {
+            return Self::fixed(path, file, size, generation);
+        }
+        if magic != MAGIC {
+            return Err(Error::Index("Invalid table magic".to_string()));
+        }
+        if size < 4 + 8 + FOOTER {
+            return Err(Error::Index("Table file too short".to_string()));
+        }
+
+        let mut foot = [0u8; FOOTER as usize];
+        file.seek(SeekFrom::Start(size - FOOTER))?;
+        file.read_exact(&mut foot)?;
+        if u32::from_le_bytes(foot[24..28].try_into().unwrap()) != MAGIC {
+            return Err(Error::Index("Invalid table footer".to_string()));
+        }
+        let start = u64::from_le_bytes(foot[0..8].try_into().unwrap());
+        let total = u64::from_le_bytes(foot[8..16].try_into().unwrap());
+        let count = u64::from_le_bytes(foot[16..24].try_into().unwrap());
+
+        let mut region = vec![0u8; (size - FOOTER - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut region)?;
+
+        let mut fences = Vec::with_capacity(total as usize);
+        let mut cursor = 0usize;
+        for _ in 0..total {
+            let len = Self::take(&region, &mut cursor, 4)?;
+            let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+            let key = Self::take(&region, &mut cursor, len)?.to_vec();
+            let offset = u64::from_le_bytes(Self::take(&region, &mut cursor, 8)?.try_into().unwrap());
+            let length = u32::from_le_bytes(Self::take(&region, &mut cursor, 4)?.try_into().unwrap());
+            fences.push(Fence { key, offset, length });
+        }
+
+        Ok(Self {
+            path,
+            generation,
+            count,
+            fences,
+            width: None,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Opens a fixed table whose magic and generation were read, checking its size
+    fn fixed(path: PathBuf, mut file: File, size: u64, generation: u64) -> Result<Self> {
+        let mut width = [0u8; 4];
+        file.read_exact(&mut width)?;
+        let width = u32::from_le_bytes(width) as usize;
+        let mut foot = [0u8; 12];
+        file.seek(SeekFrom::Start(size.saturating_sub(12)))?;
+        file.read_exact(&mut foot)?;
+        if u32::from_le_bytes(foot[8..12].try_into().unwrap()) != FIXED {
+            return Err(Error::Index("Invalid table footer".to_string()));
+        }
+        let count = u64::from_le_bytes(foot[0..8].try_into().unwrap());
+        if HEAD + count * (width + TAIL) as u64 + 12 != size {
+            return Err(Error::Index(format!("Fixed table of {} bytes holds {} entries", size, count)));
+        }
+
+        Ok(Self {
+            path,
+            generation,
+            count,
+            fences: Vec::new(),
+            width: Some(width),
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Looks up the position stored for a key
+    pub fn get(&self, key: &[u8]) -> Result<Option<Position>> {
+        if self.width.is_some() {
+            let slot = self.search(key)?;
+            if slot == self.count {
+                return Ok(None);
+            }
+            let (found, position) = self.entry(slot)?;
+            return Ok((found == key).then_some(position));
+        }
+
+        // Last block whose fence key is <= key
+        let slot = self.fences.partition_point(|fence| fence.key.as_slice() <= key);
+        if slot == 0 {
+            return Ok(None);
+        }
+
+        for (candidate, position) in self.block(slot - 1)? {
+            if candidate == key {
+                return Ok(Some(position));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Iterates over all entries in key order, one block at a time
+    pub fn iter(&self) -> Cursor<'_> {
+        Cursor {
+            table: self,
+            block: 0,
+            items: Vec::new().into_iter(),
+        }
+    }
+
+    /// Iterates from the block that may hold `key` onward
+    ///
+    /// Entries of that block below `key` are still yielded; callers
+    /// skip them.
+    pub fn seek(&self, key: &[u8]) -> Cursor<'_> {
+        let block = match self.width {
+            // An I/O error here resurfaces when the block is read
+            Some(_) => self.search(key).map_or(0, |slot| (slot / self.span()) as usize),
+            None => self.fences.partition_point(|fence| fence.key.as_slice() <= key).saturating_sub(1),
+        };
+        Cursor {
+            table: self,
+            block,
+            items: Vec::new().into_iter(),
+        }
+    }
+
+    /// Table file path
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Generation number of this table
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Number of entries in the table
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Width of every key, for a table of fixed-size entries
+    pub fn width(&self) -> Option<usize> {
+        self.width
+    }
+
+    /// Approximate memory held by the fence keys
+    pub fn memory(&self) -> usize {
+        self.fences.iter()
+            .map(|fence| fence.key.len() + std::mem::size_of::<Fence>())
+            .sum()
+    }
+
+    /// Entries per block of a fixed table
+    fn span(&self) -> u64 {
+        let size = self.width.unwrap_or(0) + TAIL;
+        (BLOCKSIZE / size).max(1) as u64
+    }
+
+    /// Number of blocks the table is read in
+    fn blocks(&self) -> usize {
+        match self.width {
+            Some(_) => self.count.div_ceil(self.span()) as usize,
+            None => self.fences.len(),
+        }
+    }
+
+    /// Reads `count` consecutive entries of a fixed table starting at entry `first`
+    fn entries(&self, first: u64, count: u64) -> Result<Vec<(Vec<u8>, Position)>> {
+        let width = self.width.unwrap_or(0);
+        let size = width + TAIL;
+        let mut data = vec![0u8; count as usize * size];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(HEAD + first * size as u64))?;
+            file.read_exact(&mut data)?;
+        }
+
+        Ok(data.chunks_exact(size)
+            .map(|entry| {
+                let word = |at: usize| u64::from_le_bytes(entry[width + at..width + at + 8].try_into().unwrap());
+                (entry[..width].to_vec(), Position { segment: word(0), offset: word(8), length: word(16) })
+            })
+            .collect())
+    }
+
+    /// Reads one entry of a fixed table
+    fn entry(&self, slot: u64) -> Result<(Vec<u8>, Position)> {
+        Ok(self.entries(slot, 1)?.remove(0))
+    }
+
+    /// Index of the first entry of a fixed table whose key is not below `key`
+    fn search(&self, key: &[u8]) -> Result<u64> {
+        let (mut low, mut high) = (0, self.count);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            if self.entry(middle)?.0.as_slice() < key {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        Ok(low)
+    }
+
+    /// Reads and decodes a single block
+    fn block(&self, index: usize) -> Result<Vec<(Vec<u8>, Position)>> {
+        if self.width.is_some() {
+            let first = index as u64 * self.span();
+            return self.entries(first, self.span().min(self.count - first));
+        }
+        let fence = &self.fences[index];
+        let mut data = vec![0u8; fence.length as usize];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(fence.offset))?;
+            file.read_exact(&mut data)?;
+        }
+
+        let mut items = Vec::new();
+        let mut cursor = 0usize;
+        while cursor < data.len() {
+            let len = Self::take(&data, &mut cursor, 4)?;
+            let len = u32::from_le_bytes(len.try_into().unwrap()) as usize;
+            let key = Self::take(&data, &mut cursor, len)?.to_vec();
+            let segment = u64::from_le_bytes(Self::take(&data, &mut cursor, 8)?.try_into().unwrap());
+            let offset = u64::from_le_bytes(Self::take(&data, &mut cursor, 8)?.try_into().unwrap());
+            let length = u64::from_le_bytes(Self::take(&data, &mut cursor, 8)?.try_into().unwrap());
+            items.push((key, Position { segment, offset, length }));
+        }
+
+        Ok(items)
+    }
+
+    /// Writes the pending block and records its fence
+    fn seal(
+        writer: &mut BufWriter<File>,
+        block: &mut Vec<u8>,
+        key: Vec<u8>,
+        offset: u64,
+        fences: &mut Vec<Fence>,
+    ) -> Result<u64> {
+        writer.write_all(block)?;
+        fences.push(Fence {
+            key,
+            offset,
+            length: block.len() as u32,
+        });
+        let next = offset + block.len() as u64;
+        block.clear();
+        Ok(next)
+    }
+
+    /// Takes the next `len` bytes from a buffer
+    fn take<'a>(data: &'a [u8], cursor: &mut usize, len: usize) -> Result<&'a [u8]> {
+        let end = *cursor + len;
+        if end > data.len() {
+            return Err(Error::Index("Table block truncated".to_string()));
+        }
+        let slice = &data[*cursor..end];
+        *cursor = end;
+        Ok(slice)
+    }
+}
+
+/// Sequential iterator over table entries
+pub struct Cursor<'a> {
+    table: &'a Table,
+    block: usize,
+    items: std::vec::IntoIter<(Vec<u8>, Position)>,
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = Result<(Vec<u8>, Position)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.items.next() {
+                return Some(Ok(item));
+            }
+
+            if self.block >= self.table.blocks() {
+                return None;
+            }
+
+            match self.table.block(self.block) {
+                Ok(items) => {
+                    self.block += 1;
+                    self.items = items.into_iter();
+                }
+                Err(e) => {
+                    self.block = self.table.blocks();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Fault injection for crash-recovery tests
+//!
+//! `Faulty` wraps the disk backend and numbers every write passing
+//! through it. Faults are armed against those numbers, so a test can
+//! fail or tear exactly the write it wants and then reopen the store
+//! to check what survived. A write is one call to `Write::write`;
+//! appending records takes one per segment they land in.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use crate::backend::{Backend, Disk, Handle};
+use crate::Result;
+
+/// What happens to an armed write
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The write fails without touching the file
+    Fail,
+    /// Only the first bytes reach the file, then the backend crashes
+    Tear(usize),
+}
+
+/// Shared state of a faulty backend and its handles
+#[derive(Debug, Default)]
+struct State {
+    /// Writes seen so far
+    writes: AtomicU64,
+    /// Faults keyed by the 1-based write number they hit
+    armed: Mutex<BTreeMap<u64, Fault>>,
+    /// Set by a torn write; every later operation fails
+    crashed: AtomicBool,
+}
+
+/// Disk backend that fails or tears chosen writes
+///
+/// Clones share their counters, so a test keeps one to arm faults
+/// while the store holds another.
+#[derive(Debug, Default, Clone)]
+pub struct Faulty {
+    /// Counters and armed faults
+    state: Arc<State>,
+}
+
+impl Faulty {
+    /// Creates a backend with no faults armed
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    /// Arms a fault for the write with the given 1-based number
+    pub fn arm(&self, write: u64, fault: Fault) {
+        self.state.armed.lock().unwrap().insert(write, fault);
+    }
+    
+    /// Arms a fault for the next write
+    pub fn next(&self, fault: Fault) {
+        self.arm(self.writes() + 1, fault);
+    }
+    
+    /// Number of writes seen so far, including failed ones
+    pub fn writes(&self) -> u64 {
+        self.state.writes.load(Ordering::SeqCst)
+    }
+    
+    /// Whether a torn write has crashed the backend
+    pub fn crashed(&self) -> bool {
+        self.state.crashed.load(Ordering::SeqCst)
+    }
+    
+    /// Fails the calling operation once the backend has crashed
+    fn alive(&self) -> io::Result<()> {
+        if self.crashed() {
+            return Err(io::Error::other("backend crashed"));
+        }
+        Ok(())
+    }
+    
+    /// Counts a write and takes the fault armed for it, if any
+    fn hit(&self) -> Option<Fault> {
+        let write = self.state.writes.fetch_add(1, Ordering::SeqCst) + 1;
+        self.state.armed.lock().unwrap().remove(&write)
+    }
+}
+
+impl Backend for Faulty {
+    fn create(&self, path: &Path) -> Result<Box<dyn Handle>> {
+        self.alive()?;
+        Ok(Box::new(Wrapped {
+            inner: Disk.create(path)?,
+            faults: self.clone(),
+        }))
+    }
+    
+    fn open(&self, path: &Path) -> Result<Box<dyn Handle>> {
+        self.alive()?;
+        Ok(Box::new(Wrapped {
+            inner: Disk.open(path)?,
+            faults: self.clone(),
+        }))
+    }
+    
+    fn directory(&self, path: &Path) -> Result<()> {
+        self.alive()?;
+        Disk.directory(path)
+    }
+}
+
+/// File handle routed through a faulty backend
+struct Wrapped {
+    /// Underlying disk file
+    inner: Box<dyn Handle>,
+    /// Backend deciding which writes fail
+    faults: Faulty,
+}
+
+impl Read for Wrapped {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.faults.alive()?;
+        self.inner.read(buf)
+    }
+}
+
+impl Seek for Wrapped {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.faults.alive()?;
+        self.inner.seek(pos)
+    }
+}
+
+impl Write for Wrapped {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.faults.alive()?;
+        match self.faults.hit() {
+            None => self.inner.write(buf),
+            Some(Fault::Fail) => Err(io::Error::other("injected write failure")),
+            Some(Fault::Tear(keep)) => {
+                self.inner.write_all(&buf[..keep.min(buf.len())])?;
+                self.faults.state.crashed.store(true, Ordering::SeqCst);
+                Err(io::Error::other("injected torn write"))
+            }
+        }
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        self.faults.alive()?;
+        self.inner.flush()
+    }
+}
+
+impl Handle for Wrapped {
+    fn sync(&self) -> Result<()> {
+        self.faults.alive()?;
+        self.inner.sync()
+    }
+    
+    fn data(&self) -> Result<()> {
+        self.faults.alive()?;
+        self.inner.data()
+    }
+    
+    fn size(&self) -> Result<u64> {
+        self.faults.alive()?;
+        self.inner.size()
+    }
+    
+    fn reserve(&self, size: u64) -> Result<()> {
+        self.faults.alive()?;
+        self.inner.reserve(size)
+    }
+    
+    fn release(&self) -> Result<()> {
+        self.faults.alive()?;
+        self.inner.release()
+    }
+}
@@ -0,0 +1,223 @@
+//! Rate limiting for background work
+//!
+//! Keeps compaction from starving foreground traffic by capping its
+//! bandwidth and operation rate, lowering the I/O priority of the
+//! threads doing it, and lets operators pause or stop it.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tokio::time::sleep;
+
+/// Caps bytes per second and operations per second
+#[derive(Debug)]
+pub struct Throttle {
+    /// Maximum bytes per second (0 means unlimited)
+    bandwidth: u64,
+    /// Maximum operations per second (0 means unlimited)
+    iops: u64,
+    /// Start of the accounting window
+    start: Instant,
+    /// Bytes charged in the current window
+    bytes: u64,
+    /// Operations charged in the current window
+    ops: u64,
+}
+
+impl Throttle {
+    /// Creates a throttle; zero disables the corresponding cap
+    pub fn new(bandwidth: u64, iops: u64) -> Self {
+        Self {
+            bandwidth,
+            iops,
+            start: Instant::now(),
+            bytes: 0,
+            ops: 0,
+        }
+    }
+
+    /// Creates a throttle that never waits
+    pub fn unlimited() -> Self {
+        Self::new(0, 0)
+    }
+
+    /// Accounts for one operation of `bytes` and sleeps if over budget
+    pub async fn charge(&mut self, bytes: u64) {
+        if let Some(delay) = self.debit(bytes) {
+            sleep(delay).await;
+        }
+    }
+
+    /// Records usage and returns how long to wait to stay within caps
+    pub fn debit(&mut self, bytes: u64) -> Option<Duration> {
+        self.bytes += bytes;
+        self.ops += 1;
+
+        let mut due = Duration::ZERO;
+        if self.bandwidth > 0 {
+            due = due.max(Duration::from_secs_f64(self.bytes as f64 / self.bandwidth as f64));
+        }
+        if self.iops > 0 {
+            due = due.max(Duration::from_secs_f64(self.ops as f64 / self.iops as f64));
+        }
+
+        let elapsed = self.start.elapsed();
+
+        // Restart the window periodically so idle time isn't banked forever
+        if elapsed >= Duration::from_secs(1) && due <= elapsed {
+            self.start = Instant::now();
+            self.bytes = 0;
+            self.ops = 0;
+        }
+
+        (due > elapsed).then(|| due - elapsed)
+    }
+}
+
+/// Pause switch shared between a background task and its controller
+#[derive(Debug, Default)]
+pub struct Gate {
+    /// Whether work should currently be held back
+    paused: AtomicBool,
+    /// Wakes waiters on resume
+    notify: Notify,
+}
+
+impl Gate {
+    /// Holds back work until resumed
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Lets held-back work continue
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Whether the gate is currently paused
+    pub fn paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Waits while the gate is paused
+    pub async fn wait(&self) {
+        loop {
+            let notified = self.notify.notified();
+            if !self.paused() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// One-way stop signal shared between a background task and its owner
+#[derive(Debug, Default)]
+pub struct Latch {
+    /// Whether the latch has been tripped
+    tripped: AtomicBool,
+    /// Wakes waiters when tripped
+    notify: Notify,
+}
+
+impl Latch {
+    /// Trips the latch for good
+    pub fn trip(&self) {
+        self.tripped.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    /// Whether the latch has been tripped
+    pub fn tripped(&self) -> bool {
+        self.tripped.load(Ordering::SeqCst)
+    }
+
+    /// Waits until the latch is tripped
+    pub async fn wait(&self) {
+        loop {
+            let notified = self.notify.notified();
+            if self.tripped() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+/// I/O scheduling priority of background threads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Priority {
+    /// Leaves the thread's I/O priority alone
+    Normal,
+    /// Lowest best-effort level, behind foreground traffic but never starved
+    #[default]
+    Low,
+    /// Served only while the disk is otherwise idle
+    Idle,
+}
+
+impl Priority {
+    /// Applies this priority to the calling thread's I/O until the guard drops
+    ///
+    /// Only Linux schedules I/O per thread (`ioprio_set`, as `ionice`
+    /// does); elsewhere, or where the kernel refuses, this does nothing.
+    pub fn apply(self) -> Lowered {
+        #[cfg(target_os = "linux")]
+        return Lowered { previous: linux::lower(self) };
+        #[cfg(not(target_os = "linux"))]
+        return Lowered {};
+    }
+}
+
+/// Restores a thread's I/O priority when dropped
+#[derive(Debug)]
+pub struct Lowered {
+    /// Priority the thread had before, when it was changed
+    #[cfg(target_os = "linux")]
+    previous: Option<i32>,
+}
+
+impl Drop for Lowered {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        if let Some(previous) = self.previous {
+            linux::set(previous);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::Priority;
+
+    /// `ioprio_set` target kind naming a single thread
+    const WHO: libc::c_int = 1;
+    /// Bit position of the scheduling class within a priority value
+    const SHIFT: i32 = 13;
+    /// Best-effort class, levels 0 (highest) to 7
+    const EFFORT: i32 = 2;
+    /// Idle class
+    const IDLE: i32 = 3;
+
+    /// Switches the calling thread to a priority, returning the one it replaced
+    pub fn lower(priority: Priority) -> Option<i32> {
+        let value = match priority {
+            Priority::Normal => return None,
+            Priority::Low => EFFORT << SHIFT | 7,
+            Priority::Idle => IDLE << SHIFT,
+        };
+        // Safety: plain syscalls on the calling thread, no memory passed
+        let previous = unsafe { libc::syscall(libc::SYS_ioprio_get, WHO, 0) };
+        if previous < 0 || !set(value) {
+            return None;
+        }
+        Some(previous as i32)
+    }
+
+    /// Sets the calling thread's I/O priority, reporting success
+    pub fn set(value: i32) -> bool {
+        // Safety: as in `lower`
+        unsafe { libc::syscall(libc::SYS_ioprio_set, WHO, 0, value) == 0 }
+    }
+}
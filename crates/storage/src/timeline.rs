@@ -0,0 +1,127 @@
+//! Time index over record timestamps
+//!
+//! Keeps two secondary indexes ordered by `created` and `updated` so
+//! time-range queries walk only the matching slice instead of every
+//! record. Keys are the big-endian timestamp followed by the big-endian
+//! user ID; the primary index still resolves IDs to positions, so
+//! compaction can move records without touching the timeline.
+
+use std::path::{Path, PathBuf};
+use crate::Result;
+use crate::index::{Index, Shape, Snapshot};
+use crate::model::{Position, User};
+
+/// Directory holding the timeline indexes inside a store
+const NAME: &str = "timeline";
+
+/// Secondary indexes on creation and update time
+pub struct Timeline {
+    /// Entries ordered by creation time
+    created: Index,
+    /// Entries ordered by last update time
+    updated: Index,
+}
+
+impl Timeline {
+    /// Opens the timeline of a store base directory
+    pub fn new<P: AsRef<Path>>(base: P) -> Result<Self> {
+        let path = Self::locate(base);
+        Ok(Self {
+            created: Index::new(path.join("created"))?,
+            updated: Index::new(path.join("updated"))?,
+        })
+    }
+
+    /// Directory of the timeline for a base directory
+    pub fn locate<P: AsRef<Path>>(base: P) -> PathBuf {
+        base.as_ref().join(NAME)
+    }
+
+    /// Adds a record's timestamps
+    pub fn insert(&mut self, user: &User) -> Result<()> {
+        self.created.put(&key(user.created, user.id), Position::default())?;
+        self.updated.put(&key(user.updated, user.id), Position::default())
+    }
+
+    /// Drops a record's timestamps
+    pub fn remove(&mut self, user: &User) -> Result<()> {
+        self.created.delete(&key(user.created, user.id))?;
+        self.updated.delete(&key(user.updated, user.id))
+    }
+
+    /// IDs created within `from..=to`, oldest first
+    pub fn created(&self, from: u64, to: u64) -> Result<Vec<(u64, u64)>> {
+        Self::range(&self.created, from, to)
+    }
+
+    /// IDs updated at or after `from`, oldest first
+    pub fn updated(&self, from: u64) -> Result<Vec<(u64, u64)>> {
+        Self::range(&self.updated, from, u64::MAX)
+    }
+
+    /// Drops every entry of both indexes
+    pub fn clear(&mut self) -> Result<()> {
+        self.created.clear()?;
+        self.updated.clear()
+    }
+
+    /// Both indexes as they are now, by name
+    pub fn snapshots(&self) -> Vec<(&'static str, Snapshot)> {
+        vec![("created", self.created.snapshot()), ("updated", self.updated.snapshot())]
+    }
+
+    /// Flushes both indexes to disk
+    pub fn sync(&self) -> Result<()> {
+        self.created.sync()?;
+        self.updated.sync()
+    }
+
+    /// Approximate bytes of memory held by both indexes
+    pub fn memory(&self) -> usize {
+        self.created.memory() + self.updated.memory()
+    }
+
+    /// Entries and size of both indexes, counted in a full walk
+    pub fn shapes(&self) -> Result<Vec<Shape>> {
+        Ok(vec![self.created.shape("created")?, self.updated.shape("updated")?])
+    }
+
+    /// Merges index deltas above `floor` bytes into their tables
+    pub fn shrink(&mut self, floor: usize) -> Result<()> {
+        self.created.shrink(floor)?;
+        self.updated.shrink(floor)
+    }
+
+    /// Collects `(timestamp, id)` pairs whose timestamp lies in `from..=to`
+    fn range(index: &Index, from: u64, to: u64) -> Result<Vec<(u64, u64)>> {
+        let mut found = Vec::new();
+
+        // Every full key sorts after its bare timestamp prefix
+        let start = from.to_be_bytes();
+        for result in index.after(&start) {
+            let (key, _) = result?;
+            let (time, id) = split(&key);
+            if time > to {
+                break;
+            }
+            found.push((time, id));
+        }
+
+        Ok(found)
+    }
+}
+
+/// Encodes a timeline key so byte order matches time order
+fn key(time: u64, id: u64) -> [u8; 16] {
+    let mut key = [0u8; 16];
+    key[..8].copy_from_slice(&time.to_be_bytes());
+    key[8..].copy_from_slice(&id.to_be_bytes());
+    key
+}
+
+/// Decodes a timeline key into its timestamp and ID
+fn split(key: &[u8]) -> (u64, u64) {
+    let time = u64::from_be_bytes(key[..8].try_into().unwrap());
+    let id = u64::from_be_bytes(key[8..16].try_into().unwrap());
+    (time, id)
+}
@@ -0,0 +1,331 @@
+//! io_uring backend (Linux)
+//!
+//! `Uring` hands out handles whose reads and writes go through a small
+//! io_uring ring owned by the calling thread. Each operation carries
+//! its own offset, so seeks never reach the kernel and a read or write
+//! costs one `io_uring_enter`. Where a ring cannot be set up, because
+//! the kernel is too old or io_uring is disabled, handles fall back to
+//! positional reads and writes, as do those of a thread whose ring
+//! failed. On other platforms `Uring` is `Disk`.
+
+use std::path::Path;
+use crate::Result;
+use crate::backend::{Backend, Disk, Handle};
+
+/// Disk backend issuing reads and writes through io_uring
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Uring;
+
+impl Uring {
+    /// Whether this thread's I/O goes through a ring rather than the fallback
+    pub fn active() -> bool {
+        #[cfg(target_os = "linux")]
+        return linux::active();
+        #[cfg(not(target_os = "linux"))]
+        return false;
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+impl Backend for Uring {
+    fn create(&self, path: &Path) -> Result<Box<dyn Handle>> {
+        Disk.create(path)
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn Handle>> {
+        Disk.open(path)
+    }
+
+    fn directory(&self, path: &Path) -> Result<()> {
+        Disk.directory(path)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Backend for Uring {
+    fn create(&self, path: &Path) -> Result<Box<dyn Handle>> {
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .read(true)
+            .open(path)?;
+        Ok(Box::new(linux::Ringed::new(file)))
+    }
+
+    fn open(&self, path: &Path) -> Result<Box<dyn Handle>> {
+        Ok(Box::new(linux::Ringed::new(std::fs::File::open(path)?)))
+    }
+
+    fn directory(&self, path: &Path) -> Result<()> {
+        Disk.directory(path)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::cell::RefCell;
+    use std::ffi::c_void;
+    use std::fs::File;
+    use std::io::{self, Read, Seek, SeekFrom, Write};
+    use std::os::fd::{AsRawFd, OwnedFd};
+    use std::os::unix::fs::FileExt;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use rustix::io::Errno;
+    use rustix::io_uring::{
+        addr_or_splice_off_in_union, io_uring_cqe, io_uring_enter, io_uring_params, io_uring_ptr,
+        io_uring_setup, io_uring_sqe, len_union, off_or_addr2_union, IoringEnterFlags,
+        IoringFeatureFlags, IoringOp, IORING_OFF_CQ_RING, IORING_OFF_SQES, IORING_OFF_SQ_RING,
+    };
+    use rustix::mm::{mmap, munmap, MapFlags, ProtFlags};
+    use crate::Result;
+    use crate::backend::Handle;
+
+    /// Submission slots per ring; operations are issued one at a time
+    const ENTRIES: u32 = 4;
+
+    thread_local! {
+        /// This thread's ring, absent where io_uring is unavailable
+        static RING: Option<RefCell<Ring>> = Ring::new(ENTRIES).ok().map(RefCell::new);
+    }
+
+    /// Whether this thread has a ring in working order
+    pub fn active() -> bool {
+        RING.with(|ring| ring.as_ref().is_some_and(|ring| !ring.borrow().broken))
+    }
+
+    /// Runs an operation on this thread's ring, or through `fallback` without one
+    fn submit(sqe: io_uring_sqe, fallback: impl FnOnce() -> io::Result<usize>) -> io::Result<usize> {
+        RING.with(|ring| match ring {
+            Some(ring) if !ring.borrow().broken => ring.borrow_mut().run(sqe),
+            _ => fallback(),
+        })
+    }
+
+    /// A memory mapping shared with the kernel
+    struct Map {
+        /// Start of the mapping
+        base: *mut c_void,
+        /// Length in bytes
+        length: usize,
+    }
+
+    impl Map {
+        /// Maps a region of a ring
+        fn new(fd: &OwnedFd, length: usize, offset: u64) -> io::Result<Self> {
+            // Safety: a fresh shared mapping of the ring fd, unmapped on drop
+            let base = unsafe {
+                mmap(std::ptr::null_mut(), length, ProtFlags::READ | ProtFlags::WRITE, MapFlags::SHARED | MapFlags::POPULATE, fd, offset)
+            }?;
+            Ok(Self { base, length })
+        }
+
+        /// Pointer `offset` bytes into the mapping
+        fn at<T>(&self, offset: u32) -> *mut T {
+            // Safety: offsets come from the kernel and lie within the mapping
+            unsafe { self.base.cast::<u8>().add(offset as usize).cast() }
+        }
+    }
+
+    impl Drop for Map {
+        fn drop(&mut self) {
+            // Safety: the mapping is not used after this
+            let _ = unsafe { munmap(self.base, self.length) };
+        }
+    }
+
+    /// An io_uring instance with its queues mapped
+    struct Ring {
+        /// Submission queue ring
+        submissions: Map,
+        /// Completion queue ring, when not sharing the submission mapping
+        completions: Option<Map>,
+        /// Submission queue entries
+        entries: Map,
+        /// Offsets of the fields inside the rings
+        params: io_uring_params,
+        /// Whether an enter failed, after which the ring is left unused
+        broken: bool,
+        /// The ring itself; declared last so the maps go first
+        fd: OwnedFd,
+    }
+
+    impl Ring {
+        /// Sets up a ring with `entries` submission slots
+        fn new(entries: u32) -> io::Result<Self> {
+            let mut params = io_uring_params::default();
+            // Safety: `params` is a valid, zeroed parameter block
+            let fd = unsafe { io_uring_setup(entries, &mut params) }?;
+
+            let submitted = params.sq_off.array as usize + params.sq_entries as usize * size_of::<u32>();
+            let completed = params.cq_off.cqes as usize + params.cq_entries as usize * size_of::<io_uring_cqe>();
+            let (submissions, completions) = if params.features.contains(IoringFeatureFlags::SINGLE_MMAP) {
+                (Map::new(&fd, submitted.max(completed), IORING_OFF_SQ_RING)?, None)
+            } else {
+                (Map::new(&fd, submitted, IORING_OFF_SQ_RING)?, Some(Map::new(&fd, completed, IORING_OFF_CQ_RING)?))
+            };
+            let entries = Map::new(&fd, params.sq_entries as usize * size_of::<io_uring_sqe>(), IORING_OFF_SQES)?;
+            Ok(Self { submissions, completions, entries, params, broken: false, fd })
+        }
+
+        /// Mapping holding the completion queue
+        fn completions(&self) -> &Map {
+            self.completions.as_ref().unwrap_or(&self.submissions)
+        }
+
+        /// Submits one operation and waits for its result
+        fn run(&mut self, sqe: io_uring_sqe) -> io::Result<usize> {
+            let offsets = self.params.sq_off;
+            // Safety: the ring fields are mapped for the ring's lifetime,
+            // and this thread is the ring's only producer and consumer
+            let (head, tail) = unsafe {
+                let head = &*self.submissions.at::<AtomicU32>(offsets.head);
+                let tail = &*self.submissions.at::<AtomicU32>(offsets.tail);
+                let mask = *self.submissions.at::<u32>(offsets.ring_mask);
+                let next = tail.load(Ordering::Relaxed);
+                let slot = next & mask;
+                *self.entries.at::<io_uring_sqe>(0).add(slot as usize) = sqe;
+                *self.submissions.at::<u32>(offsets.array).add(slot as usize) = slot;
+                tail.store(next.wrapping_add(1), Ordering::Release);
+                (head, tail)
+            };
+
+            loop {
+                if let Some(result) = self.reap() {
+                    return result;
+                }
+                // An interrupted enter may or may not have taken the entry
+                let pending = tail.load(Ordering::Relaxed).wrapping_sub(head.load(Ordering::Acquire));
+                // Safety: the ring is set up and its fd open
+                match unsafe { io_uring_enter(&self.fd, pending, 1, IoringEnterFlags::GETEVENTS) } {
+                    Ok(_) | Err(Errno::INTR) => {}
+                    Err(errno) => {
+                        // The entry points into the caller's buffer, so it must
+                        // not outlive this call. Without SQPOLL the kernel only
+                        // takes entries inside an enter, so one still queued now
+                        // stays untouched and is withdrawn. One it took is in
+                        // flight and is waited out, so neither its access to the
+                        // buffer nor its completion comes after we return.
+                        self.broken = true;
+                        if tail.load(Ordering::Relaxed) != head.load(Ordering::Acquire) {
+                            tail.store(tail.load(Ordering::Relaxed).wrapping_sub(1), Ordering::Release);
+                            return Err(errno.into());
+                        }
+                        std::thread::yield_now();
+                    }
+                }
+            }
+        }
+
+        /// Takes the next completion, if one has arrived
+        fn reap(&self) -> Option<io::Result<usize>> {
+            let offsets = self.params.cq_off;
+            let queue = self.completions();
+            // Safety: as in `run`
+            let result = unsafe {
+                let head = &*queue.at::<AtomicU32>(offsets.head);
+                let tail = &*queue.at::<AtomicU32>(offsets.tail);
+                let mask = *queue.at::<u32>(offsets.ring_mask);
+                let current = head.load(Ordering::Relaxed);
+                if tail.load(Ordering::Acquire) == current {
+                    return None;
+                }
+                let result = (*queue.at::<io_uring_cqe>(offsets.cqes).add((current & mask) as usize)).res;
+                head.store(current.wrapping_add(1), Ordering::Release);
+                result
+            };
+            Some(match result {
+                result if result < 0 => Err(io::Error::from_raw_os_error(-result)),
+                result => Ok(result as usize),
+            })
+        }
+    }
+
+    /// Builds a read or write of `length` bytes at `address`
+    fn operation(op: IoringOp, file: &File, address: *mut c_void, length: usize, offset: u64) -> io_uring_sqe {
+        io_uring_sqe {
+            opcode: op,
+            fd: file.as_raw_fd(),
+            off_or_addr2: off_or_addr2_union { off: offset },
+            addr_or_splice_off_in: addr_or_splice_off_in_union { addr: io_uring_ptr::new(address) },
+            // Larger transfers come back short, like any read or write
+            len: len_union { len: length.min(u32::MAX as usize) as u32 },
+            ..Default::default()
+        }
+    }
+
+    /// A file read and written through the thread's ring
+    pub struct Ringed {
+        /// Underlying file
+        file: File,
+        /// Offset of the next read or write
+        cursor: u64,
+    }
+
+    impl Ringed {
+        /// Wraps an open file, starting at offset 0
+        pub fn new(file: File) -> Self {
+            Self { file, cursor: 0 }
+        }
+    }
+
+    impl Read for Ringed {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let sqe = operation(IoringOp::Read, &self.file, buffer.as_mut_ptr().cast(), buffer.len(), self.cursor);
+            let read = submit(sqe, || self.file.read_at(buffer, self.cursor))?;
+            self.cursor += read as u64;
+            Ok(read)
+        }
+    }
+
+    impl Write for Ringed {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            // The kernel only reads from the buffer for a write
+            let sqe = operation(IoringOp::Write, &self.file, buffer.as_ptr().cast_mut().cast(), buffer.len(), self.cursor);
+            let written = submit(sqe, || self.file.write_at(buffer, self.cursor))?;
+            self.cursor += written as u64;
+            Ok(written)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for Ringed {
+        fn seek(&mut self, position: SeekFrom) -> io::Result<u64> {
+            let (base, delta) = match position {
+                SeekFrom::Start(offset) => (offset, 0),
+                SeekFrom::Current(delta) => (self.cursor, delta),
+                SeekFrom::End(delta) => (self.file.metadata()?.len(), delta),
+            };
+            self.cursor = base.checked_add_signed(delta)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "seek before the start of the file"))?;
+            Ok(self.cursor)
+        }
+    }
+
+    impl Handle for Ringed {
+        fn sync(&self) -> Result<()> {
+            self.file.sync_all()?;
+            Ok(())
+        }
+
+        fn data(&self) -> Result<()> {
+            self.file.sync_data()?;
+            Ok(())
+        }
+
+        fn size(&self) -> Result<u64> {
+            Ok(self.file.metadata()?.len())
+        }
+
+        fn reserve(&self, size: u64) -> Result<()> {
+            self.file.reserve(size)
+        }
+
+        fn release(&self) -> Result<()> {
+            self.file.release()
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Record validation hooks
+//!
+//! A store runs its validators on every record before writing it, so
+//! malformed users are refused with `Error::Invalid` instead of landing
+//! on disk.
+
+use std::fmt;
+use crate::{Error, Result};
+use crate::model::User;
+
+/// Check a record must pass before it is written
+pub trait Validator: Send + Sync + fmt::Debug {
+    /// Returns `Error::Invalid` naming the offending field when `user` is refused
+    fn validate(&self, user: &User) -> Result<()>;
+}
+
+/// Rejects users without a name or with an email lacking `@`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Basic;
+
+impl Validator for Basic {
+    fn validate(&self, user: &User) -> Result<()> {
+        if user.name.trim().is_empty() {
+            return Err(invalid("name", "must not be empty"));
+        }
+        match user.email.split_once('@') {
+            Some((local, domain)) if !local.is_empty() && !domain.is_empty() => Ok(()),
+            _ => Err(invalid("email", "must look like local@domain")),
+        }
+    }
+}
+
+/// Builds an `Error::Invalid` for a field
+pub fn invalid(field: &str, reason: &str) -> Error {
+    Error::Invalid {
+        field: field.to_string(),
+        reason: reason.to_string(),
+    }
+}
@@ -0,0 +1,284 @@
+//! Asynchronous write queue
+//!
+//! `Store::writer` moves the store onto a blocking worker behind a
+//! bounded channel. Producers wait for room in the channel, which is
+//! the backpressure, and then for their own write to commit. The worker
+//! takes whatever is queued, up to `BATCH` writes, and commits runs of
+//! saves with one `Store::batch`, so concurrent producers share a flush
+//! instead of each paying for its own. With a group commit window, see
+//! `Builder::window`, the worker lingers that long after the first
+//! write arrives so more of them make the same flush.
+//!
+//! Queued writes are also held in a buffer until they commit, and
+//! `Writer::find` consults it before the store, so a producer always
+//! reads back what it has submitted.
+//!
+//! While the queue is empty the worker also advances any secondary
+//! index backfill, see `Builder::backfill`.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{JoinError, JoinHandle};
+use crate::{Error, Result, Store, User};
+
+/// Most writes committed together
+pub const BATCH: usize = 1024;
+
+/// Records backfilled between checks for queued writes
+const STEP: usize = 256;
+
+/// A queued write, its buffer ticket and where its outcome goes
+enum Job {
+    /// Save a user
+    Save(Box<User>, u64, oneshot::Sender<Result<()>>),
+    /// Delete a user by ID
+    Delete(u64, u64, oneshot::Sender<Result<()>>),
+}
+
+/// Handle to a store behind a write queue, see `Store::writer`
+///
+/// Methods take `&self`, so producers can share it through an `Arc`.
+pub struct Writer {
+    /// Queue feeding the worker
+    sender: mpsc::Sender<Job>,
+    /// Worker committing queued writes until the queue closes
+    worker: JoinHandle<()>,
+    /// The store being written, shared with the worker and readers
+    store: Arc<Store>,
+    /// Writes queued but not yet committed
+    buffer: Arc<Buffer>,
+}
+
+impl Writer {
+    /// Starts the worker on the current Tokio runtime
+    pub(crate) fn spawn(store: Store, depth: usize) -> Result<Self> {
+        tokio::runtime::Handle::try_current()
+            .map_err(|_| Error::Config("The write queue requires a Tokio runtime".to_string()))?;
+        let (sender, receiver) = mpsc::channel(depth.max(1));
+        let window = store.window();
+        let store = Arc::new(store);
+        let buffer = Arc::new(Buffer::default());
+        let worker = {
+            let (store, buffer) = (Arc::clone(&store), Arc::clone(&buffer));
+            tokio::task::spawn_blocking(move || drain(&store, &buffer, receiver, window))
+        };
+        Ok(Self { sender, worker, store, buffer })
+    }
+
+    /// Queues a save, waiting for room, and returns its completion
+    pub async fn submit(&self, user: User) -> Result<Pending> {
+        self.enqueue(user.id, Some(user.clone()), |ticket, done| Job::Save(Box::new(user), ticket, done)).await
+    }
+
+    /// Saves a user, resolving once the write is committed
+    pub async fn save(&self, user: User) -> Result<()> {
+        self.submit(user).await?.await
+    }
+
+    /// Deletes a user, resolving once the delete is committed
+    pub async fn delete(&self, id: u64) -> Result<()> {
+        self.enqueue(id, None, |ticket, done| Job::Delete(id, ticket, done)).await?.await
+    }
+
+    /// Finds a user, seeing writes submitted through this writer before they commit
+    ///
+    /// A queued save is returned as submitted, its revision assigned
+    /// only once it commits, and a queued delete hides the user. Other
+    /// users are read from the store off the async executor, alongside
+    /// whatever batch the worker is committing.
+    pub async fn find(&self, id: u64) -> Result<Option<User>> {
+        if let Some(queued) = self.buffer.get(id) {
+            return Ok(queued);
+        }
+        let store = Arc::clone(&self.store);
+        tokio::task::spawn_blocking(move || store.find(id))
+            .await
+            .map_err(failed)?
+    }
+
+    /// Commits every queued write and hands the store back
+    pub async fn close(self) -> Result<Store> {
+        drop(self.sender);
+        self.worker.await.map_err(failed)?;
+        // Reads whose caller gave up still hold the store until they finish
+        let mut store = self.store;
+        loop {
+            match Arc::try_unwrap(store) {
+                Ok(store) => return Ok(store),
+                Err(shared) => {
+                    store = shared;
+                    tokio::task::yield_now().await;
+                }
+            }
+        }
+    }
+
+    /// Buffers and sends a job once the queue has room
+    async fn enqueue(
+        &self,
+        id: u64,
+        user: Option<User>,
+        job: impl FnOnce(u64, oneshot::Sender<Result<()>>) -> Job,
+    ) -> Result<Pending> {
+        // Buffered only once a slot is held, so a cancelled wait leaves nothing behind
+        let permit = self.sender.reserve().await.map_err(|_| Error::Closed)?;
+        let ticket = self.buffer.hold(id, user);
+        let (done, outcome) = oneshot::channel();
+        permit.send(job(ticket, done));
+        Ok(Pending { outcome })
+    }
+}
+
+/// Writes queued but not yet committed, the latest per user
+#[derive(Default)]
+struct Buffer {
+    /// Ticket of each user's latest queued write, with the user saved or `None` for a delete
+    writes: Mutex<HashMap<u64, (u64, Option<User>)>>,
+    /// Tickets handed out so far
+    tickets: AtomicU64,
+}
+
+impl Buffer {
+    /// Records a queued write and returns its ticket
+    fn hold(&self, id: u64, user: Option<User>) -> u64 {
+        let mut writes = self.writes.lock().unwrap();
+        let ticket = self.tickets.fetch_add(1, Ordering::Relaxed) + 1;
+        writes.insert(id, (ticket, user));
+        ticket
+    }
+
+    /// The latest queued write of a user, if any is pending
+    fn get(&self, id: u64) -> Option<Option<User>> {
+        self.writes.lock().unwrap().get(&id).map(|(_, user)| user.clone())
+    }
+
+    /// Forgets a write once it has committed or failed, unless a later one replaced it
+    fn settle(&self, id: u64, ticket: u64) {
+        let mut writes = self.writes.lock().unwrap();
+        if writes.get(&id).is_some_and(|&(latest, _)| latest == ticket) {
+            writes.remove(&id);
+        }
+    }
+}
+
+/// Completion of a queued write
+///
+/// Resolves to the write's outcome, or `Error::Closed` if the worker
+/// stopped before reaching it.
+pub struct Pending {
+    /// Where the worker reports the outcome
+    outcome: oneshot::Receiver<Result<()>>,
+}
+
+impl Future for Pending {
+    type Output = Result<()>;
+
+    fn poll(mut self: Pin<&mut Self>, context: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.outcome)
+            .poll(context)
+            .map(|outcome| outcome.unwrap_or(Err(Error::Closed)))
+    }
+}
+
+/// Commits queued writes until every handle is gone
+///
+/// Once a write arrives, the worker waits out `window` before taking
+/// what is queued, so writes submitted meanwhile share its flush.
+fn drain(store: &Store, buffer: &Buffer, mut receiver: mpsc::Receiver<Job>, window: Duration) {
+    let mut pending = true;
+    loop {
+        // A backfill runs a step at a time while no write is queued
+        let job = match receiver.try_recv() {
+            Ok(job) => job,
+            Err(mpsc::error::TryRecvError::Disconnected) => return,
+            Err(mpsc::error::TryRecvError::Empty) => {
+                if pending {
+                    pending = step(store);
+                    continue;
+                }
+                match receiver.blocking_recv() {
+                    Some(job) => job,
+                    None => return,
+                }
+            }
+        };
+        if !window.is_zero() {
+            std::thread::sleep(window);
+        }
+        let mut saves = Vec::new();
+        let mut next = Some(job);
+        let mut taken = 1;
+        while let Some(job) = next {
+            match job {
+                Job::Save(user, ticket, done) => saves.push((*user, ticket, done)),
+                Job::Delete(id, ticket, done) => {
+                    // Earlier saves land first so order is kept
+                    commit(store, buffer, std::mem::take(&mut saves));
+                    let outcome = store.delete(id).map(|_| ());
+                    buffer.settle(id, ticket);
+                    let _ = done.send(outcome);
+                }
+            }
+            next = if taken < BATCH { receiver.try_recv().ok() } else { None };
+            taken += 1;
+        }
+        commit(store, buffer, saves);
+    }
+}
+
+/// Backfills one step, returning whether more remains
+///
+/// A failed backfill is logged and left for the next open to resume.
+fn step(store: &Store) -> bool {
+    match store.backfill(STEP) {
+        Ok(done) => !done,
+        Err(error) => {
+            tracing::warn!(%error, "secondary index backfill stopped");
+            false
+        }
+    }
+}
+
+/// Commits saves as one batch, or one by one to tell failures apart
+///
+/// Each user's `Event::Save` hooks run once, ahead of the batch, so a
+/// retry never fires them again. The saves leave the buffer once their
+/// outcome is known.
+fn commit(store: &Store, buffer: &Buffer, saves: Vec<(User, u64, oneshot::Sender<Result<()>>)>) {
+    if saves.is_empty() {
+        return;
+    }
+    let mut outcomes = saves.iter().map(|(user, _, _)| store.vet(user)).collect::<Vec<_>>();
+    let vetted = saves.iter().zip(&outcomes)
+        .filter(|(_, outcome)| outcome.is_ok())
+        .map(|((user, _, _), _)| user.clone())
+        .collect::<Vec<_>>();
+    let landed: Vec<Result<()>> = match store.land(&vetted) {
+        Ok(()) => vetted.iter().map(|_| Ok(())).collect(),
+        // A batch is all or nothing, so retrying each user is safe
+        Err(_) if vetted.len() > 1 => vetted.iter().map(|user| store.land(std::slice::from_ref(user))).collect(),
+        Err(error) => vec![Err(error)],
+    };
+    // Vetted users take the batch's outcomes in order
+    let mut landed = landed.into_iter();
+    for outcome in outcomes.iter_mut().filter(|outcome| outcome.is_ok()) {
+        if let Some(result) = landed.next() {
+            *outcome = result;
+        }
+    }
+    for ((user, ticket, done), outcome) in saves.into_iter().zip(outcomes) {
+        buffer.settle(user.id, ticket);
+        let _ = done.send(outcome);
+    }
+}
+
+/// Wraps a worker failure
+fn failed(error: JoinError) -> Error {
+    Error::Storage(std::io::Error::other(format!("Write queue failed: {}", error)))
+}
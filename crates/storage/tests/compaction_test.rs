@@ -0,0 +1,438 @@
+//! Compaction tests for Guardian-Store
+//!
+//! Covers throttling and pause/resume of the compaction service
+
+use std::sync::Arc;
+use std::time::Duration;
+use guardian_store::compaction::{Compaction, Config, Status};
+use guardian_store::index::Index;
+use guardian_store::segment::{Segment, Tally};
+use guardian_store::throttle::{Priority, Throttle};
+use guardian_store::{Error, Kind, Store, User, Location, Result};
+use std::collections::BTreeMap;
+use tempfile::TempDir;
+use std::sync::Mutex;
+
+/// Creates a test user with sample data
+fn create_test_user(id: u64) -> User {
+    User {
+        id,
+        name: format!("User {}", id),
+        email: format!("user{}@test.com", id),
+        location: Location {
+            street: "Street".to_string(),
+            city: "City".to_string(),
+            country: "Country".to_string(),
+            postal: "12345".to_string(),
+            point: None,
+        },
+        profile: None,
+        created: 0,
+        updated: 0,
+        revision: 0,
+    }
+}
+
+#[test]
+fn test_throttle_caps_rate() {
+    let mut throttle = Throttle::new(1000, 0);
+    let delay = throttle.debit(500).expect("Should be throttled");
+    assert!(delay > Duration::from_millis(400) && delay <= Duration::from_millis(500));
+    
+    let mut throttle = Throttle::new(0, 10);
+    assert!(throttle.debit(1 << 20).is_some());
+    
+    let mut throttle = Throttle::unlimited();
+    assert!(throttle.debit(u32::MAX as u64).is_none());
+}
+
+#[tokio::test]
+async fn test_pause_and_resume() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let segment = Arc::new(Segment::new(temp_dir.path().join("segments"))?);
+    let index = Arc::new(Mutex::new(Index::new(temp_dir.path().join("index"))?));
+    
+    for id in 1..=3u64 {
+        let position = segment.append(&create_test_user(id))?;
+        index.lock().unwrap().put(&id.to_le_bytes(), position)?;
+    }
+    
+    let config = Config {
+        throttle: false,
+        ..Config::default()
+    };
+    let compaction = Arc::new(Compaction::new(config, segment, index));
+    
+    compaction.pause();
+    assert!(compaction.paused());
+    
+    let task = {
+        let compaction = Arc::clone(&compaction);
+        tokio::spawn(async move { compaction.trigger().await })
+    };
+    
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(matches!(compaction.state().await.status, Status::Paused));
+    assert!(!task.is_finished());
+    
+    compaction.resume();
+    task.await.expect("Task should not panic")?;
+    
+    let state = compaction.state().await;
+    assert!(matches!(state.status, Status::Idle));
+    assert_eq!(state.processed, 3);
+    
+    Ok(())
+}
+
+#[test]
+fn test_pick_worst_segments() {
+    let mut tallies = BTreeMap::new();
+    tallies.insert(1, Tally { live: 9, dead: 1 , ..Tally::default() });
+    tallies.insert(2, Tally { live: 2, dead: 8 , ..Tally::default() });
+    tallies.insert(3, Tally { live: 5, dead: 5 , ..Tally::default() });
+    tallies.insert(4, Tally { live: 0, dead: 10 , ..Tally::default() });
+    
+    // Active segment 4 is never picked, worst ratio comes first
+    assert_eq!(Compaction::pick(&tallies, 0.3, 10, 4), vec![2, 3]);
+    assert_eq!(Compaction::pick(&tallies, 0.3, 1, 4), vec![2]);
+    assert!(Compaction::pick(&tallies, 0.95, 10, 4).is_empty());
+}
+
+#[tokio::test]
+async fn test_major_rewrites_picked_segments() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let segment = Arc::new(Segment::new(temp_dir.path().join("segments"))?);
+    let index = Arc::new(Mutex::new(Index::new(temp_dir.path().join("index"))?));
+    
+    // Fill segment 1, then retire most of it and move on to segment 2
+    for id in 1..=10u64 {
+        let position = segment.append(&create_test_user(id))?;
+        index.lock().unwrap().put(&id.to_le_bytes(), position)?;
+    }
+    for id in 1..=8u64 {
+        let key = id.to_le_bytes();
+        let old = index.lock().unwrap().get(&key)?.expect("Key should exist");
+        segment.retire(old);
+        index.lock().unwrap().delete(&key)?;
+    }
+    segment.seal()?;
+    
+    let moved = Segment::restore(
+        temp_dir.path().join("segments"),
+        segment.list(),
+        segment.tallies(),
+        segment.sealed(),
+    )?;
+    let segment = Arc::new(moved);
+    
+    let config = Config {
+        throttle: false,
+        ..Config::default()
+    };
+    let compaction = Compaction::new(config, Arc::clone(&segment), Arc::clone(&index));
+    compaction.trigger().await?;
+    
+    // Segment 1 left the live set and its survivors moved to segment 2
+    assert_eq!(segment.list(), vec![2]);
+    let mut bytes = 0;
+    for id in 9..=10u64 {
+        bytes += index.lock().unwrap().get(&id.to_le_bytes())?.expect("Key should exist").length;
+    }
+    assert_eq!(segment.tallies().get(&2), Some(&Tally { live: 2, dead: 0, bytes }));
+    
+    for id in 9..=10u64 {
+        let position = index.lock().unwrap().get(&id.to_le_bytes())?.expect("Key should exist");
+        assert_eq!(position.segment, 2);
+        assert_eq!(segment.read(position)?.id, id);
+    }
+    
+    let state = compaction.state().await;
+    assert_eq!(state.processed, 2 + 2);
+    assert_eq!(state.removed, 8);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_major_skips_damaged_segments() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let segment = Arc::new(Segment::new(temp_dir.path().join("segments"))?.capacity(1024));
+    let index = Arc::new(Mutex::new(Index::new(temp_dir.path().join("index"))?));
+    
+    for id in 1..=20u64 {
+        let position = segment.append(&create_test_user(id))?;
+        index.lock().unwrap().put(&id.to_le_bytes(), position)?;
+    }
+    let sealed = segment.sealed();
+    assert!(!sealed.is_empty());
+    assert!(!sealed.contains(&segment.current()));
+    for &id in &sealed {
+        let footer = segment.footer(id)?.expect("Segment should be sealed");
+        assert_eq!(footer.records, segment.tallies()[&id].live);
+        segment.check(id)?;
+    }
+    
+    // Retire every record of the first segment but one
+    let first = sealed[0];
+    let mut kept = None;
+    for id in 1..=20u64 {
+        let key = id.to_le_bytes();
+        let position = index.lock().unwrap().get(&key)?.expect("Key should exist");
+        if position.segment != first {
+            continue;
+        }
+        if kept.is_none() {
+            kept = Some((key, position));
+            continue;
+        }
+        segment.retire(position);
+        index.lock().unwrap().delete(&key)?;
+    }
+    
+    // Rot the survivor's name: it still decodes and the cheap check
+    // passes, but the checksum does not
+    let (key, kept) = kept.expect("Segment should hold records");
+    let path = temp_dir.path().join("segments").join(format!("segment_{}.dat", first));
+    let mut data = std::fs::read(&path)?;
+    let record = kept.offset as usize..(kept.offset + 4 + kept.length) as usize;
+    let name = data[record.clone()].windows(4).position(|bytes| bytes == b"User").expect("Name should be stored");
+    data[record.start + name] = b'X';
+    std::fs::write(&path, data)?;
+    segment.verify(first)?;
+    assert!(matches!(segment.check(first), Err(Error::Checksum { segment, .. }) if segment == first));
+    
+    let config = Config {
+        throttle: false,
+        ..Config::default()
+    };
+    let compaction = Compaction::new(config, Arc::clone(&segment), Arc::clone(&index));
+    compaction.trigger().await?;
+    
+    // The damaged segment stays for repair instead of losing its survivor
+    assert!(segment.list().contains(&first));
+    assert_eq!(index.lock().unwrap().get(&key)?, Some(kept));
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_store_schedule() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder().path(temp_dir.path()).segment(2048).open()?;
+    
+    for id in 1..=40u64 {
+        store.save(&create_test_user(id))?;
+    }
+    for id in 1..=30u64 {
+        store.delete(id)?;
+    }
+    let before = store.stats()?.segments;
+    
+    let config = Config {
+        interval: Duration::from_millis(10),
+        throttle: false,
+        ..Config::default()
+    };
+    let guard = store.schedule(config)?;
+    
+    // Wait for the background task to rewrite the worst segments
+    for _ in 0..200 {
+        if store.stats()?.segments < before {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    guard.idle().await;
+    guard.stop().await?;
+    
+    assert!(store.stats()?.segments < before);
+    assert_eq!(store.scan().count(), 10);
+    assert_eq!(store.find(35)?.expect("User should survive").id, 35);
+    
+    Ok(())
+}
+
+#[test]
+fn test_store_compact() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    {
+        let store = Store::builder().path(temp_dir.path()).segment(2048).open()?;
+        for id in 1..=40u64 {
+            store.save(&create_test_user(id))?;
+        }
+        for id in 1..=30u64 {
+            store.delete(id)?;
+        }
+        let before = store.stats()?.segments;
+        
+        let state = store.compact(Config { throttle: false, ..Config::default() })?;
+        assert!(matches!(state.status, Status::Idle));
+        assert!(state.removed > 0);
+        assert!(store.stats()?.segments < before);
+        assert_eq!(store.stats()?.compactions, 1);
+    }
+    
+    // The outcome is durable without a clean close
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.scan().count(), 10);
+    assert_eq!(store.find(35)?.expect("User should survive").id, 35);
+    
+    Ok(())
+}
+
+#[test]
+fn test_store_rewrite() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder().path(temp_dir.path()).segment(2048).open()?;
+    for id in 1..=40u64 {
+        store.save(&create_test_user(id))?;
+    }
+    store.delete(1)?;
+    let segments = store.manifest().segments.clone();
+    assert!(segments.len() > 2);
+    let config = Config { throttle: false, ..Config::default() };
+    
+    // Only the named segment moves, though the picker would skip it
+    let target = segments[1];
+    assert_eq!(store.tallies()[&target].dead, 0);
+    let state = store.rewrite(&[target], config.clone())?;
+    assert!(state.processed > 0);
+    assert!(!store.manifest().segments.contains(&target));
+    assert!(store.manifest().segments.contains(&segments[0]));
+    assert_eq!(store.scan().count(), 39);
+    
+    // Unknown and active segments are refused
+    let unknown = store.rewrite(&[target], config.clone()).unwrap_err();
+    assert_eq!(unknown.kind(), Kind::Missing);
+    let active = *store.manifest().segments.last().unwrap();
+    assert!(matches!(store.rewrite(&[active], config), Err(Error::Compact(_))));
+    
+    Ok(())
+}
+
+#[test]
+fn test_parallel_workers() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder().path(temp_dir.path()).segment(1024).open()?;
+    for id in 1..=60u64 {
+        store.save(&create_test_user(id))?;
+    }
+    for id in (1..=60u64).step_by(2) {
+        store.delete(id)?;
+    }
+    let before = store.manifest().segments.clone();
+    
+    let config = Config {
+        throttle: false,
+        threshold: 0.0,
+        limit: usize::MAX,
+        workers: 4,
+        priority: Priority::Idle,
+        ..Config::default()
+    };
+    let picked = store.compaction(config.clone()).plan();
+    assert!(picked.len() > 4);
+    let dead: u64 = picked.iter().map(|id| store.tallies()[id].dead).sum();
+    let state = store.compact(config)?;
+    assert!(matches!(state.status, Status::Idle));
+    assert_eq!(state.removed, dead);
+    
+    // Every picked segment left, and every survivor moved intact
+    let after = store.manifest().segments.clone();
+    assert!(picked.iter().all(|id| !after.contains(id)));
+    assert!(after.len() < before.len());
+    assert_eq!(store.tallies().values().map(|tally| tally.live).sum::<u64>(), 30);
+    for id in (2..=60u64).step_by(2) {
+        assert_eq!(store.find(id)?.expect("User should survive").id, id);
+    }
+    assert_eq!(store.scan().count(), 30);
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_store_compact_in_runtime() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    assert!(matches!(store.compact(Config::default()), Err(Error::Config(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_low_disk() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder().path(temp_dir.path()).reserve(u64::MAX).open()?;
+    let config = Config {
+        interval: Duration::from_secs(3600),
+        throttle: false,
+        ..Config::default()
+    };
+    let guard = store.schedule(config)?;
+    for _ in 0..200 {
+        if store.stats()?.compactions == 1 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(store.stats()?.compactions, 1);
+    
+    // Writes are refused up front, with nothing appended
+    let error = store.save(&create_test_user(1)).unwrap_err();
+    assert!(matches!(error, Error::Capacity { reserve: u64::MAX, .. }));
+    assert_eq!(error.kind(), Kind::Capacity);
+    assert!(store.batch(&[create_test_user(2)]).is_err());
+    assert_eq!(store.scan().count(), 0);
+    assert_eq!(store.stats()?.written, 0);
+    
+    // And start the next pass without waiting out the interval
+    for _ in 0..200 {
+        if store.stats()?.compactions >= 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert!(store.stats()?.compactions >= 2);
+    guard.stop().await?;
+    
+    Ok(())
+}
+
+#[test]
+fn test_schedule_requires_runtime() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    assert!(matches!(store.schedule(Config::default()), Err(Error::Config(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stop_handle() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let segment = Arc::new(Segment::new(temp_dir.path().join("segments"))?);
+    let index = Arc::new(Mutex::new(Index::new(temp_dir.path().join("index"))?));
+    
+    let config = Config {
+        interval: Duration::from_secs(3600),
+        throttle: false,
+        ..Config::default()
+    };
+    let compaction = Compaction::new(config, segment, index);
+    
+    // A task sleeping out its interval stops promptly
+    let handle = compaction.start().await?;
+    handle.idle().await;
+    tokio::time::timeout(Duration::from_secs(5), handle.stop())
+        .await
+        .expect("Stop should not wait for the interval")?;
+    
+    // So does a paused one
+    compaction.pause();
+    let handle = compaction.start().await?;
+    tokio::time::timeout(Duration::from_secs(5), handle.stop())
+        .await
+        .expect("Stop should not wait for resume")?;
+    assert!(compaction.paused());
+    
+    Ok(())
+}
@@ -0,0 +1,330 @@
+//! Crash-recovery tests for Guardian-Store
+//!
+//! Runs a store over the fault-injecting backend, breaks a chosen
+//! write, then reopens the directory from disk to check what survived
+
+use std::sync::Arc;
+use std::time::Duration;
+use guardian_store::compaction::Config;
+use guardian_store::testing::{Fault, Faulty};
+use guardian_store::{Store, User, Location, Result};
+use tempfile::TempDir;
+
+/// Creates a test user with sample data
+fn create_test_user(id: u64) -> User {
+    User {
+        id,
+        name: format!("User {}", id),
+        email: format!("user{}@test.com", id),
+        location: Location {
+            street: "Street".to_string(),
+            city: "City".to_string(),
+            country: "Country".to_string(),
+            postal: "12345".to_string(),
+            point: None,
+        },
+        profile: None,
+        created: 0,
+        updated: 0,
+        revision: 0,
+    }
+}
+
+/// Opens a store whose segments go through a faulty backend
+fn open_faulty(temp_dir: &TempDir, faulty: &Faulty) -> Result<Store> {
+    Store::builder()
+        .path(temp_dir.path())
+        .segment(1024)
+        .backend(Arc::new(faulty.clone()))
+        .open()
+}
+
+#[test]
+fn test_torn_append() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let faulty = Faulty::new();
+    {
+        let store = open_faulty(&temp_dir, &faulty)?;
+        for id in 1..=3u64 {
+            store.save(&create_test_user(id))?;
+        }
+        
+        // The head of user 4's record only half reaches the disk
+        faulty.next(Fault::Tear(2));
+        assert!(store.save(&create_test_user(4)).is_err());
+        assert!(faulty.crashed());
+    }
+    
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.scan().count(), 3);
+    assert!(store.find(4)?.is_none());
+    
+    // Later writes land past the torn tail
+    store.save(&create_test_user(5))?;
+    assert_eq!(store.find(5)?.expect("User should exist").id, 5);
+    
+    Ok(())
+}
+
+#[test]
+fn test_failed_write_is_reported() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let faulty = Faulty::new();
+    let store = open_faulty(&temp_dir, &faulty)?;
+    store.save(&create_test_user(1))?;
+    
+    faulty.next(Fault::Fail);
+    assert!(store.save(&create_test_user(2)).is_err());
+    assert!(!faulty.crashed());
+    
+    // A failed write leaves the store usable
+    store.save(&create_test_user(3))?;
+    assert!(store.find(2)?.is_none());
+    assert_eq!(store.find(3)?.expect("User should exist").id, 3);
+    
+    Ok(())
+}
+
+#[test]
+fn test_torn_rotation() -> Result<()> {
+    // Find the save that opens the second segment: it also writes a header
+    let rotation = {
+        let temp_dir = TempDir::new()?;
+        let faulty = Faulty::new();
+        let store = open_faulty(&temp_dir, &faulty)?;
+        store.save(&create_test_user(1))?;
+        let mut id = 1;
+        loop {
+            id += 1;
+            let before = faulty.writes();
+            store.save(&create_test_user(id))?;
+            if faulty.writes() - before > 2 {
+                break id;
+            }
+        }
+    };
+    
+    let temp_dir = TempDir::new()?;
+    let faulty = Faulty::new();
+    {
+        let store = open_faulty(&temp_dir, &faulty)?;
+        for id in 1..rotation {
+            store.save(&create_test_user(id))?;
+        }
+        
+        // Sealing the old segment takes two writes, its footer and its
+        // header, then crash while writing the length prefix of the new
+        // segment's header
+        faulty.arm(faulty.writes() + 3, Fault::Tear(3));
+        assert!(store.save(&create_test_user(rotation)).is_err());
+    }
+    
+    let store = Store::builder().path(temp_dir.path()).segment(1024).open()?;
+    assert_eq!(store.scan().count() as u64, rotation - 1);
+    
+    // The torn file is never reused as the active segment
+    store.save(&create_test_user(rotation))?;
+    assert_eq!(store.find(rotation)?.expect("User should exist").id, rotation);
+    assert!(!store.manifest().segments.contains(&2));
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_torn_compaction() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let faulty = Faulty::new();
+    {
+        let store = open_faulty(&temp_dir, &faulty)?;
+        for id in 1..=40u64 {
+            store.save(&create_test_user(id))?;
+        }
+        // Every segment keeps a few survivors to move
+        for id in (1..=40u64).filter(|id| id % 4 != 0) {
+            store.delete(id)?;
+        }
+        
+        // Let a few records move, then crash mid-move
+        faulty.arm(faulty.writes() + 5, Fault::Tear(3));
+        let config = Config {
+            interval: Duration::from_millis(10),
+            throttle: false,
+            ..Config::default()
+        };
+        assert!(store.compaction(config).trigger().await.is_err());
+        assert!(faulty.crashed());
+    }
+    
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.scan().count(), 10);
+    for id in (4..=40u64).step_by(4) {
+        assert_eq!(store.find(id)?.expect("User should survive").id, id);
+    }
+    
+    Ok(())
+}
+
+#[test]
+fn test_batch_single_write() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let faulty = Faulty::new();
+    let store = Store::builder()
+        .path(temp_dir.path())
+        .backend(Arc::new(faulty.clone()))
+        .open()?;
+    store.save(&create_test_user(1))?;
+    
+    // One buffer for the whole batch
+    let users: Vec<User> = (2..=20).map(create_test_user).collect();
+    let before = faulty.writes();
+    store.batch(&users)?;
+    assert_eq!(faulty.writes() - before, 1);
+    
+    // A batch spanning segments writes once per segment
+    let temp_dir = TempDir::new()?;
+    let store = open_faulty(&temp_dir, &faulty)?;
+    let before = faulty.writes();
+    store.batch(&users)?;
+    let segments = store.stats()?.segments;
+    assert!(segments > 1);
+    // Plus two header writes per new segment and a footer and seal per rotation
+    assert_eq!(faulty.writes() - before, segments + 2 * segments + 2 * (segments - 1));
+    assert_eq!(store.scan().count(), users.len());
+    
+    Ok(())
+}
+
+#[test]
+fn test_batch_torn_index() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let log = temp_dir.path().join("index");
+    let length = {
+        let store = Store::new(temp_dir.path())?;
+        store.save(&create_test_user(1))?;
+        store.save(&create_test_user(2))?;
+        let length = std::fs::metadata(&log)?.len();
+        let users: Vec<User> = (3..=8).map(create_test_user).collect();
+        store.batch(&users)?;
+        length
+    };
+    
+    // Cut the group record short, as a crash mid-append would
+    let file = std::fs::OpenOptions::new().write(true).open(&log)?;
+    file.set_len(length + 20)?;
+    drop(file);
+    
+    {
+        let store = Store::new(temp_dir.path())?;
+        assert!(store.find(1)?.is_some());
+        assert!(store.find(2)?.is_some());
+        for id in 3..=8 {
+            assert!(store.find(id)?.is_none());
+        }
+        
+        // The torn tail is dropped, so later writes are not lost behind it
+        store.save(&create_test_user(9))?;
+    }
+    
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.scan().count(), 3);
+    assert!(store.find(9)?.is_some());
+    
+    Ok(())
+}
+
+#[test]
+fn test_batch_failed_write() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let faulty = Faulty::new();
+    let store = open_faulty(&temp_dir, &faulty)?;
+    store.save(&create_test_user(1))?;
+    let tallies = store.tallies();
+    
+    // The first segment's buffer lands, then the write into the next fails
+    let users: Vec<User> = (1..=20).map(create_test_user).collect();
+    faulty.arm(faulty.writes() + 5, Fault::Fail);
+    assert!(store.batch(&users).is_err());
+    assert!(!faulty.crashed());
+    
+    assert_eq!(store.find(1)?.unwrap().revision, 1);
+    for id in 2..=20 {
+        assert!(store.find(id)?.is_none());
+    }
+    // Records that did land are counted dead, never live
+    for (id, tally) in store.tallies() {
+        assert_eq!(tally.live, tallies.get(&id).map_or(0, |tally| tally.live));
+    }
+    assert!(store.tallies().values().any(|tally| tally.dead > 0));
+    
+    Ok(())
+}
+
+/// Path of the newest segment file of a store
+fn newest(temp_dir: &TempDir) -> Result<std::path::PathBuf> {
+    let mut paths = std::fs::read_dir(temp_dir.path().join("segments"))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.sort_by_key(|path| {
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        name.trim_start_matches("segment_").parse::<u64>().unwrap()
+    });
+    Ok(paths.pop().unwrap())
+}
+
+#[test]
+fn test_recovery_trims_torn_tail() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let faulty = Faulty::new();
+    let whole = {
+        let store = open_faulty(&temp_dir, &faulty)?;
+        for id in 1..=3u64 {
+            store.save(&create_test_user(id))?;
+        }
+        let whole = std::fs::metadata(newest(&temp_dir)?)?.len();
+        
+        faulty.next(Fault::Tear(20));
+        assert!(store.save(&create_test_user(4)).is_err());
+        whole
+    };
+    let torn = newest(&temp_dir)?;
+    assert!(std::fs::metadata(&torn)?.len() > whole);
+    
+    // Opening cuts the segment back to its last whole record
+    let store = Store::builder().path(temp_dir.path()).recovery(true).open()?;
+    assert_eq!(std::fs::metadata(&torn)?.len(), whole);
+    assert_eq!(store.scan().count(), 3);
+    store.save(&create_test_user(5))?;
+    assert_eq!(store.find(5)?.expect("User should exist").id, 5);
+    
+    Ok(())
+}
+
+#[test]
+fn test_recovery_drops_lost_records() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    {
+        let store = Store::new(temp_dir.path())?;
+        for id in 1..=5u64 {
+            store.save(&create_test_user(id))?;
+        }
+    }
+    
+    // Unsynced data lost in a power failure: the index outlives user 5's record
+    let path = newest(&temp_dir)?;
+    let size = std::fs::metadata(&path)?.len();
+    std::fs::OpenOptions::new().write(true).open(&path)?.set_len(size - 10)?;
+    assert!(Store::new(temp_dir.path())?.find(5).is_err());
+    
+    let store = Store::builder().path(temp_dir.path()).recovery(true).open()?;
+    assert!(store.find(5)?.is_none());
+    assert_eq!(store.find(4)?.expect("User should exist").id, 4);
+    assert_eq!(store.count()?, 4);
+    drop(store);
+    
+    // The repair is durable
+    let store = Store::new(temp_dir.path())?;
+    assert!(store.find(5)?.is_none());
+    assert_eq!(store.tallies().values().map(|tally| tally.live).sum::<u64>(), 4);
+    
+    Ok(())
+}
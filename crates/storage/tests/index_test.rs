@@ -0,0 +1,269 @@
+//! Index tests for Guardian-Store
+//!
+//! Exercises the bounded in-memory delta and the sorted on-disk table
+
+use guardian_store::index::{Index, Operation};
+use guardian_store::key::{self, Key, UserId};
+use guardian_store::{Error, Position, Result};
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Builds a distinct position for a numeric key
+fn position(id: u64) -> Position {
+    Position {
+        segment: 1,
+        offset: id * 100,
+        length: id,
+    }
+}
+
+#[test]
+fn test_spill_to_table() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let mut index = Index::bounded(temp_dir.path().join("index"), 4096)?;
+
+    for id in 0..1000u64 {
+        index.put(&id.to_be_bytes(), position(id))?;
+    }
+
+    // Delta stays within budget once entries spill to the table
+    assert!(index.memory() < 4096 + 1000);
+
+    for id in 0..1000u64 {
+        let found = index.get(&id.to_be_bytes())?.expect("Key should exist");
+        assert_eq!(found.offset, id * 100);
+    }
+    assert!(index.get(&5000u64.to_be_bytes())?.is_none());
+
+    Ok(())
+}
+
+#[test]
+fn test_scan_merges_delta_and_table() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let mut index = Index::bounded(temp_dir.path().join("index"), 1 << 20)?;
+
+    for id in 0..100u64 {
+        index.put(&id.to_be_bytes(), position(id))?;
+    }
+    index.merge()?;
+
+    // Overwrite, delete and add on top of the table
+    index.put(&10u64.to_be_bytes(), position(999))?;
+    index.delete(&20u64.to_be_bytes())?;
+    index.put(&500u64.to_be_bytes(), position(500))?;
+
+    let entries = index.scan().collect::<Result<Vec<_>>>()?;
+    assert_eq!(entries.len(), 100);
+    assert!(entries.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+    assert_eq!(index.get(&10u64.to_be_bytes())?.unwrap().offset, 99900);
+    assert!(index.get(&20u64.to_be_bytes())?.is_none());
+    assert!(index.get(&500u64.to_be_bytes())?.is_some());
+
+    Ok(())
+}
+
+#[test]
+fn test_reopen_preserves_state() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("index");
+
+    {
+        let mut index = Index::bounded(&path, 2048)?;
+        let operations = (0..200u64)
+            .map(|id| Operation::Put {
+                key: id.to_be_bytes().to_vec(),
+                position: position(id),
+            })
+            .collect();
+        index.batch(operations)?;
+        index.delete(&7u64.to_be_bytes())?;
+        index.put(&300u64.to_be_bytes(), position(300))?;
+    }
+
+    let index = Index::bounded(&path, 2048)?;
+    assert!(index.get(&7u64.to_be_bytes())?.is_none());
+    assert_eq!(index.get(&150u64.to_be_bytes())?.unwrap().length, 150);
+    assert_eq!(index.get(&300u64.to_be_bytes())?.unwrap().length, 300);
+    assert_eq!(index.scan().count(), 200);
+
+    Ok(())
+}
+
+/// Order identifier sharing an index with users
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct OrderId(u64);
+
+impl Key for OrderId {
+    const TAG: u8 = 2;
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend_from_slice(&self.0.to_be_bytes());
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self> {
+        UserId::decode(bytes).map(|UserId(id)| Self(id))
+    }
+}
+
+#[test]
+fn test_typed_keys() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let mut index = Index::new(temp_dir.path().join("index"))?;
+
+    // Equal values of different key types never collide
+    for id in 1..=3u64 {
+        index.space::<UserId>().put(&UserId(id), position(id))?;
+        index.space::<OrderId>().put(&OrderId(id), position(id + 10))?;
+    }
+    index.space::<OrderId>().delete(&OrderId(2))?;
+    assert_eq!(index.space::<UserId>().get(&UserId(2))?, Some(position(2)));
+    assert_eq!(index.space::<OrderId>().get(&OrderId(3))?, Some(position(13)));
+    assert!(index.space::<OrderId>().get(&OrderId(2))?.is_none());
+
+    // Scans stay inside their namespace
+    let users = index.space::<UserId>().scan().collect::<Result<Vec<_>>>()?;
+    assert_eq!(users.iter().map(|(key, _)| key.0).collect::<Vec<_>>(), vec![1, 2, 3]);
+    let orders = index.space::<OrderId>().scan().collect::<Result<Vec<_>>>()?;
+    assert_eq!(orders, vec![(OrderId(1), position(11)), (OrderId(3), position(13))]);
+
+    // Keys only decode in their own namespace
+    let encoded = key::encode(&OrderId(7));
+    assert_eq!(key::decode::<OrderId>(&encoded)?, OrderId(7));
+    assert!(matches!(key::decode::<UserId>(&encoded), Err(Error::Key { .. })));
+
+    Ok(())
+}
+
+#[test]
+fn test_write_buffer() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("index");
+    let hour = Duration::from_secs(3600);
+    let log = |path: &std::path::Path| std::fs::metadata(path).map(|meta| meta.len());
+
+    // Held records are readable but not yet in the log
+    let mut index = Index::new(&path)?.buffer(4096, hour);
+    for id in 0..10u64 {
+        index.put(&id.to_be_bytes(), position(id))?;
+    }
+    assert_eq!(log(&path)?, 0);
+    assert_eq!(index.get(&3u64.to_be_bytes())?, Some(position(3)));
+    assert!(index.backlog()? > 0);
+
+    // A crash loses them; nothing runs on the way down
+    std::mem::forget(index);
+    let mut index = Index::new(&path)?.buffer(4096, hour);
+    assert_eq!(index.scan().count(), 0);
+
+    // Filling the buffer writes it in one go, and replay recovers it
+    let mut id = 0u64;
+    while log(&path)? == 0 {
+        index.put(&id.to_be_bytes(), position(id))?;
+        id += 1;
+    }
+    index.put(&id.to_be_bytes(), position(id))?;
+    std::mem::forget(index);
+    let index = Index::new(&path)?.buffer(4096, hour);
+    assert_eq!(index.scan().count() as u64, id);
+    assert_eq!(index.get(&id.to_be_bytes())?, None);
+
+    // Stale records go out with the next change, and sync forces the rest
+    let mut index = index.buffer(4096, Duration::ZERO);
+    index.delete(&0u64.to_be_bytes())?;
+    let mut index = index.buffer(4096, hour);
+    index.delete(&1u64.to_be_bytes())?;
+    index.sync()?;
+    std::mem::forget(index);
+    let index = Index::new(&path)?;
+    assert_eq!(index.scan().count() as u64, id - 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_hashed_keys() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("index");
+    let long = |id: u64| format!("{:0>200}", id).into_bytes();
+    {
+        let mut index = Index::bounded(&path, 4096)?.hashed()?;
+        for id in 0..1000u64 {
+            index.put(&long(id), position(id))?;
+        }
+        index.delete(&long(7))?;
+        index.merge()?;
+        index.put(&long(1000), position(1000))?;
+        
+        // Fixed-size entries need no fences in memory
+        assert!(index.memory() < 4096);
+        assert!(index.scan().all(|entry| entry.is_ok_and(|(key, _)| key.len() == 16)));
+    }
+    
+    // A hashed table turns hashing on when reopened
+    let index = Index::bounded(&path, 4096)?;
+    assert_eq!(index.scan().count(), 1000);
+    for id in (0..=1000u64).filter(|&id| id != 7) {
+        assert_eq!(index.get(&long(id))?, Some(position(id)));
+    }
+    assert!(index.get(&long(7))?.is_none());
+    assert!(index.get(&long(5000))?.is_none());
+    assert!(matches!(Index::new(temp_dir.path().join("plain")).and_then(|mut plain| {
+        plain.put(b"key", position(1))?;
+        plain.hashed()
+    }), Err(Error::Config(_))));
+    
+    Ok(())
+}
+
+#[test]
+fn test_hash_collisions() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let mut index = Index::new(temp_dir.path().join("index"))?.hashed()?;
+    index.put(b"first", position(1))?;
+    
+    // `owns` stands in for reading the record and comparing its key
+    assert_eq!(index.lookup(b"first", |_| Ok(true))?, Some(position(1)));
+    assert!(index.lookup(b"first", |_| Ok(false))?.is_none());
+    
+    index.claim(b"first", position(2), |held| Ok(held == position(1)))?;
+    assert_eq!(index.get(b"first")?, Some(position(2)));
+    let refused = index.claim(b"first", position(3), |_| Ok(false));
+    assert!(matches!(refused, Err(Error::Collision { position: held, .. }) if held == position(2)));
+    assert_eq!(index.get(b"first")?, Some(position(2)));
+    
+    Ok(())
+}
+
+#[test]
+fn test_snapshot() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let mut index = Index::bounded(temp_dir.path().join("index"), 4096)?;
+    for id in 0..200u64 {
+        index.put(&id.to_be_bytes(), position(id))?;
+    }
+    let snapshot = index.snapshot();
+    
+    // Writes and merges after the snapshot do not show through it
+    index.delete(&5u64.to_be_bytes())?;
+    index.put(&7u64.to_be_bytes(), position(700))?;
+    index.put(&500u64.to_be_bytes(), position(500))?;
+    index.merge()?;
+    index.put(&9u64.to_be_bytes(), position(900))?;
+    
+    let frozen = snapshot.scan().collect::<Result<Vec<_>>>()?;
+    assert_eq!(frozen.len(), 200);
+    assert!(frozen.iter().enumerate().all(|(id, (key, found))| {
+        *key == (id as u64).to_be_bytes() && *found == position(id as u64)
+    }));
+    assert_eq!(snapshot.get(&5u64.to_be_bytes())?, Some(position(5)));
+    assert!(snapshot.get(&500u64.to_be_bytes())?.is_none());
+    assert_eq!(snapshot.page(Some(&8u64.to_be_bytes()), 2)?.len(), 2);
+    
+    assert!(index.get(&5u64.to_be_bytes())?.is_none());
+    assert_eq!(index.get(&9u64.to_be_bytes())?, Some(position(900)));
+    assert_eq!(index.scan().count(), 200);
+    
+    Ok(())
+}
@@ -2,7 +2,33 @@
 //! 
 //! Tests the complete flow from SDK -> Index -> Segment
 
-use guardian_store::{Store, User, Location, Profile, Result};
+use guardian_store::{Store, User, Location, Profile, Result, Error, Kind, Durability, Compression, Field, Projection};
+use guardian_store::manifest::{Manifest, Quota, Upgrade};
+use guardian_store::model::SCHEMA;
+use guardian_store::{directory, legacy, Point};
+use guardian_store::history::Retention;
+use guardian_store::audit::{Action, Entry};
+use guardian_store::compaction::Config;
+use guardian_store::validator::{self, Basic, Validator};
+use guardian_store::codec::Json;
+use guardian_store::generator::{Generator, Monotonic, Snowflake};
+use guardian_store::hook::Event;
+use guardian_store::catalog::Plan;
+use guardian_store::filter::Filter;
+use guardian_store::language::Statement;
+use guardian_store::migration;
+use guardian_store::publish::{Publisher, Sink};
+use guardian_store::Position;
+use guardian_store::registry::{Change, Member, Registry};
+use guardian_store::shard::Shards;
+use guardian_store::raw::Raw;
+use guardian_store::probe;
+use std::ops::Bound;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use guardian_store::segment::Segment;
+use guardian_store::index::Index;
 use tempfile::TempDir;
 
 /// Creates a test user with sample data
@@ -12,6 +38,7 @@ fn create_test_user(id: u64) -> User {
         city: "Test City".to_string(),
         country: "Test Country".to_string(),
         postal: "12345".to_string(),
+        point: None,
     };
     
     User {
@@ -28,13 +55,14 @@ fn create_test_user(id: u64) -> User {
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
             .as_secs(),
+        revision: 0,
     }
 }
 
 #[test]
 fn test_basic_crud() -> Result<()> {
     let temp_dir = TempDir::new()?;
-    let mut store = Store::new(temp_dir.path())?;
+    let store = Store::new(temp_dir.path())?;
     
     let user = create_test_user(1);
     store.save(&user)?;
@@ -52,7 +80,7 @@ fn test_basic_crud() -> Result<()> {
 #[test]
 fn test_batch_operations() -> Result<()> {
     let temp_dir = TempDir::new()?;
-    let mut store = Store::new(temp_dir.path())?;
+    let store = Store::new(temp_dir.path())?;
     
     // Create multiple users
     let users: Vec<User> = (1..=10).map(create_test_user).collect();
@@ -73,7 +101,7 @@ fn test_batch_operations() -> Result<()> {
 #[test]
 fn test_zero_copy_access() -> Result<()> {
     let temp_dir = TempDir::new()?;
-    let mut store = Store::new(temp_dir.path())?;
+    let store = Store::new(temp_dir.path())?;
     
     // Create and save a user
     let user = create_test_user(1);
@@ -93,7 +121,7 @@ fn test_zero_copy_access() -> Result<()> {
 #[test]
 fn test_scan_operations() -> Result<()> {
     let temp_dir = TempDir::new()?;
-    let mut store = Store::new(temp_dir.path())?;
+    let store = Store::new(temp_dir.path())?;
     
     // Create multiple users
     let users: Vec<User> = (1..=5).map(create_test_user).collect();
@@ -117,10 +145,68 @@ fn test_scan_operations() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_scan_disk_order() -> Result<()> {
+    for compression in [Compression::None, Compression::Lz4, Compression::Dictionary] {
+        let temp_dir = TempDir::new()?;
+        let store = Store::builder()
+            .path(temp_dir.path())
+            .segment(16 * 1024)
+            .compression(compression)
+            .open()?;
+        
+        // Rewriting in reverse leaves disk order opposite to key order,
+        // with pages of the index spread over many segments
+        for id in 1..=3000 {
+            store.save(&create_test_user(id))?;
+        }
+        for id in (1..=3000).rev().step_by(2) {
+            let mut user = create_test_user(id);
+            user.name = format!("Renamed {}", id);
+            store.save(&user)?;
+        }
+        
+        let users = store.scan().collect::<Result<Vec<_>>>()?;
+        let keys = store.keys().collect::<Result<Vec<_>>>()?;
+        assert_eq!(users.iter().map(|user| user.id).collect::<Vec<_>>(), keys);
+        for user in &users {
+            let renamed = user.id % 2 == 0;
+            assert_eq!(user.name.starts_with("Renamed"), renamed, "user {} under {:?}", user.id, compression);
+            assert_eq!(user.revision, if renamed { 2 } else { 1 });
+        }
+    }
+    
+    // A damaged record fails alone, in its place
+    let temp_dir = TempDir::new()?;
+    {
+        let store = Store::new(temp_dir.path())?;
+        for id in 1..=3u64 {
+            store.save(&create_test_user(id))?;
+        }
+    }
+    let position = Index::new(temp_dir.path().join("index"))?
+        .get(&2u64.to_le_bytes())?
+        .expect("Key should exist");
+    let path = temp_dir.path().join("segments").join("segment_1.dat");
+    let mut bytes = std::fs::read(&path)?;
+    let offset = position.offset as usize;
+    bytes[offset..offset + 4].copy_from_slice(&[0xff, 0xff, 0x00, 0x00]);
+    std::fs::write(&path, bytes)?;
+    
+    let store = Store::new(temp_dir.path())?;
+    let results = store.scan().collect::<Vec<_>>();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].as_ref().map(|user| user.id).ok(), Some(1));
+    assert!(matches!(results[1], Err(Error::Corrupt { .. })));
+    assert_eq!(results[2].as_ref().map(|user| user.id).ok(), Some(3));
+    
+    Ok(())
+}
+
 #[test]
 fn test_storage_statistics() -> Result<()> {
     let temp_dir = TempDir::new()?;
-    let mut store = Store::new(temp_dir.path())?;
+    let store = Store::new(temp_dir.path())?;
     
     // Initially should have no records
     let stats = store.stats()?;
@@ -146,10 +232,373 @@ fn test_storage_statistics() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_persisted_counters() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    {
+        let store = Store::new(temp_dir.path())?;
+        for id in 1..=3 {
+            store.save(&create_test_user(id))?;
+        }
+        store.update(&create_test_user(2))?;
+        store.delete(1)?;
+        // Deleting a missing user is not counted
+        store.delete(99)?;
+        store.batch(&[create_test_user(4), create_test_user(5)])?;
+        
+        let stats = store.stats()?;
+        assert_eq!(stats.records, 4);
+        assert_eq!(stats.written, 6);
+        assert_eq!(stats.deleted, 1);
+        assert!(stats.bytes > 0);
+        
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(store.compaction(Config::default()).trigger())?;
+        assert_eq!(store.stats()?.compactions, 1);
+        store.close()?;
+    }
+    
+    // Lifetime numbers survive a restart
+    let store = Store::new(temp_dir.path())?;
+    let stats = store.stats()?;
+    assert_eq!(stats.records, 4);
+    assert_eq!(stats.written, 6);
+    assert_eq!(stats.deleted, 1);
+    assert_eq!(stats.compactions, 1);
+    
+    store.save(&create_test_user(6))?;
+    assert_eq!(store.stats()?.written, 7);
+    
+    Ok(())
+}
+
+#[test]
+fn test_scan_snapshot() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder().path(temp_dir.path()).segment(4096).cache(4096).open()?;
+    for id in 1..=3000 {
+        store.save(&create_test_user(id))?;
+    }
+    for id in (1..=3000).step_by(2) {
+        store.delete(id)?;
+    }
+    
+    // Compaction repoints every record and merges the index mid-scan
+    let mut scan = store.scan();
+    let mut ids: Vec<u64> = scan.by_ref().take(10).map(|user| user.map(|user| user.id)).collect::<Result<_>>()?;
+    let config = Config { threshold: 0.0, limit: usize::MAX, throttle: false, ..Config::default() };
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(store.compaction(config).trigger())?;
+    for user in scan {
+        ids.push(user?.id);
+    }
+    
+    let mut unique = ids.clone();
+    unique.sort_unstable();
+    unique.dedup();
+    assert_eq!(unique.len(), ids.len());
+    assert_eq!(ids.len(), 1500);
+    assert!(ids.iter().all(|id| id % 2 == 0));
+    
+    Ok(())
+}
+
+#[test]
+fn test_ship_seed() -> Result<()> {
+    let source_dir = TempDir::new()?;
+    let replica_dir = TempDir::new()?;
+    let source = Store::new(source_dir.path())?;
+    for id in 1..=600 {
+        source.save(&create_test_user(id))?;
+    }
+    source.save(&create_test_user(7))?;
+    source.delete(8)?;
+    let sequence = source.sequence()?;
+    
+    // Over a socket into a store using another codec
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let address = listener.local_addr()?;
+    let replica = Store::builder().path(replica_dir.path()).codec(Arc::new(Json)).open()?;
+    let shipped = std::thread::scope(|scope| {
+        let sender = scope.spawn(|| source.ship(std::io::BufWriter::new(std::net::TcpStream::connect(address)?)));
+        let (socket, _) = listener.accept()?;
+        let seeded = replica.seed(std::io::BufReader::new(socket))?;
+        let shipped = sender.join().unwrap()?;
+        assert_eq!(seeded, shipped);
+        Result::Ok(shipped)
+    })?;
+    assert_eq!(shipped, 599);
+    
+    assert_eq!(replica.count()?, 599);
+    assert_eq!(replica.find(7)?.unwrap().revision, 2);
+    assert_eq!(replica.find(9)?.unwrap().revision, 1);
+    assert!(replica.find(8)?.is_none());
+    assert!(replica.sequence()? > sequence);
+    
+    // Seeding needs an empty store
+    let mut stream = Vec::new();
+    source.ship(&mut stream)?;
+    assert!(matches!(replica.seed(&stream[..]), Err(Error::Config(_))));
+    
+    Ok(())
+}
+
+#[test]
+fn test_seed_damaged() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let source = Store::new(temp_dir.path().join("source"))?;
+    for id in 1..=10 {
+        source.save(&create_test_user(id))?;
+    }
+    let mut stream = Vec::new();
+    assert_eq!(source.ship(&mut stream)?, 10);
+    
+    // Cut short before the end frame
+    let replica = Store::new(temp_dir.path().join("short"))?;
+    let error = replica.seed(&stream[..stream.len() - 17]).unwrap_err();
+    assert_eq!(error.kind(), Kind::Corruption);
+    
+    // A flipped byte inside a record fails the checksum
+    let mut flipped = stream.clone();
+    let last = flipped.len() - 30;
+    flipped[last] ^= 0x01;
+    let replica = Store::new(temp_dir.path().join("flipped"))?;
+    assert!(replica.seed(&flipped[..]).is_err());
+    
+    // Anything but a snapshot stream is refused outright
+    let replica = Store::new(temp_dir.path().join("garbage"))?;
+    let junk = [0, 0, 0, 5, 0, b'j', b'u', b'n', b'k'];
+    assert!(matches!(replica.seed(&junk[..]), Err(Error::Format(_))));
+    assert_eq!(replica.count()?, 0);
+    
+    Ok(())
+}
+
+#[test]
+fn test_health() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder()
+        .path(temp_dir.path())
+        .durability(Durability::Sync)
+        .segment(1024)
+        .open()?;
+    
+    let health = store.health()?;
+    assert!(health.healthy());
+    assert!(health.synced.is_none());
+    
+    for id in 1..=20 {
+        store.save(&create_test_user(id))?;
+    }
+    let health = store.health()?;
+    assert!(health.healthy());
+    assert!(health.synced.is_some());
+    assert!(health.backlog > 0);
+    assert!(health.pending.is_empty());
+    assert_eq!(health.available.is_some(), cfg!(unix));
+    
+    // Deleted records make sealed segments due for compaction
+    for id in 1..=10 {
+        store.delete(id)?;
+    }
+    assert!(!store.health()?.pending.is_empty());
+    
+    // A damaged header is reported rather than failing the probe
+    let segments = temp_dir.path().join("segments");
+    let first = Segment::discover(&segments)?[0];
+    let path = segments.join(format!("segment_{}.dat", first));
+    let mut data = std::fs::read(&path)?;
+    data[4..12].fill(0xFF);
+    std::fs::write(&path, data)?;
+    
+    let health = store.health()?;
+    assert!(!health.healthy());
+    assert_eq!(health.corrupt, vec![first]);
+    
+    Ok(())
+}
+
+#[test]
+fn test_memory() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let hour = Duration::from_secs(3600);
+    {
+        let store = Store::builder()
+            .path(temp_dir.path())
+            .compression(Compression::Dictionary)
+            .buffer(1 << 20, hour)
+            .open()?;
+        for id in 1..=200 {
+            store.save(&create_test_user(id))?;
+        }
+        let memory = store.memory();
+        assert!(memory.index > 0 && memory.secondary > 0 && memory.cache > 0 && memory.buffer > 0);
+        assert_eq!(memory.total(), memory.index + memory.secondary + memory.cache + memory.buffer);
+        assert_eq!(memory.budget, None);
+    }
+    
+    // Over budget, writes flush the buffer and merge deltas away
+    let budget = 64 * 1024;
+    let store = Store::builder()
+        .path(temp_dir.path())
+        .buffer(1 << 20, hour)
+        .budget(budget)
+        .open()?;
+    for id in 201..=1000 {
+        store.save(&create_test_user(id))?;
+        assert!(store.memory().total() <= budget);
+    }
+    assert!(store.manifest().generation > 0);
+    drop(store);
+    
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.scan().count(), 1000);
+    
+    Ok(())
+}
+
+#[test]
+fn test_sealed_segments() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    {
+        let store = Store::builder().path(temp_dir.path()).segment(1024).open()?;
+        for id in 1..=20 {
+            store.save(&create_test_user(id))?;
+        }
+        store.close()?;
+    }
+    
+    // Rotated segments are sealed, and stay so across a reopen
+    let store = Store::builder().path(temp_dir.path()).segment(1024).open()?;
+    let sealed = store.manifest().sealed.clone();
+    assert!(!sealed.is_empty());
+    assert!(sealed.iter().all(|id| store.manifest().segments.contains(id)));
+    assert!(!sealed.contains(store.manifest().segments.last().unwrap()));
+    assert!(store.health()?.healthy());
+    
+    // A sealed segment that lost its tail fails the cheap check
+    let path = temp_dir.path().join("segments").join(format!("segment_{}.dat", sealed[0]));
+    let data = std::fs::read(&path)?;
+    std::fs::write(&path, &data[..data.len() - 1])?;
+    assert_eq!(store.health()?.corrupt, vec![sealed[0]]);
+    
+    Ok(())
+}
+
+/// Requires postal codes made of digits only
+#[derive(Debug)]
+struct Postal;
+
+impl Validator for Postal {
+    fn validate(&self, user: &User) -> Result<()> {
+        if user.location.postal.chars().all(|c| c.is_ascii_digit()) {
+            Ok(())
+        } else {
+            Err(validator::invalid("location.postal", "must be digits"))
+        }
+    }
+}
+
+#[test]
+fn test_validation() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder()
+        .path(temp_dir.path())
+        .validator(Arc::new(Basic))
+        .validator(Arc::new(Postal))
+        .open()?;
+    store.save(&create_test_user(1))?;
+    
+    let mut user = create_test_user(2);
+    user.email = "nobody".to_string();
+    let error = store.save(&user).unwrap_err();
+    assert!(matches!(&error, Error::Invalid { field, .. } if field == "email"));
+    assert_eq!(error.kind(), Kind::Invalid);
+    
+    let mut user = create_test_user(2);
+    user.location.postal = "AB-12".to_string();
+    let error = store.commit(&user, 0).unwrap_err();
+    assert!(matches!(&error, Error::Invalid { field, .. } if field == "location.postal"));
+    
+    // One bad record refuses the whole batch
+    let mut users: Vec<User> = (3..=5).map(create_test_user).collect();
+    users[1].name = " ".to_string();
+    assert!(matches!(store.batch(&users), Err(Error::Invalid { .. })));
+    assert_eq!(store.scan().count(), 1);
+    
+    Ok(())
+}
+
+#[test]
+fn test_record_limit() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder().path(temp_dir.path()).limit(512).open()?;
+    store.save(&create_test_user(1))?;
+    
+    let mut user = create_test_user(2);
+    user.name = "x".repeat(1024);
+    let error = store.save(&user).unwrap_err();
+    assert!(matches!(&error, Error::Invalid { field, .. } if field == "record"));
+    assert_eq!(error.kind(), Kind::Invalid);
+    
+    // Nothing of the batch is written
+    assert!(store.batch(&[create_test_user(3), user]).is_err());
+    assert_eq!(store.scan().count(), 1);
+    assert_eq!(store.stats()?.written, 1);
+    
+    Ok(())
+}
+
+#[test]
+fn test_segment_expiry() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let segments = temp_dir.path().join("segments");
+    {
+        let store = Store::builder().path(temp_dir.path()).segment(1024).open()?;
+        for id in 1..=20 {
+            store.save(&create_test_user(id))?;
+        }
+        // Nothing expires without a policy
+        assert!(store.expire()?.is_empty());
+        store.expiry(Some(3600))?;
+        assert!(store.expire()?.is_empty());
+        
+        // Age every sealed segment past the expiry
+        let ids = Segment::discover(&segments)?;
+        let (&active, sealed) = ids.split_last().unwrap();
+        let past = std::time::SystemTime::now() - std::time::Duration::from_secs(7200);
+        for id in sealed {
+            let file = std::fs::File::options().write(true).open(segments.join(format!("segment_{}.dat", id)))?;
+            file.set_modified(past)?;
+        }
+        
+        // A user rewritten since keeps its fresh version
+        store.update(&create_test_user(1))?;
+        let survivors = store.tallies()[&active].live;
+        assert!(survivors < 20);
+        
+        let expired = store.expire()?;
+        assert_eq!(expired, sealed.to_vec());
+        assert_eq!(store.stats()?.segments, 1);
+        assert_eq!(store.scan().count() as u64, survivors);
+        assert_eq!(store.find(1)?.unwrap().revision, 2);
+        
+        store.collect(false)?;
+        assert_eq!(Segment::discover(&segments)?, vec![active]);
+    }
+    
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.manifest().expiry, Some(3600));
+    assert!(store.find(1)?.is_some());
+    assert!(store.find(2)?.is_none());
+    
+    Ok(())
+}
+
 #[test]
 fn test_schema_evolution() -> Result<()> {
     let temp_dir = TempDir::new()?;
-    let mut store = Store::new(temp_dir.path())?;
+    let store = Store::new(temp_dir.path())?;
     
     // Create a user without profile (old schema)
     let mut user = create_test_user(1);
@@ -177,4 +626,2738 @@ fn test_schema_evolution() -> Result<()> {
     assert_eq!(retrieved.profile.as_ref().unwrap().age, 30);
     
     Ok(())
-} 
\ No newline at end of file
+} 
+#[test]
+fn test_close_semantics() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    
+    let user = create_test_user(1);
+    store.save(&user)?;
+    store.close()?;
+    
+    // Every operation after close must fail
+    assert!(matches!(store.find(1), Err(Error::Closed)));
+    assert!(matches!(store.save(&user), Err(Error::Closed)));
+    assert!(matches!(store.delete(1), Err(Error::Closed)));
+    assert!(matches!(store.scan().next(), Some(Err(Error::Closed))));
+    
+    // Closing twice is harmless and the manifest describes the store
+    store.close()?;
+    let manifest = Manifest::load(temp_dir.path())?.expect("Manifest should exist");
+    assert_eq!(manifest.segments, vec![1]);
+    drop(store);
+    
+    // Data written before close is visible after reopening
+    let store = Store::new(temp_dir.path())?;
+    let retrieved = store.find(1)?.expect("User should exist");
+    assert_eq!(retrieved.name, user.name);
+    
+    Ok(())
+}
+
+#[test]
+fn test_manifest_drives_open() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    
+    {
+        let store = Store::new(temp_dir.path())?;
+        store.save(&create_test_user(1))?;
+        assert_eq!(store.manifest().segments, vec![1]);
+    }
+    
+    // A stray segment file not listed in the manifest is ignored
+    std::fs::write(temp_dir.path().join("segments").join("segment_9.dat"), b"junk")?;
+    
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.manifest().segments, vec![1]);
+    store.save(&create_test_user(2))?;
+    assert_eq!(store.manifest().segments, vec![1, 2]);
+    
+    let manifest = Manifest::load(temp_dir.path())?.expect("Manifest should exist");
+    assert_eq!(manifest.segments, vec![1, 2]);
+    assert_eq!(manifest.schema, SCHEMA);
+    assert!(store.find(1)?.is_some());
+    
+    Ok(())
+}
+
+#[test]
+fn test_manifest_behind_index() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    
+    let generation = {
+        let store = Store::builder().path(temp_dir.path()).cache(4096).open()?;
+        for id in 1..=500 {
+            store.save(&create_test_user(id))?;
+        }
+        store.manifest().generation
+    };
+    assert!(generation > 1);
+    
+    // As if a crash came between a merge and the manifest naming its table
+    let mut manifest = Manifest::load(temp_dir.path())?.expect("Manifest should exist");
+    manifest.generation = generation - 1;
+    manifest.save(temp_dir.path())?;
+    assert!(!temp_dir.path().join(format!("index.{}.table", generation - 1)).exists());
+    
+    let store = Store::builder().path(temp_dir.path()).cache(4096).open()?;
+    assert_eq!(store.manifest().generation, generation);
+    assert_eq!(Manifest::load(temp_dir.path())?.expect("Manifest should exist").generation, generation);
+    for id in 1..=500 {
+        assert!(store.contains(id)?);
+    }
+    
+    // The newer table is live, not garbage
+    store.collect(false)?;
+    assert!(temp_dir.path().join(format!("index.{}.table", generation)).exists());
+    
+    Ok(())
+}
+
+#[test]
+fn test_garbage_collection() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    store.save(&create_test_user(1))?;
+    
+    // Leftovers from a failed compaction and an unreferenced segment
+    let orphan = temp_dir.path().join("segments").join("segment_7.dat");
+    let scratch = temp_dir.path().join("temp_compact");
+    std::fs::write(&orphan, b"orphan")?;
+    std::fs::create_dir_all(scratch.join("segments"))?;
+    std::fs::write(scratch.join("segments").join("segment_1.dat"), b"scratch")?;
+    std::fs::write(temp_dir.path().join("notes.txt"), b"keep")?;
+    
+    let report = store.collect(true)?;
+    assert_eq!(report.paths.len(), 2);
+    assert!(orphan.exists() && scratch.exists());
+    
+    let report = store.collect(false)?;
+    assert_eq!(report.paths.len(), 2);
+    assert!(!orphan.exists() && !scratch.exists());
+    assert!(temp_dir.path().join("notes.txt").exists());
+    assert!(store.find(1)?.is_some());
+    
+    Ok(())
+}
+
+#[test]
+fn test_segment_tallies() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    
+    {
+        let store = Store::new(temp_dir.path())?;
+        let users: Vec<User> = (1..=4).map(create_test_user).collect();
+        store.batch(&users)?;
+        store.update(&create_test_user(1))?;
+        store.save(&create_test_user(2))?;
+        store.delete(3)?;
+        
+        let tally = store.tallies()[&1];
+        assert_eq!((tally.live, tally.dead), (3, 3));
+    }
+    
+    // Counters survive a restart through the manifest
+    let store = Store::new(temp_dir.path())?;
+    let tally = store.tallies()[&1];
+    assert_eq!((tally.live, tally.dead), (3, 3));
+    assert!((tally.ratio() - 0.5).abs() < f64::EPSILON);
+    
+    Ok(())
+}
+
+#[test]
+fn test_json_records() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    
+    // Optional fields may be left out of a JSON record
+    let json = r#"{"id":7,"name":"Json","email":"json@test.com",
+        "location":{"street":"1 Main","city":"Hue","country":"VN","postal":"530000"}}"#;
+    let user: User = serde_json::from_str(json).unwrap();
+    assert!(user.profile.is_none());
+    assert_eq!(user.created, 0);
+    
+    store.save(&user)?;
+    let found = store.find(7)?.unwrap();
+    
+    let text = serde_json::to_string(&found).unwrap();
+    let back: User = serde_json::from_str(&text).unwrap();
+    assert_eq!(back.name, "Json");
+    assert_eq!(back.location.city, "Hue");
+    
+    Ok(())
+}
+
+#[test]
+fn test_time_queries() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let ids = |users: Vec<User>| users.iter().map(|user| user.id).collect::<Vec<_>>();
+    
+    {
+        let store = Store::new(temp_dir.path())?;
+        for id in 1..=5 {
+            let mut user = create_test_user(id);
+            user.created = 100 * id;
+            user.updated = 100 * id;
+            store.save(&user)?;
+        }
+        
+        // Touch one record and drop another
+        let mut user = store.find(2)?.unwrap();
+        user.updated = 1000;
+        store.save(&user)?;
+        store.delete(4)?;
+        
+        assert_eq!(ids(store.created(200, 400)?), vec![2, 3]);
+        assert_eq!(ids(store.updated(300)?), vec![3, 5, 2]);
+        assert!(store.created(600, 700)?.is_empty());
+    }
+    
+    // Reopening, and rebuilding a missing timeline, give the same answers
+    for rebuild in [false, true] {
+        if rebuild {
+            std::fs::remove_dir_all(temp_dir.path().join("timeline"))?;
+        }
+        let store = Store::new(temp_dir.path())?;
+        assert_eq!(ids(store.created(0, u64::MAX)?), vec![1, 2, 3, 5]);
+        assert_eq!(ids(store.updated(300)?), vec![3, 5, 2]);
+    }
+    
+    Ok(())
+}
+
+#[test]
+fn test_geo_queries() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    
+    // Hanoi, Hai Phong (~100km), Ho Chi Minh City (~1140km), and no coordinates
+    let places = [(1, 21.0285, 105.8542), (2, 20.8449, 106.6881), (3, 10.8231, 106.6297)];
+    for (id, latitude, longitude) in places {
+        let mut user = create_test_user(id);
+        user.location.point = Some(Point { latitude, longitude });
+        store.save(&user)?;
+    }
+    store.save(&create_test_user(4))?;
+    
+    let ids = |users: Vec<User>| users.iter().map(|user| user.id).collect::<Vec<_>>();
+    assert_eq!(ids(store.near(21.0285, 105.8542, 10_000.0)?), vec![1]);
+    assert_eq!(ids(store.near(21.0285, 105.8542, 150_000.0)?), vec![1, 2]);
+    assert_eq!(ids(store.near(21.0, 106.0, 2_000_000.0)?), vec![1, 2, 3]);
+    
+    // Moving a user moves its atlas entry
+    let mut user = store.find(2)?.unwrap();
+    user.location.point = Some(Point { latitude: 10.8, longitude: 106.6 });
+    store.save(&user)?;
+    assert_eq!(ids(store.near(21.0285, 105.8542, 150_000.0)?), vec![1]);
+    assert_eq!(ids(store.near(10.8231, 106.6297, 10_000.0)?), vec![3, 2]);
+    
+    Ok(())
+}
+
+#[test]
+fn test_lookup() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let ids = |users: Vec<User>| users.iter().map(|user| user.id).collect::<Vec<_>>();
+    
+    {
+        let store = Store::new(temp_dir.path())?;
+        // "V" and "VNM" share prefixes with "VN" but must not match it
+        for (id, country) in [(1, "VN"), (2, "JP"), (3, "US"), (4, "VN"), (5, "V"), (6, "VNM")] {
+            let mut user = create_test_user(id);
+            user.location.country = country.to_string();
+            store.save(&user)?;
+        }
+        
+        assert_eq!(ids(store.lookup(Field::Country, &["VN", "JP"])?), vec![1, 2, 4]);
+        // Repeated values return each user once
+        assert_eq!(ids(store.lookup(Field::Country, &["JP", "JP"])?), vec![2]);
+        assert!(store.lookup(Field::Country, &["FR"])?.is_empty());
+        assert!(store.lookup(Field::Country, &[])?.is_empty());
+        
+        // Moving and deleting users updates the catalog
+        let mut user = store.find(1)?.unwrap();
+        user.location.country = "JP".to_string();
+        store.save(&user)?;
+        store.delete(4)?;
+        assert!(store.lookup(Field::Country, &["VN"])?.is_empty());
+        assert_eq!(ids(store.lookup(Field::Country, &["JP"])?), vec![1, 2]);
+        
+        assert!(matches!(store.lookup(Field::Name, &["User 1"]), Err(Error::Unsupported(_))));
+    }
+    
+    // A store without a catalog gets one built when opened
+    std::fs::remove_dir_all(temp_dir.path().join("catalog"))?;
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(ids(store.lookup(Field::Country, &["JP", "US", "V"])?), vec![1, 2, 3, 5]);
+    
+    Ok(())
+}
+
+#[test]
+fn test_composite_query() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let ids = |users: Vec<User>| users.iter().map(|user| user.id).collect::<Vec<_>>();
+    let place = |id: u64, country: &str, city: &str| {
+        let mut user = create_test_user(id);
+        user.location.country = country.to_string();
+        user.location.city = city.to_string();
+        user
+    };
+    
+    {
+        let store = Store::new(temp_dir.path())?;
+        // "Ha" prefixes "Hanoi" but must not match it
+        for user in [place(1, "VN", "Hanoi"), place(2, "VN", "Hue"), place(3, "VN", "Ha"), place(4, "JP", "Hanoi")] {
+            store.save(&user)?;
+        }
+        
+        // Country alone reads the same index as country and city
+        assert_eq!(ids(store.query(&[(Field::Country, "VN")])?), vec![1, 2, 3]);
+        assert_eq!(ids(store.lookup(Field::Country, &["VN"])?), vec![1, 2, 3]);
+        assert_eq!(ids(store.query(&[(Field::Country, "VN"), (Field::City, "Hanoi")])?), vec![1]);
+        assert_eq!(ids(store.query(&[(Field::City, "Ha"), (Field::Country, "VN")])?), vec![3]);
+        assert!(store.query(&[(Field::Country, "JP"), (Field::City, "Hue")])?.is_empty());
+        
+        // City alone does not lead the index
+        assert!(matches!(store.query(&[(Field::City, "Hanoi")]), Err(Error::Unsupported(_))));
+        
+        store.save(&place(2, "VN", "Hanoi"))?;
+        assert_eq!(ids(store.query(&[(Field::Country, "VN"), (Field::City, "Hanoi")])?), vec![1, 2]);
+        assert!(store.query(&[(Field::Country, "VN"), (Field::City, "Hue")])?.is_empty());
+    }
+    
+    // Single-field catalogs from older stores are replaced on open
+    let catalog = temp_dir.path().join("catalog");
+    for entry in std::fs::read_dir(&catalog)? {
+        let path = entry?.path();
+        if path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("composite")) {
+            std::fs::remove_file(path)?;
+        }
+    }
+    std::fs::write(catalog.join("values"), b"")?;
+    let store = Store::new(temp_dir.path())?;
+    assert!(!catalog.join("values").exists());
+    assert_eq!(ids(store.query(&[(Field::Country, "VN"), (Field::City, "Hanoi")])?), vec![1, 2]);
+    
+    Ok(())
+}
+
+#[test]
+fn test_index_statistics() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    // 190 users across 19 Vietnamese cities, 10 in one Japanese city
+    for id in 1..=200 {
+        let mut user = create_test_user(id);
+        user.location.country = if id % 20 == 0 { "JP" } else { "VN" }.to_string();
+        user.location.city = format!("City {}", id % 20);
+        store.save(&user)?;
+    }
+    let vn = [(Field::Country, "VN")];
+    let city = [(Field::Country, "VN"), (Field::City, "City 3")];
+    
+    // Without statistics every lookup goes through its index
+    assert_eq!(store.plan(&vn)?, Plan::Index);
+    
+    let shapes = store.indexes()?;
+    let shape = |name: &str| shapes.iter().find(|shape| shape.name == name).unwrap();
+    assert_eq!(shape("primary").entries, 200);
+    assert!(shape("primary").bytes > 0);
+    assert_eq!(shape("country,city").fields, vec![Field::Country, Field::City]);
+    assert_eq!(shape("country,city").entries, 200);
+    assert_eq!(shape("country,city").distinct, vec![2, 20]);
+    assert_eq!(shape("email").distinct, vec![200]);
+    assert_eq!(shape("created").entries, 200);
+    assert_eq!(shape("history").entries, 0);
+    
+    // A country holds half the records, too many to read one by one
+    assert_eq!(store.plan(&vn)?, Plan::Scan);
+    assert_eq!(store.plan(&city)?, Plan::Index);
+    assert_eq!(store.plan(&[(Field::Email, "user7@test.com")])?, Plan::Index);
+    
+    // Either plan returns the same users by ascending ID
+    let ids = |users: Vec<User>| users.iter().map(|user| user.id).collect::<Vec<_>>();
+    let expected = (1..=200).filter(|id| id % 20 != 0).collect::<Vec<_>>();
+    assert_eq!(ids(store.query(&vn)?), expected);
+    assert_eq!(ids(store.lookup(Field::Country, &["JP"])?), (1..=10).map(|n| n * 20).collect::<Vec<_>>());
+    assert_eq!(ids(store.query(&city)?), (0..10).map(|n| n * 20 + 3).collect::<Vec<_>>());
+    
+    Ok(())
+}
+
+#[test]
+fn test_online_backfill() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let ids = |users: Vec<User>| users.iter().map(|user| user.id).collect::<Vec<_>>();
+    let place = |id: u64| {
+        let mut user = create_test_user(id);
+        user.location.country = if id.is_multiple_of(2) { "VN" } else { "JP" }.to_string();
+        user.location.point = (id == 7).then_some(Point { latitude: 21.0285, longitude: 105.8542 });
+        user
+    };
+    {
+        let store = Store::new(temp_dir.path())?;
+        for id in 1..=30 {
+            store.save(&place(id))?;
+        }
+    }
+    // A store predating every secondary index
+    for name in ["timeline", "atlas", "catalog"] {
+        std::fs::remove_dir_all(temp_dir.path().join(name))?;
+    }
+    
+    let vn = (1..=30u64).filter(|id| id.is_multiple_of(2)).collect::<Vec<_>>();
+    {
+        let store = Store::builder().path(temp_dir.path()).backfill(true).open()?;
+        assert_eq!(store.manifest().backfill.as_ref().map(|backfill| backfill.records), Some(0));
+        
+        // Queries stay whole before and while records are indexed
+        for step in 0..2 {
+            if step == 1 {
+                assert!(!store.backfill(10)?);
+            }
+            assert_eq!(ids(store.lookup(Field::Country, &["VN"])?), vn);
+            assert_eq!(ids(store.created(0, u64::MAX)?).len(), 30);
+            assert_eq!(ids(store.near(21.0, 105.8, 10_000.0)?), vec![7]);
+            let taken = User { email: "user5@test.com".to_string(), ..place(31) };
+            assert!(matches!(store.save(&taken), Err(Error::Duplicate { holder: 5, .. })));
+        }
+        assert_eq!(store.manifest().backfill.as_ref().map(|backfill| backfill.records), Some(10));
+        
+        // Live writes land in the indexes meanwhile
+        store.save(&place(32))?;
+        store.delete(2)?;
+    }
+    
+    // A reopened store resumes the backfill, here during open
+    let store = Store::new(temp_dir.path())?;
+    assert!(store.manifest().backfill.is_none());
+    assert!(store.backfill(10)?);
+    let expected = vn.iter().copied().filter(|&id| id != 2).chain([32]).collect::<Vec<_>>();
+    assert_eq!(ids(store.lookup(Field::Country, &["VN"])?), expected);
+    assert_eq!(ids(store.query(&[(Field::Country, "JP")])?).len(), 15);
+    assert_eq!(ids(store.near(21.0, 105.8, 10_000.0)?), vec![7]);
+    assert_eq!(store.lookup(Field::Email, &["user30@test.com"])?.len(), 1);
+    
+    Ok(())
+}
+
+#[test]
+fn test_shards() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let paths = ["a", "b", "c", "d"].map(|name| temp_dir.path().join(name));
+    let ids = |users: Vec<User>| users.iter().map(|user| user.id).collect::<Vec<_>>();
+    
+    {
+        let shards = Shards::new(&paths[..3])?;
+        assert_eq!(shards.len(), 3);
+        shards.batch(&(1..=150).map(create_test_user).collect::<Vec<_>>())?;
+        for id in 151..=300 {
+            shards.save(&create_test_user(id))?;
+        }
+        shards.delete(300)?;
+        
+        // Every shard takes a share, and each user lives on its owner only
+        for at in 0..3 {
+            let held = shards.shard(at).unwrap().count()?;
+            assert!((50..150).contains(&held), "shard {} holds {}", at, held);
+        }
+        assert_eq!(shards.count()?, 299);
+        assert_eq!(shards.stats()?.records, 299);
+        let mut scanned = Vec::new();
+        shards.scan(|user| {
+            scanned.push(user.id);
+            Ok(())
+        })?;
+        scanned.sort_unstable();
+        assert_eq!(scanned, (1..300).collect::<Vec<_>>());
+        assert!(matches!(shards.scan(|_| Err(Error::Closed)), Err(Error::Closed)));
+        assert_eq!(shards.find(7)?.unwrap().email, "user7@test.com");
+        assert!(!shards.contains(300)?);
+        assert!(shards.shard(shards.locate(7)).unwrap().contains(7)?);
+        assert_eq!(ids(shards.lookup(Field::Email, &["user9@test.com", "user200@test.com"])?), vec![9, 200]);
+        assert_eq!(shards.query(&[(Field::Country, "Test Country")])?.len(), 299);
+        assert_eq!(shards.compact(Config::default())?.len(), 3);
+        shards.close()?;
+    }
+    
+    // Order does not change placement; a new shard claims a share only
+    let shards = Shards::new(&[&paths[2], &paths[0], &paths[1], &paths[3]])?;
+    let moved = shards.rebalance()?;
+    assert!((30..150).contains(&moved), "moved {}", moved);
+    assert_eq!(shards.rebalance()?, 0);
+    assert_eq!(shards.count()?, 299);
+    for id in 1..300 {
+        assert_eq!(shards.find(id)?.map(|user| user.id), Some(id));
+    }
+    
+    // Ordered answers from every shard merge into one order
+    let mut users = (1..300).map(|id| shards.find(id).map(Option::unwrap)).collect::<Result<Vec<_>>>()?;
+    for user in &mut users {
+        user.created = 1_000 - user.id % 97;
+        user.location.point = Some(Point { latitude: 21.0 + user.id as f64 / 1_000.0, longitude: 105.8 });
+    }
+    shards.batch(&users)?;
+    let created = shards.created(0, 950)?;
+    assert_eq!(created.len(), (1..300).filter(|id| id % 97 >= 50).count());
+    assert!(created.windows(2).all(|pair| (pair[0].created, pair[0].id) < (pair[1].created, pair[1].id)));
+    assert_eq!(shards.updated(0)?.len(), 299);
+    let near = shards.near(21.0, 105.8, 5_000.0)?;
+    assert_eq!(ids(near), (1..=44).collect::<Vec<_>>());
+    
+    assert!(matches!(Shards::new(&[temp_dir.path().join("x/a"), temp_dir.path().join("y/a")]), Err(Error::Config(_))));
+    assert!(matches!(Shards::new::<&Path>(&[]), Err(Error::Config(_))));
+    
+    Ok(())
+}
+
+#[test]
+fn test_raw() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let entries = |raw: &Raw, range: (Bound<&[u8]>, Bound<&[u8]>)| {
+        raw.range(range).map(|entry| entry.map(|(key, _)| String::from_utf8(key).unwrap())).collect::<Result<Vec<_>>>()
+    };
+    
+    {
+        let store = Store::builder().path(temp_dir.path()).segment(4096).compression(Compression::Lz4).open()?;
+        store.save(&create_test_user(1))?;
+        let mut raw = store.raw()?;
+        for n in 0..100 {
+            raw.put(format!("order/{:03}", n).as_bytes(), &[n as u8; 100])?;
+        }
+        raw.put(b"user/1", b"not a user")?;
+        raw.put(b"order/007", b"replaced")?;
+        assert!(raw.delete(b"order/008")?);
+        assert!(!raw.delete(b"order/008")?);
+        assert!(matches!(raw.put(b"", b"empty"), Err(Error::Invalid { .. })));
+        
+        assert_eq!(raw.get(b"order/007")?.as_deref(), Some(b"replaced".as_slice()));
+        assert_eq!(raw.get(b"order/009")?, Some(vec![9; 100]));
+        assert_eq!(raw.get(b"order/008")?, None);
+        assert_eq!(
+            entries(&raw, (Bound::Included(b"order/006"), Bound::Excluded(b"order/010")))?,
+            vec!["order/006", "order/007", "order/009"],
+        );
+        assert_eq!(entries(&raw, (Bound::Excluded(b"order/098"), Bound::Unbounded))?, vec!["order/099", "user/1"]);
+        assert_eq!(raw.range(..).count(), 100);
+    }
+    
+    // Raw entries survive a reopen and stay out of the user side
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.count()?, 1);
+    assert_eq!(store.scan().count(), 1);
+    let mut raw = store.raw()?;
+    assert_eq!(raw.get(b"user/1")?.as_deref(), Some(b"not a user".as_slice()));
+    
+    // Overwriting most values leaves segments to compact
+    for n in 0..90 {
+        raw.put(format!("order/{:03}", n).as_bytes(), b"small")?;
+    }
+    assert!(raw.compact(0.5)? > 0);
+    assert_eq!(raw.get(b"order/095")?, Some(vec![95; 100]));
+    assert_eq!(raw.get(b"order/001")?.as_deref(), Some(b"small".as_slice()));
+    // The deleted key was written again
+    assert_eq!(raw.range(..).count(), 101);
+    
+    Ok(())
+}
+
+#[test]
+fn test_field_encryption() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let key = [7u8; 32];
+    // Every byte the store keeps on disk, segments and indexes alike
+    fn disk(path: &Path, bytes: &mut Vec<u8>) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(path)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                disk(&path, bytes)?;
+            } else {
+                bytes.extend(std::fs::read(path)?);
+            }
+        }
+        Ok(())
+    }
+    let holds = |needle: &[u8]| -> Result<bool> {
+        let mut bytes = Vec::new();
+        disk(temp_dir.path(), &mut bytes)?;
+        Ok(bytes.windows(needle.len()).any(|window| window == needle))
+    };
+    
+    {
+        let store = Store::builder().path(temp_dir.path()).cipher(key).open()?;
+        store.save(&create_test_user(1))?;
+        store.save(&create_test_user(2))?;
+        assert_eq!(store.find(1)?.unwrap().email, "user1@test.com");
+        assert_eq!(store.lookup(Field::Email, &["user2@test.com"])?.len(), 1);
+        assert_eq!(store.query(&[(Field::Country, "Test Country")])?.len(), 2);
+        
+        let mut taken = create_test_user(3);
+        taken.email = "user1@test.com".to_string();
+        assert!(matches!(store.save(&taken), Err(Error::Duplicate { holder: 1, .. })));
+        store.close()?;
+    }
+    assert!(!holds(b"user1@test.com")?);
+    assert!(holds(b"Test City")?);
+    
+    // The key is required from now on, and must be the same one
+    assert!(matches!(Store::new(temp_dir.path()), Err(Error::Config(_))));
+    assert!(matches!(Store::builder().path(temp_dir.path()).cipher([8u8; 32]).open(), Err(Error::Config(_))));
+    let store = Store::builder().path(temp_dir.path()).cipher(key).open()?;
+    assert_eq!(store.find(2)?.unwrap().email, "user2@test.com");
+    assert_eq!(store.lookup(Field::Email, &["user1@test.com"])?.iter().map(|user| user.id).collect::<Vec<_>>(), vec![1]);
+    drop(store);
+    
+    // A plaintext store takes a key later; old records stay readable
+    let other = TempDir::new()?;
+    {
+        let store = Store::new(other.path())?;
+        store.save(&create_test_user(4))?;
+        store.close()?;
+    }
+    let store = Store::builder().path(other.path()).cipher(key).open()?;
+    assert_eq!(store.find(4)?.unwrap().email, "user4@test.com");
+    assert_eq!(store.lookup(Field::Email, &["user4@test.com"])?.len(), 1);
+    let mut taken = create_test_user(5);
+    taken.email = "user4@test.com".to_string();
+    assert!(matches!(store.save(&taken), Err(Error::Duplicate { holder: 4, .. })));
+    Ok(())
+}
+
+#[test]
+fn test_rewrap() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (old, new) = ([1u8; 32], [2u8; 32]);
+    let open = |keys: &[[u8; 32]]| {
+        let mut builder = Store::builder().path(temp_dir.path()).segment(1024);
+        if let Some((current, former)) = keys.split_last() {
+            builder = former.iter().fold(builder.cipher(*current), |builder, key| builder.former(*key));
+        }
+        builder.open()
+    };
+    
+    {
+        let store = open(&[old])?;
+        for id in 1..=20 {
+            store.save(&create_test_user(id))?;
+        }
+        assert!(store.stale()?.is_empty());
+        
+        store.rewrap(new)?;
+        assert!(matches!(store.rewrap(old), Err(Error::Invalid { .. })));
+        store.save(&create_test_user(21))?;
+        assert!(!store.stale()?.is_empty());
+        assert_eq!(store.find(3)?.unwrap().email, "user3@test.com");
+        assert_eq!(store.lookup(Field::Email, &["user3@test.com", "user21@test.com"])?.len(), 2);
+        let mut taken = create_test_user(22);
+        taken.email = "user4@test.com".to_string();
+        assert!(matches!(store.save(&taken), Err(Error::Duplicate { holder: 4, .. })));
+        store.close()?;
+    }
+    
+    // Segments sealed under the old key need it until rewritten
+    assert!(matches!(open(&[new]), Err(Error::Config(_))));
+    assert!(matches!(open(&[new, old]), Err(Error::Config(_))));
+    {
+        let store = open(&[old, new])?;
+        assert_eq!(store.find(1)?.unwrap().email, "user1@test.com");
+        assert_eq!(store.find(21)?.unwrap().email, "user21@test.com");
+        let stale = store.stale()?;
+        store.rewrite(&stale, Config { throttle: false, ..Config::default() })?;
+        assert!(store.stale()?.is_empty());
+        store.close()?;
+    }
+    
+    let store = open(&[new])?;
+    assert_eq!(store.count()?, 21);
+    assert_eq!(store.find(1)?.unwrap().email, "user1@test.com");
+    assert_eq!(store.lookup(Field::Email, &["user2@test.com"])?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_unique_email() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    assert_eq!(User::UNIQUE, &["email"]);
+    let with = |id: u64, email: &str| User { email: email.to_string(), ..create_test_user(id) };
+    
+    {
+        let store = Store::new(temp_dir.path())?;
+        store.save(&with(1, "a@test.com"))?;
+        store.save(&with(2, "b@test.com"))?;
+        
+        // Another user's email is refused and nothing is written
+        let error = store.save(&with(3, "a@test.com")).unwrap_err();
+        assert_eq!(error.kind(), Kind::Conflict);
+        assert!(matches!(error, Error::Duplicate { ref field, holder: 1, .. } if field == "email"));
+        assert!(!store.contains(3)?);
+        assert!(matches!(store.save(&with(2, "a@test.com")), Err(Error::Duplicate { .. })));
+        assert_eq!(store.find(2)?.unwrap().email, "b@test.com");
+        
+        // A user keeps its own email, and frees it by changing or deleting
+        store.save(&with(1, "a@test.com"))?;
+        store.save(&with(1, "c@test.com"))?;
+        store.save(&with(3, "a@test.com"))?;
+        store.delete(3)?;
+        store.save(&with(4, "a@test.com"))?;
+        
+        // Batches are judged by their final values
+        assert!(matches!(store.batch(&[with(5, "d@test.com"), with(6, "d@test.com")]), Err(Error::Duplicate { .. })));
+        assert!(!store.contains(5)?);
+        store.batch(&[with(1, "b@test.com"), with(2, "c@test.com")])?;
+        store.batch(&[with(5, "e@test.com"), with(5, "f@test.com"), with(6, "e@test.com")])?;
+        
+        // Empty emails count as none
+        store.save(&with(7, ""))?;
+        store.save(&with(8, ""))?;
+        
+        let found = store.lookup(Field::Email, &["b@test.com", "e@test.com"])?;
+        assert_eq!(found.iter().map(|user| user.id).collect::<Vec<_>>(), vec![1, 6]);
+    }
+    
+    // A store predating the unique index gets it built when opened
+    for entry in std::fs::read_dir(temp_dir.path().join("catalog"))? {
+        let path = entry?.path();
+        if path.file_name().is_some_and(|name| name.to_string_lossy().starts_with("unique")) {
+            std::fs::remove_file(path)?;
+        }
+    }
+    let store = Store::new(temp_dir.path())?;
+    assert!(matches!(store.save(&with(9, "f@test.com")), Err(Error::Duplicate { holder: 5, .. })));
+    
+    Ok(())
+}
+
+#[test]
+fn test_audit_log() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let trail = |entries: Vec<Entry>| entries.into_iter()
+        .map(|entry| (entry.actor, entry.action, entry.revision))
+        .collect::<Vec<_>>();
+    
+    {
+        let mut store = Store::builder().path(temp_dir.path()).audit("alice").open()?;
+        store.save(&create_test_user(1))?;
+        store.batch(&[create_test_user(1), create_test_user(2)])?;
+        store.actor("bob");
+        store.delete(1)?;
+        // Deleting a missing user changes nothing, so is not audited
+        store.delete(3)?;
+        
+        assert_eq!(trail(store.audit(1)?), vec![
+            ("alice".to_string(), Action::Save, 1),
+            ("alice".to_string(), Action::Save, 2),
+            ("bob".to_string(), Action::Delete, 2),
+        ]);
+        assert_eq!(trail(store.audit(2)?), vec![("alice".to_string(), Action::Save, 1)]);
+        assert!(store.audit(3)?.is_empty());
+        assert!(store.audit(1)?.iter().all(|entry| entry.id == 1 && entry.time > 0));
+        store.close()?;
+    }
+    
+    // The trail survives reopening and keeps growing
+    {
+        let store = Store::builder().path(temp_dir.path()).audit("carol").open()?;
+        store.save(&create_test_user(2))?;
+        assert_eq!(store.audit(1)?.len(), 3);
+        assert_eq!(trail(store.audit(2)?).last(), Some(&("carol".to_string(), Action::Save, 2)));
+    }
+    
+    // Without auditing nothing is logged or readable
+    let store = Store::new(temp_dir.path())?;
+    store.save(&create_test_user(4))?;
+    assert!(matches!(store.audit(2), Err(Error::Config(_))));
+    let store = Store::builder().path(temp_dir.path()).audit("dave").open()?;
+    assert!(store.audit(4)?.is_empty());
+    
+    Ok(())
+}
+
+#[test]
+fn test_interrupted_upgrade() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let legacy = |id: u64| legacy::first::User {
+        id,
+        name: format!("User {}", id),
+        email: format!("user{}@test.com", id),
+        location: legacy::first::Location {
+            street: "Old Street".to_string(),
+            city: "Old City".to_string(),
+            country: "Old Country".to_string(),
+            postal: "00000".to_string(),
+        },
+        profile: None,
+        created: id,
+        updated: id,
+    };
+    
+    // An upgrade cut short: user 1 rewritten and recorded, user 2
+    // rewritten after the last saved progress, user 3 untouched
+    {
+        let segments = temp_dir.path().join("segments");
+        let mut index = Index::new(temp_dir.path().join("index"))?;
+        let segment = Segment::new(&segments)?;
+        for id in 1..=3u64 {
+            // Earlier layouts were always plain rkyv archives
+            let bytes = rkyv::to_bytes::<_, 1024>(&legacy(id)).unwrap().into_vec();
+            index.put(&id.to_le_bytes(), segment.push(vec![bytes], u64::MAX)?[0])?;
+        }
+        segment.seal()?;
+        
+        let segment = Segment::restore(&segments, vec![1], Default::default(), Vec::new())?;
+        for id in 1..=2u64 {
+            let user: User = legacy(id).into();
+            index.put(&id.to_le_bytes(), segment.append(&user)?)?;
+        }
+        segment.seal()?;
+        index.sync()?;
+        
+        let manifest = Manifest {
+            segments: vec![1, 2],
+            generation: index.generation(),
+            schema: 1,
+            upgrade: Some(Upgrade { watermark: Some(1u64.to_le_bytes().to_vec()), records: 1, segment: 2 }),
+            ..Manifest::default()
+        };
+        manifest.save(temp_dir.path())?;
+    }
+    
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.manifest().schema, SCHEMA);
+    assert!(store.manifest().upgrade.is_none());
+    for id in 1..=3 {
+        assert_eq!(store.find(id)?.unwrap().location.city, "Old City");
+    }
+    
+    Ok(())
+}
+
+#[test]
+fn test_legacy_upgrade() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    
+    // A store written before coordinates existed, without a manifest
+    {
+        let segment = Segment::new(temp_dir.path().join("segments"))?;
+        let mut index = Index::new(temp_dir.path().join("index"))?;
+        for id in 1..=3 {
+            let user = legacy::first::User {
+                id,
+                name: format!("User {}", id),
+                email: format!("user{}@test.com", id),
+                location: legacy::first::Location {
+                    street: "Old Street".to_string(),
+                    city: "Old City".to_string(),
+                    country: "Old Country".to_string(),
+                    postal: "00000".to_string(),
+                },
+                profile: None,
+                created: id,
+                updated: id,
+            };
+            // Earlier layouts were always plain rkyv archives
+            let bytes = rkyv::to_bytes::<_, 1024>(&user).unwrap().into_vec();
+            let position = segment.push(vec![bytes], u64::MAX)?[0];
+            index.put(&id.to_le_bytes(), position)?;
+        }
+        segment.seal()?;
+    }
+    
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.manifest().schema, SCHEMA);
+    
+    let user = store.find(2)?.unwrap();
+    assert_eq!(user.location.city, "Old City");
+    assert!(user.location.point.is_none());
+    assert_eq!(store.scan().count(), 3);
+    assert_eq!(store.created(2, 3)?.len(), 2);
+    
+    Ok(())
+}
+
+#[test]
+fn test_migration_plan() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let segments = temp_dir.path().join("segments");
+    {
+        let segment = Segment::new(&segments)?;
+        let mut index = Index::new(temp_dir.path().join("index"))?;
+        for id in 1..=3 {
+            let user = legacy::first::User {
+                id,
+                name: format!("User {}", id),
+                email: format!("user{}@test.com", id),
+                location: legacy::first::Location {
+                    street: "Old Street".to_string(),
+                    city: "Old City".to_string(),
+                    country: "Old Country".to_string(),
+                    postal: "00000".to_string(),
+                },
+                profile: None,
+                created: id,
+                updated: id,
+            };
+            let bytes = rkyv::to_bytes::<_, 1024>(&user).unwrap().into_vec();
+            let position = segment.push(vec![bytes], u64::MAX)?[0];
+            index.put(&id.to_le_bytes(), position)?;
+        }
+        segment.seal()?;
+    }
+    
+    // Held, opening records the manifest but leaves the records alone
+    let held = Store::builder().path(temp_dir.path()).hold(true).open();
+    assert!(matches!(held, Err(Error::Unsupported(_))));
+    let plan = migration::plan(temp_dir.path(), SCHEMA)?;
+    assert_eq!((plan.from, plan.to, plan.records, plan.done), (1, SCHEMA, 3, 0));
+    assert_eq!(plan.segments.len(), 1);
+    assert!(plan.bytes > 0);
+    assert!(plan.changes.iter().any(|change| matches!(change, Change::Added { path, .. } if path == "Location.point")));
+    assert!(matches!(migration::plan(temp_dir.path(), 2), Err(Error::Unsupported(_))));
+    
+    // A run cut short after rewriting user 1 but before saving its watermark
+    {
+        let segment = Segment::new(&segments)?;
+        let mut index = Index::new(temp_dir.path().join("index"))?;
+        let mut user = create_test_user(1);
+        user.location.city = "New City".to_string();
+        let moved = segment.append(&user)?;
+        segment.seal()?;
+        index.put(&1u64.to_le_bytes(), moved)?;
+        
+        let mut manifest = Manifest::load(temp_dir.path())?.unwrap();
+        manifest.upgrade = Some(Upgrade { watermark: None, records: 0, segment: moved.segment });
+        manifest.segments.push(moved.segment);
+        manifest.tallies.get_mut(&1).unwrap().live = 2;
+        manifest.save(temp_dir.path())?;
+    }
+    let plan = migration::plan(temp_dir.path(), SCHEMA)?;
+    assert_eq!((plan.records, plan.segments.len()), (2, 1));
+    
+    // Resuming skips what was rewritten instead of reading it as schema 1
+    let store = Store::new(temp_dir.path())?;
+    store.migrate(SCHEMA)?;
+    assert!(matches!(store.migrate(2), Err(Error::Unsupported(_))));
+    assert_eq!(store.find(1)?.unwrap().location.city, "New City");
+    assert_eq!(store.find(3)?.unwrap().location.city, "Old City");
+    let manifest = store.manifest();
+    assert_eq!((manifest.schema, manifest.upgrade), (SCHEMA, None));
+    store.close()?;
+    assert!(migration::plan(temp_dir.path(), SCHEMA)?.current());
+    
+    Ok(())
+}
+
+#[test]
+fn test_count_and_aggregate() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    
+    for id in 1..=6 {
+        let mut user = create_test_user(id);
+        // Users 5 and 6 have no profile
+        if id <= 4 {
+            user.profile = Some(Profile {
+                age: 20 + id as u32 * 5,
+                job: "Engineer".to_string(),
+                interests: vec![],
+            });
+        }
+        store.save(&user)?;
+    }
+    store.delete(1)?;
+    
+    assert_eq!(store.count()?, 5);
+    assert_eq!(store.census(|user| user.profile.is_some())?, 3);
+    assert_eq!(store.census(|user| user.name.as_str() == "User 6")?, 1);
+    
+    let ages = store.aggregate(|user| user.profile.as_ref().map(|profile| profile.age as f64))?;
+    assert_eq!(ages.count, 3);
+    assert_eq!(ages.min, Some(30.0));
+    assert_eq!(ages.max, Some(40.0));
+    assert_eq!(ages.mean(), Some(35.0));
+    
+    let none = store.aggregate(|_| None)?;
+    assert_eq!(none.count, 0);
+    assert_eq!(none.mean(), None);
+    
+    Ok(())
+}
+
+#[test]
+fn test_record_history() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    
+    let mut user = create_test_user(1);
+    for version in 1..=4 {
+        user.name = format!("Version {}", version);
+        user.updated = version;
+        store.save(&user)?;
+    }
+    store.save(&create_test_user(2))?;
+    
+    let names = |users: Vec<User>| users.into_iter().map(|user| user.name).collect::<Vec<_>>();
+    assert_eq!(names(store.history(1)?), vec!["Version 1", "Version 2", "Version 3"]);
+    assert!(store.history(2)?.is_empty());
+    
+    // Deleting keeps the last version in the history
+    store.delete(1)?;
+    assert_eq!(store.history(1)?.len(), 4);
+    
+    // Retention trims on the next write, and everywhere on collect
+    store.retain(Retention { versions: Some(2), ..Retention::default() })?;
+    let report = store.collect(true)?;
+    assert_eq!(report.versions, 2);
+    store.collect(false)?;
+    assert_eq!(names(store.history(1)?), vec!["Version 3", "Version 4"]);
+    
+    drop(store);
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.manifest().retention.versions, Some(2));
+    assert_eq!(store.history(1)?.len(), 2);
+    
+    Ok(())
+}
+
+#[test]
+fn test_retention_policy() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    let stamped = |id: u64, updated: u64| User { updated, ..create_test_user(id) };
+    
+    for _ in 0..3 {
+        store.save(&stamped(1, now))?;
+    }
+    store.save(&stamped(2, 1))?;
+    store.save(&stamped(4, now))?;
+    store.delete(4)?;
+    assert_eq!(store.history(1)?.len(), 2);
+    assert_eq!(store.history(4)?.len(), 1);
+    
+    store.retain(Retention { versions: Some(1), records: Some(3600), purge: true, ..Retention::default() })?;
+    store.save(&stamped(3, now))?;
+    store.save(&stamped(3, now))?;
+    store.delete(3)?;
+    assert!(store.history(3)?.is_empty());
+    
+    // Compaction enforces the rest and reports it
+    let state = store.compact(Config { throttle: false, ..Config::default() })?;
+    assert_eq!((state.purged.records, state.purged.versions), (1, 2));
+    assert!(state.purged.time >= now);
+    assert!(store.find(2)?.is_none());
+    assert!(store.find(1)?.is_some());
+    assert_eq!(store.history(1)?.len(), 1);
+    assert!(store.history(4)?.is_empty());
+    
+    let purge = store.enforce()?;
+    assert_eq!((purge.records, purge.versions), (0, 0));
+    Ok(())
+}
+
+#[test]
+fn test_shared_writes() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Arc::new(Store::new(temp_dir.path())?);
+    store.save(&create_test_user(1000))?;
+    
+    let workers: Vec<_> = (0..4u64)
+        .map(|worker| {
+            let store = Arc::clone(&store);
+            std::thread::spawn(move || -> Result<()> {
+                for n in 0..25 {
+                    store.save(&create_test_user(worker * 100 + n))?;
+                    // Read-modify-write on a shared record, retried on conflict
+                    loop {
+                        let mut user = store.find(1000)?.expect("shared user");
+                        user.created += 1;
+                        match store.commit(&user, user.revision) {
+                            Err(Error::Conflict { .. }) => continue,
+                            outcome => break outcome.map(|_| ())?,
+                        }
+                    }
+                }
+                Ok(())
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().expect("writer panicked")?;
+    }
+    
+    assert_eq!(store.count()?, 101);
+    let shared = store.find(1000)?.expect("shared user");
+    assert_eq!(shared.revision, 101);
+    assert_eq!(shared.created, store.history(1000)?[0].created + 100);
+    assert_eq!(store.lookup(Field::Email, &["user325@test.com"])?.len(), 0);
+    assert_eq!(store.lookup(Field::Email, &["user324@test.com"])?.len(), 1);
+    Ok(())
+}
+
+#[test]
+fn test_absence() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    
+    assert!(matches!(store.fetch(1), Err(Error::Missing(_))));
+    assert!(matches!(store.update(&create_test_user(1)), Err(Error::Missing(_))));
+    assert!(!store.contains(1)?);
+    
+    store.save(&create_test_user(1))?;
+    store.update(&create_test_user(1))?;
+    assert_eq!(store.fetch(1)?.revision, 2);
+    
+    assert_eq!(store.delete(1)?.map(|user| user.revision), Some(2));
+    assert!(store.delete(1)?.is_none());
+    let error = store.fetch(1).unwrap_err();
+    assert!(matches!(error, Error::Deleted { id: 1 }));
+    assert_eq!(error.kind(), Kind::Missing);
+    assert!(matches!(store.update(&create_test_user(1)), Err(Error::Deleted { id: 1 })));
+    
+    // Erased users leave no trace to tell them apart
+    store.save(&create_test_user(2))?;
+    store.erase(2)?;
+    assert!(matches!(store.fetch(2), Err(Error::Missing(_))));
+    Ok(())
+}
+
+#[test]
+fn test_previous() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    let named = |name: &str| User { name: name.to_string(), ..create_test_user(1) };
+    
+    assert!(store.save(&named("First"))?.is_none());
+    let replaced = store.save(&named("Second"))?.expect("first version");
+    assert_eq!((replaced.name.as_str(), replaced.revision), ("First", 1));
+    let replaced = store.update(&named("Third"))?.expect("second version");
+    assert_eq!((replaced.name.as_str(), replaced.revision), ("Second", 2));
+    
+    let removed = store.delete(1)?.expect("third version");
+    assert_eq!((removed.name.as_str(), removed.revision), ("Third", 3));
+    assert!(store.delete(1)?.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_erase() -> Result<()> {
+    /// Whether any file under `path` holds `needle`
+    fn holds(path: &Path, needle: &str) -> Result<bool> {
+        if path.is_dir() {
+            for entry in std::fs::read_dir(path)? {
+                if holds(&entry?.path(), needle)? {
+                    return Ok(true);
+                }
+            }
+            return Ok(false);
+        }
+        Ok(std::fs::read(path)?.windows(needle.len()).any(|window| window == needle.as_bytes()))
+    }
+    
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    
+    let mut user = create_test_user(1);
+    for name in ["First", "Second", "Third"] {
+        user.name = name.to_string();
+        store.save(&user)?;
+    }
+    store.save(&create_test_user(2))?;
+    assert_eq!(store.history(1)?.len(), 2);
+    assert!(holds(temp_dir.path(), "user1@test.com")?);
+    
+    // Refused inside a runtime before anything changes
+    let runtime = tokio::runtime::Runtime::new()?;
+    assert!(matches!(runtime.block_on(async { store.erase(1) }), Err(Error::Config(_))));
+    assert_eq!(store.find(1)?.map(|user| user.name).as_deref(), Some("Third"));
+    assert_eq!(store.history(1)?.len(), 2);
+    
+    let certificate = store.erase(1)?;
+    assert_eq!((certificate.id, certificate.copies), (1, 3));
+    assert!(!certificate.segments.is_empty());
+    assert!(certificate.verify());
+    assert!(store.find(1)?.is_none());
+    assert!(store.history(1)?.is_empty());
+    // Not in the segments, nor in any index log or table
+    assert!(!holds(temp_dir.path(), "user1@test.com")?);
+    assert!(holds(temp_dir.path(), "user2@test.com")?);
+    assert_eq!(store.find(2)?.map(|user| user.email).as_deref(), Some("user2@test.com"));
+    
+    // A tampered certificate no longer verifies
+    let forged = guardian_store::erasure::Certificate { copies: 0, ..certificate };
+    assert!(!forged.verify());
+    
+    // Survives a reopen, with nothing left to erase
+    drop(store);
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.find(2)?.map(|user| user.id), Some(2));
+    assert_eq!(store.erase(1)?.copies, 0);
+    Ok(())
+}
+
+#[test]
+fn test_erase_indexes() -> Result<()> {
+    /// Whether any file under `path` holds `needle`
+    fn holds(path: &Path, needle: &[u8]) -> Result<bool> {
+        if path.is_dir() {
+            for entry in std::fs::read_dir(path)? {
+                if holds(&entry?.path(), needle)? {
+                    return Ok(true);
+                }
+            }
+            return Ok(false);
+        }
+        Ok(std::fs::read(path)?.windows(needle.len()).any(|window| window == needle))
+    }
+    
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    let id = 0x5EC2_E7AB_CDEF_0123;
+    let mut user = create_test_user(id);
+    user.location.point = Some(Point { latitude: 21.03, longitude: 105.85 });
+    store.save(&user)?;
+    store.save(&create_test_user(2))?;
+    let traces = |path: &Path| -> Result<bool> {
+        Ok(holds(path, &id.to_le_bytes())? || holds(path, &id.to_be_bytes())?)
+    };
+    assert!(traces(temp_dir.path())?);
+    
+    // Neither the primary index nor any secondary one keeps the ID
+    store.erase(id)?;
+    assert!(!traces(temp_dir.path())?);
+    assert!(store.find(id)?.is_none());
+    assert!(store.find(2)?.is_some());
+    assert!(store.near(21.03, 105.85, 1.0)?.is_empty());
+    
+    drop(store);
+    let store = Store::new(temp_dir.path())?;
+    assert!(store.find(id)?.is_none());
+    assert_eq!(store.count()?, 1);
+    Ok(())
+}
+
+#[test]
+fn test_recall() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder().path(temp_dir.path()).audit("tester").open()?;
+    
+    let mut user = create_test_user(1);
+    for (write, updated) in [100, 200, 200, 300].into_iter().enumerate() {
+        user.name = format!("At {} #{}", updated, write);
+        user.updated = updated;
+        store.save(&user)?;
+    }
+    let name = |user: Option<User>| user.map(|user| user.name);
+    assert_eq!(name(store.recall(1, 99)?), None);
+    assert_eq!(name(store.recall(1, 100)?).as_deref(), Some("At 100 #0"));
+    assert_eq!(name(store.recall(1, 250)?).as_deref(), Some("At 200 #2"));
+    assert_eq!(name(store.recall(1, 300)?).as_deref(), Some("At 300 #3"));
+    assert_eq!(name(store.recall(2, 300)?), None);
+    
+    // The audit log tells a deleted user from one still current
+    store.delete(1)?;
+    assert_eq!(name(store.recall(1, 300)?).as_deref(), Some("At 300 #3"));
+    assert_eq!(name(store.recall(1, u64::MAX)?), None);
+    
+    Ok(())
+}
+
+#[test]
+fn test_revisions() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    
+    // Revisions are assigned by the store, whatever the caller passes
+    let mut user = create_test_user(1);
+    user.revision = 42;
+    store.save(&user)?;
+    assert_eq!(store.find(1)?.unwrap().revision, 1);
+    store.update(&user)?;
+    assert_eq!(store.find(1)?.unwrap().revision, 2);
+    
+    // Two writers read revision 2; only the first commit wins
+    let mut first = store.find(1)?.unwrap();
+    let mut second = first.clone();
+    first.name = "First".to_string();
+    second.name = "Second".to_string();
+    assert_eq!(store.commit(&first, first.revision)?, 3);
+    assert!(matches!(store.commit(&second, second.revision), Err(Error::Conflict { .. })));
+    assert_eq!(store.find(1)?.unwrap().name, "First");
+    
+    // Revision 0 means the record must not exist yet
+    assert!(matches!(store.commit(&create_test_user(1), 0), Err(Error::Conflict { .. })));
+    assert_eq!(store.commit(&create_test_user(2), 0)?, 1);
+    
+    // Batches continue each record's sequence, including repeats
+    store.batch(&[create_test_user(2), create_test_user(2), create_test_user(3)])?;
+    assert_eq!(store.find(2)?.unwrap().revision, 3);
+    assert_eq!(store.find(3)?.unwrap().revision, 1);
+    
+    Ok(())
+}
+
+#[test]
+fn test_store_builder() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    
+    assert!(matches!(Store::builder().open(), Err(Error::Config(_))));
+    
+    {
+        let store = Store::builder()
+            .path(temp_dir.path())
+            .segment(1024)
+            .cache(4096)
+            .durability(Durability::Sync)
+            .compression(Compression::Lz4)
+            .open()?;
+        
+        for id in 1..=20 {
+            store.save(&create_test_user(id))?;
+        }
+        
+        // Small segments rotate often
+        assert!(store.stats()?.segments > 1);
+        assert_eq!(store.find(7)?.unwrap().name, "User 7");
+    }
+    
+    // Compressed records stay readable whatever the current setting
+    let store = Store::new(temp_dir.path())?;
+    store.save(&create_test_user(21))?;
+    assert_eq!(store.scan().count(), 21);
+    assert_eq!(store.find(20)?.unwrap().email, "user20@test.com");
+    
+    Ok(())
+}
+
+#[test]
+fn test_compression_cutoff() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let user = create_test_user(1);
+    let plain = Segment::new(temp_dir.path().join("plain"))?.append(&user)?.length as usize;
+    
+    // Records under the cutoff are stored as archived
+    let mut lengths = Vec::new();
+    for cutoff in [0, plain, plain + 1] {
+        let segment = Segment::new(temp_dir.path().join(format!("cutoff{}", cutoff)))?
+            .compression(Compression::Lz4)
+            .cutoff(cutoff);
+        let position = segment.append(&user)?;
+        assert_eq!(segment.read(position)?.email, user.email);
+        lengths.push(position.length as usize);
+    }
+    assert!(lengths[0] < plain, "{:?}", lengths);
+    assert_eq!(lengths[1], lengths[0]);
+    assert_eq!(lengths[2], plain);
+    
+    // Compression never grows a record, even one of mostly noise
+    let segment = Segment::new(temp_dir.path().join("noise"))?.compression(Compression::Lz4).cutoff(0);
+    let mut noise = create_test_user(2);
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    noise.name = (0..512).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        char::from(b'!' + (state % 94) as u8)
+    }).collect();
+    let position = segment.append(&noise)?;
+    let archived = Segment::new(temp_dir.path().join("raw"))?.append(&noise)?;
+    assert!(position.length <= archived.length);
+    assert_eq!(segment.read(position)?.name, noise.name);
+    
+    Ok(())
+}
+
+#[test]
+fn test_dictionary_compression() -> Result<()> {
+    let mut sizes = Vec::new();
+    for compression in [Compression::None, Compression::Lz4, Compression::Dictionary] {
+        let temp_dir = TempDir::new()?;
+        {
+            let store = Store::builder()
+                .path(temp_dir.path())
+                .segment(8192)
+                .compression(compression)
+                .open()?;
+            for id in 1..=200u64 {
+                let mut user = create_test_user(id);
+                user.location.street = format!("{} Nguyen Hue Boulevard", id);
+                user.location.city = ["Ho Chi Minh City", "Ha Noi"][(id % 2) as usize].to_string();
+                user.location.country = "Viet Nam".to_string();
+                store.save(&user)?;
+            }
+            assert!(store.stats()?.segments > 1);
+            assert_eq!(store.find(7)?.unwrap().location.city, "Ha Noi");
+        }
+        
+        // Dictionaries are read back from the segments themselves
+        let store = Store::new(temp_dir.path())?;
+        assert_eq!(store.find(8)?.unwrap().location.city, "Ho Chi Minh City");
+        let mut archives = store.archived();
+        let mut count = 0;
+        while let Some(user) = archives.advance() {
+            assert_eq!(user?.location.country.as_str(), "Viet Nam");
+            count += 1;
+        }
+        assert_eq!(count, 200);
+        
+        let mut bytes = 0;
+        for entry in std::fs::read_dir(temp_dir.path().join("segments"))? {
+            bytes += entry?.metadata()?.len();
+        }
+        sizes.push(bytes);
+    }
+    
+    // Shared strings are stored once per segment
+    assert!(sizes[2] * 2 < sizes[1], "{:?}", sizes);
+    assert!(sizes[1] < sizes[0], "{:?}", sizes);
+    
+    Ok(())
+}
+
+#[test]
+fn test_pin() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder().path(temp_dir.path()).segment(1024).open()?;
+    for id in 1..=20 {
+        store.save(&create_test_user(id))?;
+    }
+    assert!(store.pin(99)?.is_none());
+    
+    let pinned = store.pin(3)?.unwrap();
+    let found = store.find(3)?.unwrap();
+    assert_eq!(pinned.id, 3);
+    assert_eq!(pinned.name.as_str(), found.name);
+    
+    // The guard keeps the revision it pinned, past rewrites and deletes
+    let mut renamed = found.clone();
+    renamed.name = "Renamed".to_string();
+    store.save(&renamed)?;
+    for id in 4..=20 {
+        store.delete(id)?;
+    }
+    store.compact(Config::default())?;
+    store.collect(false)?;
+    assert_eq!(pinned.name.as_str(), found.name);
+    assert_eq!(store.pin(3)?.unwrap().name.as_str(), "Renamed");
+    
+    Ok(())
+}
+
+#[test]
+fn test_fork() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let origin = temp_dir.path().join("origin");
+    let clone = temp_dir.path().join("clone");
+    
+    let store = Store::builder().path(&origin).segment(1024).cache(2048).open()?;
+    for id in 1..=30 {
+        store.save(&create_test_user(id))?;
+    }
+    store.fork(&clone)?;
+    assert!(matches!(store.fork(&clone), Err(Error::Config(_))));
+    
+    // Sealed segments are shared, not copied
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let path = Path::new("segments").join("segment_1.dat");
+        let inode = |base: &Path| std::fs::metadata(base.join(&path)).map(|metadata| metadata.ino());
+        assert_eq!(inode(&origin)?, inode(&clone)?);
+    }
+    
+    // The two stores diverge from here on
+    let fork = Store::builder().path(&clone).segment(1024).open()?;
+    assert_eq!(fork.scan().count(), 30);
+    fork.save(&create_test_user(31))?;
+    fork.delete(1)?;
+    store.save(&create_test_user(32))?;
+    store.update(&create_test_user(2))?;
+    
+    assert!(store.find(31)?.is_none());
+    assert!(store.find(1)?.is_some());
+    assert!(fork.find(32)?.is_none());
+    assert_eq!(fork.find(2)?.unwrap().revision, 1);
+    
+    drop(fork);
+    drop(store);
+    let store = Store::new(&origin)?;
+    assert_eq!(store.scan().count(), 31);
+    assert_eq!(store.find(2)?.unwrap().revision, 2);
+    let fork = Store::new(&clone)?;
+    assert_eq!(fork.scan().count(), 30);
+    assert!(fork.find(31)?.is_some());
+    
+    Ok(())
+}
+
+#[test]
+fn test_error_kinds() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    store.save(&create_test_user(1))?;
+    store.save(&create_test_user(2))?;
+    
+    let conflict = store.commit(&create_test_user(1), 7).unwrap_err();
+    assert_eq!(conflict.kind(), Kind::Conflict);
+    assert!(matches!(conflict, Error::Conflict { id: 1, expected: 7, actual: 1 }));
+    
+    // Cut the last record short
+    let path = temp_dir.path().join("segments").join("segment_1.dat");
+    let length = std::fs::metadata(&path)?.len();
+    std::fs::OpenOptions::new().write(true).open(&path)?.set_len(length - 8)?;
+    
+    let corrupt = store.find(2).unwrap_err();
+    assert_eq!(corrupt.kind(), Kind::Corruption);
+    assert!(matches!(corrupt, Error::Corrupt { segment: 1, .. }));
+    
+    store.close()?;
+    assert_eq!(store.find(1).unwrap_err().kind(), Kind::Closed);
+    
+    Ok(())
+}
+
+#[test]
+fn test_save_over_corrupt() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    {
+        let store = Store::new(temp_dir.path())?;
+        for id in 1..=3u64 {
+            store.save(&create_test_user(id))?;
+            store.save(&create_test_user(id))?;
+        }
+    }
+    let index = Index::new(temp_dir.path().join("index"))?;
+    let path = temp_dir.path().join("segments").join("segment_1.dat");
+    let mut bytes = std::fs::read(&path)?;
+    for id in [2u64, 3] {
+        let position = index.get(&id.to_le_bytes())?.expect("Key should exist");
+        let offset = position.offset as usize;
+        bytes[offset..offset + 4].copy_from_slice(&[0xff, 0xff, 0x00, 0x00]);
+    }
+    std::fs::write(&path, bytes)?;
+    drop(index);
+    
+    // An unreadable record counts as revision 0, for saves and deletes alike
+    let store = Store::new(temp_dir.path())?;
+    assert!(matches!(store.find(2), Err(Error::Corrupt { .. })));
+    let conflict = store.commit(&create_test_user(2), 2).unwrap_err();
+    assert!(matches!(conflict, Error::Conflict { id: 2, expected: 2, actual: 0 }));
+    assert!(store.save(&create_test_user(2))?.is_none());
+    assert_eq!(store.find(2)?.unwrap().revision, 1);
+    assert_eq!(store.commit(&create_test_user(2), 1)?, 2);
+    assert!(store.delete(3)?.is_none());
+    assert!(store.find(3)?.is_none());
+    assert_eq!(store.find(1)?.unwrap().revision, 2);
+    
+    Ok(())
+}
+
+#[test]
+fn test_salvage_scan() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    {
+        let store = Store::new(temp_dir.path())?;
+        for id in 1..=3u64 {
+            store.save(&create_test_user(id))?;
+        }
+        store.close()?;
+    }
+    
+    // Overwrite the length prefix of the middle record
+    let position = Index::new(temp_dir.path().join("index"))?
+        .get(&2u64.to_le_bytes())?
+        .expect("Key should exist");
+    let path = temp_dir.path().join("segments").join("segment_1.dat");
+    let mut bytes = std::fs::read(&path)?;
+    let offset = position.offset as usize;
+    bytes[offset..offset + 4].copy_from_slice(&[0xff, 0xff, 0x00, 0x00]);
+    std::fs::write(&path, bytes)?;
+    
+    let store = Store::new(temp_dir.path())?;
+    assert!(store.scan().any(|user| user.is_err()));
+    
+    let mut salvage = store.salvage();
+    let ids: Vec<u64> = salvage.by_ref().map(|user| user.map(|u| u.id)).collect::<Result<_>>()?;
+    assert_eq!(ids, vec![1, 3]);
+    
+    let quarantine = salvage.finish();
+    assert_eq!(quarantine.positions(), vec![position]);
+    assert_eq!(quarantine.records[0].id, 2);
+    assert_eq!(quarantine.records[0].error.kind(), Kind::Corruption);
+    
+    Ok(())
+}
+
+#[test]
+fn test_directory_sync() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    directory::sync(temp_dir.path())?;
+    directory::parent(temp_dir.path().join("MANIFEST"))?;
+    assert_eq!(directory::sync(temp_dir.path().join("missing")).unwrap_err().kind(), Kind::Io);
+    
+    // Every rotation leaves a named, reopenable segment behind
+    {
+        let store = Store::builder().path(temp_dir.path()).segment(1024).open()?;
+        for id in 1..=30u64 {
+            store.save(&create_test_user(id))?;
+        }
+        store.close()?;
+    }
+    
+    let store = Store::new(temp_dir.path())?;
+    let segments = store.manifest().segments.clone();
+    assert!(segments.len() > 1);
+    for id in segments {
+        assert!(temp_dir.path().join("segments").join(format!("segment_{}.dat", id)).exists());
+    }
+    assert_eq!(store.scan().count(), 30);
+    
+    Ok(())
+}
+
+#[test]
+fn test_archived_scan() -> Result<()> {
+    for compression in [Compression::None, Compression::Lz4] {
+        let temp_dir = TempDir::new()?;
+        let store = Store::builder()
+            .path(temp_dir.path())
+            .segment(2048)
+            .compression(compression)
+            .open()?;
+        for id in 1..=50u64 {
+            store.save(&create_test_user(id))?;
+        }
+        
+        let mut archives = store.archived();
+        let mut ids = Vec::new();
+        while let Some(user) = archives.advance() {
+            let user = user?;
+            assert_eq!(user.email.as_str(), format!("user{}@test.com", user.id));
+            ids.push(user.id);
+        }
+        assert_eq!(ids, (1..=50).collect::<Vec<u64>>());
+        assert!(store.stats()?.segments > 1);
+    }
+    
+    Ok(())
+}
+
+#[test]
+fn test_projection() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    
+    let mut user = create_test_user(1);
+    user.profile = Some(Profile {
+        age: 30,
+        job: "Engineer".to_string(),
+        interests: (0..1000).map(|i| format!("interest {}", i)).collect(),
+    });
+    store.save(&user)?;
+    store.save(&create_test_user(2))?;
+    
+    let projection = store.project(1, &[Field::Name, Field::Email])?.expect("User should exist");
+    assert_eq!(projection.id, 1);
+    assert_eq!(projection.name.as_deref(), Some("User 1"));
+    assert_eq!(projection.email.as_deref(), Some("user1@test.com"));
+    assert!(projection.profile.is_none() && projection.location.is_none());
+    assert_eq!(projection.revision, None);
+    
+    let projection = store.project(1, &[Field::Profile, Field::Revision])?.expect("User should exist");
+    assert_eq!(projection.profile.expect("Profile was requested").interests.len(), 1000);
+    assert_eq!(projection.revision, Some(1));
+    assert!(projection.name.is_none());
+    
+    // Requested but absent stays None
+    assert!(store.project(2, &[Field::Profile])?.expect("User should exist").profile.is_none());
+    assert!(store.project(3, &[Field::Name])?.is_none());
+    
+    let json = serde_json::to_value(store.project(2, &[Field::Name])?).unwrap();
+    assert_eq!(json, serde_json::json!({ "id": 2, "name": "User 2" }));
+    
+    Ok(())
+}
+
+#[test]
+fn test_batch_repeats() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder().path(temp_dir.path()).segment(1024).open()?;
+    store.save(&create_test_user(1))?;
+    
+    // The same user three times, across segment boundaries
+    let mut users = Vec::new();
+    for version in 1..=3 {
+        let mut user = create_test_user(1);
+        user.name = format!("Version {}", version);
+        users.push(user);
+        users.extend((10 + version * 10..15 + version * 10).map(create_test_user));
+    }
+    store.batch(&users)?;
+    
+    let user = store.find(1)?.expect("User should exist");
+    assert_eq!(user.name, "Version 3");
+    assert_eq!(user.revision, 4);
+    assert_eq!(store.count()?, 16);
+    
+    let names: Vec<String> = store.history(1)?.into_iter().map(|user| user.name).collect();
+    assert_eq!(names, vec!["User 1", "Version 1", "Version 2"]);
+    
+    let tallies = store.tallies();
+    assert_eq!(tallies.values().map(|tally| tally.dead).sum::<u64>(), 3);
+    
+    Ok(())
+}
+
+#[test]
+fn test_json_codec() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let json = || Store::builder().path(temp_dir.path()).codec(Arc::new(Json));
+    {
+        let store = json().compression(Compression::Lz4).open()?;
+        for id in 1..=10 {
+            store.save(&create_test_user(id))?;
+        }
+        store.update(&create_test_user(3))?;
+        store.close()?;
+    }
+    
+    // Another codec cannot read the records
+    assert!(matches!(Store::new(temp_dir.path()), Err(Error::Config(_))));
+    
+    let store = json().open()?;
+    assert_eq!(store.manifest().codec, "json");
+    assert_eq!(store.find(3)?.unwrap().revision, 2);
+    assert_eq!(store.scan().count(), 10);
+    assert_eq!(store.census(|user| user.location.city.as_str() == "Test City")?, 10);
+    
+    // Archived views are rebuilt from the decoded records
+    let mut archives = store.archived();
+    let mut ids = Vec::new();
+    while let Some(user) = archives.advance() {
+        ids.push(user?.id);
+    }
+    assert_eq!(ids, (1..=10).collect::<Vec<_>>());
+    
+    Ok(())
+}
+
+#[test]
+fn test_schema_registry() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    {
+        let store = Store::new(temp_dir.path())?;
+        let registry = &store.manifest().registry;
+        assert_eq!(registry.versions().collect::<Vec<_>>(), (1..=SCHEMA).collect::<Vec<_>>());
+        assert_eq!(registry, &Registry::builtin());
+        
+        let user = registry.get(SCHEMA).unwrap().fields("User").unwrap();
+        assert_eq!(user.last(), Some(&Member { name: "revision".to_string(), kind: "u64".to_string() }));
+        
+        let changes = registry.changes(1, SCHEMA)?;
+        assert!(changes.contains(&Change::Added { path: "Location.point".to_string(), kind: "Option<Point>".to_string() }));
+        assert!(changes.contains(&Change::Added { path: "User.revision".to_string(), kind: "u64".to_string() }));
+        assert!(registry.changes(SCHEMA, SCHEMA)?.is_empty());
+        assert!(matches!(registry.changes(1, SCHEMA + 1), Err(Error::Unsupported(_))));
+    }
+    
+    // A layout recorded differently means the model changed without a new version
+    let mut manifest = Manifest::load(temp_dir.path())?.unwrap();
+    let mut tampered = serde_json::to_value(&manifest.registry).unwrap();
+    tampered["versions"][SCHEMA.to_string()]["structs"]["User"][0]["kind"] = "u32".into();
+    manifest.registry = serde_json::from_value(tampered).unwrap();
+    manifest.save(temp_dir.path())?;
+    assert!(matches!(Store::new(temp_dir.path()), Err(Error::Format(_))));
+    
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_write_queue() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder()
+        .path(temp_dir.path())
+        .validator(Arc::new(Basic))
+        .open()?;
+    let writer = Arc::new(store.writer(8)?);
+    
+    // Many producers through a queue shallower than their writes
+    let mut producers = Vec::new();
+    for producer in 0..4u64 {
+        let writer = Arc::clone(&writer);
+        producers.push(tokio::spawn(async move {
+            let mut pending = Vec::new();
+            for id in 1..=25 {
+                pending.push(writer.submit(create_test_user(producer * 100 + id)).await?);
+            }
+            for done in pending {
+                done.await?;
+            }
+            Result::Ok(())
+        }));
+    }
+    for producer in producers {
+        producer.await.unwrap()?;
+    }
+    
+    // A refused write fails alone, even when batched with others
+    let mut nameless = create_test_user(500);
+    nameless.name.clear();
+    let refused = writer.submit(nameless).await?;
+    let accepted = writer.submit(create_test_user(501)).await?;
+    assert!(matches!(refused.await, Err(Error::Invalid { .. })));
+    accepted.await?;
+    writer.delete(101).await?;
+    
+    let writer = Arc::into_inner(writer).unwrap();
+    let store = writer.close().await?;
+    assert_eq!(store.count()?, 100);
+    assert!(store.find(101)?.is_none());
+    assert!(store.find(501)?.is_some());
+    
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_write_queue_hooks() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let mut store = Store::new(temp_dir.path())?;
+    let fired = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = Arc::clone(&fired);
+    store.on(Event::Save, move |_| {
+        counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Ok(())
+    });
+    let writer = store.writer(16)?;
+    
+    // A batch failing on a duplicate is retried user by user, hooks not run again
+    let mut twin = create_test_user(2);
+    twin.email = create_test_user(1).email;
+    let first = writer.submit(create_test_user(1)).await?;
+    let second = writer.submit(twin).await?;
+    let third = writer.submit(create_test_user(3)).await?;
+    first.await?;
+    assert!(matches!(second.await, Err(Error::Duplicate { .. })));
+    third.await?;
+    assert_eq!(fired.load(std::sync::atomic::Ordering::Relaxed), 3);
+    
+    let store = writer.close().await?;
+    assert_eq!(store.count()?, 2);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_write_queue_reads() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    store.save(&create_test_user(1))?;
+    store.save(&create_test_user(2))?;
+    let writer = store.writer(64)?;
+    
+    // Queued writes are visible before they commit
+    let mut renamed = create_test_user(1);
+    renamed.name = "Renamed".to_string();
+    let saved = writer.submit(renamed).await?;
+    let created = writer.submit(create_test_user(3)).await?;
+    assert_eq!(writer.find(1).await?.unwrap().name, "Renamed");
+    assert!(writer.find(3).await?.is_some());
+    assert_eq!(writer.find(2).await?.unwrap().name, "User 2");
+    assert!(writer.find(4).await?.is_none());
+    
+    // Once committed they come from the store, with their revision
+    saved.await?;
+    created.await?;
+    let stored = writer.find(1).await?.unwrap();
+    assert_eq!((stored.name.as_str(), stored.revision), ("Renamed", 2));
+    writer.delete(2).await?;
+    assert!(writer.find(2).await?.is_none());
+    
+    // A refused write stops showing once it fails
+    writer.close().await?.close()?;
+    let writer = Store::builder().path(temp_dir.path()).validator(Arc::new(Basic)).open()?.writer(4)?;
+    let mut nameless = create_test_user(5);
+    nameless.name.clear();
+    assert!(writer.submit(nameless).await?.await.is_err());
+    assert!(writer.find(5).await?.is_none());
+    assert!(writer.close().await?.find(1)?.is_some());
+    
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stream() -> Result<()> {
+    use futures::StreamExt;
+    
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    for id in (1..=300).rev() {
+        store.save(&create_test_user(id))?;
+    }
+    store.delete(150)?;
+    
+    // Same users in the same order as a scan
+    let streamed = store.stream().map(|user| user.map(|user| user.id)).collect::<Vec<_>>().await;
+    let streamed = streamed.into_iter().collect::<Result<Vec<_>>>()?;
+    let scanned = store.scan().map(|user| user.map(|user| user.id)).collect::<Result<Vec<_>>>()?;
+    assert_eq!(streamed.len(), 299);
+    assert_eq!(streamed, scanned);
+    
+    // Other tasks on a single thread get to run while it streams
+    let ticks = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let ticker = {
+        let ticks = Arc::clone(&ticks);
+        tokio::spawn(async move {
+            loop {
+                ticks.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                tokio::task::yield_now().await;
+            }
+        })
+    };
+    tokio::task::yield_now().await;
+    let before = ticks.load(std::sync::atomic::Ordering::Relaxed);
+    assert_eq!(store.stream().count().await, 299);
+    assert!(ticks.load(std::sync::atomic::Ordering::Relaxed) > before);
+    ticker.abort();
+    
+    Ok(())
+}
+
+#[test]
+fn test_write_queue_runtime() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    assert!(matches!(store.writer(4), Err(Error::Config(_))));
+    Ok(())
+}
+
+#[test]
+fn test_preallocate() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let capacity = 256 * 1024;
+    let store = Store::builder().path(temp_dir.path()).segment(capacity).preallocate(true).open()?;
+    for id in 1..=10 {
+        store.save(&create_test_user(id))?;
+    }
+    
+    // The file holds only its records, its blocks the whole segment
+    let path = temp_dir.path().join("segments").join("segment_1.dat");
+    let size = std::fs::metadata(&path)?.len();
+    assert!(size < capacity);
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::MetadataExt;
+        assert!(std::fs::metadata(&path)?.blocks() * 512 >= capacity);
+    }
+    for id in 1..=10 {
+        assert_eq!(store.find(id)?.unwrap().email, format!("user{}@test.com", id));
+    }
+    
+    // Sealing gives back what the records did not fill
+    store.close()?;
+    assert_eq!(std::fs::metadata(&path)?.len(), size);
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::MetadataExt;
+        assert!(std::fs::metadata(&path)?.blocks() * 512 < capacity);
+    }
+    let store = Store::builder().path(temp_dir.path()).segment(capacity).preallocate(true).open()?;
+    assert_eq!(store.count()?, 10);
+    
+    // Rotated segments end with footers that hold up
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder().path(temp_dir.path()).segment(4096).preallocate(true).open()?;
+    for id in 1..=100 {
+        store.save(&create_test_user(id))?;
+    }
+    store.close()?;
+    let store = Store::builder().path(temp_dir.path()).segment(4096).recovery(true).open()?;
+    assert!(store.health()?.corrupt.is_empty());
+    assert_eq!(store.count()?, 100);
+    
+    Ok(())
+}
+
+#[test]
+fn test_tail_and_sample() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    for id in 1..=300 {
+        store.save(&create_test_user(id))?;
+    }
+    let keys = store.keys().collect::<Result<Vec<_>>>()?;
+    let ids = |users: Vec<User>| users.into_iter().map(|user| user.id).collect::<Vec<_>>();
+    
+    assert_eq!(ids(store.tail(3)?), keys[297..]);
+    assert!(store.tail(0)?.is_empty());
+    assert_eq!(store.tail(500)?.len(), 300);
+    
+    // A sample keeps index order and is repeatable by seed
+    let sample = ids(store.sample(10, 7)?);
+    assert_eq!(sample.len(), 10);
+    let positions: Vec<_> = sample.iter().map(|id| keys.iter().position(|key| key == id).unwrap()).collect();
+    assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(ids(store.sample(10, 7)?), sample);
+    assert_ne!(ids(store.sample(10, 8)?), sample);
+    assert_eq!(ids(store.sample(500, 7)?), keys);
+    
+    Ok(())
+}
+
+#[test]
+fn test_filter() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    for id in 1..=40 {
+        let mut user = create_test_user(id);
+        if id % 2 == 0 {
+            user.email = format!("user{}@example.com", id);
+        }
+        user.location.country = if id % 4 == 0 { "VN" } else { "JP" }.to_string();
+        store.save(&user)?;
+    }
+    let ids = |users: Vec<User>| {
+        let mut ids = users.into_iter().map(|user| user.id).collect::<Vec<_>>();
+        ids.sort();
+        ids
+    };
+    let parse = |filters: &[&str]| filters.iter().map(|text| text.parse()).collect::<Result<Vec<Filter>>>();
+    
+    assert!(matches!("email".parse::<Filter>(), Err(Error::Invalid { .. })));
+    assert!(matches!("postal=12345".parse::<Filter>(), Err(Error::Invalid { .. })));
+    let filter: Filter = "email~@example.com".parse()?;
+    assert_eq!(filter.to_string(), "email~@example.com");
+    assert_eq!("name=a=b".parse::<Filter>()?.value, "a=b");
+    
+    // Country is served by the catalog and email checked on each user
+    let both = parse(&["email~@example.com", "country=VN"])?;
+    assert_eq!(ids(store.filter(&both, usize::MAX)?), (1..=10).map(|n| n * 4).collect::<Vec<_>>());
+    assert_eq!(store.filter(&both, 3)?.len(), 3);
+    assert!(store.filter(&both, 0)?.is_empty());
+    
+    // Without an exact filter the catalog serves, the store is scanned
+    let scanned = parse(&["email~@example.com", "name~3"])?;
+    assert_eq!(ids(store.filter(&scanned, usize::MAX)?), vec![30, 32, 34, 36, 38]);
+    assert_eq!(ids(store.filter(&parse(&["country=FR"])?, 10)?), Vec::<u64>::new());
+    
+    Ok(())
+}
+
+#[test]
+fn test_statement() -> Result<()> {
+    let statement: Statement = r#"select name, city WHERE email ~ "@example.com" and country = VN LIMIT 50"#.parse()?;
+    assert_eq!(statement.fields, vec![Field::Name, Field::City]);
+    assert_eq!(statement.filters, vec!["email~@example.com".parse()?, "country=VN".parse()?]);
+    assert_eq!(statement.limit, Some(50));
+    
+    let statement: Statement = r#"SELECT * WHERE name="Ann \"A, B\" Lee""#.parse()?;
+    assert!(statement.fields.is_empty());
+    assert_eq!(statement.filters[0].value, r#"Ann "A, B" Lee"#);
+    assert_eq!(statement.limit, None);
+    
+    for broken in ["", "name", "SELECT", "SELECT name,", "SELECT postal", "SELECT * WHERE", "SELECT * WHERE name",
+        "SELECT * WHERE name = ", "SELECT * WHERE name = \"open", "SELECT * WHERE profile = x", "SELECT * LIMIT -1",
+        "SELECT * LIMIT 5 name"] {
+        assert!(matches!(broken.parse::<Statement>(), Err(Error::Invalid { .. })), "{:?} parsed", broken);
+    }
+    
+    // A statement runs as the filters and projection it compiles to
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    for id in 1..=10 {
+        let mut user = create_test_user(id);
+        user.location.country = if id <= 4 { "VN" } else { "JP" }.to_string();
+        store.save(&user)?;
+    }
+    let statement: Statement = "SELECT email WHERE country = VN AND name ~ 1 LIMIT 3".parse()?;
+    let users = store.filter(&statement.filters, statement.limit.unwrap_or(usize::MAX))?;
+    assert_eq!(users.len(), 1);
+    let projection = Projection::pick(&users[0], &statement.fields);
+    assert_eq!((projection.id, projection.email.as_deref(), projection.name), (1, Some("user1@test.com"), None));
+    
+    Ok(())
+}
+
+#[test]
+fn test_rebuild() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    for id in 1..=50 {
+        store.save(&create_test_user(id))?;
+    }
+    store.close()?;
+    
+    // An entry left behind by a record that is gone
+    let mut stray = create_test_user(99);
+    stray.created = 7;
+    let mut timeline = guardian_store::timeline::Timeline::new(temp_dir.path())?;
+    timeline.insert(&stray)?;
+    timeline.sync()?;
+    drop(timeline);
+    
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.created(0, 10)?.len(), 0);
+    let mut reported = Vec::new();
+    let rebuild = store.rebuild(true, |records, total| reported.push((records, total)))?;
+    assert_eq!(rebuild.records, 50);
+    assert_eq!(reported.last(), Some(&(50, 50)));
+    assert!(!rebuild.matched());
+    let created = rebuild.checks.iter().find(|check| check.name == "created").unwrap();
+    assert_eq!((created.kept, created.stale, created.missing), (50, 1, 0));
+    
+    // Rebuilt again, nothing differs
+    assert!(store.rebuild(true, |_, _| {})?.matched());
+    assert!(store.rebuild(false, |_, _| {})?.checks.is_empty());
+    assert_eq!(store.lookup(Field::Email, &["user7@test.com"])?.iter().map(|user| user.id).collect::<Vec<_>>(), [7]);
+    
+    Ok(())
+}
+
+#[test]
+fn test_walk() -> Result<()> {
+    for compression in [Compression::None, Compression::Dictionary] {
+        let temp_dir = TempDir::new()?;
+        let store = Store::builder().path(temp_dir.path()).segment(4096).compression(compression).open()?;
+        for id in 1..=60 {
+            store.save(&create_test_user(id))?;
+        }
+        store.close()?;
+        
+        // A sealed segment walks to its footer, with or without a dictionary
+        let segments = Segment::new(temp_dir.path().join("segments"))?;
+        let walk = segments.walk(1)?;
+        let footer = walk.footer.unwrap();
+        assert_eq!(walk.entries.len() as u64, footer.records);
+        assert_eq!((walk.end, walk.limit), (footer.bytes, footer.bytes));
+        assert_eq!(walk.dictionary.is_some(), compression == Compression::Dictionary);
+        
+        // The last segment is open, its dictionary learned from the first
+        let last = *segments.list().last().unwrap();
+        let path = temp_dir.path().join("segments").join(format!("segment_{}.dat", last));
+        let whole = segments.walk(last)?;
+        assert!(whole.footer.is_none());
+        assert_eq!(whole.end, whole.limit);
+        assert_eq!(whole.dictionary.is_some_and(|length| length > 0), compression == Compression::Dictionary);
+        
+        // Damage shows as a walk falling short
+        let cut = whole.entries.last().unwrap().offset + 8;
+        std::fs::OpenOptions::new().write(true).open(&path)?.set_len(cut)?;
+        let torn = segments.walk(last)?;
+        assert_eq!(torn.entries.len(), whole.entries.len() - 1);
+        assert!(torn.end < torn.limit);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_direct_reads() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let open = || Store::builder().path(temp_dir.path()).segment(64 * 1024).direct(true).open();
+    let store = open()?;
+    for id in 1..=2000 {
+        store.save(&create_test_user(id))?;
+    }
+    
+    // Point reads and scans cross block boundaries, the active segment included
+    for id in [1, 999, 2000] {
+        assert_eq!(store.find(id)?.unwrap().email, format!("user{}@test.com", id));
+    }
+    let scanned = store.scan().collect::<Result<Vec<_>>>()?;
+    assert_eq!(scanned.len(), 2000);
+    assert!(scanned.iter().all(|user| user.email == format!("user{}@test.com", user.id)));
+    
+    // Compaction reads directly as well
+    for id in 1..=1500 {
+        store.delete(id)?;
+    }
+    store.compact(Config { throttle: false, ..Config::default() })?;
+    store.close()?;
+    let store = open()?;
+    assert_eq!(store.count()?, 500);
+    assert_eq!(store.find(1750)?.unwrap().name, create_test_user(1750).name);
+    
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_group_commit_window() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let latency = probe::fsync(temp_dir.path(), 8)?;
+    assert_eq!(latency.rounds, 8);
+    assert!(latency.median <= latency.worst);
+    assert!(!temp_dir.path().join("probe.tmp").exists());
+    
+    // A set window is kept; tuning picks one from the device under sync writes only
+    let window = Duration::from_millis(2);
+    let store = Store::builder().path(temp_dir.path()).window(window).tune(true).open()?;
+    assert_eq!(store.window(), window);
+    store.close()?;
+    let store = Store::builder().path(temp_dir.path()).durability(Durability::Sync).tune(true).open()?;
+    assert!((Duration::from_micros(100)..=Duration::from_millis(10)).contains(&store.window()));
+    
+    // Writes waiting out the window still all commit
+    let writer = Arc::new(store.writer(16)?);
+    let producers: Vec<_> = (1..=16)
+        .map(|id| {
+            let writer = Arc::clone(&writer);
+            tokio::spawn(async move { writer.save(create_test_user(id)).await })
+        })
+        .collect();
+    for producer in producers {
+        producer.await.unwrap()?;
+    }
+    let store = Arc::into_inner(writer).unwrap().close().await?;
+    assert_eq!(store.count()?, 16);
+    
+    Ok(())
+}
+
+/// Field values recorded on one span, by field name
+type Recorded = std::collections::BTreeMap<String, String>;
+
+/// Span names with the fields recorded on them
+#[derive(Clone, Default)]
+struct Spans(Arc<std::sync::Mutex<Vec<(String, Recorded)>>>);
+
+impl Spans {
+    /// Fields of every span with a name
+    fn named(&self, name: &str) -> Vec<Recorded> {
+        let spans = self.0.lock().unwrap();
+        spans.iter().filter(|(span, _)| span == name).map(|(_, fields)| fields.clone()).collect()
+    }
+}
+
+/// Writes visited field values into a map
+struct Fields<'a>(&'a mut Recorded);
+
+impl tracing::field::Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.0.insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Spans {
+    fn on_new_span(&self, attrs: &tracing::span::Attributes<'_>, id: &tracing::span::Id, _: tracing_subscriber::layer::Context<'_, S>) {
+        let mut spans = self.0.lock().unwrap();
+        let mut fields = Recorded::new();
+        attrs.record(&mut Fields(&mut fields));
+        fields.insert("#".to_string(), id.into_u64().to_string());
+        spans.push((attrs.metadata().name().to_string(), fields));
+    }
+    
+    fn on_record(&self, id: &tracing::span::Id, values: &tracing::span::Record<'_>, _: tracing_subscriber::layer::Context<'_, S>) {
+        let mut spans = self.0.lock().unwrap();
+        let key = id.into_u64().to_string();
+        if let Some((_, fields)) = spans.iter_mut().rev().find(|(_, fields)| fields["#"] == key) {
+            values.record(&mut Fields(fields));
+        }
+    }
+}
+
+#[test]
+fn test_tracing_spans() -> Result<()> {
+    use tracing_subscriber::layer::SubscriberExt;
+    
+    let temp_dir = TempDir::new()?;
+    let spans = Spans::default();
+    let subscriber = tracing_subscriber::registry().with(spans.clone());
+    tracing::subscriber::with_default(subscriber, || -> Result<()> {
+        let store = Store::new(temp_dir.path())?;
+        for id in 1..=3 {
+            store.save(&create_test_user(id))?;
+        }
+        store.batch(&[create_test_user(4), create_test_user(5)])?;
+        store.find(2)?;
+        store.delete(3)?;
+        assert_eq!(store.scan().count(), 4);
+        Ok(())
+    })?;
+    
+    let saves = spans.named("save");
+    assert_eq!(saves.len(), 3);
+    assert_eq!(saves[1]["id"], "2");
+    assert_eq!(saves[0]["segment"], "1");
+    assert!(saves[1]["offset"].parse::<u64>().unwrap() > saves[0]["offset"].parse::<u64>().unwrap());
+    assert!(saves.iter().all(|save| save["bytes"].parse::<u64>().unwrap() > 0));
+    
+    let batch = &spans.named("batch")[0];
+    assert_eq!(batch["records"], "2");
+    assert!(batch.contains_key("bytes"));
+    
+    let find = &spans.named("find")[0];
+    assert_eq!(find["id"], "2");
+    assert_eq!(find["offset"], saves[1]["offset"]);
+    assert_eq!(spans.named("delete")[0]["offset"], saves[2]["offset"]);
+    assert_eq!(spans.named("scan")[0]["entries"], "4");
+    // Secondary indexes load their own logs too
+    let index = temp_dir.path().join("index").display().to_string();
+    assert!(spans.named("load").iter().any(|load| load["path"] == index && load["entries"] == "0"));
+    
+    Ok(())
+}
+
+#[test]
+fn test_keys() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder().path(temp_dir.path()).segment(4096).open()?;
+    for id in 1..=300 {
+        store.save(&create_test_user(id))?;
+    }
+    for id in (1..=300).step_by(3) {
+        store.delete(id)?;
+    }
+    
+    let mut keys = store.keys().collect::<Result<Vec<_>>>()?;
+    keys.sort_unstable();
+    assert_eq!(keys, (1..=300).filter(|id| id % 3 != 1).collect::<Vec<_>>());
+    
+    // Segment files are never opened
+    std::fs::remove_dir_all(temp_dir.path().join("segments"))?;
+    assert_eq!(store.keys().count(), 200);
+    assert!(store.scan().all(|user| user.is_err()));
+    
+    store.close()?;
+    assert!(matches!(store.keys().next(), Some(Err(Error::Closed))));
+    
+    Ok(())
+}
+
+#[test]
+fn test_create() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder()
+        .path(temp_dir.path())
+        .generator(Arc::new(Monotonic::default()))
+        .open()?;
+    store.save(&create_test_user(1))?;
+    store.save(&create_test_user(3))?;
+    
+    // Taken IDs are skipped and the passed ID is ignored
+    let first = store.create(&create_test_user(10))?;
+    let second = store.create(&create_test_user(20))?;
+    assert_eq!((first, second), (2, 4));
+    assert_eq!(store.find(2)?.unwrap().revision, 1);
+    assert_eq!(store.find(1)?.unwrap().name, "User 1");
+    assert_eq!(store.count()?, 4);
+    
+    // Snowflakes increase, carry their node and are distinct within a millisecond
+    let snowflake = Snowflake::new(5);
+    let ids = (0..10_000).map(|_| snowflake.next()).collect::<Vec<_>>();
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(ids.iter().all(|id| (id >> 12) & 0x3ff == 5));
+    
+    let store = Store::new(temp_dir.path().join("default"))?;
+    let id = store.create(&create_test_user(0))?;
+    assert!(id > 0 && store.contains(id)?);
+    
+    Ok(())
+}
+
+#[test]
+fn test_create_race() -> Result<()> {
+    /// Proposes every ID twice, so concurrent creates collide
+    #[derive(Debug, Default)]
+    struct Twice(std::sync::atomic::AtomicU64);
+    
+    impl Generator for Twice {
+        fn next(&self) -> u64 {
+            self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed) / 2 + 1
+        }
+    }
+    
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder()
+        .path(temp_dir.path())
+        .generator(Arc::new(Twice::default()))
+        .open()?;
+    
+    // A taken ID, whether seen by the probe or taken after it, is skipped
+    let mut ids = std::thread::scope(|scope| {
+        let workers = (0..4)
+            .map(|worker| {
+                let store = &store;
+                // Distinct users, as emails are unique
+                scope.spawn(move || (0..100).map(|n| store.create(&create_test_user(worker * 100 + n))).collect::<Result<Vec<_>>>())
+            })
+            .collect::<Vec<_>>();
+        workers.into_iter().map(|worker| worker.join().unwrap()).collect::<Result<Vec<_>>>()
+    })?.concat();
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids.len(), 400);
+    assert_eq!(store.count()?, 400);
+    assert!(store.find(ids[0])?.is_some_and(|user| user.revision == 1));
+    
+    Ok(())
+}
+
+#[test]
+fn test_sequence() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!((store.sequence()?, store.sequence()?, store.sequence()?), (1, 2, 3));
+    // Reserved before any value is handed out
+    assert!(Manifest::load(temp_dir.path())?.unwrap().sequence >= 3);
+    
+    // A clean close releases the unused reservation
+    store.close()?;
+    assert!(matches!(store.sequence(), Err(Error::Closed)));
+    let store = Store::new(temp_dir.path())?;
+    assert_eq!(store.sequence()?, 4);
+    
+    // A crash skips the rest of the block but never repeats a value
+    let reserved = Manifest::load(temp_dir.path())?.unwrap().sequence;
+    std::mem::forget(store);
+    let store = Store::new(temp_dir.path())?;
+    let next = store.sequence()?;
+    assert_eq!(next, reserved + 1);
+    assert_eq!(store.sequence()?, next + 1);
+    
+    Ok(())
+}
+
+#[test]
+fn test_contains() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    store.save(&create_test_user(1))?;
+    store.save(&create_test_user(2))?;
+    store.delete(2)?;
+    
+    assert!(store.contains(1)?);
+    assert!(!store.contains(2)?);
+    assert!(!store.contains(3)?);
+    
+    // Answered from the index alone
+    std::fs::remove_dir_all(temp_dir.path().join("segments"))?;
+    assert!(store.find(1).is_err());
+    assert!(store.contains(1)?);
+    
+    store.close()?;
+    assert!(matches!(store.contains(1), Err(Error::Closed)));
+    
+    Ok(())
+}
+
+#[test]
+fn test_quota() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path())?;
+    store.save(&create_test_user(1))?;
+    store.save(&create_test_user(2))?;
+    store.quota(Quota { records: Some(3), bytes: None })?;
+    
+    // Replacing a record takes no room, adding one past the limit fails
+    store.save(&create_test_user(2))?;
+    store.save(&create_test_user(3))?;
+    let error = store.save(&create_test_user(4)).unwrap_err();
+    assert_eq!(error.kind(), Kind::Capacity);
+    assert!(matches!(error, Error::Quota { used: 3, limit: 3, .. }));
+    assert!(matches!(store.batch(&[create_test_user(1), create_test_user(5)]), Err(Error::Quota { .. })));
+    assert!(store.find(5)?.is_none());
+    
+    // Deletes free room
+    store.delete(1)?;
+    store.batch(&[create_test_user(4), create_test_user(4)])?;
+    
+    // Live bytes are tracked and survive reopening with the quota
+    let stats = store.stats()?;
+    assert_eq!(stats.records, 3);
+    assert!(stats.size > 0 && stats.size < stats.bytes);
+    store.close()?;
+    // Manifests from before byte tracking are counted on open
+    let mut manifest = Manifest::load(temp_dir.path())?.unwrap();
+    manifest.tallies.values_mut().for_each(|tally| tally.bytes = 0);
+    manifest.save(temp_dir.path())?;
+    let store = Store::new(temp_dir.path())?;
+    let reopened = store.stats()?;
+    assert_eq!((reopened.records, reopened.size), (3, stats.size));
+    assert_eq!(reopened.quota.records, Some(3));
+    
+    // Once live bytes reach their limit any save is refused
+    store.quota(Quota { records: None, bytes: Some(stats.size) })?;
+    assert!(matches!(store.save(&create_test_user(2)), Err(Error::Quota { .. })));
+    store.quota(Quota::default())?;
+    store.save(&create_test_user(6))?;
+    
+    Ok(())
+}
+
+#[test]
+fn test_hooks() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let mut store = Store::new(temp_dir.path())?;
+    let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+    
+    // Before hooks refuse writes, after hooks see what landed
+    store.on(Event::Save, |user| match user.id {
+        13 => Err(validator::invalid("id", "is unlucky")),
+        _ => Ok(()),
+    });
+    store.on(Event::Delete, |user| match user.id {
+        1 => Err(validator::invalid("id", "is protected")),
+        _ => Ok(()),
+    });
+    for event in [Event::Saved, Event::Deleted] {
+        let seen = seen.clone();
+        store.on(event, move |user| {
+            seen.lock().unwrap().push((event, user.id, user.revision));
+            Ok(())
+        });
+    }
+    
+    store.save(&create_test_user(1))?;
+    store.save(&create_test_user(1))?;
+    assert!(matches!(store.save(&create_test_user(13)), Err(Error::Invalid { .. })));
+    assert!(store.find(13)?.is_none());
+    
+    // One refused user keeps the whole batch out
+    let users = [create_test_user(2), create_test_user(13)];
+    assert!(store.batch(&users).is_err());
+    assert!(store.find(2)?.is_none());
+    store.batch(&users[..1])?;
+    
+    assert!(matches!(store.delete(1), Err(Error::Invalid { .. })));
+    assert!(store.contains(1)?);
+    store.delete(2)?;
+    // Nothing stored, nothing to hook
+    store.delete(3)?;
+    
+    assert_eq!(*seen.lock().unwrap(), vec![
+        (Event::Saved, 1, 1),
+        (Event::Saved, 1, 2),
+        (Event::Saved, 2, 1),
+        (Event::Deleted, 2, 1),
+    ]);
+    
+    // Errors from after hooks surface once the write has landed
+    store.on(Event::Saved, |_| Err(Error::Unsupported("queue unavailable".to_string())));
+    assert!(store.save(&create_test_user(4)).is_err());
+    assert!(store.contains(4)?);
+    
+    Ok(())
+}
+
+/// Sink remembering what it was sent, failing while told to
+#[derive(Default)]
+struct Recorder {
+    /// IDs and revisions received, in order
+    sent: Vec<(u64, u64)>,
+    /// Refuse the next batch
+    down: bool,
+}
+
+impl Sink for Recorder {
+    fn send(&mut self, changes: &[(Position, Entry)]) -> Result<()> {
+        if std::mem::take(&mut self.down) {
+            return Err(Error::Publish("broker unreachable".to_string()));
+        }
+        self.sent.extend(changes.iter().map(|(_, entry)| (entry.id, entry.revision)));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_publisher() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::builder().path(temp_dir.path()).audit("alice").open()?;
+    store.save(&create_test_user(1))?;
+    store.batch(&[create_test_user(2), create_test_user(3)])?;
+    store.delete(2)?;
+    
+    // Changes come out in write order, across batches
+    let changes = store.changes(None, 10)?;
+    assert_eq!(changes.len(), 4);
+    assert_eq!(store.changes(Some(changes[1].0), 10)?.len(), 2);
+    
+    let mut publisher = Publisher::new(temp_dir.path(), "orders", Recorder::default())?.batch(3);
+    assert_eq!(publisher.pump(&store)?, 4);
+    assert_eq!(publisher.sink().sent, vec![(1, 1), (2, 1), (3, 1), (2, 1)]);
+    assert_eq!(publisher.pump(&store)?, 0);
+    
+    // A restarted publisher resumes after its last acknowledged change
+    store.save(&create_test_user(1))?;
+    let mut publisher = Publisher::new(temp_dir.path(), "orders", Recorder { down: true, ..Recorder::default() })?;
+    assert!(matches!(publisher.pump(&store), Err(Error::Publish(_))));
+    assert_eq!(publisher.pump(&store)?, 1);
+    assert_eq!(publisher.sink().sent, vec![(1, 2)]);
+    
+    // Each name keeps its own cursor
+    let mut other = Publisher::new(temp_dir.path(), "search", Recorder::default())?;
+    assert_eq!(other.pump(&store)?, 5);
+    assert!(Publisher::new(temp_dir.path(), "../escape", Recorder::default()).is_err());
+    
+    // The change stream needs auditing
+    store.close()?;
+    let store = Store::new(temp_dir.path())?;
+    assert!(matches!(store.changes(None, 10), Err(Error::Config(_))));
+    
+    Ok(())
+}
+
+#[cfg(feature = "uring")]
+#[test]
+fn test_uring_backend() -> Result<()> {
+    use guardian_store::uring::Uring;
+    
+    let temp_dir = TempDir::new()?;
+    let open = || Store::builder()
+        .path(temp_dir.path())
+        .segment(4096)
+        .compression(Compression::Lz4)
+        .backend(Arc::new(Uring))
+        .open();
+    
+    let store = open()?;
+    for id in 1..=100 {
+        store.save(&create_test_user(id))?;
+    }
+    store.delete(50)?;
+    assert_eq!(store.find(7)?.unwrap().name, "User 7");
+    store.close()?;
+    
+    // Reopens through the same backend, rolled over into many segments
+    let store = open()?;
+    let ids = store.scan().map(|user| user.map(|user| user.id)).collect::<Result<Vec<_>>>()?;
+    assert_eq!(ids.len(), 99);
+    assert!(!ids.contains(&50));
+    assert_eq!(store.find(100)?.unwrap().id, 100);
+    
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_parquet_export() -> Result<()> {
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path().join("store"))?;
+    for id in 1..=50 {
+        let mut user = create_test_user(id);
+        if id % 3 == 0 {
+            user.location.point = Some(Point { latitude: 10.5, longitude: 106.7 });
+            user.profile = Some(Profile {
+                age: 30,
+                job: "Analyst".to_string(),
+                interests: vec!["data".to_string()],
+            });
+        }
+        store.save(&user)?;
+    }
+    
+    let path = temp_dir.path().join("users.parquet");
+    assert_eq!(store.export(&path)?, 50);
+    
+    let bytes = std::fs::read(&path)?;
+    assert_eq!(&bytes[..4], b"PAR1");
+    assert_eq!(&bytes[bytes.len() - 4..], b"PAR1");
+    let footer = u32::from_le_bytes(bytes[bytes.len() - 8..bytes.len() - 4].try_into().unwrap()) as usize;
+    assert!(footer + 12 < bytes.len());
+    let meta = &bytes[bytes.len() - 8 - footer..bytes.len() - 8];
+    let contains = |needle: &[u8]| meta.windows(needle.len()).any(|window| window == needle);
+    assert!(contains(b"interests"));
+    assert!(contains(b"guardian-store"));
+    
+    // Empty stores still produce a readable file
+    let empty = Store::new(temp_dir.path().join("empty"))?;
+    assert_eq!(empty.export(temp_dir.path().join("empty.parquet"))?, 0);
+    
+    Ok(())
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_parquet_roundtrip() -> Result<()> {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::{Field as Value, RowAccessor};
+    
+    let temp_dir = TempDir::new()?;
+    let store = Store::new(temp_dir.path().join("store"))?;
+    for id in 1..=20 {
+        let mut user = create_test_user(id);
+        if id % 2 == 0 {
+            user.location.point = Some(Point { latitude: 10.5, longitude: 106.7 });
+        }
+        store.save(&user)?;
+    }
+    let path = temp_dir.path().join("users.parquet");
+    assert_eq!(store.export(&path)?, 20);
+    
+    // The footer and pages decode with a stock reader
+    let reader = SerializedFileReader::new(std::fs::File::open(&path)?).unwrap();
+    let metadata = reader.metadata().file_metadata();
+    assert_eq!(metadata.num_rows(), 20);
+    let names = metadata.schema_descr().columns().iter().map(|column| column.name().to_string()).collect::<Vec<_>>();
+    assert_eq!(names[..3], ["id", "name", "email"]);
+    assert_eq!(names.last().map(String::as_str), Some("revision"));
+    
+    let rows = reader.get_row_iter(None).unwrap().collect::<std::result::Result<Vec<_>, _>>().unwrap();
+    assert_eq!(rows.len(), 20);
+    let mut ids = rows.iter().map(|row| row.get_ulong(0).unwrap()).collect::<Vec<_>>();
+    ids.sort_unstable();
+    assert_eq!(ids, (1..=20).collect::<Vec<_>>());
+    for row in &rows {
+        let id = row.get_ulong(0).unwrap();
+        assert_eq!(row.get_string(2).unwrap(), &format!("user{}@test.com", id));
+        assert_eq!(row.get_ulong(14).unwrap(), 1);
+        let latitude = row.get_column_iter().find(|(name, _)| name.as_str() == "latitude").map(|(_, value)| value.clone());
+        match id % 2 {
+            0 => assert_eq!(latitude, Some(Value::Double(10.5))),
+            _ => assert_eq!(latitude, Some(Value::Null)),
+        }
+    }
+    
+    Ok(())
+}
+
+#[cfg(feature = "resp")]
+#[tokio::test]
+async fn test_resp_server() -> Result<()> {
+    use guardian_store::resp::Server;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    
+    async fn call(client: &mut TcpStream, parts: &[&str], expected: &str) -> Result<()> {
+        let mut command = format!("*{}\r\n", parts.len());
+        for part in parts {
+            command.push_str(&format!("${}\r\n{}\r\n", part.len(), part));
+        }
+        client.write_all(command.as_bytes()).await?;
+        let mut reply = vec![0u8; expected.len()];
+        client.read_exact(&mut reply).await?;
+        assert_eq!(String::from_utf8_lossy(&reply), expected);
+        Ok(())
+    }
+    
+    let temp_dir = TempDir::new()?;
+    let store = Arc::new(Store::new(temp_dir.path())?);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    let server = tokio::spawn(async move { Server::new(store).serve(listener).await });
+    let client = &mut TcpStream::connect(address).await?;
+    
+    call(client, &["PING"], "+PONG\r\n").await?;
+    call(client, &["SET", "greeting", "hello"], "+OK\r\n").await?;
+    call(client, &["get", "greeting"], "$5\r\nhello\r\n").await?;
+    call(client, &["GET", "missing"], "$-1\r\n").await?;
+    for key in ["a", "b", "c", "d"] {
+        call(client, &["SET", key, ""], "+OK\r\n").await?;
+    }
+    call(client, &["DEL", "a", "missing", "b"], ":2\r\n").await?;
+    call(client, &["EXISTS", "c", "greeting", "a"], ":2\r\n").await?;
+    
+    // Keys come in order; the cursor resumes after the last one looked at
+    call(client, &["SCAN", "0", "COUNT", "2"], "*2\r\n$1\r\n1\r\n*2\r\n$1\r\nc\r\n$1\r\nd\r\n").await?;
+    call(client, &["SCAN", "1", "COUNT", "2"], "*2\r\n$1\r\n0\r\n*1\r\n$8\r\ngreeting\r\n").await?;
+    call(client, &["SCAN", "0", "MATCH", "g*"], "*2\r\n$1\r\n0\r\n*1\r\n$8\r\ngreeting\r\n").await?;
+    call(client, &["SCAN", "1"], "-ERR Invalid cursor: 1\r\n").await?;
+    
+    call(client, &["GET"], "-ERR wrong number of arguments for 'get' command\r\n").await?;
+    call(client, &["FLUSHALL"], "-ERR unknown command 'flushall'\r\n").await?;
+    call(client, &["QUIT"], "+OK\r\n").await?;
+    assert_eq!(client.read(&mut [0u8; 8]).await?, 0);
+    
+    server.abort();
+    Ok(())
+}
@@ -1,4 +1,7 @@
-use guardian_macros::frame;
+use guardian_macros::{frame, Record};
+use guardian_store::{Location, User};
+use proptest::collection::vec;
+use proptest::prelude::*;
 
 #[frame]
 pub struct TestFrame {
@@ -6,6 +9,163 @@ pub struct TestFrame {
     data: rest,
 }
 
+#[frame]
+pub struct Mixed {
+    kind: u8,
+    delta: i8,
+    port: u16,
+    skew: i16_le,
+    id: u32_le,
+    shift: i32,
+    stamp: u64,
+    balance: i64_le,
+    data: rest,
+}
+
+#[frame(endian = "le", version = 2)]
+pub struct Little {
+    id: u32,
+    port: u16_be,
+    skew: i16,
+    stamp: u64_be,
+    data: rest,
+}
+
+#[frame(tokio = true)]
+pub struct Ping {
+    id: u16,
+    stamp: u32,
+}
+
+#[frame(endian = "le")]
+pub struct Table {
+    count: u8,
+    weights: [u16; 3],
+    ports: [u16_be; 2],
+    deltas: [i32; 2],
+    data: rest,
+}
+
+#[frame]
+pub struct Sealed {
+    #[bytes(9)]
+    body: Bytes,
+    #[crc32]
+    crc: u32,
+    data: rest,
+}
+
+#[frame(endian = "le")]
+pub struct Covered {
+    #[crc16(2..11)]
+    crc: u16,
+    #[bytes(9)]
+    body: Bytes,
+}
+
+#[frame]
+pub struct Stored {
+    id: u64,
+    revision: u64,
+    #[archived(User)]
+    user: rest,
+}
+
+#[frame]
+pub struct Placed {
+    kind: u8,
+    #[at(4)]
+    id: u32,
+    port: u16,
+    #[at(12)]
+    data: rest,
+}
+
+/// Frames in a module shadowing the names generated code relies on
+mod shadowed {
+    use guardian_macros::frame;
+    use guardian_store::User;
+    
+    #[allow(dead_code)]
+    pub type Result<T> = std::result::Result<T, String>;
+    
+    #[allow(dead_code)]
+    pub struct Vec;
+    
+    #[allow(dead_code)]
+    mod rkyv {}
+    
+    #[allow(dead_code)]
+    pub trait Unpin {}
+    
+    #[allow(dead_code)]
+    pub trait TryInto {}
+    
+    #[frame(tokio = true)]
+    pub struct Shadowed {
+        #[crc16]
+        crc: u16,
+        ports: [u16; 2],
+        #[str(3)]
+        code: Str,
+        #[archived(User)]
+        user: rest,
+    }
+}
+
+mod nested {
+    /// Stands in for a type reached through a module path
+    pub struct Inner;
+}
+
+#[derive(Record)]
+pub struct Described<T> {
+    id: u64,
+    #[index(unique)]
+    tags: Vec<String>,
+    #[secret]
+    inner: Option<nested::Inner>,
+    value: T,
+}
+
+/// Field values of a `Mixed` frame
+type Values = (u8, i8, u16, i16, u32, i32, u64, i64, Vec<u8>);
+
+/// Fixed-size prefix of a frame according to its layout
+fn min(layout: &[(&str, usize, Option<usize>)]) -> usize {
+    layout.iter().filter_map(|(_, _, size)| *size).sum()
+}
+
+/// Encodes values at the offsets `Mixed::layout` reports
+fn pack(values: &Values) -> Vec<u8> {
+    let layout = Mixed::layout();
+    let mut bytes = vec![0u8; min(layout)];
+    let (kind, delta, port, skew, id, shift, stamp, balance, data) = values;
+    
+    for (name, offset, size) in layout {
+        let encoded = match *name {
+            "kind" => kind.to_be_bytes().to_vec(),
+            "delta" => delta.to_be_bytes().to_vec(),
+            "port" => port.to_be_bytes().to_vec(),
+            "skew" => skew.to_le_bytes().to_vec(),
+            "id" => id.to_le_bytes().to_vec(),
+            "shift" => shift.to_be_bytes().to_vec(),
+            "stamp" => stamp.to_be_bytes().to_vec(),
+            "balance" => balance.to_le_bytes().to_vec(),
+            "data" => {
+                assert_eq!(*size, None);
+                assert_eq!(*offset, bytes.len());
+                bytes.extend_from_slice(data);
+                continue;
+            }
+            other => panic!("unexpected field {}", other),
+        };
+        assert_eq!(Some(encoded.len()), *size);
+        bytes[*offset..*offset + encoded.len()].copy_from_slice(&encoded);
+    }
+    bytes
+}
+
 #[test]
 fn test_frame_macro() {
     // Test data: id=1234567890, data=[1,2,3]
@@ -17,4 +177,308 @@ fn test_frame_macro() {
     let frame = TestFrame::new(&data).unwrap();
     assert_eq!(frame.id(), 1234567890);
     assert_eq!(frame.data(), &[0x01, 0x02, 0x03]);
-} 
\ No newline at end of file
+}
+
+#[test]
+fn test_frame_layout() {
+    assert_eq!(TestFrame::layout(), &[("id", 0, Some(4)), ("data", 4, None)]);
+    
+    // Fields are packed back to back
+    let mut next = 0;
+    for (_, offset, size) in Mixed::layout() {
+        assert_eq!(*offset, next);
+        next += size.unwrap_or(0);
+    }
+    assert_eq!(min(Mixed::layout()), 30);
+}
+
+#[test]
+fn test_frame_endian() {
+    let mut data = Vec::new();
+    data.extend_from_slice(&[0x01, 0x02, 0x03, 0x04]); // id, little-endian
+    data.extend_from_slice(&[0x1F, 0x90]); // port, big-endian override
+    data.extend_from_slice(&[0xFE, 0xFF]); // skew, little-endian
+    data.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0x01, 0x00]); // stamp, big-endian override
+    data.push(0xAA);
+    
+    let frame = Little::new(&data).unwrap();
+    assert_eq!(frame.id(), 0x04030201);
+    assert_eq!(frame.port(), 8080);
+    assert_eq!(frame.skew(), -2);
+    assert_eq!(frame.stamp(), 256);
+    assert_eq!(frame.data(), &[0xAA]);
+    assert_eq!(frame.version(), 2);
+    
+    // The same bytes read big-endian by default
+    let frame = TestFrame::new(&data).unwrap();
+    assert_eq!(frame.id(), 0x01020304);
+    
+    let description: serde_json::Value = serde_json::from_str(Little::describe()).unwrap();
+    assert_eq!(description["fields"][0]["endian"], "little");
+    assert_eq!(description["fields"][1]["endian"], "big");
+}
+
+#[test]
+fn test_frame_reader() {
+    // Fixed-size frames follow each other directly
+    let stream = [0x00, 0x01, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x02, 0x00, 0x00, 0x00, 0x14];
+    let mut reader = PingReader::new(&stream[..]);
+    let mut seen = Vec::new();
+    while let Some(frame) = reader.read().unwrap() {
+        seen.push((frame.id(), frame.stamp()));
+    }
+    assert_eq!(seen, vec![(1, 10), (2, 20)]);
+    
+    // Frames ending in `rest` carry a length prefix
+    let mut stream = Vec::new();
+    for payload in [&[0, 0, 0, 7, 0xAA][..], &[0, 0, 0, 8][..]] {
+        stream.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        stream.extend_from_slice(payload);
+    }
+    let mut reader = TestFrameReader::new(std::io::Cursor::new(stream));
+    let frame = reader.read().unwrap().unwrap();
+    assert_eq!((frame.id(), frame.data()), (7, &[0xAA][..]));
+    let frame = reader.read().unwrap().unwrap();
+    assert_eq!((frame.id(), frame.data()), (8, &[][..]));
+    assert!(reader.read().unwrap().is_none());
+    
+    // A stream cut mid-frame is an error, not a clean end
+    let mut reader = PingReader::new(&[0x00, 0x01, 0x00][..]);
+    assert_eq!(reader.read().unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+    
+    // Oversized and undersized frames are rejected
+    let mut reader = TestFrameReader::new(&[0, 0, 1, 0, 1, 2][..]).limit(16);
+    assert_eq!(reader.read().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    let mut reader = TestFrameReader::new(&[0, 0, 0, 2, 1, 2][..]);
+    assert_eq!(reader.read().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[tokio::test]
+async fn test_frame_reader_async() {
+    let stream = [0x00, 0x01, 0x00, 0x00, 0x00, 0x0A, 0x00, 0x02];
+    let mut reader = PingReader::new(&stream[..]);
+    let frame = reader.receive().await.unwrap().unwrap();
+    assert_eq!((frame.id(), frame.stamp()), (1, 10));
+    assert_eq!(reader.receive().await.unwrap_err().kind(), std::io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn test_frame_constants() {
+    assert_eq!(TestFrame::SIZE, 4);
+    assert_eq!(TestFrame::OFFSET_DATA, 4);
+    assert_eq!(Mixed::SIZE, min(Mixed::layout()));
+    assert_eq!(Mixed::OFFSET_SKEW, 4);
+    assert_eq!(Mixed::OFFSET_BALANCE, 22);
+    
+    let description: serde_json::Value = serde_json::from_str(Mixed::describe()).unwrap();
+    assert_eq!(description["name"], "Mixed");
+    assert_eq!(description["size"], 30);
+    
+    let fields = description["fields"].as_array().unwrap();
+    assert_eq!(fields.len(), Mixed::layout().len());
+    assert_eq!(fields[3], serde_json::json!({
+        "name": "skew", "offset": 4, "size": 2, "kind": "i16", "endian": "little",
+    }));
+    assert_eq!(fields[8]["kind"], "rest");
+    assert!(fields[8]["size"].is_null());
+}
+
+#[test]
+fn test_record_fields() {
+    assert_eq!(Described::<u8>::FIELDS, &[
+        ("id", "u64"),
+        ("tags", "Vec<String>"),
+        ("inner", "Option<Inner>"),
+        ("value", "T"),
+    ]);
+    assert_eq!(Described::<u8>::UNIQUE, &["tags"]);
+    assert_eq!(Described::<u8>::SECRET, &["inner"]);
+    let described = Described { id: 1, tags: Vec::new(), inner: Some(nested::Inner), value: 0u8 };
+    assert!(described.inner.is_some() && described.id == 1 && described.tags.is_empty() && described.value == 0);
+}
+
+#[test]
+fn test_frame_truncated() {
+    // One byte short of the fixed prefix
+    let error = Mixed::new(&[0u8; 29]).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("need 30 bytes, got 29"));
+    
+    // The fixed prefix alone leaves `rest` empty
+    let frame = Mixed::new(&[0u8; 30]).unwrap();
+    assert!(frame.data().is_empty());
+}
+
+#[test]
+fn test_frame_array() {
+    let mut bytes = vec![3u8];
+    for weight in [1u16, 2, 0x0300] {
+        bytes.extend_from_slice(&weight.to_le_bytes());
+    }
+    for port in [80u16, 443] {
+        bytes.extend_from_slice(&port.to_be_bytes());
+    }
+    for delta in [-1i32, i32::MAX] {
+        bytes.extend_from_slice(&delta.to_le_bytes());
+    }
+    bytes.push(9);
+    
+    let frame = Table::new(&bytes).unwrap();
+    assert_eq!(frame.count(), 3);
+    assert_eq!(frame.weights(), [1, 2, 0x0300]);
+    assert_eq!(frame.ports(), [80, 443]);
+    assert_eq!(frame.deltas(), [-1, i32::MAX]);
+    assert_eq!(frame.data(), &[9]);
+    
+    assert_eq!(Table::SIZE, 1 + 6 + 4 + 8);
+    assert_eq!((Table::OFFSET_PORTS, Table::OFFSET_DELTAS), (7, 11));
+    assert_eq!(Table::layout()[1], ("weights", 1, Some(6)));
+    let description: serde_json::Value = serde_json::from_str(Table::describe()).unwrap();
+    assert_eq!(description["fields"][2], serde_json::json!({
+        "name": "ports", "offset": 7, "size": 4, "kind": "array", "endian": "big", "element": "u16", "count": 2,
+    }));
+}
+
+#[test]
+fn test_frame_checksum() {
+    // The standard check input and its published CRCs
+    let mut bytes = b"123456789".to_vec();
+    bytes.extend_from_slice(&0xCBF4_3926u32.to_be_bytes());
+    bytes.extend_from_slice(b"unchecked");
+    let frame = Sealed::new(&bytes).unwrap();
+    assert_eq!(frame.checksum(), 0xCBF4_3926);
+    assert_eq!(frame.checksum(), crc32fast::hash(b"123456789"));
+    frame.verify().unwrap();
+    
+    bytes[0] = b'0';
+    let error = Sealed::new(&bytes).unwrap().verify().unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("Checksum mismatch in `crc`: stored 0xcbf43926"));
+    
+    let mut bytes = 0x29B1u16.to_le_bytes().to_vec();
+    bytes.extend_from_slice(b"123456789");
+    let frame = Covered::new(&bytes).unwrap();
+    assert_eq!(frame.checksum(), 0x29B1);
+    frame.verify().unwrap();
+    
+    let description: serde_json::Value = serde_json::from_str(Covered::describe()).unwrap();
+    assert_eq!(description["fields"][0]["checksum"], "crc16");
+    assert_eq!(description["fields"][0]["range"], serde_json::json!([2, 11]));
+}
+
+#[test]
+fn test_frame_archived() {
+    let user = User {
+        id: 7,
+        name: "Lan".to_string(),
+        email: "lan@test.com".to_string(),
+        location: Location {
+            street: "1 Trang Tien".to_string(),
+            city: "Hanoi".to_string(),
+            country: "VN".to_string(),
+            postal: "100000".to_string(),
+            point: None,
+        },
+        profile: None,
+        created: 1,
+        updated: 1,
+        revision: 3,
+    };
+    let mut bytes = rkyv::AlignedVec::new();
+    bytes.extend_from_slice(&7u64.to_be_bytes());
+    bytes.extend_from_slice(&3u64.to_be_bytes());
+    bytes.extend_from_slice(&rkyv::to_bytes::<_, 256>(&user).unwrap());
+    
+    // Sub-objects are borrowed from the input, outliving the frame
+    let location = {
+        let frame = Stored::new(&bytes).unwrap();
+        assert_eq!((frame.id(), frame.revision()), (7, 3));
+        let archived = frame.user().unwrap();
+        assert_eq!(archived.email, "lan@test.com");
+        &archived.location
+    };
+    assert_eq!((location.city.as_str(), location.country.as_str()), ("Hanoi", "VN"));
+    
+    // Malformed and misaligned archives are refused rather than trusted
+    let damaged = &bytes[..Stored::SIZE + 3];
+    let error = Stored::new(damaged).unwrap().user().err().expect("a truncated archive is refused");
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("Invalid archive in `user`"));
+    let mut shifted = rkyv::AlignedVec::new();
+    shifted.push(0);
+    shifted.extend_from_slice(&bytes);
+    assert!(Stored::new(&shifted[1..]).unwrap().user().is_err());
+}
+
+#[test]
+fn test_frame_placed() {
+    let mut bytes = vec![7, 0xff, 0xff, 0xff];
+    bytes.extend_from_slice(&42u32.to_be_bytes());
+    bytes.extend_from_slice(&8080u16.to_be_bytes());
+    bytes.extend_from_slice(&[0xee, 0xee, 1, 2]);
+    
+    let frame = Placed::new(&bytes).unwrap();
+    assert_eq!((frame.kind(), frame.id(), frame.port()), (7, 42, 8080));
+    assert_eq!(frame.data(), &[1, 2]);
+    
+    // Padding between placed fields counts toward the size but is never read
+    assert_eq!((Placed::OFFSET_ID, Placed::OFFSET_PORT, Placed::OFFSET_DATA), (4, 8, 12));
+    assert_eq!(Placed::SIZE, 12);
+    assert_eq!(Placed::layout()[2], ("port", 8, Some(2)));
+    assert!(Placed::new(&bytes[..11]).is_err());
+}
+
+#[test]
+fn test_frame_hygiene() {
+    use shadowed::{Shadowed, ShadowedReader};
+    
+    let mut bytes = vec![0, 0, 0, 80, 1, 187];
+    bytes.extend_from_slice(b"VNM");
+    let crc = Shadowed::new(&bytes).unwrap().checksum();
+    bytes[..2].copy_from_slice(&crc.to_be_bytes());
+    
+    let frame = Shadowed::new(&bytes).unwrap();
+    assert_eq!((frame.ports(), frame.code()), ([80, 443], "VNM"));
+    assert!(frame.verify().is_ok());
+    assert!(frame.user().is_err());
+    assert!(Shadowed::new(&bytes[..8]).is_err());
+    
+    let mut reader = ShadowedReader::new(&[0u8, 0, 0, 0][..]);
+    assert!(reader.read().is_err());
+    let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    let mut reader = ShadowedReader::new(&[0u8, 0, 0, 0][..]);
+    assert!(runtime.block_on(reader.receive()).is_err());
+}
+
+proptest! {
+    #[test]
+    fn test_frame_roundtrip(
+        values in (
+            any::<u8>(), any::<i8>(), any::<u16>(), any::<i16>(),
+            any::<u32>(), any::<i32>(), any::<u64>(), any::<i64>(),
+            vec(any::<u8>(), 0..64),
+        )
+    ) {
+        let bytes = pack(&values);
+        let frame = Mixed::new(&bytes).unwrap();
+        let parsed = (
+            frame.kind(), frame.delta(), frame.port(), frame.skew(),
+            frame.id(), frame.shift(), frame.stamp(), frame.balance(),
+            frame.data().to_vec(),
+        );
+        prop_assert_eq!(parsed, values);
+        prop_assert_eq!(frame.size(), bytes.len());
+    }
+    
+    #[test]
+    fn test_frame_short_input(bytes in vec(any::<u8>(), 0..64)) {
+        // Short input is rejected up front; anything accepted reads without panicking
+        match Mixed::new(&bytes) {
+            Ok(frame) => {
+                prop_assert!(bytes.len() >= min(Mixed::layout()));
+                let _ = (frame.kind(), frame.skew(), frame.stamp(), frame.balance(), frame.data());
+            }
+            Err(_) => prop_assert!(bytes.len() < min(Mixed::layout())),
+        }
+    }
+}